@@ -1,33 +1,170 @@
-#![feature(test)]
+//! Throughput of a Writer and a Reader running on separate threads.
+//!
+//! The benchmarked thread writes, a background thread reads.
+//! `construct` measures the setup cost of short-lived queues.
+//! `wait_strategy` measures the round trip latency of blocking operations:
+//! the benchmarked thread sends an element, and waits for the echo of the background thread.
+//! Set `CUEUE_BENCH_CORES=<writer>,<reader>` to pin the threads to the given cores (Linux only),
+//! e.g: to compare cores sharing a cache with ones that do not.
 
-use cueue::cueue;
+use criterion::{
+    black_box, criterion_group, criterion_main, Bencher, BenchmarkId, Criterion, Throughput,
+};
 
-extern crate test;
-use self::test::Bencher;
+use cueue::wait::{Backoff, HybridWait, WaitStrategy};
+use cueue::{cueue, Builder};
 
-#[bench]
-fn bench_write(b: &mut Bencher) {
-    let (mut w, mut r) = cueue(16).unwrap();
+/// The cores of the writer and the reader thread, from `CUEUE_BENCH_CORES`.
+fn cores() -> Option<(usize, usize)> {
+    let var = std::env::var("CUEUE_BENCH_CORES").ok()?;
+    let (writer, reader) = var.split_once(',')?;
+    Some((writer.trim().parse().ok()?, reader.trim().parse().ok()?))
+}
+
+#[cfg(target_os = "linux")]
+fn pin(core: usize) {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        let result = libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set);
+        assert_eq!(result, 0, "failed to pin the thread to core {}", core);
+    }
+}
 
-    let rt = std::thread::spawn(move || {
+#[cfg(not(target_os = "linux"))]
+fn pin(_core: usize) {}
+
+/// Write `batch` elements per iteration, while the reader consumes whatever is available.
+fn write_batches<T>(b: &mut Bencher<'_>, batch: usize)
+where
+    T: Copy + Default + Send + 'static,
+{
+    let cores = cores();
+    let (mut w, mut r) = cueue::<T>(1 << 16).unwrap();
+
+    let reader = std::thread::spawn(move || {
+        if let Some((_, core)) = cores {
+            pin(core);
+        }
         while !r.is_abandoned() {
-            let _rr = r.read_chunk();
+            black_box(r.read_chunk());
             r.commit();
         }
     });
+    if let Some((core, _)) = cores {
+        pin(core);
+    }
+
+    let src = vec![T::default(); batch];
+    b.iter(|| {
+        while w.write_chunk().len() < batch {}
+        w.write_chunk_exact(batch).unwrap().copy_from_slice(&src);
+        w.commit(batch);
+    });
+
+    drop(w);
+    reader.join().unwrap();
+}
+
+fn bench_batch_size(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch_size");
+    for batch in [1, 16, 256, 4096] {
+        group.throughput(Throughput::Bytes(batch as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(batch), &batch, |b, &batch| {
+            write_batches::<u8>(b, batch)
+        });
+    }
+    group.finish();
+}
 
-    b.iter(move || {
-        let buf = loop {
-            let buf = w.write_chunk();
-            if buf.len() >= 16 {
-                break buf;
-            }
-        };
-        unsafe {
-            std::ptr::copy_nonoverlapping(b"123456789abcdefh", buf.as_mut_ptr(), 16);
+fn bench_element_size(c: &mut Criterion) {
+    const BATCH: usize = 64;
+    let mut group = c.benchmark_group("element_size");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    group.bench_function("1", |b| write_batches::<u8>(b, BATCH));
+    group.bench_function("8", |b| write_batches::<u64>(b, BATCH));
+    group.bench_function("64", |b| write_batches::<[u64; 8]>(b, BATCH));
+    group.bench_function("256", |b| write_batches::<[u64; 32]>(b, BATCH));
+    group.finish();
+}
+
+/// Creating and dropping a queue, e.g: per connection.
+///
+/// The double map takes three mmap calls: a reservation, the control block with the buffer,
+/// and the mirror. The ftruncate sizing the memory file is required at any capacity.
+/// The memory file is neither reused (the file of a dropped queue would have to be cleared,
+/// as costly as creating it), nor mapped without MAP_POPULATE for small capacities
+/// (populating was not measurably slower than faulting the pages in).
+fn bench_construct(c: &mut Criterion) {
+    let mut group = c.benchmark_group("construct");
+    for capacity in [1 << 12, 1 << 16, 1 << 20] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(capacity),
+            &capacity,
+            |b, &capacity| b.iter(|| cueue::<u8>(capacity).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+/// Send an element per iteration, and wait for its echo, both sides using `strategy`.
+fn ping_pong<S>(b: &mut Bencher<'_>, strategy: S, futex: bool)
+where
+    S: WaitStrategy + Clone + 'static,
+{
+    let cores = cores();
+    let builder = || Builder::new(16).futex(futex).build::<u64>().unwrap();
+    let (mut ping_w, mut ping_r) = builder();
+    let (mut pong_w, mut pong_r) = builder();
+    ping_r.set_wait_strategy(strategy.clone());
+    pong_r.set_wait_strategy(strategy);
+
+    let echo = std::thread::spawn(move || {
+        if let Some((_, core)) = cores {
+            pin(core);
+        }
+        while let Ok(chunk) = ping_r.read_chunk_blocking() {
+            let value = chunk[0];
+            ping_r.limited_read_chunk(1);
+            ping_r.commit();
+            pong_w.push(value).unwrap();
         }
-        w.commit(16);
     });
+    if let Some((core, _)) = cores {
+        pin(core);
+    }
 
-    rt.join().unwrap();
+    let mut value = 0;
+    b.iter(|| {
+        ping_w.push(value).unwrap();
+        let echo = pong_r.read_chunk_blocking().unwrap()[0];
+        pong_r.commit();
+        value = black_box(echo) + 1;
+    });
+
+    drop(ping_w);
+    echo.join().unwrap();
 }
+
+fn bench_wait_strategy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wait_strategy");
+    let futex = cfg!(target_os = "linux");
+    group.bench_function("backoff", |b| ping_pong(b, Backoff::default(), false));
+    group.bench_function("hybrid", |b| ping_pong(b, HybridWait::default(), false));
+    if futex {
+        group.bench_function("backoff_futex", |b| ping_pong(b, Backoff::default(), true));
+        group.bench_function("hybrid_futex", |b| {
+            ping_pong(b, HybridWait::default(), true)
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_batch_size,
+    bench_element_size,
+    bench_construct,
+    bench_wait_strategy
+);
+criterion_main!(benches);
@@ -0,0 +1,97 @@
+//! The shared memory layout of a queue, for non-Rust producers and consumers.
+//!
+//! The layout described here does not change within a major version of this crate,
+//! except that new fields might be appended to the control block (growing `CONTROL_BLOCK_SIZE`).
+//! Other changes require bumping `LAYOUT_VERSION`, which is part of `MAGIC`.
+//!
+//! A queue of `capacity` elements of type `T` (see `Writer::capacity`, `Writer::page_size`)
+//! is a control block of `page_size` bytes, followed by the buffer of `capacity * size_of::<T>()`
+//! bytes. For file backed queues (see `Builder::file`), that's the layout of the file.
+//!
+//! The control block is a sequence of little endian (native, on every supported platform)
+//! 64 bit unsigned integers, each on its own 128 byte block (to avoid false sharing),
+//! accessed atomically. See the `*_OFFSET` constants for the offsets from the start
+//! of the control block:
+//!
+//!  - `magic`: `MAGIC`, stored last when the control block is initialized.
+//!  - `write_position`: the number of elements committed so far. Written by the Writer,
+//!    with release semantics, after the elements are written.
+//!  - `read_position`: the number of elements consumed so far. Written by the Reader,
+//!    with release semantics, after the elements are read. The `READER_BUSY` bit
+//!    must be ignored by Writers (see `FullPolicy::DropOldest`).
+//!  - `sequence`: the number of records committed (if enabled by `Builder::sequence`)
+//!    or dropped, written by the Writer.
+//!  - `dropped`: the number of records dropped, written by the Writer.
+//!  - `completion`: 0 while running, 1: finished successfully, 2: failed with `completion_code`.
+//!  - `completion_code`: the error code of a failed stream.
+//!  - `read_watermark`, `write_watermark`: see `Reader::set_notify_watermark`
+//!    and `Writer::set_notify_watermark`.
+//!  - `clock`: 0: CLOCK_MONOTONIC, 1: CLOCK_MONOTONIC_RAW, 2: CLOCK_REALTIME, see `clock::Clock`.
+//!  - `epoch_offset`: realtime minus `clock` in nanoseconds, two's complement.
+//!  - `reader_waiting`, `writer_waiting`: 1 while the Reader (Writer) sleeps on
+//!    `write_epoch` (`read_epoch`), see `Builder::futex`.
+//!  - `write_epoch`, `read_epoch`: the low 32 bits are a process-shared futex word,
+//!    incremented by the Writer (Reader) before waking the other side: on commit
+//!    (after updating its position, and a sequentially consistent fence), if the other side is waiting,
+//!    and on drop.
+//!  - `closed`: `WRITER_CLOSED` and `READER_CLOSED` bits, set when the handle is dropped,
+//!    or by the other handle, if it finds the process of the handle dead.
+//!  - `writer_pid`, `reader_pid`: the process id of the Writer (Reader), 0 if unknown,
+//!    see `Writer::register_process`.
+//!  - `retention`: the number of consumed elements the Writer does not overwrite,
+//!    see `Builder::retention`.
+//!  - `peak_occupancy`: the highest number of unread elements seen by the Writer,
+//!    written by the Writer, see `stats::Stats::peak_occupancy`.
+//!
+//! The element at position `p` is at index `p % capacity` of the buffer.
+//! The queue is empty if `write_position == read_position`, and full if
+//! `write_position == read_position + capacity - retention`.
+
+use crate::{CacheLineAlignedAU64, ControlBlock};
+
+/// Version of the layout, incremented on incompatible changes.
+pub const LAYOUT_VERSION: u64 = 1;
+
+/// Identifies an initialized control block: "cueue", then the layout version.
+pub const MAGIC: u64 = 0x6375_6575_6500_0000 | LAYOUT_VERSION;
+
+/// Size of each field of the control block.
+pub const FIELD_SIZE: usize = 128;
+
+/// Set in `read_position` by a Reader, while it reads a chunk.
+pub const READER_BUSY: u64 = 1 << 63;
+
+/// Set in `closed`, when the Writer is dropped.
+pub const WRITER_CLOSED: u64 = 1;
+
+/// Set in `closed`, when the Reader is dropped.
+pub const READER_CLOSED: u64 = 2;
+
+pub const MAGIC_OFFSET: usize = 0;
+pub const WRITE_POSITION_OFFSET: usize = FIELD_SIZE;
+pub const READ_POSITION_OFFSET: usize = 2 * FIELD_SIZE;
+pub const SEQUENCE_OFFSET: usize = 3 * FIELD_SIZE;
+pub const DROPPED_OFFSET: usize = 4 * FIELD_SIZE;
+pub const COMPLETION_OFFSET: usize = 5 * FIELD_SIZE;
+pub const COMPLETION_CODE_OFFSET: usize = 6 * FIELD_SIZE;
+pub const READ_WATERMARK_OFFSET: usize = 7 * FIELD_SIZE;
+pub const WRITE_WATERMARK_OFFSET: usize = 8 * FIELD_SIZE;
+pub const CLOCK_OFFSET: usize = 9 * FIELD_SIZE;
+pub const EPOCH_OFFSET_OFFSET: usize = 10 * FIELD_SIZE;
+pub const READER_WAITING_OFFSET: usize = 11 * FIELD_SIZE;
+pub const WRITER_WAITING_OFFSET: usize = 12 * FIELD_SIZE;
+pub const WRITE_EPOCH_OFFSET: usize = 13 * FIELD_SIZE;
+pub const READ_EPOCH_OFFSET: usize = 14 * FIELD_SIZE;
+pub const CLOSED_OFFSET: usize = 15 * FIELD_SIZE;
+pub const WRITER_PID_OFFSET: usize = 16 * FIELD_SIZE;
+pub const READER_PID_OFFSET: usize = 17 * FIELD_SIZE;
+pub const RETENTION_OFFSET: usize = 18 * FIELD_SIZE;
+pub const PEAK_OCCUPANCY_OFFSET: usize = 19 * FIELD_SIZE;
+
+/// Size of the used part of the control block.
+pub const CONTROL_BLOCK_SIZE: usize = 20 * FIELD_SIZE;
+
+const _: () = {
+    assert!(std::mem::size_of::<CacheLineAlignedAU64>() == FIELD_SIZE);
+    assert!(std::mem::size_of::<ControlBlock>() == CONTROL_BLOCK_SIZE);
+};
@@ -0,0 +1,319 @@
+//! Arrow RecordBatches over a byte queue, encoded in the Arrow IPC stream format.
+//!
+//! The Writer encodes each batch (with its dictionaries) into the queue, and commits it at once.
+//! The Reader decodes batches without copying the column data: the returned batches
+//! borrow their buffers from the queue. `ArrowReader::commit` only consumes batches that
+//! are no longer referenced, therefore the Writer never overwrites a live batch.
+//!
+//!```
+//! use std::sync::Arc;
+//!
+//! use arrow_array::{Int32Array, RecordBatch};
+//! use arrow_schema::{DataType, Field, Schema};
+//! use cueue::arrow::{ArrowReader, ArrowWriter};
+//!
+//! let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+//! let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![1, 2, 3]))]).unwrap();
+//!
+//! let (w, r) = cueue::cueue::<u8>(1 << 16).unwrap();
+//! let mut w = ArrowWriter::new(w, schema);
+//! let mut r = ArrowReader::new(r);
+//!
+//! w.write_batch(&batch).unwrap();
+//! assert_eq!(r.read_batch().unwrap(), Some(batch));
+//! r.commit();
+//!```
+
+use std::collections::{HashMap, VecDeque};
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_buffer::Buffer;
+use arrow_ipc::convert::fb_to_schema;
+use arrow_ipc::reader::{read_dictionary, read_record_batch};
+use arrow_ipc::writer::{
+    write_message, DictionaryTracker, EncodedData, IpcDataGenerator, IpcWriteOptions,
+};
+use arrow_ipc::{root_as_message, MessageHeader, MetadataVersion};
+use arrow_schema::{ArrowError, SchemaRef};
+
+use crate::framed::FrameError;
+use crate::{Reader, Writer};
+
+/// Alignment of the messages and the buffers in the queue
+const ALIGNMENT: usize = 64;
+
+/// Marks the beginning of an IPC message
+const CONTINUATION: [u8; 4] = [0xff; 4];
+
+/// Error of writing or reading a batch.
+#[derive(Debug)]
+pub enum Error {
+    /// The batch could not be written or read
+    Frame(FrameError),
+    /// The batch could not be encoded or decoded
+    Arrow(ArrowError),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Frame(err) => write!(f, "{}", err),
+            Error::Arrow(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<FrameError> for Error {
+    fn from(err: FrameError) -> Self {
+        Error::Frame(err)
+    }
+}
+
+impl From<ArrowError> for Error {
+    fn from(err: ArrowError) -> Self {
+        Error::Arrow(err)
+    }
+}
+
+/// Writes RecordBatches of a given schema.
+pub struct ArrowWriter {
+    writer: Writer<u8>,
+    schema: SchemaRef,
+    options: IpcWriteOptions,
+    schema_written: bool,
+}
+
+impl ArrowWriter {
+    pub fn new(writer: Writer<u8>, schema: SchemaRef) -> Self {
+        Self {
+            writer,
+            schema,
+            options: IpcWriteOptions::try_new(ALIGNMENT, false, MetadataVersion::V5)
+                .expect("valid options"),
+            schema_written: false,
+        }
+    }
+
+    /// Encode and commit `batch`, or nothing, if there's not enough space.
+    ///
+    /// The schema is written before the first batch.
+    /// The dictionaries of `batch` are written with each batch, therefore
+    /// a failed write can be retried later.
+    pub fn write_batch(&mut self, batch: &RecordBatch) -> Result<(), Error> {
+        if batch.schema() != self.schema {
+            return Err(Error::Arrow(ArrowError::SchemaError(
+                "batch schema does not match the schema of the writer".to_string(),
+            )));
+        }
+
+        let generator = IpcDataGenerator::default();
+        let mut tracker = DictionaryTracker::new(false);
+        let schema = generator.schema_to_bytes_with_dictionary_tracker(
+            &self.schema,
+            &mut tracker,
+            &self.options,
+        );
+        let (dictionaries, batch) = generator.encoded_batch(batch, &mut tracker, &self.options)?;
+
+        let mut messages = Vec::with_capacity(dictionaries.len() + 2);
+        if !self.schema_written {
+            messages.push(schema);
+        }
+        messages.extend(dictionaries);
+        messages.push(batch);
+
+        let len = messages.iter().map(message_len).sum();
+        if len > self.writer.capacity() {
+            return Err(Error::Frame(FrameError::TooLarge));
+        }
+        let mut buf = self.writer.write_chunk_exact(len).ok_or(FrameError::Full)?;
+        for message in messages {
+            write_message(&mut buf, message, &self.options)?;
+        }
+        self.writer.commit(len);
+        self.schema_written = true;
+        Ok(())
+    }
+
+    /// Returns true, if the Reader counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.writer.is_abandoned()
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<u8> {
+        self.writer
+    }
+}
+
+/// Size of `message` in the queue: prefix, padded metadata and body
+fn message_len(message: &EncodedData) -> usize {
+    align(8 + message.ipc_message.len()) + align(message.arrow_data.len())
+}
+
+/// `n`, rounded up to a multiple of `ALIGNMENT`
+fn align(n: usize) -> usize {
+    (n + ALIGNMENT - 1) & !(ALIGNMENT - 1)
+}
+
+/// Keeps the queue mapped while the buffers of a returned batch are alive.
+struct Lease(#[allow(dead_code)] Arc<Mutex<Reader<u8>>>);
+
+/// Reads RecordBatches, without copying the column data.
+pub struct ArrowReader {
+    /// shared with the leases, that keep it alive
+    reader: Arc<Mutex<Reader<u8>>>,
+    /// bytes of the messages read since the last commit
+    consumed: usize,
+    schema: Option<SchemaRef>,
+    dictionaries: HashMap<i64, ArrayRef>,
+    /// offset and lease of the batches read since the last commit
+    leases: VecDeque<(usize, Arc<Lease>)>,
+}
+
+impl ArrowReader {
+    pub fn new(reader: Reader<u8>) -> Self {
+        Self {
+            reader: Arc::new(Mutex::new(reader)),
+            consumed: 0,
+            schema: None,
+            dictionaries: HashMap::new(),
+            leases: VecDeque::new(),
+        }
+    }
+
+    /// Return the next complete batch, if available.
+    ///
+    /// The column data of the batch is borrowed from the queue (dictionaries are copied).
+    /// The batch remains in the queue until it is dropped and `commit` is called.
+    /// The Writer counterpart sees the Reader alive while any returned batch is alive.
+    pub fn read_batch(&mut self) -> Result<Option<RecordBatch>, Error> {
+        let shared = self.reader.clone();
+        let mut reader = lock(&shared);
+        let capacity = reader.capacity();
+        loop {
+            let chunk = &reader.read_chunk()[self.consumed..];
+            if chunk.len() < 8 {
+                return Ok(None);
+            }
+            if chunk[..4] != CONTINUATION {
+                return Err(Error::Frame(FrameError::Malformed));
+            }
+            let meta_len = i32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+            let meta_len = usize::try_from(meta_len).map_err(|_| FrameError::Malformed)?;
+            if 8 + meta_len > capacity {
+                return Err(Error::Frame(FrameError::Malformed));
+            }
+            if 8 + meta_len > chunk.len() {
+                return Ok(None);
+            }
+            let message = root_as_message(&chunk[8..8 + meta_len])
+                .map_err(|err| ArrowError::ParseError(err.to_string()))?;
+            let body_len =
+                usize::try_from(message.bodyLength()).map_err(|_| FrameError::Malformed)?;
+            let len = 8 + meta_len + body_len;
+            if len > capacity {
+                return Err(Error::Frame(FrameError::Malformed));
+            }
+            if len > chunk.len() {
+                return Ok(None);
+            }
+            let body = &chunk[8 + meta_len..len];
+            let begin = self.consumed;
+            self.consumed += len;
+
+            match message.header_type() {
+                MessageHeader::Schema => {
+                    let schema = message.header_as_schema().unwrap();
+                    self.schema = Some(Arc::new(fb_to_schema(schema)));
+                }
+                MessageHeader::DictionaryBatch => {
+                    let schema = self.schema.as_deref().ok_or_else(missing_schema)?;
+                    read_dictionary(
+                        &Buffer::from_slice_ref(body),
+                        message.header_as_dictionary_batch().unwrap(),
+                        schema,
+                        &mut self.dictionaries,
+                        &message.version(),
+                    )?;
+                }
+                MessageHeader::RecordBatch => {
+                    let schema = self.schema.clone().ok_or_else(missing_schema)?;
+                    let lease = Arc::new(Lease(shared.clone()));
+                    // Safety: the body is not overwritten until the lease is dropped, see commit
+                    let body = unsafe {
+                        let ptr = NonNull::new(body.as_ptr() as *mut u8).unwrap();
+                        Buffer::from_custom_allocation(ptr, body.len(), lease.clone())
+                    };
+                    let batch = read_record_batch(
+                        &body,
+                        message.header_as_record_batch().unwrap(),
+                        schema,
+                        &self.dictionaries,
+                        None,
+                        &message.version(),
+                    )?;
+                    self.leases.push_back((begin, lease));
+                    return Ok(Some(batch));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Consume the batches read so far, up to the first one that is still alive,
+    /// making space for the Writer.
+    pub fn commit(&mut self) {
+        let end = self
+            .leases
+            .iter()
+            .find(|(_, lease)| Arc::strong_count(lease) > 1)
+            .map_or(self.consumed, |(begin, _)| *begin);
+        while matches!(self.leases.front(), Some((begin, _)) if *begin < end) {
+            self.leases.pop_front();
+        }
+        for (begin, _) in &mut self.leases {
+            *begin -= end;
+        }
+        self.consumed -= end;
+
+        let mut reader = lock(&self.reader);
+        reader.limited_read_chunk(end);
+        reader.commit();
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        lock(&self.reader).is_abandoned()
+    }
+
+    /// Returns the wrapped Reader, or self, if a batch returned by `read_batch` is still alive.
+    ///
+    /// Batches returned by `read_batch`, but not committed are not consumed.
+    pub fn into_inner(mut self) -> Result<Reader<u8>, Self> {
+        if self
+            .leases
+            .iter()
+            .any(|(_, lease)| Arc::strong_count(lease) > 1)
+        {
+            return Err(self);
+        }
+        self.leases.clear();
+        match Arc::try_unwrap(self.reader) {
+            Ok(reader) => Ok(reader.into_inner().unwrap_or_else(|err| err.into_inner())),
+            Err(_) => unreachable!("no lease is alive"),
+        }
+    }
+}
+
+fn lock(reader: &Mutex<Reader<u8>>) -> MutexGuard<'_, Reader<u8>> {
+    reader.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn missing_schema() -> ArrowError {
+    ArrowError::IpcError("missing schema".to_string())
+}
@@ -0,0 +1,227 @@
+//! Awaitable Writer and Reader, independent of the async runtime.
+//!
+//! Requires a queue created with `Builder::notify`: the readiness file descriptors
+//! of the handles are registered with a `Reactor`. With the `async-io` feature,
+//! `async_io::Async` (the reactor of smol and async-std) implements `Reactor`.
+//!
+//!```ignore
+//! let (w, r) = cueue::Builder::new(1 << 16).notify(true).build::<u8>()?;
+//! let mut r = cueue::asynch::AsyncReader::<_, async_io::Async<_>>::new(r)?;
+//!
+//! let chunk = r.read_chunk().await?;
+//! process(chunk);
+//! r.commit();
+//!```
+
+use std::future::Future;
+use std::io;
+use std::os::unix::io::BorrowedFd;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::{Reader, Writer};
+
+/// A reactor, that can wait for a file descriptor to become readable.
+pub trait Reactor: Sized {
+    /// Register `fd` with the reactor.
+    ///
+    /// `fd` is non-blocking, and remains readable until cleared by the queue handle.
+    fn register(fd: BorrowedFd<'_>) -> io::Result<Self>;
+
+    /// Poll the readability of the registered file descriptor.
+    fn poll_readable(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+}
+
+#[cfg(feature = "async-io")]
+impl Reactor for async_io::Async<std::os::unix::io::OwnedFd> {
+    fn register(fd: BorrowedFd<'_>) -> io::Result<Self> {
+        async_io::Async::new(fd.try_clone_to_owned()?)
+    }
+
+    fn poll_readable(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        async_io::Async::poll_readable(self, cx)
+    }
+}
+
+fn notify_disabled() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "notification is not enabled, see Builder::notify",
+    )
+}
+
+/// Future calling `f` when polled, like `std::future::poll_fn` (rust 1.64).
+fn poll_fn<T, F>(f: F) -> PollFn<F>
+where
+    F: FnMut(&mut Context<'_>) -> Poll<T> + Unpin,
+{
+    PollFn(f)
+}
+
+struct PollFn<F>(F);
+
+impl<T, F> Future for PollFn<F>
+where
+    F: FnMut(&mut Context<'_>) -> Poll<T> + Unpin,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        (self.0)(cx)
+    }
+}
+
+/// A Writer, that can wait for free space asynchronously.
+pub struct AsyncWriter<T, R> {
+    writer: Writer<T>,
+    reactor: R,
+}
+
+impl<T, R> AsyncWriter<T, R>
+where
+    T: Default,
+    R: Reactor,
+{
+    /// Register `writer` with the reactor `R`.
+    ///
+    /// Fails if the queue was created without `Builder::notify`.
+    pub fn new(writer: Writer<T>) -> io::Result<Self> {
+        let reactor = R::register(writer.readiness_fd().ok_or_else(notify_disabled)?)?;
+        Ok(Self { writer, reactor })
+    }
+
+    /// Wait until the queue is not full, then return a writable slice of maximum available size.
+    ///
+    /// See `Writer::write_chunk`. Fails with `BrokenPipe` if the Reader was dropped.
+    pub async fn write_chunk(&mut self) -> io::Result<&mut [T]> {
+        loop {
+            self.writer.clear_readiness();
+            if self.writer.is_abandoned() {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            if !self.writer.write_chunk().is_empty() {
+                break;
+            }
+            poll_fn(|cx| self.reactor.poll_readable(cx)).await?;
+        }
+        Ok(self.writer.write_chunk())
+    }
+
+    /// See `Writer::commit`.
+    pub fn commit(&mut self, n: usize) -> usize {
+        self.writer.commit(n)
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<T> {
+        self.writer
+    }
+}
+
+/// A Reader, that can wait for elements asynchronously.
+pub struct AsyncReader<T, R> {
+    reader: Reader<T>,
+    reactor: R,
+}
+
+impl<T, R> AsyncReader<T, R>
+where
+    T: Default,
+    R: Reactor,
+{
+    /// Register `reader` with the reactor `R`.
+    ///
+    /// Fails if the queue was created without `Builder::notify`.
+    pub fn new(reader: Reader<T>) -> io::Result<Self> {
+        let reactor = R::register(reader.readiness_fd().ok_or_else(notify_disabled)?)?;
+        Ok(Self { reader, reactor })
+    }
+
+    /// Wait until the queue is not empty, then return the elements committed by the Writer.
+    ///
+    /// See `Reader::read_chunk`. Returns an empty slice if the Writer was dropped,
+    /// and every committed element was consumed.
+    pub async fn read_chunk(&mut self) -> io::Result<&[T]> {
+        loop {
+            self.reader.clear_readiness();
+            let abandoned = self.reader.is_abandoned();
+            if !self.reader.read_chunk().is_empty() || abandoned {
+                break;
+            }
+            poll_fn(|cx| self.reactor.poll_readable(cx)).await?;
+        }
+        Ok(self.reader.read_chunk())
+    }
+
+    /// See `Reader::commit`.
+    pub fn commit(&mut self) {
+        self.reader.commit()
+    }
+
+    /// Stream over owned batches of the committed elements, see `ChunkStream`.
+    ///
+    /// A batch is yielded once at least `min` elements are available (or the Writer
+    /// was dropped), and holds at most `max` elements. `min` is clamped to `1..=max`,
+    /// and to the capacity. Sets the notification watermark of the Reader to `min`
+    /// (see `Reader::set_notify_watermark`), to avoid waking up for smaller batches.
+    pub fn chunk_stream(&mut self, min: usize, max: usize) -> ChunkStream<'_, T, R> {
+        let max = max.max(1);
+        let min = min.clamp(1, max).min(self.reader.capacity());
+        self.reader.set_notify_watermark(min);
+        ChunkStream {
+            reader: self,
+            min,
+            max,
+        }
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<T> {
+        self.reader
+    }
+}
+
+/// Owned batches of the elements of an `AsyncReader`, see `AsyncReader::chunk_stream`.
+///
+/// The elements of a batch are consumed when it is yielded. `poll_next` has the signature
+/// of `futures::Stream::poll_next`: wrap it with `futures::stream::poll_fn` to get a `Stream`.
+pub struct ChunkStream<'a, T, R> {
+    reader: &'a mut AsyncReader<T, R>,
+    min: usize,
+    max: usize,
+}
+
+impl<T, R> ChunkStream<'_, T, R>
+where
+    T: Clone + Default,
+    R: Reactor,
+{
+    /// Wait for the next batch. Returns None, if the Writer was dropped,
+    /// and every committed element was consumed.
+    pub async fn next(&mut self) -> Option<io::Result<Vec<T>>> {
+        poll_fn(|cx| self.poll_next(cx)).await
+    }
+
+    /// Poll the next batch, see `next`.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<Vec<T>>>> {
+        let reader = &mut self.reader.reader;
+        loop {
+            reader.clear_readiness();
+            let abandoned = reader.is_abandoned();
+            let available = reader.read_chunk().len();
+            if available >= self.min || (abandoned && available != 0) {
+                let batch = reader.limited_read_chunk(self.max).to_vec();
+                reader.commit();
+                return Poll::Ready(Some(Ok(batch)));
+            }
+            if abandoned {
+                return Poll::Ready(None);
+            }
+            match self.reader.reactor.poll_readable(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
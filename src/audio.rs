@@ -0,0 +1,150 @@
+//! Interleaved multi-channel audio over a queue of samples.
+//!
+//! A frame is one sample of each channel, stored next to each other.
+//! `AudioWriter` and `AudioReader` express chunks and commits in whole frames,
+//! therefore the channels never get misaligned by committing a partial frame.
+//!
+//!```
+//! use cueue::audio::{AudioReader, AudioWriter};
+//!
+//! let (w, r) = cueue::cueue::<f32>(1 << 12).unwrap();
+//! let mut w = AudioWriter::new(w, 2);
+//! let mut r = AudioReader::new(r, 2);
+//!
+//! let buf = w.write_frames();
+//! for frame in buf[..2 * 64].chunks_exact_mut(2) {
+//!     frame[0] = 0.5; // left
+//!     frame[1] = -0.5; // right
+//! }
+//! w.commit(64);
+//!
+//! let frames = r.read_frames();
+//! assert_eq!(frames.len(), 2 * 64);
+//! assert!(frames.chunks_exact(2).all(|frame| frame == [0.5, -0.5]));
+//! r.commit();
+//!```
+
+use crate::{Reader, Writer};
+
+/// Writes interleaved frames of `channels` samples.
+pub struct AudioWriter<T = f32> {
+    writer: Writer<T>,
+    channels: usize,
+}
+
+impl<T> AudioWriter<T>
+where
+    T: Default,
+{
+    /// Panics if `channels` is zero.
+    pub fn new(writer: Writer<T>, channels: usize) -> Self {
+        assert!(channels != 0, "channels must be positive");
+        Self { writer, channels }
+    }
+
+    /// Number of samples in a frame.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Maximum number of frames the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.writer.capacity() / self.channels
+    }
+
+    /// Get a writable slice of the maximum number of whole frames available.
+    ///
+    /// The length of the slice is a multiple of `channels`. See `Writer::write_chunk`.
+    pub fn write_frames(&mut self) -> &mut [T] {
+        let frames = self.writer.write_chunk().len() / self.channels;
+        self.writer.limited_write_chunk(frames * self.channels)
+    }
+
+    /// Get a writable slice of exactly `n` frames, or None, if not enough space is available.
+    pub fn write_frames_exact(&mut self, n: usize) -> Option<&mut [T]> {
+        self.writer.write_chunk_exact(n * self.channels)
+    }
+
+    /// Make `n` frames, written to the slice returned by `write_frames` available for reading.
+    ///
+    /// `n` is checked: if too large, gets truncated to the maximum committable number of frames.
+    ///
+    /// Returns the number of committed frames.
+    pub fn commit(&mut self, n: usize) -> usize {
+        self.writer.commit(n.saturating_mul(self.channels)) / self.channels
+    }
+
+    /// Returns true, if the Reader counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.writer.is_abandoned()
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<T> {
+        self.writer
+    }
+}
+
+/// Reads interleaved frames of `channels` samples.
+pub struct AudioReader<T = f32> {
+    reader: Reader<T>,
+    channels: usize,
+}
+
+impl<T> AudioReader<T>
+where
+    T: Default,
+{
+    /// Panics if `channels` is zero.
+    pub fn new(reader: Reader<T>, channels: usize) -> Self {
+        assert!(channels != 0, "channels must be positive");
+        Self { reader, channels }
+    }
+
+    /// Number of samples in a frame.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Maximum number of frames the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.reader.capacity() / self.channels
+    }
+
+    /// Return a slice of the whole frames written and committed by the Writer.
+    ///
+    /// The length of the slice is a multiple of `channels`. See `Reader::read_chunk`.
+    pub fn read_frames(&mut self) -> &[T] {
+        let frames = self.reader.read_chunk().len() / self.channels;
+        self.reader.limited_read_chunk(frames * self.channels)
+    }
+
+    /// Return a slice of at most `n` whole frames.
+    ///
+    /// `commit` consumes the returned frames only.
+    pub fn limited_read_frames(&mut self, n: usize) -> &[T] {
+        let frames = usize::min(self.reader.read_chunk().len() / self.channels, n);
+        self.reader.limited_read_chunk(frames * self.channels)
+    }
+
+    /// Return a slice of exactly `n` frames, or None, if less frames are available.
+    pub fn read_frames_exact(&mut self, n: usize) -> Option<&[T]> {
+        self.reader.read_chunk_exact(n * self.channels)
+    }
+
+    /// Mark the frames previously returned by `read_frames` as consumed,
+    /// making space for the Writer.
+    pub fn commit(&mut self) {
+        self.reader.commit();
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.reader.is_abandoned()
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<T> {
+        self.reader
+    }
+}
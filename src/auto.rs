@@ -0,0 +1,76 @@
+//! Auto-committing Reader.
+//!
+//! `AutoReader` commits the previously returned chunk when a new chunk is requested,
+//! therefore the consumer can't stall the Writer by forgetting to commit.
+//! The returned slice is borrowed from the AutoReader: it must be processed
+//! before requesting the next one.
+//!
+//!```
+//! use cueue::auto::AutoReader;
+//!
+//! let (mut w, r) = cueue::cueue::<u8>(1 << 12).unwrap();
+//! let mut r = AutoReader::new(r);
+//!
+//! w.push(1).unwrap();
+//! assert_eq!(r.read_chunk(), [1]);
+//! w.push(2).unwrap();
+//! assert_eq!(r.read_chunk(), [2]); // 1 is consumed
+//!```
+
+use crate::Reader;
+
+/// Wraps a Reader, to commit each chunk when the next one is requested.
+pub struct AutoReader<T> {
+    reader: Reader<T>,
+    /// true, if a chunk was returned, and not committed yet
+    outstanding: bool,
+}
+
+impl<T> AutoReader<T>
+where
+    T: Default,
+{
+    /// Wrap `reader`. A chunk acquired by `reader` before is not consumed,
+    /// the first `read_chunk` returns it again.
+    pub fn new(reader: Reader<T>) -> Self {
+        Self {
+            reader,
+            outstanding: false,
+        }
+    }
+
+    /// Consume the previously returned chunk, then return the elements available now.
+    ///
+    /// See `Reader::read_chunk`.
+    pub fn read_chunk(&mut self) -> &[T] {
+        self.commit_outstanding();
+        self.outstanding = true;
+        self.reader.read_chunk()
+    }
+
+    /// Consume the previously returned chunk, then return at most `n` elements.
+    ///
+    /// See `Reader::limited_read_chunk`.
+    pub fn limited_read_chunk(&mut self, n: usize) -> &[T] {
+        self.commit_outstanding();
+        self.outstanding = true;
+        self.reader.limited_read_chunk(n)
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.reader.is_abandoned()
+    }
+
+    /// Consume the previously returned chunk, and return the wrapped Reader.
+    pub fn into_inner(mut self) -> Reader<T> {
+        self.commit_outstanding();
+        self.reader
+    }
+
+    fn commit_outstanding(&mut self) {
+        if std::mem::take(&mut self.outstanding) {
+            self.reader.commit();
+        }
+    }
+}
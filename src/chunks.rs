@@ -0,0 +1,121 @@
+//! Streaming over successive chunks, with commits handled between them.
+//!
+//! `Reader::chunks` and `Writer::chunks_mut` return lending iterators: each `next`
+//! commits the chunk returned by the previous one, then returns the next chunk.
+//! The last chunk is committed when the iterator is dropped.
+//! `next` does not wait: it returns None if the queue is empty (or full, for the Writer).
+//!
+//!```
+//! let (mut w, mut r) = cueue::cueue::<u32>(1 << 12).unwrap();
+//!
+//! let mut chunks = w.chunks_mut(100);
+//! let mut value = 0;
+//! while let Some(chunk) = chunks.next() {
+//!     for slot in chunk {
+//!         *slot = value;
+//!         value += 1;
+//!     }
+//! }
+//! drop(chunks);
+//!
+//! let mut sum = 0;
+//! let mut chunks = r.chunks(100);
+//! while let Some(chunk) = chunks.next() {
+//!     sum += chunk.iter().sum::<u32>();
+//! }
+//! assert_eq!(sum, (0..value).sum::<u32>());
+//!```
+
+use crate::{Reader, Writer};
+
+/// Successive readable chunks of a Reader, see `Reader::chunks`.
+pub struct Chunks<'a, T>
+where
+    T: Default,
+{
+    reader: &'a mut Reader<T>,
+    max: usize,
+}
+
+impl<'a, T> Chunks<'a, T>
+where
+    T: Default,
+{
+    pub(crate) fn new(reader: &'a mut Reader<T>, max: usize) -> Self {
+        // a chunk acquired before is not committed by the first `next`
+        reader.limited_read_chunk(0);
+        Self { reader, max }
+    }
+
+    /// Consume the previous chunk, then return the next one, of at most `max` elements,
+    /// or None, if the queue is empty.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&[T]> {
+        self.reader.commit();
+        let chunk = self.reader.limited_read_chunk(self.max);
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+}
+
+impl<T> Drop for Chunks<'_, T>
+where
+    T: Default,
+{
+    fn drop(&mut self) {
+        self.reader.commit();
+    }
+}
+
+/// Successive writable chunks of a Writer, see `Writer::chunks_mut`.
+pub struct ChunksMut<'a, T>
+where
+    T: Default,
+{
+    writer: &'a mut Writer<T>,
+    max: usize,
+}
+
+impl<'a, T> ChunksMut<'a, T>
+where
+    T: Default,
+{
+    pub(crate) fn new(writer: &'a mut Writer<T>, max: usize) -> Self {
+        // a chunk acquired before is not committed by the first `next`
+        writer.limited_write_chunk(0);
+        Self { writer, max }
+    }
+
+    /// Commit the whole previous chunk, then return the next one, of at most `max` elements,
+    /// or None, if the queue is full.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&mut [T]> {
+        self.writer.commit_all();
+        let chunk = self.writer.limited_write_chunk(self.max);
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+
+    /// Commit only the first `n` elements of the previous chunk, instead of the whole chunk.
+    ///
+    /// The rest of the chunk is returned again, at the start of the next chunk.
+    pub fn truncate(&mut self, n: usize) {
+        self.writer.commit(n);
+        self.writer.limited_write_chunk(0);
+    }
+}
+
+impl<T> Drop for ChunksMut<'_, T>
+where
+    T: Default,
+{
+    fn drop(&mut self) {
+        self.writer.commit_all();
+    }
+}
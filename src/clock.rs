@@ -0,0 +1,117 @@
+//! Timestamps comparable across the processes sharing a queue.
+//!
+//! The header of the queue records the clock its timestamps are taken from,
+//! and the offset of that clock to the realtime clock, measured when the queue was created.
+//! Writers stamp records using `Writer::timestamp`, Readers convert them to wall clock time,
+//! or measure latency using `Reader::timestamp` of the same clock.
+//!
+//!```
+//! use cueue::clock::Clock;
+//!
+//! let (mut w, r) = cueue::Builder::new(16)
+//!     .clock(Clock::MonotonicRaw)
+//!     .build::<u64>()
+//!     .unwrap();
+//!
+//! let sent = w.timestamp();
+//! w.push(sent).unwrap();
+//!
+//! let info = r.clock_info();
+//! assert_eq!(info.clock, Clock::MonotonicRaw);
+//! assert!(r.timestamp() >= sent);
+//! assert!(info.to_system_time(sent) <= std::time::SystemTime::now());
+//!```
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The clock source of the timestamps of a queue, see `Builder::clock`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Clock {
+    /// CLOCK_MONOTONIC: not affected by setting the time, but adjusted by NTP.
+    #[default]
+    Monotonic,
+    /// CLOCK_MONOTONIC_RAW: not adjusted by NTP, the best for measuring short intervals.
+    /// Where not available (QNX), same as `Monotonic`.
+    MonotonicRaw,
+    /// CLOCK_REALTIME: the wall clock, might jump.
+    Realtime,
+}
+
+impl Clock {
+    /// Current time of the clock, in nanoseconds.
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    pub fn now(self) -> u64 {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe {
+            libc::clock_gettime(self.id(), &mut ts);
+        }
+        ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "nto")))]
+    pub fn now(self) -> u64 {
+        todo!("Only Linux, macOS and QNX are supported so far");
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    fn id(self) -> libc::clockid_t {
+        match self {
+            Clock::Monotonic => libc::CLOCK_MONOTONIC,
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            Clock::MonotonicRaw => libc::CLOCK_MONOTONIC_RAW,
+            #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+            Clock::MonotonicRaw => libc::CLOCK_MONOTONIC,
+            Clock::Realtime => libc::CLOCK_REALTIME,
+        }
+    }
+
+    /// Returns the value stored in the ControlBlock
+    pub(crate) fn encode(self) -> u64 {
+        match self {
+            Clock::Monotonic => 0,
+            Clock::MonotonicRaw => 1,
+            Clock::Realtime => 2,
+        }
+    }
+
+    /// Inverse of `encode`
+    pub(crate) fn decode(value: u64) -> Self {
+        match value {
+            1 => Clock::MonotonicRaw,
+            2 => Clock::Realtime,
+            _ => Clock::Monotonic,
+        }
+    }
+}
+
+/// The clock convention of a queue, as recorded in its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClockInfo {
+    /// The clock timestamps are taken from
+    pub clock: Clock,
+    /// Realtime minus `clock`, in nanoseconds, measured when the queue was created (or reopened)
+    pub epoch_offset: i64,
+}
+
+impl ClockInfo {
+    /// Measure the offset of `clock` to the realtime clock.
+    pub(crate) fn measure(clock: Clock) -> Self {
+        let epoch_offset = match clock {
+            Clock::Realtime => 0,
+            _ => Clock::Realtime.now() as i64 - clock.now() as i64,
+        };
+        Self {
+            clock,
+            epoch_offset,
+        }
+    }
+
+    /// Convert a timestamp of `clock` to wall clock time.
+    pub fn to_system_time(&self, timestamp: u64) -> SystemTime {
+        let nanos = (timestamp as i64).saturating_add(self.epoch_offset).max(0);
+        UNIX_EPOCH + Duration::from_nanos(nanos as u64)
+    }
+}
@@ -0,0 +1,171 @@
+//! Typed messages over a byte queue, encoded by a `tokio_util` codec.
+//!
+//! Each message is a frame of the `framed` layer, encoded by an `Encoder`, and decoded by
+//! a `Decoder` of `tokio_util::codec`, therefore the codecs of existing protocols
+//! (written for sockets) can be reused unchanged over a queue, e.g: in shared memory.
+//! The codecs work on `BytesMut` buffers: a message is encoded into a buffer of the Writer,
+//! then copied into the queue, and copied out into a buffer of the Reader to decode it.
+//! The buffers are reused, therefore a steady stream of messages does not allocate.
+//!
+//!```
+//! use cueue::codec::{CodecReader, CodecWriter};
+//! use tokio_util::codec::LinesCodec;
+//!
+//! let (w, r) = cueue::cueue::<u8>(1 << 16).unwrap();
+//! let mut w = CodecWriter::new(w, LinesCodec::new());
+//! let mut r = CodecReader::new(r, LinesCodec::new());
+//!
+//! w.send("foo").unwrap();
+//! assert_eq!(r.recv().unwrap(), Some("foo".to_string()));
+//! r.commit();
+//!```
+
+use std::io;
+
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::framed::{FrameError, FrameReader, FrameWriter, LengthPrefix};
+use crate::{Reader, Writer};
+
+/// Error of a typed send or receive, `E` is the error of the codec.
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The message could not be written or read as a frame
+    Frame(FrameError),
+    /// The message could not be encoded or decoded
+    Codec(E),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Frame(err) => write!(f, "{}", err),
+            Error::Codec(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for Error<E> {}
+
+impl<E> From<FrameError> for Error<E> {
+    fn from(err: FrameError) -> Self {
+        Error::Frame(err)
+    }
+}
+
+/// Sends messages encoded by `E`.
+pub struct CodecWriter<E> {
+    frames: FrameWriter,
+    encoder: E,
+    buf: BytesMut,
+}
+
+impl<E> CodecWriter<E> {
+    pub fn new(writer: Writer<u8>, encoder: E) -> Self {
+        Self {
+            frames: FrameWriter::with_prefix(writer, LengthPrefix::Varint),
+            encoder,
+            buf: BytesMut::new(),
+        }
+    }
+
+    /// Encode and commit `item`, or nothing, if there's not enough space.
+    ///
+    /// The item is consumed by the encoder, even if it does not fit in the queue.
+    pub fn send<I>(&mut self, item: I) -> Result<(), Error<E::Error>>
+    where
+        E: Encoder<I>,
+    {
+        self.buf.clear();
+        self.encoder
+            .encode(item, &mut self.buf)
+            .map_err(Error::Codec)?;
+        self.frames.write_frame(&self.buf)?;
+        Ok(())
+    }
+
+    /// Returns the wrapped encoder.
+    pub fn encoder(&mut self) -> &mut E {
+        &mut self.encoder
+    }
+
+    /// Returns true, if the Reader counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.frames.is_abandoned()
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<u8> {
+        self.frames.into_inner()
+    }
+}
+
+/// Receives messages decoded by `D`.
+pub struct CodecReader<D> {
+    frames: FrameReader,
+    decoder: D,
+    buf: BytesMut,
+}
+
+impl<D> CodecReader<D>
+where
+    D: Decoder,
+{
+    pub fn new(reader: Reader<u8>, decoder: D) -> Self {
+        Self {
+            frames: FrameReader::with_prefix(reader, LengthPrefix::Varint),
+            decoder,
+            buf: BytesMut::new(),
+        }
+    }
+
+    /// Decode the next message, if available.
+    ///
+    /// A frame is decoded as the whole stream of the codec: `Decoder::decode_eof` is called,
+    /// if `Decoder::decode` needs more bytes. The bytes of the frame left after the message
+    /// are discarded. Received messages are consumed from the queue by `commit`.
+    pub fn recv(&mut self) -> Result<Option<D::Item>, Error<D::Error>> {
+        let frame = match self.frames.try_read_frame()? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        self.buf.clear();
+        self.buf.extend_from_slice(frame);
+        let item = match self.decoder.decode(&mut self.buf).map_err(Error::Codec)? {
+            Some(item) => item,
+            None => match self
+                .decoder
+                .decode_eof(&mut self.buf)
+                .map_err(Error::Codec)?
+            {
+                Some(item) => item,
+                None => {
+                    let err = io::Error::new(io::ErrorKind::InvalidData, "empty frame");
+                    return Err(Error::Codec(err.into()));
+                }
+            },
+        };
+        Ok(Some(item))
+    }
+
+    /// Consume every message received so far, making space for the Writer.
+    pub fn commit(&mut self) {
+        self.frames.commit();
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn decoder(&mut self) -> &mut D {
+        &mut self.decoder
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.frames.is_abandoned()
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<u8> {
+        self.frames.into_inner()
+    }
+}
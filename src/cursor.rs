@@ -0,0 +1,102 @@
+//! Independent read cursors, that run ahead of the read position of a Reader.
+//!
+//! A `Cursor` is a position in the stream of the queue. `Reader::read_at` returns
+//! the committed elements after a cursor, without consuming anything, and
+//! `Reader::commit_to` consumes the elements before a cursor. A Reader can have
+//! any number of cursors: e.g: a protocol decoder scans ahead for message boundaries
+//! with a "parse" cursor, while a "commit" cursor trails behind, at the end of the last
+//! fully processed message.
+//!
+//!```
+//! let (mut w, mut r) = cueue::cueue::<u8>(1 << 12).unwrap();
+//! w.write_chunk()[..8].copy_from_slice(b"foo;bar;");
+//! w.commit(8);
+//!
+//! let mut parse = r.cursor();
+//! let mut done = r.cursor();
+//! while let Some(end) = r.read_at(&parse).iter().position(|&b| b == b';') {
+//!     parse.advance(end + 1);
+//!     // ... process the message, then mark it done
+//!     done = parse;
+//! }
+//! r.commit_to(&done);
+//! assert!(r.read_chunk().is_empty());
+//!```
+
+use std::sync::atomic::Ordering;
+
+use crate::abi::READER_BUSY;
+use crate::{fork, Reader};
+
+/// A position in the stream of a queue, see the module docs.
+///
+/// Counts the elements committed before it: comparable with `stats::Stats::read_position`
+/// and `stats::Stats::write_position`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cursor {
+    position: u64,
+}
+
+impl Cursor {
+    /// Move the cursor forward by `n` elements.
+    pub fn advance(&mut self, n: usize) {
+        self.position += n as u64;
+    }
+
+    /// The position of the cursor.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<T> Reader<T>
+where
+    T: Default,
+{
+    /// Create a cursor at the read position: the first element not consumed yet.
+    pub fn cursor(&self) -> Cursor {
+        Cursor {
+            position: self.read_pos().load(Ordering::Relaxed) & !READER_BUSY,
+        }
+    }
+
+    /// Return the committed elements after `cursor`, without consuming them.
+    ///
+    /// If `cursor` is behind the read position, the elements are returned from the read position,
+    /// as the ones before it were consumed already. If it is ahead of the write position, the slice is empty.
+    /// The Reader is not changed (e.g: the chunk acquired by `read_chunk`, or the `on_empty` hook):
+    /// use `commit_to` to consume elements.
+    ///
+    /// With `FullPolicy::DropOldest`, the Writer is free to drop the elements, unless the Reader
+    /// holds a chunk: the slice is empty then, call `read_chunk` first.
+    pub fn read_at(&self, cursor: &Cursor) -> &[T] {
+        if fork::is_forked(self.generation) {
+            return &[];
+        }
+        let r = self.read_pos().load(Ordering::Relaxed);
+        if self.mark_busy && r & READER_BUSY == 0 {
+            return &[];
+        }
+        let r = r & !READER_BUSY;
+        let w = self.write_pos().load(Ordering::Acquire);
+        let begin = u64::max(cursor.position, r);
+        if begin >= w {
+            return &[];
+        }
+        // the buffer is mapped twice: the elements are contiguous, even if they wrap around
+        unsafe {
+            let ptr = self.buffer.add((begin & self.mask) as usize);
+            std::slice::from_raw_parts(ptr, (w - begin) as usize)
+        }
+    }
+
+    /// Consume the elements before `cursor`, making space for the Writer.
+    ///
+    /// A cursor behind the read position consumes nothing, a cursor ahead of the write position
+    /// consumes every committed element.
+    pub fn commit_to(&mut self, cursor: &Cursor) {
+        let r = self.read_pos().load(Ordering::Relaxed) & !READER_BUSY;
+        self.limited_read_chunk(cursor.position.saturating_sub(r) as usize);
+        self.commit();
+    }
+}
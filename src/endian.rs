@@ -0,0 +1,79 @@
+//! Endian-aware integer and float helpers for byte queues.
+//!
+//! Each `put_*` method writes and commits a single value, or gives it back, if there's not enough space.
+//! Each `get_*` method reads and consumes a single value, if it is fully available.
+//!
+//!```
+//! let (mut w, mut r) = cueue::cueue::<u8>(16).unwrap();
+//! w.put_u32_be(0xdead_beef).unwrap();
+//! w.put_u16_le(42).unwrap();
+//!
+//! assert_eq!(r.get_u32_be(), Some(0xdead_beef));
+//! assert_eq!(r.get_u16_le(), Some(42));
+//! assert_eq!(r.get_u16_le(), None);
+//!```
+
+use crate::{Reader, Writer};
+
+macro_rules! endian_helpers {
+    ($($t:ty, $put_le:ident, $put_be:ident, $get_le:ident, $get_be:ident;)*) => {
+        impl Writer<u8> {
+            $(
+                #[doc = concat!("Write and commit a little endian `", stringify!($t), "`, or return it if the queue is full.")]
+                pub fn $put_le(&mut self, value: $t) -> Result<(), $t> {
+                    self.put_bytes(&value.to_le_bytes()).map_err(|_| value)
+                }
+
+                #[doc = concat!("Write and commit a big endian `", stringify!($t), "`, or return it if the queue is full.")]
+                pub fn $put_be(&mut self, value: $t) -> Result<(), $t> {
+                    self.put_bytes(&value.to_be_bytes()).map_err(|_| value)
+                }
+            )*
+        }
+
+        impl Reader<u8> {
+            $(
+                #[doc = concat!("Read and consume a little endian `", stringify!($t), "`, if available.")]
+                pub fn $get_le(&mut self) -> Option<$t> {
+                    self.get_bytes().map(<$t>::from_le_bytes)
+                }
+
+                #[doc = concat!("Read and consume a big endian `", stringify!($t), "`, if available.")]
+                pub fn $get_be(&mut self) -> Option<$t> {
+                    self.get_bytes().map(<$t>::from_be_bytes)
+                }
+            )*
+        }
+    };
+}
+
+endian_helpers! {
+    u16, put_u16_le, put_u16_be, get_u16_le, get_u16_be;
+    u32, put_u32_le, put_u32_be, get_u32_le, get_u32_be;
+    u64, put_u64_le, put_u64_be, get_u64_le, get_u64_be;
+    i16, put_i16_le, put_i16_be, get_i16_le, get_i16_be;
+    i32, put_i32_le, put_i32_be, get_i32_le, get_i32_be;
+    i64, put_i64_le, put_i64_be, get_i64_le, get_i64_be;
+    f32, put_f32_le, put_f32_be, get_f32_le, get_f32_be;
+    f64, put_f64_le, put_f64_be, get_f64_le, get_f64_be;
+}
+
+impl Writer<u8> {
+    /// Write and commit `bytes` entirely, or nothing, if there's not enough space.
+    fn put_bytes(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        let buf = self.write_chunk_exact(bytes.len()).ok_or(())?;
+        buf.copy_from_slice(bytes);
+        self.commit(bytes.len());
+        Ok(())
+    }
+}
+
+impl Reader<u8> {
+    /// Read and consume exactly `N` bytes, if available.
+    fn get_bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.read_chunk_exact(N)?);
+        self.commit();
+        Some(bytes)
+    }
+}
@@ -0,0 +1,214 @@
+//! Fault injection around a real queue, to test the error paths of applications.
+//!
+//! Unlike `mock`, the faults are injected into a real queue (mapped memory, shared by
+//! threads or processes): `FaultPlan` scripts the faults, then builds the queue (or fails,
+//! as if the system did), and returns a `FaultyWriter` and a `FaultyReader`, implementing
+//! the `Producer` and `Consumer` traits. The faults are scripted at calls of `write_chunk`
+//! and `read_chunk` of the handles, counted from zero.
+//!
+//!```
+//! use cueue::fault::FaultPlan;
+//! use cueue::{Consumer, Producer};
+//!
+//! let plan = FaultPlan::new().full_at(0).abandon_writer_at(2);
+//! let (mut w, mut r) = plan.build::<u8>(cueue::Builder::new(16)).unwrap();
+//!
+//! assert_eq!(w.push(1), Err(1));
+//! assert_eq!(w.push(1), Ok(()));
+//! assert_eq!(r.read_chunk(), [1]);
+//! r.commit();
+//! assert!(!r.is_abandoned());
+//! assert!(r.read_chunk().is_empty());
+//! assert!(r.is_abandoned());
+//!
+//! let plan = FaultPlan::new().fail_build(libc::ENOMEM);
+//! assert!(plan.build::<u8>(cueue::Builder::new(16)).is_err());
+//!```
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use crate::{Builder, CError, Consumer, Producer, Reader, Writer};
+
+/// The faults to inject into a queue, see the module docs.
+#[derive(Clone, Debug, Default)]
+pub struct FaultPlan {
+    fail_build: Option<i32>,
+    full_at: BTreeSet<u64>,
+    empty_at: BTreeSet<u64>,
+    commit_delay: Duration,
+    reader_abandoned_at: Option<u64>,
+    writer_abandoned_at: Option<u64>,
+}
+
+impl FaultPlan {
+    /// A plan without faults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make `build` fail with `errno`, as if the system did, e.g: `libc::ENOMEM`.
+    pub fn fail_build(mut self, errno: i32) -> Self {
+        self.fail_build = Some(errno);
+        self
+    }
+
+    /// Make the `call`-th `write_chunk` of the Writer return an empty slice, as if the queue was full.
+    pub fn full_at(mut self, call: u64) -> Self {
+        self.full_at.insert(call);
+        self
+    }
+
+    /// Make the `call`-th `read_chunk` of the Reader return an empty slice, as if the queue was empty.
+    pub fn empty_at(mut self, call: u64) -> Self {
+        self.empty_at.insert(call);
+        self
+    }
+
+    /// Sleep for `delay` before every non-empty commit of both sides,
+    /// e.g: as if the committing thread was preempted.
+    pub fn delay_commits(mut self, delay: Duration) -> Self {
+        self.commit_delay = delay;
+        self
+    }
+
+    /// Make the Writer report its Reader abandoned, once `write_chunk` was called `calls` times.
+    pub fn abandon_reader_at(mut self, calls: u64) -> Self {
+        self.reader_abandoned_at = Some(calls);
+        self
+    }
+
+    /// Make the Reader report its Writer abandoned, once `read_chunk` was called `calls` times.
+    pub fn abandon_writer_at(mut self, calls: u64) -> Self {
+        self.writer_abandoned_at = Some(calls);
+        self
+    }
+
+    /// Build a queue configured by `builder`, wrapped by handles injecting the faults of the plan.
+    pub fn build<T>(&self, builder: Builder) -> Result<(FaultyWriter<T>, FaultyReader<T>), CError>
+    where
+        T: Default,
+    {
+        if let Some(errno) = self.fail_build {
+            return Err(CError {
+                hint: "injected fault",
+                err: std::io::Error::from_raw_os_error(errno),
+            });
+        }
+        let (writer, reader) = builder.build::<T>()?;
+        let writer = FaultyWriter {
+            writer,
+            calls: 0,
+            full_at: self.full_at.clone(),
+            commit_delay: self.commit_delay,
+            abandoned_at: self.reader_abandoned_at,
+        };
+        let reader = FaultyReader {
+            reader,
+            calls: 0,
+            read_size: 0,
+            empty_at: self.empty_at.clone(),
+            commit_delay: self.commit_delay,
+            abandoned_at: self.writer_abandoned_at,
+        };
+        Ok((writer, reader))
+    }
+}
+
+/// Writer injecting the faults of a `FaultPlan`.
+pub struct FaultyWriter<T> {
+    writer: Writer<T>,
+    /// number of `write_chunk` calls
+    calls: u64,
+    full_at: BTreeSet<u64>,
+    commit_delay: Duration,
+    abandoned_at: Option<u64>,
+}
+
+impl<T> FaultyWriter<T> {
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<T> {
+        self.writer
+    }
+}
+
+impl<T> Producer<T> for FaultyWriter<T>
+where
+    T: Default,
+{
+    fn capacity(&self) -> usize {
+        self.writer.capacity()
+    }
+
+    fn write_chunk(&mut self) -> &mut [T] {
+        let call = self.calls;
+        self.calls += 1;
+        if self.full_at.remove(&call) {
+            return self.writer.limited_write_chunk(0);
+        }
+        self.writer.write_chunk()
+    }
+
+    fn commit(&mut self, n: usize) -> usize {
+        if n != 0 && !self.commit_delay.is_zero() {
+            std::thread::sleep(self.commit_delay);
+        }
+        self.writer.commit(n)
+    }
+
+    fn is_abandoned(&self) -> bool {
+        self.writer.is_abandoned() || matches!(self.abandoned_at, Some(at) if self.calls >= at)
+    }
+}
+
+/// Reader injecting the faults of a `FaultPlan`.
+pub struct FaultyReader<T> {
+    reader: Reader<T>,
+    /// number of `read_chunk` calls
+    calls: u64,
+    /// length of the chunk returned by the last `read_chunk`
+    read_size: usize,
+    empty_at: BTreeSet<u64>,
+    commit_delay: Duration,
+    abandoned_at: Option<u64>,
+}
+
+impl<T> FaultyReader<T> {
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<T> {
+        self.reader
+    }
+}
+
+impl<T> Consumer<T> for FaultyReader<T>
+where
+    T: Default,
+{
+    fn capacity(&self) -> usize {
+        self.reader.capacity()
+    }
+
+    fn read_chunk(&mut self) -> &[T] {
+        let call = self.calls;
+        self.calls += 1;
+        let chunk = if self.empty_at.remove(&call) {
+            self.reader.limited_read_chunk(0)
+        } else {
+            self.reader.read_chunk()
+        };
+        self.read_size = chunk.len();
+        chunk
+    }
+
+    fn commit(&mut self) {
+        if self.read_size != 0 && !self.commit_delay.is_zero() {
+            std::thread::sleep(self.commit_delay);
+        }
+        self.read_size = 0;
+        self.reader.commit();
+    }
+
+    fn is_abandoned(&self) -> bool {
+        self.reader.is_abandoned() || matches!(self.abandoned_at, Some(at) if self.calls >= at)
+    }
+}
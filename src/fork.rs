@@ -0,0 +1,36 @@
+//! Detecting handles inherited by `fork`, see `Writer::check_fork`.
+//!
+//! A child handler, registered by pthread_atfork when the first queue is created,
+//! increments a counter in the child process. A handle records the counter when it is
+//! created (or adopted, see `Writer::register_process`): if the counter changed since,
+//! the handle is used by a child of its process.
+//!
+//! Processes forked without running the fork handlers (e.g: by a raw clone syscall,
+//! or vfork) are not detected.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+extern "C" fn on_fork_child() {
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The number of forks between the first call in the process tree and the current process.
+pub fn generation() -> u64 {
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    {
+        static REGISTER: std::sync::Once = std::sync::Once::new();
+        REGISTER.call_once(|| unsafe {
+            libc::pthread_atfork(None, None, Some(on_fork_child));
+        });
+    }
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Returns true, if the current process is a fork of the process at `generation`.
+#[inline]
+pub fn is_forked(generation: u64) -> bool {
+    GENERATION.load(Ordering::Relaxed) != generation
+}
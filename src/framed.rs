@@ -0,0 +1,425 @@
+//! Length-prefixed messages (frames) over a byte queue.
+//!
+//! `FrameWriter` writes each frame as a length prefix followed by the payload,
+//! committing the whole frame at once: the Reader never sees partial frames.
+//! `FrameReader` returns complete frames, borrowed from the queue (zero-copy),
+//! and consumes them in batches on `commit`.
+//!
+//!```
+//! use cueue::framed::{FrameReader, FrameWriter, LengthPrefix};
+//!
+//! let (w, r) = cueue::cueue::<u8>(1 << 16).unwrap();
+//! let mut w = FrameWriter::with_prefix(w, LengthPrefix::Varint);
+//! let mut r = FrameReader::with_prefix(r, LengthPrefix::Varint);
+//!
+//! w.write_frame(b"foo").unwrap();
+//! w.write_frame(b"barbaz").unwrap();
+//!
+//! assert_eq!(r.read_frame(), Some(&b"foo"[..]));
+//! assert_eq!(r.read_frame(), Some(&b"barbaz"[..]));
+//! assert_eq!(r.read_frame(), None);
+//! r.commit();
+//!```
+
+use crate::{Reader, Writer};
+
+/// The encoding of the length of the frames.
+///
+/// The Writer and the Reader must use the same encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum LengthPrefix {
+    /// 4 bytes, little endian
+    #[default]
+    U32,
+    /// LEB128: 1 byte for frames shorter than 128 bytes, at most 10 bytes
+    Varint,
+}
+
+/// Maximum size of an encoded length prefix
+const MAX_PREFIX_SIZE: usize = 10;
+
+impl LengthPrefix {
+    /// Encode `len` into `buf`, return the number of bytes written.
+    /// `buf` must be at least `encoded_len(len)` long.
+    pub(crate) fn encode(self, len: usize, buf: &mut [u8]) -> usize {
+        match self {
+            LengthPrefix::U32 => {
+                buf[..4].copy_from_slice(&(len as u32).to_le_bytes());
+                4
+            }
+            LengthPrefix::Varint => {
+                let mut len = len as u64;
+                let mut i = 0;
+                loop {
+                    let byte = (len & 0x7f) as u8;
+                    len >>= 7;
+                    if len == 0 {
+                        buf[i] = byte;
+                        return i + 1;
+                    }
+                    buf[i] = byte | 0x80;
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns the number of bytes `encode` writes for `len`.
+    pub(crate) fn encoded_len(self, len: usize) -> usize {
+        match self {
+            LengthPrefix::U32 => 4,
+            LengthPrefix::Varint => {
+                let bits = usize::BITS - len.leading_zeros();
+                usize::max(1, ((bits + 6) / 7) as usize)
+            }
+        }
+    }
+
+    /// Decode a length from the beginning of `buf`.
+    ///
+    /// Returns (length, size of the prefix), None if `buf` does not contain a complete prefix,
+    /// or Err if the prefix is malformed.
+    pub(crate) fn decode(self, buf: &[u8]) -> Result<Option<(usize, usize)>, FrameError> {
+        match self {
+            LengthPrefix::U32 => {
+                if buf.len() < 4 {
+                    return Ok(None);
+                }
+                let len = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+                Ok(Some((len as usize, 4)))
+            }
+            LengthPrefix::Varint => {
+                let mut len: u64 = 0;
+                for (i, byte) in buf.iter().take(MAX_PREFIX_SIZE).enumerate() {
+                    len |= ((byte & 0x7f) as u64) << (7 * i);
+                    if byte & 0x80 == 0 {
+                        return Ok(Some((len as usize, i + 1)));
+                    }
+                }
+                if buf.len() >= MAX_PREFIX_SIZE {
+                    Err(FrameError::Malformed)
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    /// Maximum length of a frame this prefix can encode
+    fn max_len(self) -> usize {
+        match self {
+            LengthPrefix::U32 => u32::MAX as usize,
+            LengthPrefix::Varint => usize::MAX,
+        }
+    }
+}
+
+/// Error of a frame operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// Not enough free space in the queue now, try again later
+    Full,
+    /// The frame can never fit in the queue (or its length cannot be encoded)
+    TooLarge,
+    /// The queue contains an invalid length prefix
+    Malformed,
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Full => write!(f, "queue is full"),
+            FrameError::TooLarge => write!(f, "frame is too large"),
+            FrameError::Malformed => write!(f, "malformed length prefix"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Writes length prefixed frames to a byte queue.
+pub struct FrameWriter {
+    writer: Writer<u8>,
+    prefix: LengthPrefix,
+}
+
+impl FrameWriter {
+    /// Create a FrameWriter, that uses `LengthPrefix::U32`.
+    pub fn new(writer: Writer<u8>) -> Self {
+        Self::with_prefix(writer, LengthPrefix::U32)
+    }
+
+    /// Create a FrameWriter, that uses `prefix`. The Reader must use the same prefix.
+    pub fn with_prefix(writer: Writer<u8>, prefix: LengthPrefix) -> Self {
+        Self { writer, prefix }
+    }
+
+    /// Write and commit `frame`, or nothing, if there's not enough space.
+    pub fn write_frame(&mut self, frame: &[u8]) -> Result<(), FrameError> {
+        let prefix_len = self.prefix.encoded_len(frame.len());
+        if frame.len() > self.prefix.max_len() || prefix_len + frame.len() > self.writer.capacity()
+        {
+            return Err(FrameError::TooLarge);
+        }
+        let buf = self
+            .writer
+            .write_chunk_exact(prefix_len + frame.len())
+            .ok_or(FrameError::Full)?;
+        self.prefix.encode(frame.len(), buf);
+        buf[prefix_len..].copy_from_slice(frame);
+        self.writer.commit(prefix_len + frame.len());
+        Ok(())
+    }
+
+    /// Write and commit a frame, that is encoded in place by `encode`.
+    ///
+    /// `encode` gets the free space of the queue (not including the length prefix),
+    /// writes the frame to the beginning of it, then returns the length of the frame,
+    /// or None, if the frame did not fit. This avoids encoding to a temporary buffer.
+    /// Returns `FrameError::TooLarge`, and writes nothing, if the returned length
+    /// exceeds the space `encode` got.
+    pub fn write_frame_with(
+        &mut self,
+        encode: impl FnOnce(&mut [u8]) -> Option<usize>,
+    ) -> Result<(), FrameError> {
+        let capacity = self.writer.capacity();
+        let chunk = self.writer.write_chunk();
+        let available = chunk.len();
+        // if the frame doesn't fit in an empty queue, it never will
+        let no_space = if available == capacity {
+            FrameError::TooLarge
+        } else {
+            FrameError::Full
+        };
+
+        // reserve space for the longest prefix possible, move the frame later if needed
+        let max_prefix_len = self.prefix.encoded_len(available);
+        if available <= max_prefix_len {
+            return Err(no_space);
+        }
+        let len = encode(&mut chunk[max_prefix_len..]).ok_or(no_space)?;
+        if len > available - max_prefix_len || len > self.prefix.max_len() {
+            return Err(FrameError::TooLarge);
+        }
+        let prefix_len = self.prefix.encoded_len(len);
+        chunk.copy_within(max_prefix_len..max_prefix_len + len, prefix_len);
+        self.prefix.encode(len, chunk);
+        self.writer.commit(prefix_len + len);
+        Ok(())
+    }
+
+    /// Reserve space for a frame of exactly `len` bytes, to be filled in multiple steps.
+    ///
+    /// The frame is invisible to the Reader until `FrameGrant::publish` is called.
+    /// Dropping the grant without publishing discards the frame.
+    pub fn reserve_frame(&mut self, len: usize) -> Result<FrameGrant<'_>, FrameError> {
+        let prefix_len = self.prefix.encoded_len(len);
+        if len > self.prefix.max_len() || prefix_len + len > self.writer.capacity() {
+            return Err(FrameError::TooLarge);
+        }
+        let buf = self
+            .writer
+            .write_chunk_exact(prefix_len + len)
+            .ok_or(FrameError::Full)?;
+        self.prefix.encode(len, buf);
+        Ok(FrameGrant {
+            writer: &mut self.writer,
+            prefix_len,
+            len,
+            filled: 0,
+        })
+    }
+
+    /// Returns true, if the Reader counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.writer.is_abandoned()
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<u8> {
+        self.writer
+    }
+}
+
+/// A frame reserved by `FrameWriter::reserve_frame`, not yet visible to the Reader.
+///
+/// The payload can be written directly (`payload_mut`), or appended in steps
+/// (`extend_from_slice`, or `std::io::Write`). Bytes not written hold unspecified contents.
+pub struct FrameGrant<'a> {
+    writer: &'a mut Writer<u8>,
+    prefix_len: usize,
+    len: usize,
+    /// bytes appended so far
+    filled: usize,
+}
+
+impl FrameGrant<'_> {
+    /// Length of the payload of the frame.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true, if the payload is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of payload bytes not yet appended.
+    pub fn remaining(&self) -> usize {
+        self.len - self.filled
+    }
+
+    /// The whole payload of the frame.
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            std::slice::from_raw_parts_mut(self.writer.write_begin.add(self.prefix_len), self.len)
+        }
+    }
+
+    /// Append `data` to the payload written so far.
+    ///
+    /// Panics if `data` is longer than `remaining`.
+    pub fn extend_from_slice(&mut self, data: &[u8]) {
+        assert!(data.len() <= self.remaining(), "frame overflow");
+        let begin = self.filled;
+        self.payload_mut()[begin..begin + data.len()].copy_from_slice(data);
+        self.filled += data.len();
+    }
+
+    /// Commit the frame, making it available for reading.
+    pub fn publish(self) {
+        self.writer.commit(self.prefix_len + self.len);
+    }
+}
+
+impl std::io::Write for FrameGrant<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = usize::min(buf.len(), self.remaining());
+        self.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads length prefixed frames from a byte queue.
+pub struct FrameReader {
+    reader: Reader<u8>,
+    prefix: LengthPrefix,
+    /// bytes of the frames returned since the last commit
+    consumed: usize,
+}
+
+impl FrameReader {
+    /// Create a FrameReader, that uses `LengthPrefix::U32`.
+    pub fn new(reader: Reader<u8>) -> Self {
+        Self::with_prefix(reader, LengthPrefix::U32)
+    }
+
+    /// Create a FrameReader, that uses `prefix`. The Writer must use the same prefix.
+    pub fn with_prefix(reader: Reader<u8>, prefix: LengthPrefix) -> Self {
+        Self {
+            reader,
+            prefix,
+            consumed: 0,
+        }
+    }
+
+    /// Return the next complete frame, if available.
+    ///
+    /// The returned frames remain in the queue until `commit` is called,
+    /// consuming every frame returned so far.
+    pub fn read_frame(&mut self) -> Option<&[u8]> {
+        self.try_read_frame().ok().flatten()
+    }
+
+    /// Like `read_frame`, but reports malformed length prefixes,
+    /// including lengths of frames that can never fit in the queue.
+    pub fn try_read_frame(&mut self) -> Result<Option<&[u8]>, FrameError> {
+        let capacity = self.reader.capacity();
+        let chunk = &self.reader.read_chunk()[self.consumed..];
+        let (len, prefix_len) = match self.prefix.decode(chunk)? {
+            Some(decoded) => decoded,
+            None => return Ok(None),
+        };
+        if len > capacity - prefix_len {
+            return Err(FrameError::Malformed);
+        }
+        if chunk.len() - prefix_len < len {
+            return Ok(None);
+        }
+        let begin = self.consumed + prefix_len;
+        self.consumed = begin + len;
+        Ok(Some(&self.reader.read_chunk()[begin..begin + len]))
+    }
+
+    /// Iterate over the complete frames available, see `Frames`.
+    ///
+    /// Every frame returned is consumed when the iterator is dropped.
+    pub fn frames(&mut self) -> Frames<'_> {
+        Frames { reader: self }
+    }
+
+    /// Consume every frame returned by `read_frame` so far, making space for the Writer.
+    pub fn commit(&mut self) {
+        self.reader.limited_read_chunk(self.consumed);
+        self.reader.commit();
+        self.consumed = 0;
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.reader.is_abandoned()
+    }
+
+    /// Returns the wrapped Reader.
+    ///
+    /// Frames returned by `read_frame`, but not committed are not consumed.
+    pub fn into_inner(self) -> Reader<u8> {
+        self.reader
+    }
+}
+
+/// Lending iterator over the complete frames of a FrameReader, see `FrameReader::frames`.
+///
+/// A partial frame at the end of the queue is held back until the Writer completes it.
+///
+///```
+/// use cueue::framed::{FrameReader, FrameWriter};
+///
+/// let (w, r) = cueue::cueue::<u8>(1 << 16).unwrap();
+/// let mut w = FrameWriter::new(w);
+/// let mut r = FrameReader::new(r);
+///
+/// w.write_frame(b"foo").unwrap();
+/// w.write_frame(b"bar").unwrap();
+///
+/// let mut frames = r.frames();
+/// while let Some(frame) = frames.next() {
+///     assert_eq!(frame.len(), 3);
+/// }
+///```
+pub struct Frames<'a> {
+    reader: &'a mut FrameReader,
+}
+
+impl Frames<'_> {
+    /// Return the next complete frame, if available.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&[u8]> {
+        self.reader.read_frame()
+    }
+
+    /// Like `next`, but reports malformed length prefixes.
+    pub fn try_next(&mut self) -> Result<Option<&[u8]>, FrameError> {
+        self.reader.try_read_frame()
+    }
+}
+
+impl Drop for Frames<'_> {
+    fn drop(&mut self) {
+        self.reader.commit();
+    }
+}
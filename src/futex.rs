@@ -0,0 +1,185 @@
+//! Blocking on the control block, using process-shared futexes, see `Builder::futex`.
+//!
+//! A waiting handle announces itself in its `*_waiting` word of the control block,
+//! then sleeps on the epoch word of the other handle, until that handle increments
+//! the epoch: on commit, if the waiting word is set, and on drop.
+//! The words are in the shared mapping, and the futexes are not private,
+//! therefore the Writer and the Reader can live in different processes.
+//!
+//! If the other handle is registered by a different process (see `Writer::register_process`),
+//! a waiter sleeps at most `PEER_CHECK_INTERVAL` at once, and checks in between
+//! if that process died, using a pidfd: a dead process can't wake it anymore.
+
+#[cfg(target_os = "linux")]
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Longest sleep of a waiter, if the other handle is in a different process.
+const PEER_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The futex word of `epoch`: its low 32 bits.
+fn word(epoch: &AtomicU64) -> *const u32 {
+    let p = epoch as *const AtomicU64 as *const u32;
+    if cfg!(target_endian = "big") {
+        unsafe { p.add(1) }
+    } else {
+        p
+    }
+}
+
+/// Sleep until `epoch` is incremented, or `deadline`.
+///
+/// `ready` is called after the waiter is announced: it must check the condition
+/// waited for, as the other handle does not wake a waiter it did not see.
+/// If it returns true, `wait` returns without sleeping.
+pub(crate) fn wait(
+    waiting: &AtomicU64,
+    epoch: &AtomicU64,
+    deadline: Option<Instant>,
+    ready: impl FnOnce() -> bool,
+) {
+    let expected = epoch.load(Ordering::Acquire) as u32;
+    waiting.store(1, Ordering::Relaxed);
+    // pairs with the fence of `wake`: either we see the update, or the updater sees us
+    fence(Ordering::SeqCst);
+    if !ready() {
+        let timeout = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        if timeout != Some(Duration::ZERO) {
+            futex_wait(word(epoch), expected, timeout);
+        }
+    }
+    waiting.store(0, Ordering::Relaxed);
+}
+
+/// Like `wait`, for the other handle registered by process `peer_pid` (0, if unknown).
+///
+/// Returns true, if the other handle is in a different process, that died.
+pub(crate) fn wait_peer(
+    peer: &mut Peer,
+    peer_pid: u64,
+    waiting: &AtomicU64,
+    epoch: &AtomicU64,
+    deadline: Option<Instant>,
+    ready: impl FnOnce() -> bool,
+) -> bool {
+    if peer_pid == 0 || peer_pid == std::process::id() as u64 {
+        wait(waiting, epoch, deadline, ready);
+        return false;
+    }
+    let check = Instant::now() + PEER_CHECK_INTERVAL;
+    wait(
+        waiting,
+        epoch,
+        Some(deadline.map_or(check, |d| d.min(check))),
+        ready,
+    );
+    peer.is_dead(peer_pid)
+}
+
+/// Watches the process of the other handle.
+#[derive(Default)]
+pub(crate) struct Peer {
+    pid: u64,
+    dead: bool,
+    #[cfg(target_os = "linux")]
+    pidfd: Option<OwnedFd>,
+}
+
+impl Peer {
+    /// Returns true, if process `pid` is known to be dead.
+    #[cfg(target_os = "linux")]
+    fn is_dead(&mut self, pid: u64) -> bool {
+        if pid != self.pid {
+            // the pidfd keeps referring to the same process, even if the pid is reused
+            let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+            self.pid = pid;
+            self.dead =
+                fd < 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH);
+            // without pidfd support (before Linux 5.3), the process is assumed to be alive
+            self.pidfd = (fd >= 0).then(|| unsafe { OwnedFd::from_raw_fd(fd as i32) });
+        }
+        if let Some(pidfd) = &self.pidfd {
+            let mut pfd = libc::pollfd {
+                fd: pidfd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // readable, once the process terminated
+            self.dead = unsafe { libc::poll(&mut pfd, 1, 0) } > 0;
+        }
+        self.dead
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn is_dead(&mut self, _pid: u64) -> bool {
+        false
+    }
+}
+
+/// Wake the other handle, if it waits on `epoch` (see `wait`).
+///
+/// Must be called after publishing the update the other handle waits for.
+pub(crate) fn wake(waiting: &AtomicU64, epoch: &AtomicU64) {
+    fence(Ordering::SeqCst);
+    if waiting.load(Ordering::Relaxed) != 0 {
+        wake_all(epoch);
+    }
+}
+
+/// Increment `epoch`, and wake every waiter, unconditionally.
+pub(crate) fn wake_all(epoch: &AtomicU64) {
+    rt_check!("futex wake");
+    epoch.fetch_add(1, Ordering::Release);
+    futex_wake(word(epoch));
+}
+
+#[cfg(target_os = "linux")]
+fn futex_wait(word: *const u32, expected: u32, timeout: Option<Duration>) {
+    let ts = timeout.map(|t| libc::timespec {
+        tv_sec: t.as_secs() as libc::time_t,
+        tv_nsec: t.subsec_nanos() as _,
+    });
+    let tsp = ts
+        .as_ref()
+        .map_or(std::ptr::null(), |ts| ts as *const libc::timespec);
+    // spurious wakeups, timeouts and EAGAIN (the epoch changed) are handled by the caller
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word,
+            libc::FUTEX_WAIT,
+            expected,
+            tsp,
+            std::ptr::null::<u32>(),
+            0,
+        );
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn futex_wake(word: *const u32) {
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word,
+            libc::FUTEX_WAKE,
+            i32::MAX,
+            std::ptr::null::<libc::timespec>(),
+            std::ptr::null::<u32>(),
+            0,
+        );
+    }
+}
+
+// `Builder::build` rejects `futex` on other platforms: these are never called.
+
+#[cfg(not(target_os = "linux"))]
+fn futex_wait(_word: *const u32, _expected: u32, _timeout: Option<Duration>) {
+    unreachable!("futex is not supported on this platform");
+}
+
+#[cfg(not(target_os = "linux"))]
+fn futex_wake(_word: *const u32) {
+    unreachable!("futex is not supported on this platform");
+}
@@ -0,0 +1,158 @@
+//! Typed messages over a byte queue, as newline delimited JSON (NDJSON).
+//!
+//! Each message is serialized with serde_json in place (without a temporary buffer),
+//! followed by a newline. The format is slower and larger than `postcard`, but the
+//! content of the queue is human readable, e.g: in a coredump, or in a queue file
+//! (see `Builder::file`), and other tools can produce or consume it.
+//!
+//!```
+//! use cueue::json::{JsonReader, JsonWriter};
+//!
+//! let (w, r) = cueue::cueue::<u8>(1 << 16).unwrap();
+//! let mut w = JsonWriter::<(u32, String)>::new(w);
+//! let mut r = JsonReader::<(u32, String)>::new(r);
+//!
+//! w.send(&(1, "foo".to_string())).unwrap();
+//! assert_eq!(r.recv().unwrap(), Some((1, "foo".to_string())));
+//! r.commit();
+//!```
+
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::framed::FrameError;
+use crate::{Reader, Writer};
+
+/// Error of a typed send or receive.
+#[derive(Debug)]
+pub enum Error {
+    /// The message could not be written as a line: `FrameError::Full` or `FrameError::TooLarge`
+    Frame(FrameError),
+    /// The message could not be serialized or deserialized
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Frame(err) => write!(f, "{}", err),
+            Error::Json(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Sends messages of type `T`, one per line.
+pub struct JsonWriter<T> {
+    writer: Writer<u8>,
+    _t: PhantomData<fn(&T)>,
+}
+
+impl<T> JsonWriter<T>
+where
+    T: Serialize,
+{
+    pub fn new(writer: Writer<u8>) -> Self {
+        Self {
+            writer,
+            _t: PhantomData,
+        }
+    }
+
+    /// Serialize and commit `msg`, followed by a newline, or nothing, if there's not enough space.
+    pub fn send(&mut self, msg: &T) -> Result<(), Error> {
+        let capacity = self.writer.capacity();
+        let buf = self.writer.write_chunk();
+        let available = buf.len();
+        let mut out = &mut buf[..];
+        if let Err(err) = serde_json::to_writer(&mut out, msg) {
+            if !err.is_io() {
+                return Err(Error::Json(err));
+            }
+            return Err(Error::Frame(full_or_too_large(available, capacity)));
+        }
+        // compact JSON escapes the newlines of strings: the line is not split
+        match out.split_first_mut() {
+            Some((newline, _)) => *newline = b'\n',
+            None => return Err(Error::Frame(full_or_too_large(available, capacity))),
+        }
+        let len = available - out.len() + 1;
+        self.writer.commit(len);
+        Ok(())
+    }
+
+    /// Returns true, if the Reader counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.writer.is_abandoned()
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<u8> {
+        self.writer
+    }
+}
+
+/// The line did not fit in the `available` space of a queue of `capacity` bytes.
+fn full_or_too_large(available: usize, capacity: usize) -> FrameError {
+    if available == capacity {
+        FrameError::TooLarge
+    } else {
+        FrameError::Full
+    }
+}
+
+/// Receives messages of type `T`, one per line.
+pub struct JsonReader<T> {
+    reader: Reader<u8>,
+    /// length of the lines received since the last commit
+    received: usize,
+    _t: PhantomData<fn() -> T>,
+}
+
+impl<T> JsonReader<T>
+where
+    T: DeserializeOwned,
+{
+    pub fn new(reader: Reader<u8>) -> Self {
+        Self {
+            reader,
+            received: 0,
+            _t: PhantomData,
+        }
+    }
+
+    /// Deserialize the message of the next complete line, if available.
+    ///
+    /// Received messages are consumed from the queue by `commit`.
+    pub fn recv(&mut self) -> Result<Option<T>, Error> {
+        let lines = &self.reader.read_until(b'\n')[self.received..];
+        let line = match lines.iter().position(|&b| b == b'\n') {
+            Some(end) => &lines[..end + 1],
+            // the rest of a full queue, without a newline
+            None if !lines.is_empty() => lines,
+            None => return Ok(None),
+        };
+        self.received += line.len();
+        serde_json::from_slice(line).map(Some).map_err(Error::Json)
+    }
+
+    /// Consume every message received so far, making space for the Writer.
+    pub fn commit(&mut self) {
+        self.reader.limited_read_chunk(self.received);
+        self.reader.commit();
+        self.received = 0;
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.reader.is_abandoned()
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<u8> {
+        self.reader
+    }
+}
@@ -22,18 +22,42 @@
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use std::ffi::CString;
-#[cfg(any(target_os = "linux", target_os = "macos"))]
-use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
 use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
+use libc::MAP_NORESERVE;
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
 use libc::{c_void, ftruncate, mmap, munmap, sysconf};
-#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
 use libc::{
-    MAP_ANONYMOUS, MAP_FAILED, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, PROT_READ, PROT_WRITE,
-    _SC_PAGESIZE,
+    _SC_PAGESIZE, MAP_ANONYMOUS, MAP_FAILED, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, PROT_READ,
+    PROT_WRITE,
 };
 
+use abi::READER_BUSY;
+
+/// QNX maps lazily without reservation by default, and does not define MAP_NORESERVE
+#[cfg(target_os = "nto")]
+const MAP_NORESERVE: i32 = 0;
+
+/// Flags of `mmap`, to map a file of a DAX filesystem synchronously,
+/// not defined by the libc crate on every architecture
+#[cfg(target_os = "linux")]
+const MAP_SHARED_VALIDATE: i32 = 0x03;
+#[cfg(target_os = "linux")]
+const MAP_SYNC: i32 = 0x08_0000;
+
+/// Panic, if `$what` happens in a real-time section, and auditing is enabled.
+macro_rules! rt_check {
+    ($what:expr) => {
+        #[cfg(feature = "rt-audit")]
+        crate::rt_audit::check($what);
+    };
+}
+
 /// Wraps POSIX C errno with an additional hint.
 ///
 /// The hint is used to identify the opration that triggered the error.
@@ -58,20 +82,104 @@ impl std::fmt::Debug for CError {
     }
 }
 
-/// Create a file descriptor that points to a location in memory.
+/// The kind of file backing the memory of a queue, see `Writer::backend`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// An anonymous memory file, created by memfd_create (Linux).
+    Memfd,
+    /// A POSIX shared memory object, unlinked after creation
+    /// (macOS, QNX, and Linux, if memfd_create is not available).
+    Shm,
+    /// An unnamed temporary file in /dev/shm, created by O_TMPFILE
+    /// (Linux, if neither memfd_create nor shm_open is available).
+    Tmpfile,
+    /// The file set by `Builder::file`.
+    File,
+    /// The file descriptor given to `Builder::build_on_fd`.
+    External,
+    /// Memory mapped by the caller, see `from_raw_parts`.
+    Raw,
+}
+
+/// The file backing the memory of a queue.
+#[derive(Clone, Copy, Debug)]
+struct Backing {
+    backend: Backend,
+    dev: u64,
+    inode: u64,
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+impl Backing {
+    fn new(fd: &OwnedFd, backend: Backend) -> Result<Self, CError> {
+        let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+        if unsafe { libc::fstat(fd.as_raw_fd(), stat.as_mut_ptr()) } != 0 {
+            return Err(CError::new("fstat"));
+        }
+        let stat = unsafe { stat.assume_init() };
+        // the types of the fields differ by platform
+        #[allow(clippy::unnecessary_cast)]
+        Ok(Self {
+            backend,
+            dev: stat.st_dev as u64,
+            inode: stat.st_ino as u64,
+        })
+    }
+}
+
+/// Create a file descriptor that points to a location in memory, named `name` (see `Builder::name`).
+///
+/// memfd_create might be missing (before Linux 3.17), or blocked by a seccomp policy
+/// (e.g: in containers), then POSIX shared memory, and O_TMPFILE on tmpfs are tried.
 #[cfg(target_os = "linux")]
-unsafe fn memoryfile() -> Result<OwnedFd, CError> {
-    let name = CString::new("cueue").unwrap();
-    let memfd = libc::memfd_create(name.as_ptr(), 0);
-    if memfd < 0 {
-        return Err(CError::new("memfd_create"));
+unsafe fn memoryfile(name: &CString) -> Result<(OwnedFd, Backend), CError> {
+    let memfd = libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC);
+    if memfd >= 0 {
+        return Ok((OwnedFd::from_raw_fd(memfd), Backend::Memfd));
+    }
+    shm_memoryfile(name)
+        .map(|fd| (fd, Backend::Shm))
+        .or_else(|_| tmpfile_memoryfile().map(|fd| (fd, Backend::Tmpfile)))
+}
+
+/// Create a unique POSIX shared memory object, and unlink it.
+#[cfg(target_os = "linux")]
+unsafe fn shm_memoryfile(name: &CString) -> Result<OwnedFd, CError> {
+    static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let name = format!("/{}-{}-{}", name.to_string_lossy(), std::process::id(), n);
+    let name = CString::new(name).unwrap();
+    let fd = libc::shm_open(
+        name.as_ptr(),
+        libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+        0o600,
+    );
+    if fd < 0 {
+        return Err(CError::new("shm_open"));
+    }
+    libc::shm_unlink(name.as_ptr());
+    Ok(OwnedFd::from_raw_fd(fd))
+}
+
+/// Create an unnamed temporary file on tmpfs.
+#[cfg(target_os = "linux")]
+unsafe fn tmpfile_memoryfile() -> Result<OwnedFd, CError> {
+    let dir = CString::new("/dev/shm").unwrap();
+    let fd = libc::open(
+        dir.as_ptr(),
+        libc::O_RDWR | libc::O_TMPFILE | libc::O_EXCL | libc::O_CLOEXEC,
+        0o600,
+    );
+    if fd < 0 {
+        return Err(CError::new("open O_TMPFILE"));
     }
-    Ok(OwnedFd::from_raw_fd(memfd))
+    Ok(OwnedFd::from_raw_fd(fd))
 }
 
 #[cfg(target_os = "macos")]
-unsafe fn memoryfile() -> Result<OwnedFd, CError> {
-    let path = CString::new("/tmp/cueue_XXXXXX").unwrap();
+unsafe fn memoryfile(name: &CString) -> Result<(OwnedFd, Backend), CError> {
+    let path = format!("/tmp/{}_XXXXXX", name.to_string_lossy());
+    let path = CString::new(path).unwrap();
     let path_cstr = path.into_raw();
     let tmpfd = libc::mkstemp(path_cstr);
     let path = CString::from_raw(path_cstr);
@@ -85,31 +193,116 @@ unsafe fn memoryfile() -> Result<OwnedFd, CError> {
         return Err(CError::new("shm_open"));
     }
 
-    Ok(OwnedFd::from_raw_fd(memfd))
+    Ok((OwnedFd::from_raw_fd(memfd), Backend::Shm))
 }
 
-#[cfg(not(any(target_os = "linux", target_os = "macos")))]
-unsafe fn memoryfile() {
-    todo!("Only Linux and macOS are supported so far");
+/// QNX: SHM_ANON creates an anonymous shared memory object, no need to unlink
+#[cfg(target_os = "nto")]
+unsafe fn memoryfile(_name: &CString) -> Result<(OwnedFd, Backend), CError> {
+    let memfd = libc::shm_open(libc::SHM_ANON, libc::O_RDWR | libc::O_CREAT, 0o600);
+    if memfd < 0 {
+        return Err(CError::new("shm_open"));
+    }
+    Ok((OwnedFd::from_raw_fd(memfd), Backend::Shm))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "nto")))]
+unsafe fn memoryfile(_name: &CString) {
+    todo!("Only Linux, macOS and QNX are supported so far");
+}
+
+/// Set (if `enable`) or clear the close-on-exec flag of `fd`, see `Builder::cloexec`.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+fn set_cloexec(fd: &OwnedFd, enable: bool) -> Result<(), CError> {
+    unsafe {
+        let flags = libc::fcntl(fd.as_raw_fd(), libc::F_GETFD);
+        if flags < 0 {
+            return Err(CError::new("fcntl F_GETFD"));
+        }
+        let flags = if enable {
+            flags | libc::FD_CLOEXEC
+        } else {
+            flags & !libc::FD_CLOEXEC
+        };
+        if libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, flags) != 0 {
+            return Err(CError::new("fcntl F_SETFD"));
+        }
+    }
+    Ok(())
+}
+
+/// Open (or create, if `create`) the file backing a persistent queue, return it with its current size.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+fn queuefile(path: &std::path::Path, create: bool) -> Result<(OwnedFd, u64), CError> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(create)
+        .truncate(false)
+        .open(path)
+        .map_err(|err| CError {
+            hint: "open queue file",
+            err,
+        })?;
+    let len = file
+        .metadata()
+        .map_err(|err| CError {
+            hint: "stat queue file",
+            err,
+        })?
+        .len();
+    Ok((file.into(), len))
+}
+
+/// Duplicate `fd`, given to hold a queue of `size` bytes at `offset`, see `Builder::build_on_fd`.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+fn external_fd(fd: impl AsFd, offset: u64, size: usize) -> Result<OwnedFd, CError> {
+    let pagesize = unsafe { sysconf(_SC_PAGESIZE) } as u64;
+    if offset & (pagesize - 1) != 0 {
+        return Err(CError {
+            hint: "offset must be a multiple of the system page size",
+            err: std::io::ErrorKind::InvalidInput.into(),
+        });
+    }
+    let file = std::fs::File::from(fd.as_fd().try_clone_to_owned().map_err(|err| CError {
+        hint: "dup queue fd",
+        err,
+    })?);
+    let len = file
+        .metadata()
+        .map_err(|err| CError {
+            hint: "stat queue fd",
+            err,
+        })?
+        .len();
+    // the size of e.g: device files is unknown (0)
+    if len != 0 && len < offset + size as u64 {
+        return Err(CError {
+            hint: "queue fd is smaller than the queue",
+            err: std::io::ErrorKind::InvalidInput.into(),
+        });
+    }
+    Ok(file.into())
 }
 
 /// A chunk of memory allocated using mmap.
 ///
-/// Deallocates the memory on Drop.
-#[cfg(any(target_os = "linux", target_os = "macos"))]
+/// Deallocates the memory on Drop, unless it is owned by the caller (see `from_raw_parts`).
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
 struct MemoryMap {
     map: *mut c_void,
     size: usize,
 }
 
-#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
 impl MemoryMap {
     fn new(map: *mut c_void, size: usize) -> Self {
         Self { map, size }
     }
 
-    fn failed(&self) -> bool {
-        self.map == MAP_FAILED
+    /// Memory mapped by the caller, not unmapped on Drop.
+    fn borrowed(map: *mut c_void) -> Self {
+        Self { map, size: 0 }
     }
 
     fn ptr(&self) -> *mut u8 {
@@ -117,10 +310,10 @@ impl MemoryMap {
     }
 }
 
-#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
 impl Drop for MemoryMap {
     fn drop(&mut self) {
-        if !self.failed() {
+        if self.size != 0 {
             unsafe {
                 munmap(self.map, self.size);
             }
@@ -128,13 +321,13 @@ impl Drop for MemoryMap {
     }
 }
 
-#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "nto")))]
 struct MemoryMap {}
 
-#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "nto")))]
 impl MemoryMap {
     fn ptr(&self) -> *mut u8 {
-        todo!("Only Linux and macOS are supported so far");
+        todo!("Only Linux, macOS and QNX are supported so far");
     }
 }
 
@@ -142,19 +335,68 @@ struct MemoryMapInitialized<T> {
     map: MemoryMap,
     buf: *mut T,
     cap: usize,
+    pagesize: usize,
+    backing: Backing,
+    report: BuildReport,
+    /// The file of the queue, kept open to be inherited, see `Builder::cloexec`
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    fd: Option<OwnedFd>,
+    /// The elements are owned by the process that created the queue, see `fork`
+    generation: u64,
 }
 
 impl<T> MemoryMapInitialized<T>
 where
     T: Default,
 {
-    fn new(map: MemoryMap, buf: *mut T, cap: usize) -> Self {
-        for i in 0..cap {
-            unsafe {
-                buf.add(i).write(T::default());
+    /// If `zeroed`, the elements are all zero bytes already, that is a valid `T::default()`.
+    fn new(
+        map: MemoryMap,
+        buf: *mut T,
+        cap: usize,
+        report: BuildReport,
+        backing: Backing,
+        zeroed: bool,
+    ) -> Self {
+        if !zeroed {
+            for i in 0..cap {
+                unsafe {
+                    buf.add(i).write(T::default());
+                }
             }
         }
-        Self { map, buf, cap }
+        Self {
+            map,
+            buf,
+            cap,
+            pagesize: report.page_size,
+            backing,
+            report,
+            #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+            fd: None,
+            generation: fork::generation(),
+        }
+    }
+
+    /// Like `new`, but the elements are already initialized (e.g: by a previous process).
+    fn existing(
+        map: MemoryMap,
+        buf: *mut T,
+        cap: usize,
+        report: BuildReport,
+        backing: Backing,
+    ) -> Self {
+        Self {
+            map,
+            buf,
+            cap,
+            pagesize: report.page_size,
+            backing,
+            report,
+            #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+            fd: None,
+            generation: fork::generation(),
+        }
     }
 
     #[inline]
@@ -165,6 +407,10 @@ where
 
 impl<T> Drop for MemoryMapInitialized<T> {
     fn drop(&mut self) {
+        if fork::is_forked(self.generation) {
+            // the queue might still be used by the parent process
+            return;
+        }
         for i in 0..self.cap {
             unsafe {
                 self.buf.add(i).drop_in_place();
@@ -173,6 +419,26 @@ impl<T> Drop for MemoryMapInitialized<T> {
     }
 }
 
+/// Flags of `mmap` to map a file of a DAX filesystem with.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn dax_share_flags() -> Result<i32, CError> {
+    Ok(MAP_SHARED_VALIDATE | MAP_SYNC)
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+fn dax_share_flags() -> Result<i32, CError> {
+    Err(CError {
+        hint: "DAX is not supported on this platform",
+        err: std::io::ErrorKind::Unsupported.into(),
+    })
+}
+
 /// Platform specific flags that increase performance, but not required.
 #[cfg(target_os = "linux")]
 fn platform_flags() -> i32 {
@@ -184,51 +450,80 @@ fn platform_flags() -> i32 {
     0
 }
 
-/// Map a `size` chunk of `fd` at `offset` twice, next to each other in virtual memory
-/// The size of the file pointed by `fd` must be >= offset + size.
-#[cfg(any(target_os = "linux", target_os = "macos"))]
-unsafe fn doublemap(fd: RawFd, offset: usize, size: usize) -> Result<MemoryMap, CError> {
+/// Map a `size` chunk of `fd` at `base + offset` twice, next to each other in virtual memory,
+/// preceded by the `offset` bytes of `fd` at `base`.
+/// The size of the file pointed by `fd` must be >= base + offset + size.
+///
+/// `flags` are added to the flags of the first map.
+/// If they include MAP_NORESERVE, the whole reservation is made with it.
+/// `share` is used to map the file: MAP_SHARED, or MAP_SHARED_VALIDATE | MAP_SYNC.
+///
+/// The returned map is aligned to `align`, which must be a multiple of the system page size.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+unsafe fn doublemap(
+    fd: RawFd,
+    base: u64,
+    offset: usize,
+    size: usize,
+    flags: i32,
+    share: i32,
+    align: usize,
+) -> Result<MemoryMap, CError> {
     // Create a map, offset + twice the size, to get a suitable virtual address which will work with MAP_FIXED
+    // Over-allocate, to be able to select an aligned address: mmap returns a page aligned one,
+    // therefore with the system page size as `align` (the default), no over-allocation is needed
     let rw = PROT_READ | PROT_WRITE;
     let mapsize = offset + size * 2;
-    let map = MemoryMap::new(
-        mmap(
-            std::ptr::null_mut(),
-            mapsize,
-            rw,
-            MAP_PRIVATE | MAP_ANONYMOUS,
-            -1,
-            0,
-        ),
-        mapsize,
+    let extra = align - sysconf(_SC_PAGESIZE) as usize;
+    let reserved = mmap(
+        std::ptr::null_mut(),
+        mapsize + extra,
+        rw,
+        MAP_PRIVATE | MAP_ANONYMOUS | (flags & MAP_NORESERVE),
+        -1,
+        0,
     );
-    if map.failed() {
+    if reserved == MAP_FAILED {
         return Err(CError::new("mmap 1"));
     }
 
-    // Map f twice, put maps next to each other with MAP_FIXED
-    // MAP_SHARED is required to have the changes propagated between maps
-    let first_addr = map.ptr().add(offset) as *mut c_void;
+    // Release the unaligned head and the unused tail of the reservation
+    let head = (align - reserved as usize % align) % align;
+    if head != 0 {
+        munmap(reserved, head);
+    }
+    if extra != head {
+        munmap(
+            reserved.cast::<u8>().add(head + mapsize).cast(),
+            extra - head,
+        );
+    }
+    let map = MemoryMap::new(reserved.cast::<u8>().add(head).cast(), mapsize);
+
+    // Map the head of f (the control block) and the buffer once, with a single mmap:
+    // the control block is persisted with the file (if f is a regular file).
+    // MAP_SHARED (or MAP_SHARED_VALIDATE) is required to have the changes propagated between maps
     let first_map = mmap(
-        first_addr,
-        size,
+        map.ptr().cast(),
+        offset + size,
         rw,
-        MAP_SHARED | MAP_FIXED | platform_flags(),
+        share | MAP_FIXED | flags,
         fd,
-        offset as i64,
+        base as i64,
     );
-    if first_map != first_addr {
+    if first_map != map.ptr().cast() {
         return Err(CError::new("mmap 2"));
     }
 
+    // Map the buffer of f again, right after the first map, with MAP_FIXED
     let second_addr = map.ptr().add(offset + size) as *mut c_void;
     let second_map = mmap(
         second_addr,
         size,
         rw,
-        MAP_SHARED | MAP_FIXED,
+        share | MAP_FIXED | (flags & MAP_NORESERVE),
         fd,
-        offset as i64,
+        (base + offset as u64) as i64,
     );
     if second_map != second_addr {
         return Err(CError::new("mmap 3"));
@@ -243,9 +538,89 @@ unsafe fn doublemap(fd: RawFd, offset: usize, size: usize) -> Result<MemoryMap,
     Ok(map)
 }
 
-#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "nto")))]
 unsafe fn doublemap() {
-    todo!("Only Linux and macOS are supported so far");
+    todo!("Only Linux, macOS and QNX are supported so far");
+}
+
+/// Write back the cache lines of the `len` bytes at `addr` to persistent memory,
+/// and wait for the write back to complete.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+unsafe fn flush_cache_lines(addr: *const u8, len: usize) {
+    const CACHE_LINE: usize = 64;
+    let begin = addr as usize & !(CACHE_LINE - 1);
+    let end = addr as usize + len;
+    #[cfg(target_arch = "x86_64")]
+    {
+        for line in (begin..end).step_by(CACHE_LINE) {
+            std::arch::x86_64::_mm_clflush(line as *const u8);
+        }
+        std::arch::x86_64::_mm_sfence();
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        for line in (begin..end).step_by(CACHE_LINE) {
+            std::arch::asm!("dc cvac, {}", in(reg) line);
+        }
+        std::arch::asm!("dsb ish");
+    }
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+unsafe fn flush_cache_lines(_addr: *const u8, _len: usize) {
+    unreachable!("DAX is not supported on this platform");
+}
+
+/// Return the memory backing the `len` bytes at `addr` to the OS.
+///
+/// Only pages entirely inside the given range are released. If `remove` is set,
+/// the contents are discarded, subsequent reads of those pages yield zero bytes,
+/// otherwise only the mapping is released (Linux), the contents are kept.
+/// This is only a hint: errors are ignored, the contents might be kept.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+unsafe fn release_pages(addr: *const u8, len: usize, pagesize: usize, remove: bool) {
+    let begin = (addr as usize + pagesize - 1) & !(pagesize - 1);
+    let end = (addr as usize + len) & !(pagesize - 1);
+    if begin < end {
+        advise_release(begin as *mut c_void, end - begin, remove);
+    }
+}
+
+/// Linux: MADV_REMOVE frees the backing store of shared mappings (MADV_DONTNEED would only unmap it),
+/// but punches a hole into the file: only done if the contents are not needed anymore.
+#[cfg(target_os = "linux")]
+unsafe fn advise_release(addr: *mut c_void, len: usize, remove: bool) {
+    let advice = if remove {
+        libc::MADV_REMOVE
+    } else {
+        libc::MADV_DONTNEED
+    };
+    libc::madvise(addr, len, advice);
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn advise_release(addr: *mut c_void, len: usize, remove: bool) {
+    if remove {
+        libc::madvise(addr, len, libc::MADV_FREE);
+    }
+}
+
+#[cfg(target_os = "nto")]
+unsafe fn advise_release(addr: *mut c_void, len: usize, remove: bool) {
+    if remove {
+        libc::posix_madvise(addr, len, libc::POSIX_MADV_DONTNEED);
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "nto")))]
+unsafe fn release_pages(_addr: *const u8, _len: usize, _pagesize: usize, _remove: bool) {
+    todo!("Only Linux, macOS and QNX are supported so far");
 }
 
 /// Returns smallest power of 2 not smaller than `n`,
@@ -274,7 +649,7 @@ fn next_power_two(n: usize) -> Result<usize, CError> {
 
 /// Force an AtomicU64 to a separate cache-line to avoid false-sharing.
 /// This wrapper is needed as I was unable to specify alignment for individual fields.
-#[repr(align(128))]
+#[repr(C, align(128))]
 #[derive(Default)]
 struct CacheLineAlignedAU64(std::sync::atomic::AtomicU64);
 
@@ -284,10 +659,276 @@ struct CacheLineAlignedAU64(std::sync::atomic::AtomicU64);
 /// Cueue is full if W == R+capacity
 /// Invariant: W >= R
 /// Invariant: R + capacity >= W
+///
+/// `sequence` counts the records the Writer committed or dropped,
+/// `dropped` counts the dropped ones only. Both are written by the Writer only.
+///
+/// `completion` is 0 while the stream is running, then set once by `Writer::finish`,
+/// see `Completion::encode`.
+///
+/// `read_watermark` is the minimum number of elements available to notify the Reader at,
+/// set by the Reader. `write_watermark` is the minimum free space to notify the Writer at,
+/// set by the Writer.
+///
+/// `magic` identifies an initialized control block in a persistent queue file.
+///
+/// `clock` and `epoch_offset` describe the timestamps of the queue, see `clock::ClockInfo`.
+///
+/// The layout is shared with other languages: see the `abi` module before changing it.
+#[repr(C)]
 #[derive(Default)]
 struct ControlBlock {
+    magic: CacheLineAlignedAU64,
     write_position: CacheLineAlignedAU64,
     read_position: CacheLineAlignedAU64,
+    sequence: CacheLineAlignedAU64,
+    dropped: CacheLineAlignedAU64,
+    completion: CacheLineAlignedAU64,
+    completion_code: CacheLineAlignedAU64,
+    read_watermark: CacheLineAlignedAU64,
+    write_watermark: CacheLineAlignedAU64,
+    clock: CacheLineAlignedAU64,
+    epoch_offset: CacheLineAlignedAU64,
+    reader_waiting: CacheLineAlignedAU64,
+    writer_waiting: CacheLineAlignedAU64,
+    write_epoch: CacheLineAlignedAU64,
+    read_epoch: CacheLineAlignedAU64,
+    closed: CacheLineAlignedAU64,
+    writer_pid: CacheLineAlignedAU64,
+    reader_pid: CacheLineAlignedAU64,
+    retention: CacheLineAlignedAU64,
+    peak_occupancy: CacheLineAlignedAU64,
+}
+
+impl ControlBlock {
+    fn clock_info(&self) -> clock::ClockInfo {
+        clock::ClockInfo {
+            clock: clock::Clock::decode(self.clock.0.load(Ordering::Relaxed)),
+            epoch_offset: self.epoch_offset.0.load(Ordering::Relaxed) as i64,
+        }
+    }
+
+    /// Record the clock convention of the queue, measuring the epoch offset now.
+    fn set_clock(&self, clock: clock::Clock) {
+        let info = clock::ClockInfo::measure(clock);
+        self.clock.0.store(clock.encode(), Ordering::Relaxed);
+        self.epoch_offset
+            .0
+            .store(info.epoch_offset as u64, Ordering::Relaxed);
+    }
+
+    /// Initialize a new control block at `cbp`, owned by the current process.
+    ///
+    /// `magic` is not set: the caller publishes it last, once the elements are initialized.
+    unsafe fn init(cbp: *mut ControlBlock, clock: clock::Clock, retention: u64) {
+        let pid = std::process::id() as u64;
+        cbp.write(ControlBlock::default());
+        let cb = &*cbp;
+        cb.set_clock(clock);
+        cb.writer_pid.0.store(pid, Ordering::Relaxed);
+        cb.reader_pid.0.store(pid, Ordering::Relaxed);
+        cb.retention.0.store(retention, Ordering::Relaxed);
+    }
+
+    /// Take over the initialized control block of a reopened queue, owned by the current process:
+    /// keep the positions and the counters, reset the rest.
+    fn reopen(&self, retention: u64) {
+        let pid = std::process::id() as u64;
+        let r = self.read_position.0.load(Ordering::Relaxed) & !READER_BUSY;
+        self.read_position.0.store(r, Ordering::Relaxed);
+        self.completion.0.store(0, Ordering::Relaxed);
+        self.completion_code.0.store(0, Ordering::Relaxed);
+        self.read_watermark.0.store(0, Ordering::Relaxed);
+        self.write_watermark.0.store(0, Ordering::Relaxed);
+        self.reader_waiting.0.store(0, Ordering::Relaxed);
+        self.writer_waiting.0.store(0, Ordering::Relaxed);
+        self.closed.0.store(0, Ordering::Relaxed);
+        self.writer_pid.0.store(pid, Ordering::Relaxed);
+        self.reader_pid.0.store(pid, Ordering::Relaxed);
+        self.retention.0.store(retention, Ordering::Relaxed);
+        self.set_clock(clock::Clock::decode(self.clock.0.load(Ordering::Relaxed)));
+    }
+}
+
+/// The final status of a stream, set by `Writer::finish`, observed by `Reader::completion`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Completion {
+    /// The Writer finished the stream successfully.
+    Ok,
+    /// The Writer failed, with an application specific error code.
+    Failed(u64),
+}
+
+impl Completion {
+    /// Returns (completion, completion_code), as stored in the ControlBlock
+    fn encode(self) -> (u64, u64) {
+        match self {
+            Completion::Ok => (1, 0),
+            Completion::Failed(code) => (2, code),
+        }
+    }
+
+    fn decode(completion: u64, code: u64) -> Option<Self> {
+        match completion {
+            1 => Some(Completion::Ok),
+            2 => Some(Completion::Failed(code)),
+            _ => None,
+        }
+    }
+}
+
+/// Error of blocking operations: the other side of the queue was dropped, or its process died.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Abandoned;
+
+impl std::fmt::Display for Abandoned {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the other side of the queue is gone")
+    }
+}
+
+impl std::error::Error for Abandoned {}
+
+/// Error of a handle inherited by a child process from the process using it, see `Writer::check_fork`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Forked;
+
+impl std::fmt::Display for Forked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the queue handle is used by a forked child process")
+    }
+}
+
+impl std::error::Error for Forked {}
+
+/// What `Writer::push` (and `Writer::push_many`) does, if the queue is full.
+///
+/// Set by `Builder::full_policy`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FullPolicy {
+    /// Give the element back to the caller.
+    #[default]
+    ReturnEmpty,
+    /// Wait until the Reader makes space (or is dropped), using the wait strategy of the Writer.
+    Block,
+    /// Drop the new element, and report it to the Reader as a gap (see `Reader::gap`).
+    DropNewest,
+    /// Drop the oldest unread element to make space, and report it to the Reader as a gap.
+    ///
+    /// While the Reader holds a chunk (between `read_chunk` and `commit`),
+    /// the elements of the chunk can not be dropped: the new element is dropped instead.
+    /// To let the Writer drop the oldest elements, the Reader marks its chunk
+    /// with an atomic read-modify-write in `read_chunk`, that is slightly slower.
+    DropOldest,
+}
+
+/// Where a Reader attached to a running queue starts reading, see `Builder::attach_reader`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StartPosition {
+    /// The oldest element still in the queue: the first one the previous Reader did not consume.
+    #[default]
+    Oldest,
+    /// The current write position: elements committed before attaching are skipped.
+    Latest,
+}
+
+/// Element types that can be shared between processes: required by the constructors
+/// that map a queue created by another process (e.g: `Builder::attach_reader`).
+///
+/// An element written by a process is read by another one, in a different address space:
+/// pointers (e.g: `Box`, `String`, `&T`) and handles of the process (e.g: file descriptors)
+/// are meaningless there, and dropping them frees memory the reading process never owned.
+///
+/// # Safety
+///
+/// `T` must contain no pointers and no process specific resources, and must be valid
+/// for any bit pattern written by another process, i.e: plain data, like `bytemuck::Pod`.
+/// Implemented for integers, floats and arrays of them. For plain data structs:
+///
+///```
+/// #[derive(Clone, Copy, Default)]
+/// #[repr(C)]
+/// struct Sample {
+///     timestamp: u64,
+///     value: f64,
+/// }
+///
+/// unsafe impl cueue::SharedMemSafe for Sample {}
+///```
+pub unsafe trait SharedMemSafe {}
+
+macro_rules! shared_mem_safe {
+    ($($t:ty),*) => {
+        $(unsafe impl SharedMemSafe for $t {})*
+    };
+}
+
+shared_mem_safe!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: SharedMemSafe, const N: usize> SharedMemSafe for [T; N] {}
+
+/// Types, whose default value is all zero bytes.
+///
+/// The memory of a new queue is zero filled by the OS: `Builder::build_zeroed`
+/// skips default initializing the elements, that touches every page of the buffer.
+///
+/// # Safety
+///
+/// `T::default()` must be represented by zero bytes only (except for padding).
+/// Implemented for integers, floats, `bool`, `char` and arrays of them.
+pub unsafe trait Zeroable: Default {}
+
+macro_rules! zeroable {
+    ($($t:ty),*) => {
+        $(unsafe impl Zeroable for $t {})*
+    };
+}
+
+zeroable!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char);
+
+unsafe impl<T: Zeroable, const N: usize> Zeroable for [T; N] where [T; N]: Default {}
+
+/// When the Writer of a file backed queue (see `Builder::file`) flushes the queue to storage.
+///
+/// Set by `Builder::durability`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Leave it to the OS.
+    #[default]
+    None,
+    /// On every non-empty commit: the committed elements first, then the write position.
+    OnCommit,
+    /// On commit, if the previous flush is older than the given interval, and on drop.
+    Interval(Duration),
+}
+
+/// A callback, registered by the user, to be called on certain events.
+type Hook = Box<dyn FnMut() + Send>;
+
+/// A callback, called with the number of completed cycles, see `Writer::on_wrap`.
+type WrapHook = Box<dyn FnMut(u64) + Send>;
+
+/// Occupancy watermarks of the Writer, with hysteresis, see `Writer::set_backpressure`.
+struct Backpressure {
+    high: u64,
+    low: u64,
+    engaged: bool,
+    callback: Box<dyn FnMut(bool) + Send>,
+}
+
+impl Backpressure {
+    /// Update the state according to `occupancy`, call the callback on transitions.
+    fn update(&mut self, occupancy: u64) {
+        let engaged = if self.engaged {
+            occupancy > self.low
+        } else {
+            occupancy >= self.high
+        };
+        if engaged != self.engaged {
+            self.engaged = engaged;
+            (self.callback)(engaged);
+        }
+    }
 }
 
 /// Writer of a Cueue.
@@ -301,13 +942,47 @@ pub struct Writer<T> {
     buffer: *mut T,
     write_begin: *mut T,
     write_capacity: usize,
+
+    on_full: Option<Hook>,
+    on_wrap: Option<WrapHook>,
+    backpressure: Option<Backpressure>,
+    full_policy: FullPolicy,
+    wait: Box<dyn wait::WaitStrategy>,
+    durability: Durability,
+    dax: bool,
+    synced: Instant,
+    commit_on_drop: bool,
+    drain_on_drop: Option<Duration>,
+    /// see `Builder::sequence`
+    sequence: bool,
+    futex: bool,
+    peer: futex::Peer,
+    retention: u64,
+    /// see `stats::Stats::peak_occupancy`
+    peak_occupancy: u64,
+    /// the read position loaded by the last `write_chunk`
+    seen_read_pos: u64,
+    /// elements of the chunk marked written by `commit_relaxed`, not committed yet
+    pending: usize,
+    /// see `check_fork`
+    generation: u64,
+
+    // Must be declared after `mem`: notifies the Reader on drop,
+    // that must observe the Writer abandoned by then.
+    notify: Option<notify::Notify>,
 }
 
 impl<T> Writer<T>
 where
     T: Default,
 {
-    fn new(mem: std::sync::Arc<MemoryMapInitialized<T>>, buffer: *mut T, capacity: usize) -> Self {
+    fn new(
+        mem: std::sync::Arc<MemoryMapInitialized<T>>,
+        buffer: *mut T,
+        capacity: usize,
+        full_policy: FullPolicy,
+        notify: Option<notify::Notify>,
+    ) -> Self {
         let cb = mem.controlblock();
         Self {
             mem,
@@ -316,6 +991,25 @@ where
             buffer,
             write_begin: std::ptr::null_mut(),
             write_capacity: 0,
+            on_full: None,
+            on_wrap: None,
+            backpressure: None,
+            full_policy,
+            wait: Box::<wait::HybridWait>::default(),
+            durability: Durability::None,
+            dax: false,
+            synced: Instant::now(),
+            commit_on_drop: false,
+            drain_on_drop: None,
+            sequence: false,
+            futex: false,
+            peer: futex::Peer::default(),
+            retention: 0,
+            peak_occupancy: unsafe { (*cb).peak_occupancy.0.load(Ordering::Relaxed) },
+            seen_read_pos: 0,
+            pending: 0,
+            generation: fork::generation(),
+            notify,
         }
     }
 
@@ -325,6 +1019,49 @@ where
         (self.mask + 1) as usize
     }
 
+    /// The size of the buffer of the referenced `cueue`, in bytes: `capacity() * element_size()`.
+    pub fn capacity_bytes(&self) -> usize {
+        self.capacity() * self.element_size()
+    }
+
+    /// The size of an element, in bytes.
+    pub fn element_size(&self) -> usize {
+        std::mem::size_of::<T>()
+    }
+
+    /// The memory used by the referenced `cueue`, in bytes: the control block and the buffer.
+    ///
+    /// The mirror of the buffer (see the crate docs) maps the same pages, therefore it is not
+    /// counted: the reserved virtual address space is `capacity_bytes()` larger.
+    pub fn memory_footprint(&self) -> usize {
+        self.page_size() + self.capacity_bytes()
+    }
+
+    /// The granularity of the layout of the referenced `cueue`:
+    /// the size of the control block and the alignment of the buffer.
+    ///
+    /// Either the system page size, or the value set by `Builder::page_size`.
+    pub fn page_size(&self) -> usize {
+        self.mem.pagesize
+    }
+
+    /// The kind of file backing the memory of the referenced `cueue`.
+    pub fn backend(&self) -> Backend {
+        self.mem.backing.backend
+    }
+
+    /// The file descriptor of the memory of the referenced `cueue`, to be inherited by a child process,
+    /// or None, unless `Builder::cloexec` was disabled.
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    pub fn inheritable_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.mem.fd.as_ref().map(|fd| fd.as_fd())
+    }
+
+    /// The decisions made while constructing the referenced `cueue`, see `BuildReport`.
+    pub fn build_report(&self) -> BuildReport {
+        self.mem.report
+    }
+
     /// Get a writable slice of maximum available size.
     ///
     /// The elements in the returned slice are either default initialized
@@ -334,14 +1071,33 @@ where
     /// After write, `commit` must be called, to make the written elements
     /// available for reading.
     pub fn write_chunk(&mut self) -> &mut [T] {
+        if fork::is_forked(self.generation) {
+            self.write_begin = self.buffer;
+            self.write_capacity = 0;
+            return &mut [];
+        }
         let w = self.write_pos().load(Ordering::Relaxed);
-        let r = self.read_pos().load(Ordering::Acquire);
+        let r = self.read_pos().load(Ordering::Acquire) & !READER_BUSY;
 
         debug_assert!(r <= w);
         debug_assert!(r + self.capacity() as u64 >= w);
 
         let wi = w & self.mask;
-        self.write_capacity = (self.capacity() as u64 - (w.wrapping_sub(r))) as usize;
+        self.seen_read_pos = r;
+        // the retained elements are not available for writing, see `Builder::retention`
+        let writable = self.capacity() as u64 - self.retention;
+        self.write_capacity = writable.saturating_sub(w.wrapping_sub(r)) as usize;
+        // the chunk starts where the previous one did: the marked elements are still there
+        self.pending = usize::min(self.pending, self.write_capacity);
+        if let Some(backpressure) = &mut self.backpressure {
+            backpressure.update(w - r);
+        }
+
+        if self.write_capacity == 0 {
+            if let Some(on_full) = &mut self.on_full {
+                on_full();
+            }
+        }
 
         unsafe {
             self.write_begin = self.buffer.offset(wi as isize);
@@ -349,6 +1105,28 @@ where
         }
     }
 
+    /// Get a writable slice of maximum available size, but at most `n` elements.
+    ///
+    /// See `write_chunk`. Useful to bound the size of batches,
+    /// to control the latency of the reader.
+    pub fn limited_write_chunk(&mut self, n: usize) -> &mut [T] {
+        let available = self.write_chunk().len();
+        self.write_capacity = usize::min(available, n);
+        unsafe { std::slice::from_raw_parts_mut(self.write_begin, self.write_capacity) }
+    }
+
+    /// Get a writable slice of exactly `n` elements, or None, if not enough space is available.
+    ///
+    /// See `write_chunk`. Committing more than `n` elements is not possible after this call.
+    pub fn write_chunk_exact(&mut self, n: usize) -> Option<&mut [T]> {
+        if self.write_chunk().len() < n {
+            self.write_capacity = 0;
+            return None;
+        }
+        self.write_capacity = n;
+        unsafe { Some(std::slice::from_raw_parts_mut(self.write_begin, n)) }
+    }
+
     /// Make `n` number of elements, written to the slice returned by `write_chunk`
     /// available for reading.
     ///
@@ -363,44 +1141,637 @@ where
         m
     }
 
-    unsafe fn unchecked_commit(&mut self, n: usize) {
-        let w = self.write_pos().load(Ordering::Relaxed);
-        self.write_begin = self.write_begin.add(n);
-        self.write_capacity -= n;
-        self.write_pos().store(w + n as u64, Ordering::Release);
+    /// Like `commit`, but `n` is not checked.
+    ///
+    /// # Safety
+    ///
+    /// `n` must not exceed the number of elements of the slice returned by the last `write_chunk`
+    /// (or `limited_write_chunk`, `write_chunk_exact`), minus the elements committed from it since.
+    /// Otherwise the Reader reads elements beyond the written ones, or the ones it did not consume yet.
+    pub unsafe fn commit_unchecked(&mut self, n: usize) {
+        debug_assert!(n <= self.write_capacity);
+        self.unchecked_commit(n);
     }
 
-    /// Returns true, if the Reader counterpart was dropped.
-    pub fn is_abandoned(&self) -> bool {
-        std::sync::Arc::strong_count(&self.mem) < 2
+    /// Mark the first `n` elements of the slice returned by `write_chunk` as written,
+    /// without making them available for reading: they are committed by the next `commit`
+    /// (that covers them), or on drop, if `Builder::commit_on_drop` is enabled.
+    ///
+    /// `n` is truncated to the chunk size, and replaces the previously marked length.
+    /// Cheaper than `commit`: it does not touch the shared positions, and does not notify the Reader.
+    ///
+    /// Returns the number of marked elements.
+    pub fn commit_relaxed(&mut self, n: usize) -> usize {
+        self.pending = usize::min(self.write_capacity, n);
+        self.pending
     }
 
-    /// Write and commit a single element, or return it if the queue was full.
-    pub fn push(&mut self, t: T) -> Result<(), T> {
-        let chunk = self.write_chunk();
-        if !chunk.is_empty() {
-            chunk[0] = t;
-            self.commit(1);
-            Ok(())
-        } else {
-            Err(t)
+    /// Make every element of the slice returned by the last `write_chunk`
+    /// (or `limited_write_chunk`, `write_chunk_exact`) available for reading.
+    ///
+    /// Elements already committed from the slice are not committed again.
+    /// Unlike passing a length saved earlier to `commit`, this can't commit a stale length
+    /// after the chunk was acquired again.
+    ///
+    /// Returns the number of committed elements.
+    pub fn commit_all(&mut self) -> usize {
+        let n = self.write_capacity;
+        unsafe {
+            self.unchecked_commit(n);
         }
+        n
     }
 
-    #[inline]
-    fn write_pos(&self) -> &std::sync::atomic::AtomicU64 {
-        unsafe { &(*self.cb).write_position.0 }
+    /// Acquire a writable chunk (see `write_chunk`), pass it to `f`,
+    /// then commit the number of elements `f` returns (truncated to the chunk size).
+    ///
+    /// If `f` panics, nothing is committed, not even on drop (see `Builder::commit_on_drop`).
+    ///
+    /// Returns the number of committed elements.
+    pub fn write_with<F>(&mut self, f: F) -> usize
+    where
+        F: FnOnce(&mut [T]) -> usize,
+    {
+        let chunk = self.write_chunk();
+        let (begin, len) = (chunk.as_mut_ptr(), chunk.len());
+        // forget the chunk while `f` runs: an unwinding panic leaves nothing to commit
+        self.write_capacity = 0;
+        let n = f(unsafe { std::slice::from_raw_parts_mut(begin, len) });
+        self.write_capacity = len;
+        self.commit(n)
     }
 
-    #[inline]
-    fn read_pos(&self) -> &std::sync::atomic::AtomicU64 {
-        unsafe { &(*self.cb).read_position.0 }
+    /// Stream over successive writable chunks of at most `max` elements.
+    ///
+    /// Each chunk is committed when the next one is requested, see `chunks::ChunksMut`.
+    pub fn chunks_mut(&mut self, max: usize) -> chunks::ChunksMut<'_, T> {
+        chunks::ChunksMut::new(self, max)
     }
-}
 
-unsafe impl<T> Send for Writer<T> {}
+    /// Number of records committed (each non-empty `commit` is a record) or dropped so far.
+    ///
+    /// Monotonically increasing, shared with the Reader.
+    /// Committed records are counted only if `Builder::sequence` is enabled.
+    pub fn sequence(&self) -> u64 {
+        self.seq().load(Ordering::Relaxed)
+    }
 
-/// Reader of a Cueue.
+    /// The clock convention of the queue, see `Builder::clock`.
+    pub fn clock_info(&self) -> clock::ClockInfo {
+        unsafe { (*self.cb).clock_info() }
+    }
+
+    /// Current time of the clock of the queue, in nanoseconds: use it to stamp records.
+    pub fn timestamp(&self) -> u64 {
+        self.clock_info().clock.now()
+    }
+
+    /// Record that `n` records were dropped instead of being committed
+    /// (e.g: because the queue was full, and the writer decided to shed load).
+    ///
+    /// Dropped records advance the sequence number,
+    /// and are reported to the Reader by `Reader::gap`.
+    pub fn record_dropped(&mut self, n: u64) {
+        let seq = self.seq().load(Ordering::Relaxed);
+        let dropped = self.dropped().load(Ordering::Relaxed);
+        self.seq().store(seq + n, Ordering::Relaxed);
+        self.dropped().store(dropped + n, Ordering::Release);
+    }
+
+    /// Record the current process as the process using this Writer.
+    ///
+    /// The process that created the queue is recorded by default. Call this, if the Writer
+    /// is used by a different process (e.g: a child, after `fork`): a Reader blocked
+    /// in another process on the futex of the queue (see `Builder::futex`) is woken up,
+    /// and finds the Writer abandoned, if this process dies without dropping the Writer.
+    /// A Writer inherited by `fork` is usable after this call only, see `check_fork`.
+    pub fn register_process(&mut self) {
+        let pid = std::process::id() as u64;
+        unsafe { (*self.cb).writer_pid.0.store(pid, Ordering::Relaxed) };
+        self.generation = fork::generation();
+    }
+
+    /// Returns Err, if this Writer was inherited by `fork` from the process using it,
+    /// and the child did not take it over by `register_process`.
+    ///
+    /// The queue is shared with the parent process, that might still use it,
+    /// therefore an inherited Writer is inert: `write_chunk` returns an empty chunk,
+    /// `commit` does nothing, the queue is found abandoned (see `is_abandoned`),
+    /// and it is not closed when the Writer is dropped.
+    pub fn check_fork(&self) -> Result<(), Forked> {
+        if fork::is_forked(self.generation) {
+            return Err(Forked);
+        }
+        Ok(())
+    }
+
+    /// Returns a file descriptor that becomes readable when the Reader commits
+    /// (i.e: when the queue might have space to write, see `set_notify_watermark`) or the Reader is dropped,
+    /// or None, if notification was not enabled by `Builder::notify`.
+    ///
+    /// The descriptor can be registered with any reactor (e.g: epoll, kqueue, or async runtimes).
+    /// It remains readable until `clear_readiness` is called.
+    pub fn readiness_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.notify.as_ref().map(|n| n.event().as_fd())
+    }
+
+    /// Make `readiness_fd` non-readable, until the next notification.
+    ///
+    /// To avoid missing notifications, call this before checking `write_chunk`,
+    /// and wait for readiness only if the queue was found full.
+    pub fn clear_readiness(&self) {
+        if let Some(notify) = &self.notify {
+            notify.event().clear();
+        }
+    }
+
+    /// Notify the Writer on commit only if at least `n` elements are free in the queue,
+    /// instead of on every commit of the Reader.
+    ///
+    /// Reduces the number of wakeups, if the Reader consumes small chunks.
+    /// `n` is clamped to the capacity. The Writer is still notified when the Reader is dropped.
+    /// Has no effect, if notification was not enabled by `Builder::notify`.
+    pub fn set_notify_watermark(&mut self, n: usize) {
+        let n = usize::min(n, self.capacity()) as u64;
+        unsafe { (*self.cb).write_watermark.0.store(n, Ordering::Relaxed) };
+    }
+
+    /// Register a callback, called each time `write_chunk` finds the queue full.
+    ///
+    /// Allows implementing custom backpressure or load shedding policies,
+    /// or simply counting the events, without wrapping every call site.
+    /// The callback is called on the writer thread, and should be lightweight.
+    /// Replaces the previously registered callback, if any.
+    pub fn on_full(&mut self, callback: impl FnMut() + Send + 'static) {
+        self.on_full = Some(Box::new(callback));
+    }
+
+    /// Register a callback, called each time a commit makes the write position cross
+    /// the end of the buffer (i.e: the queue wraps around).
+    ///
+    /// The callback gets the number of cycles the write position completed so far,
+    /// e.g: to flush a downstream sink, or to rotate an output file, once per cycle of the buffer.
+    /// The callback is called on the writer thread, after the elements of the commit
+    /// are made available for reading. Replaces the previously registered callback, if any.
+    pub fn on_wrap(&mut self, callback: impl FnMut(u64) + Send + 'static) {
+        self.on_wrap = Some(Box::new(callback));
+    }
+
+    /// Register a callback, called with true, when the number of unread elements
+    /// reaches `high`, then with false, when it drops back to `low` (or below).
+    ///
+    /// Allows pausing and resuming an upstream source with hysteresis, instead of
+    /// reacting to every full/empty transition. The occupancy is checked by `write_chunk`
+    /// (and the operations built on it, e.g: `push`) and `is_backpressured`,
+    /// the callback is called on the writer thread.
+    /// Replaces the previously registered callback, and resets the state.
+    ///
+    /// Panics, if `low` is not less than `high`.
+    pub fn set_backpressure(
+        &mut self,
+        high: usize,
+        low: usize,
+        callback: impl FnMut(bool) + Send + 'static,
+    ) {
+        assert!(
+            low < high,
+            "low watermark must be less than the high watermark"
+        );
+        self.backpressure = Some(Backpressure {
+            high: high as u64,
+            low: low as u64,
+            engaged: false,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Check the occupancy of the queue, and return true, if the high watermark
+    /// set by `set_backpressure` was reached, and the occupancy did not drop
+    /// to the low watermark since.
+    ///
+    /// Returns false, if no watermarks are set.
+    pub fn is_backpressured(&mut self) -> bool {
+        let w = self.write_pos().load(Ordering::Relaxed);
+        let r = self.read_pos().load(Ordering::Acquire) & !READER_BUSY;
+        match &mut self.backpressure {
+            Some(backpressure) => {
+                backpressure.update(w - r);
+                backpressure.engaged
+            }
+            None => false,
+        }
+    }
+
+    /// Finish the stream with the given status, and drop the Writer.
+    ///
+    /// Elements committed before are still available for reading.
+    /// The Reader can tell a clean end of stream from a failed producer
+    /// (or a Writer dropped without calling `finish`) using `Reader::completion`.
+    /// See `Builder::drain_on_drop` to wait for the Reader to consume them.
+    pub fn finish(mut self, completion: Completion) {
+        if self.commit_on_drop {
+            // the elements must be visible before the end of the stream, not after it
+            self.commit_pending();
+            self.commit_on_drop = false;
+        }
+        let (status, code) = completion.encode();
+        unsafe {
+            (*self.cb).completion_code.0.store(code, Ordering::Relaxed);
+            (*self.cb).completion.0.store(status, Ordering::Release);
+        }
+    }
+
+    /// Write and commit a single element, or return it if the queue was full.
+    ///
+    /// If the queue is full, the full policy decides, see `Builder::full_policy`.
+    /// With `FullPolicy::Block`, the element is returned if the Reader is dropped.
+    /// With the drop policies, it is never returned.
+    pub fn push(&mut self, t: T) -> Result<(), T> {
+        self.push_with_policy(t).map(|_| ())
+    }
+
+    /// Write and commit a single element, waiting for the Reader to make space, if needed,
+    /// until `timeout` elapses.
+    ///
+    /// Ignores the full policy. Uses the wait strategy of the Writer (see `set_wait_strategy`)
+    /// between checks, or sleeps until the Reader commits, if `Builder::futex` is enabled.
+    /// Returns the element, if `timeout` elapsed, or the Reader was dropped.
+    pub fn push_blocking(&mut self, t: T, timeout: Duration) -> Result<(), T> {
+        let deadline = Instant::now() + timeout;
+        let mut iteration = 0;
+        loop {
+            let r = self.read_pos().load(Ordering::Acquire);
+            if let Some(slot) = self.write_chunk().first_mut() {
+                *slot = t;
+                self.commit(1);
+                return Ok(());
+            }
+            if self.is_abandoned() || Instant::now() >= deadline {
+                return Err(t);
+            }
+            self.wait_for_reader(r, iteration, Some(deadline));
+            iteration = iteration.saturating_add(1);
+        }
+    }
+
+    /// Move elements from `iter` into the writable chunk, until either the chunk
+    /// or `iter` is exhausted, then commit them, in a single write_chunk/commit cycle.
+    ///
+    /// Returns the number of elements written. Elements that did not fit are left in `iter`:
+    /// no element is taken from it, if there's no space for it. Unlike `push_many`,
+    /// ignores the full policy: a building block for generator-style producers.
+    pub fn write_iter<I>(&mut self, iter: &mut I) -> usize
+    where
+        I: Iterator<Item = T>,
+    {
+        let chunk = self.write_chunk();
+        let mut n = 0;
+        // the chunk comes first: do not take an element from `iter` if there's no space for it
+        for (slot, t) in chunk.iter_mut().zip(&mut *iter) {
+            *slot = t;
+            n += 1;
+        }
+        self.commit(n)
+    }
+
+    /// Write and commit as many elements from `iter` as fit in the queue,
+    /// in a single write_chunk/commit cycle, see `write_iter`.
+    ///
+    /// Returns the number of elements written. Elements that did not fit
+    /// are left in `iter`.
+    ///
+    /// Unless the full policy is `FullPolicy::ReturnEmpty`, the remaining elements are pushed
+    /// one by one, honoring the policy, until `iter` is exhausted (or the Reader is dropped,
+    /// with `FullPolicy::Block`). Dropped elements are not counted as written.
+    pub fn push_many<I>(&mut self, iter: &mut I) -> usize
+    where
+        I: Iterator<Item = T>,
+    {
+        let mut n = self.write_iter(iter);
+        if self.full_policy != FullPolicy::ReturnEmpty {
+            for t in iter {
+                match self.push_with_policy(t) {
+                    Ok(true) => n += 1,
+                    Ok(false) => {}
+                    Err(_) => break,
+                }
+            }
+        }
+        n
+    }
+
+    /// Set the strategy `FullPolicy::Block` uses to wait for the Reader.
+    ///
+    /// By default, `wait::HybridWait` is used. If `Builder::futex` is enabled,
+    /// the strategy decides when to sleep on the futex instead, see `WaitStrategy::park`.
+    pub fn set_wait_strategy(&mut self, strategy: impl wait::WaitStrategy + 'static) {
+        self.wait = Box::new(strategy);
+    }
+
+    /// Push `t`, honoring the full policy.
+    /// Returns true if `t` was written, false if it was dropped.
+    fn push_with_policy(&mut self, t: T) -> Result<bool, T> {
+        let mut iteration = 0;
+        loop {
+            let r = self.read_pos().load(Ordering::Acquire);
+            if let Some(slot) = self.write_chunk().first_mut() {
+                *slot = t;
+                self.commit(1);
+                return Ok(true);
+            }
+            match self.full_policy {
+                FullPolicy::ReturnEmpty => return Err(t),
+                FullPolicy::Block => {
+                    if self.is_abandoned() {
+                        return Err(t);
+                    }
+                    self.wait_for_reader(r, iteration, None);
+                    iteration = iteration.saturating_add(1);
+                }
+                FullPolicy::DropNewest => {
+                    self.record_dropped(1);
+                    return Ok(false);
+                }
+                FullPolicy::DropOldest => {
+                    if !self.drop_oldest() {
+                        self.record_dropped(1);
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drop the oldest unread element, unless the Reader holds it.
+    ///
+    /// Returns false, if the Reader holds a chunk,
+    /// true if the element was dropped, or the Reader committed meanwhile.
+    fn drop_oldest(&mut self) -> bool {
+        let r = self.read_pos().load(Ordering::Acquire);
+        if r & READER_BUSY != 0 {
+            return false;
+        }
+        if r == self.write_pos().load(Ordering::Relaxed) {
+            return true;
+        }
+        match self
+            .read_pos()
+            .compare_exchange(r, r + 1, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => {
+                let dropped = self.dropped().load(Ordering::Relaxed);
+                self.dropped().store(dropped + 1, Ordering::Release);
+                true
+            }
+            Err(current) => current & READER_BUSY == 0,
+        }
+    }
+}
+
+impl<T> Writer<T> {
+    /// Flush the elements and the control block of a file backed queue to storage.
+    ///
+    /// Called on commit and on drop according to the durability setting, see `Builder::durability`.
+    pub fn sync(&mut self) {
+        self.sync_all();
+        self.synced = Instant::now();
+    }
+
+    /// Returns true, if the Reader counterpart was dropped (or its process died, see `register_process`),
+    /// or this Writer was inherited by `fork` (see `check_fork`).
+    pub fn is_abandoned(&self) -> bool {
+        fork::is_forked(self.generation) || self.closed() & abi::READER_CLOSED != 0
+    }
+
+    /// Wait until the Reader consumes every committed element, or `timeout` elapses.
+    ///
+    /// Uses the wait strategy of the Writer (see `set_wait_strategy`) between checks,
+    /// or sleeps until the Reader commits, if `Builder::futex` is enabled.
+    /// Returns true, if the queue is empty, false, if `timeout` elapsed,
+    /// or the Reader was dropped, leaving elements unread.
+    pub fn wait_empty(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let w = self.write_pos().load(Ordering::Relaxed);
+        let mut iteration = 0;
+        loop {
+            let r = self.read_pos().load(Ordering::Acquire);
+            if r & !READER_BUSY == w {
+                return true;
+            }
+            if self.is_abandoned() || Instant::now() >= deadline {
+                return false;
+            }
+            self.wait_for_reader(r, iteration, Some(deadline));
+            iteration = iteration.saturating_add(1);
+        }
+    }
+
+    /// Wait for the Reader to commit, after the read position `r` was observed,
+    /// on the futex of the queue (see `Builder::futex`), or using the wait strategy.
+    fn wait_for_reader(&mut self, r: u64, iteration: u32, deadline: Option<Instant>) {
+        if !self.futex || !self.wait.park(iteration) {
+            self.wait.wait(iteration, deadline);
+            return;
+        }
+        let cb = unsafe { &*self.cb };
+        let pid = cb.reader_pid.0.load(Ordering::Relaxed);
+        let ready = || {
+            cb.read_position.0.load(Ordering::Relaxed) != r
+                || cb.closed.0.load(Ordering::Relaxed) & abi::READER_CLOSED != 0
+        };
+        if futex::wait_peer(
+            &mut self.peer,
+            pid,
+            &cb.writer_waiting.0,
+            &cb.read_epoch.0,
+            deadline,
+            ready,
+        ) {
+            // the dead process can't mark its Reader closed
+            cb.closed.0.fetch_or(abi::READER_CLOSED, Ordering::Release);
+        }
+    }
+
+    /// Commit the elements marked by `commit_relaxed`, see `Builder::commit_on_drop`.
+    fn commit_pending(&mut self) {
+        let n = usize::min(self.pending, self.write_capacity);
+        unsafe { self.unchecked_commit(n) };
+    }
+
+    unsafe fn unchecked_commit(&mut self, n: usize) {
+        if fork::is_forked(self.generation) {
+            return;
+        }
+        let w = self.write_pos().load(Ordering::Relaxed);
+        if n != 0 && self.durability == Durability::OnCommit {
+            // the elements must be persisted before the position that makes them visible
+            self.sync_range(self.write_begin.cast(), n * std::mem::size_of::<T>());
+        }
+        self.write_begin = self.write_begin.add(n);
+        self.write_capacity -= n;
+        self.pending = self.pending.saturating_sub(n);
+        if n != 0 && self.sequence {
+            let seq = self.seq().load(Ordering::Relaxed);
+            self.seq().store(seq + 1, Ordering::Relaxed);
+        }
+        self.write_pos().store(w + n as u64, Ordering::Release);
+        if n != 0 {
+            // as of the read position loaded by the last `write_chunk`
+            let occupancy = w + n as u64 - self.seen_read_pos;
+            if occupancy > self.peak_occupancy {
+                self.peak_occupancy = occupancy;
+                (*self.cb)
+                    .peak_occupancy
+                    .0
+                    .store(occupancy, Ordering::Relaxed);
+            }
+            match self.durability {
+                Durability::None => {}
+                Durability::OnCommit => {
+                    self.sync_range(self.cb.cast(), std::mem::size_of::<ControlBlock>());
+                }
+                Durability::Interval(interval) => {
+                    if self.synced.elapsed() >= interval {
+                        self.sync();
+                    }
+                }
+            }
+            if let Some(notify) = &self.notify {
+                // a stale read position overestimates the available elements: never misses a wakeup
+                let r = self.read_pos().load(Ordering::Relaxed) & !READER_BUSY;
+                let available = w + n as u64 - r;
+                if available >= (*self.cb).read_watermark.0.load(Ordering::Relaxed) {
+                    rt_check!("readiness notification");
+                    notify.notify_peer();
+                }
+            }
+            if self.futex {
+                let cb = &*self.cb;
+                futex::wake(&cb.reader_waiting.0, &cb.write_epoch.0);
+            }
+            let end = w + n as u64;
+            if end & !self.mask != w & !self.mask {
+                if let Some(on_wrap) = &mut self.on_wrap {
+                    on_wrap(end / (self.mask + 1));
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn write_pos(&self) -> &std::sync::atomic::AtomicU64 {
+        unsafe { &(*self.cb).write_position.0 }
+    }
+
+    #[inline]
+    fn read_pos(&self) -> &std::sync::atomic::AtomicU64 {
+        unsafe { &(*self.cb).read_position.0 }
+    }
+
+    #[inline]
+    fn seq(&self) -> &std::sync::atomic::AtomicU64 {
+        unsafe { &(*self.cb).sequence.0 }
+    }
+
+    #[inline]
+    fn dropped(&self) -> &std::sync::atomic::AtomicU64 {
+        unsafe { &(*self.cb).dropped.0 }
+    }
+
+    #[inline]
+    fn closed(&self) -> u64 {
+        unsafe { (*self.cb).closed.0.load(Ordering::Acquire) }
+    }
+
+    /// Flush the buffer and the control block to storage.
+    fn sync_all(&self) {
+        let size = self.mem.cap * std::mem::size_of::<T>();
+        unsafe {
+            self.sync_range(self.buffer.cast(), size);
+            self.sync_range(self.cb.cast(), std::mem::size_of::<ControlBlock>());
+        }
+    }
+
+    /// Flush the pages (or cache lines, if the file is mapped by DAX) of `len` bytes at `addr` to storage.
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    unsafe fn sync_range(&self, addr: *const u8, len: usize) {
+        if self.dax {
+            flush_cache_lines(addr, len);
+            return;
+        }
+        rt_check!("msync");
+        let pagesize = self.mem.pagesize;
+        let begin = addr as usize & !(pagesize - 1);
+        let end = addr as usize + len;
+        libc::msync(begin as *mut c_void, end - begin, libc::MS_SYNC);
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "nto")))]
+    unsafe fn sync_range(&self, _addr: *const u8, _len: usize) {
+        todo!("Only Linux, macOS and QNX are supported so far");
+    }
+}
+
+impl<T> Drop for Writer<T> {
+    fn drop(&mut self) {
+        if fork::is_forked(self.generation) {
+            return;
+        }
+        if self.commit_on_drop {
+            self.commit_pending();
+        }
+        if let Some(timeout) = self.drain_on_drop {
+            self.wait_empty(timeout);
+        }
+        if let Durability::Interval(_) = self.durability {
+            self.sync_all();
+        }
+        let cb = unsafe { &*self.cb };
+        cb.closed.0.fetch_or(abi::WRITER_CLOSED, Ordering::Release);
+        if self.futex {
+            futex::wake_all(&cb.write_epoch.0);
+        }
+    }
+}
+
+impl Writer<u8> {
+    /// Enqueue every byte of `data`, waiting for the Reader to make space, if needed,
+    /// until `timeout` elapses.
+    ///
+    /// The bytes are committed as soon as they fit: a large `data` is passed in multiple chunks.
+    /// Uses the wait strategy of the Writer (see `set_wait_strategy`) between checks,
+    /// or sleeps until the Reader commits, if `Builder::futex` is enabled.
+    ///
+    /// Returns the number of enqueued bytes: less than the length of `data`,
+    /// if `timeout` elapsed, or the Reader was dropped.
+    pub fn write_all_blocking(&mut self, data: &[u8], timeout: Duration) -> usize {
+        let deadline = Instant::now() + timeout;
+        let mut written = 0;
+        let mut iteration = 0;
+        while written < data.len() {
+            let r = self.read_pos().load(Ordering::Acquire);
+            let rest = &data[written..];
+            let chunk = self.limited_write_chunk(rest.len());
+            if !chunk.is_empty() {
+                let n = chunk.len();
+                chunk.copy_from_slice(&rest[..n]);
+                written += self.commit(n);
+                iteration = 0;
+                continue;
+            }
+            if self.is_abandoned() || Instant::now() >= deadline {
+                break;
+            }
+            self.wait_for_reader(r, iteration, Some(deadline));
+            iteration = iteration.saturating_add(1);
+        }
+        written
+    }
+}
+
+unsafe impl<T> Send for Writer<T> {}
+
+/// Reader of a Cueue.
 ///
 /// See examples/ for usage.
 pub struct Reader<T> {
@@ -411,6 +1782,22 @@ pub struct Reader<T> {
     buffer: *const T,
     read_begin: *const T,
     read_size: u64,
+    seen_dropped: u64,
+
+    release_consumed: bool,
+    drop_consumed: bool,
+    /// mark the held chunk in the read position, see `FullPolicy::DropOldest`
+    mark_busy: bool,
+    on_empty: Option<Hook>,
+    wait: Box<dyn wait::WaitStrategy>,
+    futex: bool,
+    peer: futex::Peer,
+    /// see `Writer::check_fork`
+    generation: u64,
+
+    // Must be declared after `mem`: notifies the Writer on drop,
+    // that must observe the Reader abandoned by then.
+    notify: Option<notify::Notify>,
 }
 
 impl<T> Reader<T>
@@ -421,6 +1808,8 @@ where
         mem: std::sync::Arc<MemoryMapInitialized<T>>,
         buffer: *const T,
         capacity: usize,
+        full_policy: FullPolicy,
+        notify: Option<notify::Notify>,
     ) -> Self {
         let cb = mem.controlblock();
         Self {
@@ -430,6 +1819,16 @@ where
             buffer,
             read_begin: std::ptr::null(),
             read_size: 0,
+            seen_dropped: 0,
+            release_consumed: false,
+            drop_consumed: false,
+            mark_busy: full_policy == FullPolicy::DropOldest,
+            on_empty: None,
+            wait: Box::<wait::HybridWait>::default(),
+            futex: false,
+            peer: futex::Peer::default(),
+            generation: fork::generation(),
+            notify,
         }
     }
 
@@ -439,10 +1838,60 @@ where
         (self.mask + 1) as usize
     }
 
+    /// See `Writer::capacity_bytes`.
+    pub fn capacity_bytes(&self) -> usize {
+        self.capacity() * self.element_size()
+    }
+
+    /// See `Writer::element_size`.
+    pub fn element_size(&self) -> usize {
+        std::mem::size_of::<T>()
+    }
+
+    /// See `Writer::memory_footprint`.
+    pub fn memory_footprint(&self) -> usize {
+        self.page_size() + self.capacity_bytes()
+    }
+
+    /// The granularity of the layout of the referenced `cueue`:
+    /// the size of the control block and the alignment of the buffer.
+    ///
+    /// Either the system page size, or the value set by `Builder::page_size`.
+    pub fn page_size(&self) -> usize {
+        self.mem.pagesize
+    }
+
+    /// The kind of file backing the memory of the referenced `cueue`.
+    pub fn backend(&self) -> Backend {
+        self.mem.backing.backend
+    }
+
+    /// The file descriptor of the memory of the referenced `cueue`, to be inherited by a child process,
+    /// or None, unless `Builder::cloexec` was disabled.
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    pub fn inheritable_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.mem.fd.as_ref().map(|fd| fd.as_fd())
+    }
+
+    /// The decisions made while constructing the referenced `cueue`, see `BuildReport`.
+    pub fn build_report(&self) -> BuildReport {
+        self.mem.report
+    }
+
     /// Return a slice of elements written and committed by the Writer.
     pub fn read_chunk(&mut self) -> &[T] {
+        if fork::is_forked(self.generation) {
+            self.read_begin = self.buffer;
+            self.read_size = 0;
+            return &[];
+        }
+        let r = if self.mark_busy {
+            // the Writer does not drop the oldest elements while the Reader holds them
+            self.read_pos().fetch_or(READER_BUSY, Ordering::AcqRel) & !READER_BUSY
+        } else {
+            self.read_pos().load(Ordering::Relaxed)
+        };
         let w = self.write_pos().load(Ordering::Acquire);
-        let r = self.read_pos().load(Ordering::Relaxed);
 
         debug_assert!(r <= w);
         debug_assert!(r + self.capacity() as u64 >= w);
@@ -451,98 +1900,1791 @@ where
 
         self.read_size = w - r;
 
+        if self.read_size == 0 {
+            if let Some(on_empty) = &mut self.on_empty {
+                on_empty();
+            }
+        }
+
         unsafe {
             self.read_begin = self.buffer.offset(ri as isize);
             std::slice::from_raw_parts(self.read_begin, self.read_size as usize)
         }
     }
 
-    /// Mark the slice previously acquired by `read_chunk` as consumed,
-    /// making it available for writing.
-    pub fn commit(&mut self) {
-        let r = self.read_pos().load(Ordering::Relaxed);
-        let rs = self.read_size;
-        self.read_pos().store(r + rs, Ordering::Release);
+    /// Like `read_chunk`, but allows moving the elements out (e.g: by `resize`).
+    fn read_chunk_mut(&mut self) -> &mut [T] {
+        let len = self.read_chunk().len();
+        unsafe { std::slice::from_raw_parts_mut(self.read_begin as *mut T, len) }
     }
 
-    /// Returns true, if the Writer counterpart was dropped.
-    pub fn is_abandoned(&self) -> bool {
-        std::sync::Arc::strong_count(&self.mem) < 2
+    /// Return a slice of elements written and committed by the Writer,
+    /// but at most `n` elements.
+    ///
+    /// See `read_chunk`. `commit` consumes the returned elements only.
+    pub fn limited_read_chunk(&mut self, n: usize) -> &[T] {
+        let available = self.read_chunk().len();
+        self.read_size = usize::min(available, n) as u64;
+        unsafe { std::slice::from_raw_parts(self.read_begin, self.read_size as usize) }
     }
 
-    #[inline]
-    fn write_pos(&self) -> &std::sync::atomic::AtomicU64 {
-        unsafe { &(*self.cb).write_position.0 }
+    /// Return a slice of exactly `n` elements written and committed by the Writer,
+    /// or None, if less elements are available.
+    ///
+    /// See `read_chunk`. `commit` consumes the returned elements only (or nothing, if None was returned).
+    pub fn read_chunk_exact(&mut self, n: usize) -> Option<&[T]> {
+        if self.read_chunk().len() < n {
+            self.read_size = 0;
+            return None;
+        }
+        self.read_size = n as u64;
+        unsafe { Some(std::slice::from_raw_parts(self.read_begin, n)) }
     }
 
-    #[inline]
-    fn read_pos(&self) -> &std::sync::atomic::AtomicU64 {
-        unsafe { &(*self.cb).read_position.0 }
+    /// Copy out and consume exactly `N` elements, or None (consuming nothing),
+    /// if less elements are available.
+    ///
+    /// Convenient for processing fixed size groups, e.g: the samples of a tick.
+    pub fn pop_array<const N: usize>(&mut self) -> Option<[T; N]>
+    where
+        T: Copy,
+    {
+        let array = <[T; N]>::try_from(self.read_chunk_exact(N)?).ok()?;
+        self.commit();
+        Some(array)
     }
-}
 
-unsafe impl<T> Send for Reader<T> {}
+    /// Move up to `buf.len()` elements out of the queue into `buf`, and consume them,
+    /// without allocating. The moved elements are replaced by default values in the queue.
+    ///
+    /// Returns the number of elements moved: `buf[..n]` is initialized.
+    pub fn pop_n(&mut self, buf: &mut [std::mem::MaybeUninit<T>]) -> usize {
+        let chunk = self.read_chunk_mut();
+        let n = usize::min(chunk.len(), buf.len());
+        for (slot, t) in buf.iter_mut().zip(&mut chunk[..n]) {
+            slot.write(std::mem::take(t));
+        }
+        self.read_size = n as u64;
+        self.commit();
+        n
+    }
 
-/// Create a single-producer, single-consumer `Cueue`.
-///
-/// The `requested_capacity` is a lower bound of the actual capacity
-/// of the constructed queue: it might be rounded up to match system requirements
-/// (power of two, multiple of page size).
-///
-/// `requested_capacity` must not be bigger than 2^63.
-///
-/// On success, returns a `(Writer, Reader)` pair, that share the ownership
-/// of the underlying circular array.
-#[cfg(any(target_os = "linux", target_os = "macos"))]
-pub fn cueue<T>(requested_capacity: usize) -> Result<(Writer<T>, Reader<T>), CError>
-where
-    T: Default,
-{
-    let pagesize = unsafe { sysconf(_SC_PAGESIZE) as usize };
-    let capacity = next_power_two(usize::max(requested_capacity, pagesize))?;
-    let cbsize = pagesize;
+    /// Return the last consumed elements, kept in the queue by `Builder::retention`,
+    /// oldest first: at most as many as the retention window.
+    pub fn retained(&self) -> &[T] {
+        let r = self.read_pos().load(Ordering::Relaxed) & !READER_BUSY;
+        let retention = unsafe { (*self.cb).retention.0.load(Ordering::Relaxed) };
+        let n = u64::min(retention, r);
+        unsafe {
+            // the Writer does not overwrite them, and they are contiguous thanks to the mirrored mapping
+            let begin = self.buffer.add(((r - n) & self.mask) as usize);
+            std::slice::from_raw_parts(begin, n as usize)
+        }
+    }
 
-    if std::mem::size_of::<ControlBlock>() > pagesize {
-        return Err(CError {
-            hint: "ControlBlock does not fit in a single page",
-            err: std::io::ErrorKind::Other.into(),
-        });
+    /// Discard every element available to read in one step, skipping the backlog.
+    ///
+    /// Useful for consumers that only care about the freshest data (e.g: dashboards),
+    /// after a stall. The discarded elements are not reported by `gap`.
+    ///
+    /// Returns the number of discarded elements.
+    pub fn seek_to_latest(&mut self) -> usize {
+        let n = self.read_chunk().len();
+        self.commit();
+        n
     }
 
-    let (initmap, buffer) = unsafe {
-        let f = memoryfile()?;
-        let bufsize = capacity * std::mem::size_of::<T>();
-        if ftruncate(f.as_raw_fd(), (cbsize + bufsize) as i64) != 0 {
-            return Err(CError::new("ftruncate"));
+    /// Stream over successive readable chunks of at most `max` elements.
+    ///
+    /// Each chunk is consumed when the next one is requested, see `chunks::Chunks`.
+    pub fn chunks(&mut self, max: usize) -> chunks::Chunks<'_, T> {
+        chunks::Chunks::new(self, max)
+    }
+
+    /// Wait until at least `n` elements are available, then return a slice of exactly `n` elements.
+    ///
+    /// Uses the wait strategy of the Reader (see `set_wait_strategy`) between checks,
+    /// or sleeps until the Writer commits, if `Builder::futex` is enabled.
+    /// Returns None, if `n` elements are not available until `timeout` elapses,
+    /// or the Writer is dropped with less than `n` elements available.
+    /// See `read_chunk_exact`.
+    pub fn read_exact_timeout(&mut self, n: usize, timeout: Duration) -> Option<&[T]> {
+        let deadline = Instant::now() + timeout;
+        let mut iteration = 0;
+        loop {
+            let abandoned = self.is_abandoned();
+            let w = self.write_pos().load(Ordering::Acquire);
+            if self.read_chunk().len() >= n || abandoned || Instant::now() >= deadline {
+                break;
+            }
+            self.wait_for_writer(w, iteration, Some(deadline));
+            iteration = iteration.saturating_add(1);
         }
-        let map = doublemap(f.as_raw_fd(), cbsize, bufsize)?;
+        self.read_chunk_exact(n)
+    }
 
-        // initialize control block
-        let cbp = map.ptr() as *mut ControlBlock;
-        cbp.write(ControlBlock::default());
+    /// Wait until elements are available, then return them, see `read_chunk`.
+    ///
+    /// Uses the wait strategy of the Reader (see `set_wait_strategy`) between checks,
+    /// or sleeps until the Writer commits, if `Builder::futex` is enabled.
+    /// Returns Err, if the queue is empty, and the Writer is dropped (or its process died,
+    /// see `Writer::register_process`).
+    pub fn read_chunk_blocking(&mut self) -> Result<&[T], Abandoned> {
+        let mut iteration = 0;
+        loop {
+            let abandoned = self.is_abandoned();
+            let w = self.write_pos().load(Ordering::Acquire);
+            if !self.read_chunk().is_empty() {
+                break;
+            }
+            if abandoned {
+                return Err(Abandoned);
+            }
+            self.wait_for_writer(w, iteration, None);
+            iteration = iteration.saturating_add(1);
+        }
+        Ok(self.read_chunk())
+    }
 
-        // default initialize elems.
-        // this is required to make sure writer always sees initialized elements
-        let buffer = map.ptr().add(cbsize).cast::<T>();
-        let initmap = MemoryMapInitialized::new(map, buffer, capacity);
+    /// Wait until at least `n` elements are available, then return every readable element,
+    /// see `read_chunk`.
+    ///
+    /// Guarantees a minimum batch, to amortize a per-batch cost (e.g: a syscall) over `n` elements.
+    /// `n` larger than the capacity is treated as the capacity: a full queue is the largest batch.
+    /// Uses the wait strategy of the Reader (see `set_wait_strategy`) between checks,
+    /// or sleeps until the Writer commits, if `Builder::futex` is enabled.
+    /// If the Writer is dropped with less than `n` elements available, returns the remaining ones,
+    /// Err, if none remains.
+    pub fn read_at_least(&mut self, n: usize) -> Result<&[T], Abandoned> {
+        let n = usize::min(n, self.capacity());
+        let mut iteration = 0;
+        loop {
+            let abandoned = self.is_abandoned();
+            let w = self.write_pos().load(Ordering::Acquire);
+            let available = self.read_chunk().len();
+            if available >= n {
+                break;
+            }
+            if abandoned {
+                if available == 0 {
+                    return Err(Abandoned);
+                }
+                break;
+            }
+            self.wait_for_writer(w, iteration, None);
+            iteration = iteration.saturating_add(1);
+        }
+        Ok(self.read_chunk())
+    }
 
-        (initmap, buffer)
-    };
-    let shared_map = std::sync::Arc::new(initmap);
+    /// Wait for the Writer to commit, after the write position `w` was observed,
+    /// on the futex of the queue (see `Builder::futex`), or using the wait strategy.
+    fn wait_for_writer(&mut self, w: u64, iteration: u32, deadline: Option<Instant>) {
+        if !self.futex || !self.wait.park(iteration) {
+            self.wait.wait(iteration, deadline);
+            return;
+        }
+        let cb = unsafe { &*self.cb };
+        let pid = cb.writer_pid.0.load(Ordering::Relaxed);
+        let ready = || {
+            cb.write_position.0.load(Ordering::Relaxed) != w
+                || cb.closed.0.load(Ordering::Relaxed) & abi::WRITER_CLOSED != 0
+        };
+        if futex::wait_peer(
+            &mut self.peer,
+            pid,
+            &cb.reader_waiting.0,
+            &cb.write_epoch.0,
+            deadline,
+            ready,
+        ) {
+            // the dead process can't mark its Writer closed
+            cb.closed.0.fetch_or(abi::WRITER_CLOSED, Ordering::Release);
+        }
+    }
 
-    Ok((
-        Writer::new(shared_map.clone(), buffer, capacity),
-        Reader::new(shared_map, buffer, capacity),
-    ))
-}
+    /// Set the strategy blocking operations use to wait for the Writer.
+    ///
+    /// By default, `wait::HybridWait` is used. If `Builder::futex` is enabled,
+    /// the strategy decides when to sleep on the futex instead, see `WaitStrategy::park`.
+    pub fn set_wait_strategy(&mut self, strategy: impl wait::WaitStrategy + 'static) {
+        self.wait = Box::new(strategy);
+    }
+
+    /// Mark the first `n` elements of the slice previously acquired by `read_chunk`
+    /// as consumed, making them available for writing, without checking `n`.
+    ///
+    /// # Safety
+    ///
+    /// `n` must not exceed the number of elements of the slice returned by the last `read_chunk`
+    /// (or `limited_read_chunk`, `read_chunk_exact`), and the slice must not be committed already.
+    /// Otherwise the Writer overwrites elements the Reader did not read,
+    /// or the Reader reads elements not written yet.
+    pub unsafe fn commit_read_unchecked(&mut self, n: usize) {
+        debug_assert!(n as u64 <= self.read_size);
+        self.read_size = n as u64;
+        self.commit();
+    }
+
+    /// Mark the slice previously acquired by `read_chunk` as consumed,
+    /// making it available for writing.
+    ///
+    /// The slice is consumed once: committing again without a new `read_chunk`
+    /// (or before the first one) consumes nothing.
+    pub fn commit(&mut self) {
+        if fork::is_forked(self.generation) {
+            return;
+        }
+        let rs = self.read_size;
+        if rs == 0 {
+            if self.mark_busy {
+                // release the chunk: a store could undo an element dropped by the Writer
+                self.read_pos().fetch_and(!READER_BUSY, Ordering::Release);
+            }
+            return;
+        }
+        let r = self.read_pos().load(Ordering::Relaxed) & !READER_BUSY;
+        if self.drop_consumed {
+            // must happen before publishing the new read position, as `release_consumed`
+            let consumed = self.read_begin as *mut T;
+            for i in 0..rs as usize {
+                unsafe { *consumed.add(i) = T::default() };
+            }
+        }
+        if self.release_consumed {
+            rt_check!("releasing consumed memory");
+            // the contents of a file given by the user, or the retained elements, must be kept
+            let retention = unsafe { (*self.cb).retention.0.load(Ordering::Relaxed) };
+            let remove = retention == 0
+                && matches!(
+                    self.backend(),
+                    Backend::Memfd | Backend::Shm | Backend::Tmpfile
+                );
+            // must happen before publishing the new read position:
+            // after that, the writer is free to write the released range.
+            unsafe {
+                release_pages(
+                    self.read_begin.cast::<u8>(),
+                    rs as usize * std::mem::size_of::<T>(),
+                    self.mem.pagesize,
+                    remove,
+                );
+            }
+        }
+        if self.mark_busy {
+            // never moves the read position backwards, past an element dropped by the Writer
+            let _ = self
+                .read_pos()
+                .fetch_update(Ordering::Release, Ordering::Relaxed, |current| {
+                    Some(u64::max(current & !READER_BUSY, r + rs))
+                });
+        } else {
+            self.read_pos().store(r + rs, Ordering::Release);
+        }
+        // a stale size would consume elements not read yet
+        self.read_size = 0;
+        if let Some(notify) = &self.notify {
+            // a stale write position overestimates the free space: never misses a wakeup
+            let w = self.write_pos().load(Ordering::Relaxed);
+            let free = self.capacity() as u64 - (w - r - rs);
+            if free >= unsafe { (*self.cb).write_watermark.0.load(Ordering::Relaxed) } {
+                rt_check!("readiness notification");
+                notify.notify_peer();
+            }
+        }
+        if self.futex {
+            let cb = unsafe { &*self.cb };
+            futex::wake(&cb.writer_waiting.0, &cb.read_epoch.0);
+        }
+    }
+
+    /// Acquire the readable chunk (see `read_chunk`), pass it to `f`,
+    /// then consume the number of elements `f` returns (truncated to the chunk size).
+    ///
+    /// If `f` panics, nothing is consumed.
+    ///
+    /// Returns the number of consumed elements.
+    pub fn read_with<F>(&mut self, f: F) -> usize
+    where
+        F: FnOnce(&[T]) -> usize,
+    {
+        let chunk = self.read_chunk();
+        let (begin, len) = (chunk.as_ptr(), chunk.len());
+        self.read_size = 0;
+        let n = usize::min(f(unsafe { std::slice::from_raw_parts(begin, len) }), len);
+        self.read_size = n as u64;
+        self.commit();
+        n
+    }
+
+    /// Record the current process as the process using this Reader, see `Writer::register_process`.
+    pub fn register_process(&mut self) {
+        let pid = std::process::id() as u64;
+        unsafe { (*self.cb).reader_pid.0.store(pid, Ordering::Relaxed) };
+        self.generation = fork::generation();
+    }
 
-#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    /// Returns Err, if this Reader was inherited by `fork`, see `Writer::check_fork`.
+    ///
+    /// An inherited Reader is inert: `read_chunk` returns an empty chunk, `commit` does nothing,
+    /// the queue is found abandoned, and it is not closed when the Reader is dropped.
+    pub fn check_fork(&self) -> Result<(), Forked> {
+        if fork::is_forked(self.generation) {
+            return Err(Forked);
+        }
+        Ok(())
+    }
+
+    /// Returns true, if the Writer counterpart was dropped (or its process died, see `register_process`),
+    /// or this Reader was inherited by `fork` (see `check_fork`).
+    pub fn is_abandoned(&self) -> bool {
+        fork::is_forked(self.generation) || self.closed() & abi::WRITER_CLOSED != 0
+    }
+
+    /// The sequence number of the Writer: the number of records committed or dropped so far,
+    /// see `Writer::sequence`.
+    pub fn sequence(&self) -> u64 {
+        self.seq().load(Ordering::Relaxed)
+    }
+
+    /// The clock convention of the queue, see `Builder::clock`.
+    pub fn clock_info(&self) -> clock::ClockInfo {
+        unsafe { (*self.cb).clock_info() }
+    }
+
+    /// Current time of the clock of the queue, in nanoseconds,
+    /// comparable to the timestamps of the Writer (see `Writer::timestamp`).
+    pub fn timestamp(&self) -> u64 {
+        self.clock_info().clock.now()
+    }
+
+    /// Returns a file descriptor that becomes readable when the Writer commits
+    /// (i.e: when the queue might have elements to read, see `set_notify_watermark`) or the Writer is dropped,
+    /// or None, if notification was not enabled by `Builder::notify`.
+    ///
+    /// The descriptor can be registered with any reactor (e.g: epoll, kqueue, or async runtimes).
+    /// It remains readable until `clear_readiness` is called.
+    pub fn readiness_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.notify.as_ref().map(|n| n.event().as_fd())
+    }
+
+    /// Make `readiness_fd` non-readable, until the next notification.
+    ///
+    /// To avoid missing notifications, call this before checking `read_chunk`,
+    /// and wait for readiness only if the queue was found empty.
+    pub fn clear_readiness(&self) {
+        if let Some(notify) = &self.notify {
+            notify.event().clear();
+        }
+    }
+
+    /// Notify the Reader on commit only if at least `n` elements are available to read,
+    /// instead of on every commit of the Writer.
+    ///
+    /// Reduces the number of wakeups, if the Writer commits small chunks.
+    /// `n` is clamped to the capacity. The Reader is still notified when the Writer is dropped.
+    /// Has no effect, if notification was not enabled by `Builder::notify`.
+    pub fn set_notify_watermark(&mut self, n: usize) {
+        let n = usize::min(n, self.capacity()) as u64;
+        unsafe { (*self.cb).read_watermark.0.store(n, Ordering::Relaxed) };
+    }
+
+    /// Register a callback, called each time `read_chunk` finds the queue empty.
+    ///
+    /// The callback is called on the reader thread, and should be lightweight.
+    /// Replaces the previously registered callback, if any.
+    pub fn on_empty(&mut self, callback: impl FnMut() + Send + 'static) {
+        self.on_empty = Some(Box::new(callback));
+    }
+
+    /// Returns the status the Writer finished the stream with,
+    /// or None, if the Writer did not call `Writer::finish` (yet).
+    ///
+    /// Elements committed before finishing might be still unread:
+    /// the end of stream is reached if this returns Some, and the next
+    /// `read_chunk` is empty.
+    pub fn completion(&self) -> Option<Completion> {
+        unsafe {
+            let status = (*self.cb).completion.0.load(Ordering::Acquire);
+            let code = (*self.cb).completion_code.0.load(Ordering::Relaxed);
+            Completion::decode(status, code)
+        }
+    }
+
+    /// Returns the number of records dropped by the Writer
+    /// since the previous call of `gap` (or since the queue was created).
+    pub fn gap(&mut self) -> u64 {
+        let dropped = self.dropped().load(Ordering::Acquire);
+        let gap = dropped - self.seen_dropped;
+        self.seen_dropped = dropped;
+        gap
+    }
+
+    #[inline]
+    fn write_pos(&self) -> &std::sync::atomic::AtomicU64 {
+        unsafe { &(*self.cb).write_position.0 }
+    }
+
+    #[inline]
+    fn read_pos(&self) -> &std::sync::atomic::AtomicU64 {
+        unsafe { &(*self.cb).read_position.0 }
+    }
+
+    #[inline]
+    fn seq(&self) -> &std::sync::atomic::AtomicU64 {
+        unsafe { &(*self.cb).sequence.0 }
+    }
+
+    #[inline]
+    fn dropped(&self) -> &std::sync::atomic::AtomicU64 {
+        unsafe { &(*self.cb).dropped.0 }
+    }
+
+    #[inline]
+    fn closed(&self) -> u64 {
+        unsafe { (*self.cb).closed.0.load(Ordering::Acquire) }
+    }
+}
+
+impl<T> Drop for Reader<T> {
+    fn drop(&mut self) {
+        if fork::is_forked(self.generation) {
+            return;
+        }
+        let cb = unsafe { &*self.cb };
+        cb.closed.0.fetch_or(abi::READER_CLOSED, Ordering::Release);
+        if self.futex {
+            futex::wake_all(&cb.read_epoch.0);
+        }
+    }
+}
+
+impl Reader<u8> {
+    /// If enabled, `commit` returns the memory of fully consumed pages to the OS.
+    ///
+    /// Useful for very large queues that are mostly empty, but occasionally
+    /// have to buffer a large backlog: without this, the memory of the backlog
+    /// is held until the queue is dropped. Releasing the memory costs a syscall
+    /// per commit (if the committed slice contains at least a full page),
+    /// and the writer has to fault the pages in again when it reaches them.
+    ///
+    /// The contents of the pages are discarded only if the queue owns its memory
+    /// (i.e: not `Builder::file`, `Builder::build_on_fd` or `from_raw_parts`),
+    /// and `Builder::retention` is not set: otherwise, only the mapping is released (Linux),
+    /// or nothing.
+    pub fn release_consumed_memory(&mut self, enable: bool) {
+        self.release_consumed = enable;
+    }
+
+    /// Return the complete records of the committed bytes, each terminated by `delim`
+    /// (e.g: the lines of a log, with `b'\n'`), holding back a partial record at the end.
+    ///
+    /// See `read_chunk`. `commit` consumes the returned records only: the partial record
+    /// is returned by a later call, once the Writer completed it. Split the records with
+    /// e.g: `split_inclusive`. If the queue is full, and contains no `delim`, the record
+    /// is longer than the capacity: the whole chunk is returned, unterminated,
+    /// otherwise the Reader would wait forever.
+    ///
+    ///```
+    /// let (mut w, mut r) = cueue::cueue::<u8>(1 << 12).unwrap();
+    /// w.write_chunk()[..11].copy_from_slice(b"foo\nbar\nbaz");
+    /// w.commit(11);
+    ///
+    /// assert_eq!(r.read_until(b'\n'), b"foo\nbar\n");
+    /// r.commit();
+    /// assert_eq!(r.read_until(b'\n'), b"");
+    ///```
+    pub fn read_until(&mut self, delim: u8) -> &[u8] {
+        let capacity = self.capacity();
+        let chunk = self.read_chunk();
+        let n = match chunk.iter().rposition(|&b| b == delim) {
+            Some(last) => last + 1,
+            None if chunk.len() == capacity => capacity,
+            None => 0,
+        };
+        self.limited_read_chunk(n)
+    }
+}
+
+unsafe impl<T> Send for Reader<T> {}
+
+/// The writing side of a queue.
+///
+/// Implemented by `Writer`, by `mock::MockWriter`, and by `fault::FaultyWriter`, to allow
+/// testing code generic over `Producer` without threads and mapped memory.
+pub trait Producer<T> {
+    /// See `Writer::capacity`.
+    fn capacity(&self) -> usize;
+
+    /// See `Writer::write_chunk`.
+    fn write_chunk(&mut self) -> &mut [T];
+
+    /// See `Writer::commit`.
+    fn commit(&mut self, n: usize) -> usize;
+
+    /// See `Writer::is_abandoned`.
+    fn is_abandoned(&self) -> bool;
+
+    /// See `Writer::push`.
+    fn push(&mut self, t: T) -> Result<(), T> {
+        let chunk = self.write_chunk();
+        if !chunk.is_empty() {
+            chunk[0] = t;
+            self.commit(1);
+            Ok(())
+        } else {
+            Err(t)
+        }
+    }
+}
+
+/// The reading side of a queue.
+///
+/// Implemented by `Reader`, by `mock::MockReader`, and by `fault::FaultyReader`, to allow
+/// testing code generic over `Consumer` without threads and mapped memory.
+pub trait Consumer<T> {
+    /// See `Reader::capacity`.
+    fn capacity(&self) -> usize;
+
+    /// See `Reader::read_chunk`.
+    fn read_chunk(&mut self) -> &[T];
+
+    /// See `Reader::commit`.
+    fn commit(&mut self);
+
+    /// See `Reader::is_abandoned`.
+    fn is_abandoned(&self) -> bool;
+}
+
+impl<T> Producer<T> for Writer<T>
+where
+    T: Default,
+{
+    fn capacity(&self) -> usize {
+        Writer::capacity(self)
+    }
+
+    fn write_chunk(&mut self) -> &mut [T] {
+        Writer::write_chunk(self)
+    }
+
+    fn commit(&mut self, n: usize) -> usize {
+        Writer::commit(self, n)
+    }
+
+    fn is_abandoned(&self) -> bool {
+        Writer::is_abandoned(self)
+    }
+
+    fn push(&mut self, t: T) -> Result<(), T> {
+        Writer::push(self, t)
+    }
+}
+
+impl<T> Consumer<T> for Reader<T>
+where
+    T: Default,
+{
+    fn capacity(&self) -> usize {
+        Reader::capacity(self)
+    }
+
+    fn read_chunk(&mut self) -> &[T] {
+        Reader::read_chunk(self)
+    }
+
+    fn commit(&mut self) {
+        Reader::commit(self)
+    }
+
+    fn is_abandoned(&self) -> bool {
+        Reader::is_abandoned(self)
+    }
+}
+
+/// Create a single-producer, single-consumer `Cueue`.
+///
+/// The `requested_capacity` is a lower bound of the actual capacity
+/// of the constructed queue: it might be rounded up to match system requirements
+/// (power of two, multiple of page size).
+///
+/// `requested_capacity` must not be bigger than 2^63, see `validated_capacity`.
+///
+/// On success, returns a `(Writer, Reader)` pair, that share the ownership
+/// of the underlying circular array.
+///
+/// See `Builder` for additional options.
 pub fn cueue<T>(requested_capacity: usize) -> Result<(Writer<T>, Reader<T>), CError>
 where
     T: Default,
 {
-    todo!("Only Linux and macOS are supported so far");
+    Builder::new(requested_capacity).build()
 }
 
+/// Returns `requested` rounded up to a power of two (the least capacity `cueue` allocates),
+/// panics if `requested` is zero, or larger than 2^63 (half of the address space).
+///
+/// In a const context, an invalid capacity fails the compilation.
+///
+///```
+/// const CAPACITY: usize = cueue::validated_capacity(1000);
+/// assert_eq!(CAPACITY, 1024);
+///```
+///
+///```compile_fail
+/// const CAPACITY: usize = cueue::validated_capacity(0);
+///```
+pub const fn validated_capacity(requested: usize) -> usize {
+    assert!(requested != 0, "the capacity must not be zero");
+    assert!(
+        requested <= usize::MAX / 2 + 1,
+        "the capacity must not be larger than 2^63"
+    );
+    requested.next_power_of_two()
+}
+
+/// Validates `requested` as `validated_capacity`, and also panics, if the size of
+/// the buffer of the rounded capacity of `T` elements overflows (i.e: larger than `isize::MAX`).
+///
+///```compile_fail
+/// const CAPACITY: usize = cueue::validated_capacity_of::<[u8; 1 << 20]>(1 << 50);
+///```
+pub const fn validated_capacity_of<T>(requested: usize) -> usize {
+    let capacity = validated_capacity(requested);
+    let size = std::mem::size_of::<T>();
+    assert!(
+        size == 0 || capacity <= isize::MAX as usize / size,
+        "the size of the buffer overflows"
+    );
+    capacity
+}
+
+/// Create a `cueue` (see `cueue`), and pass its Writer and Reader to `f`, together with a
+/// scope of `std::thread::scope`, to spawn the producer and the consumer threads with.
+///
+/// Every thread spawned in the scope is joined before `scope` returns, therefore the elements
+/// can borrow local data (e.g: `&str` slices of a buffer), unlike with threads spawned by
+/// `std::thread::spawn`, that require `'static` elements.
+///
+///```
+/// let text = String::from("foo bar baz");
+/// let words = cueue::scope(16, |s, mut w, mut r| {
+///     let text = text.as_str();
+///     s.spawn(move || {
+///         w.push_many(&mut text.split(' ').map(Some));
+///     });
+///     let mut words = Vec::new();
+///     while let Ok(chunk) = r.read_chunk_blocking() {
+///         words.extend(chunk.iter().flatten().copied());
+///         r.commit();
+///     }
+///     words
+/// })
+/// .unwrap();
+/// assert_eq!(words, ["foo", "bar", "baz"]);
+///```
+pub fn scope<'env, T, F, R>(requested_capacity: usize, f: F) -> Result<R, CError>
+where
+    T: Default,
+    F: for<'scope> FnOnce(&'scope std::thread::Scope<'scope, 'env>, Writer<T>, Reader<T>) -> R,
+{
+    let (writer, reader) = cueue(requested_capacity)?;
+    Ok(std::thread::scope(|s| f(s, writer, reader)))
+}
+
+/// Create a `cueue` of exactly `capacity` elements over memory the caller already mapped,
+/// e.g: by a kernel-bypass stack (DPDK, SPDK), that manages its own mirrored hugepage mappings.
+///
+/// Fails, if `capacity` is not a power of two, or the pointers are not aligned.
+/// If `control_block` holds an initialized control block (see the `abi` module),
+/// the unread elements are available again for the new Reader, as with `Builder::file`,
+/// otherwise the control block and the elements are initialized.
+/// The queue has the default options of `Builder`, its page size is the system page size.
+///
+/// # Safety
+///
+/// `control_block` must be valid for reads and writes of `abi::CONTROL_BLOCK_SIZE` bytes,
+/// aligned to `abi::FIELD_SIZE`. `buffer` must be valid for reads and writes of `2 * capacity`
+/// elements, the second `capacity` elements mapping the same memory as the first ones.
+/// Both must stay valid, and not be used by anything else (except through the `abi`), until
+/// the Writer, the Reader and their leases (see `Writer::lease_region`) are dropped.
+/// The memory is not unmapped by the queue.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+pub unsafe fn from_raw_parts<T>(
+    control_block: *mut u8,
+    buffer: *mut T,
+    capacity: usize,
+) -> Result<(Writer<T>, Reader<T>), CError>
+where
+    T: SharedMemSafe + Default,
+{
+    if !capacity.is_power_of_two() {
+        return Err(CError {
+            hint: "capacity must be a power of two",
+            err: std::io::ErrorKind::InvalidInput.into(),
+        });
+    }
+    if control_block.align_offset(std::mem::align_of::<ControlBlock>()) != 0
+        || buffer.align_offset(std::mem::align_of::<T>()) != 0
+    {
+        return Err(CError {
+            hint: "unaligned control block or buffer",
+            err: std::io::ErrorKind::InvalidInput.into(),
+        });
+    }
+
+    let builder = Builder::new(capacity);
+    let pagesize = sysconf(_SC_PAGESIZE) as usize;
+    let bufsize = capacity * std::mem::size_of::<T>();
+    let report = builder.report(capacity, pagesize, 0, buffer.cast(), bufsize);
+    let backing = Backing {
+        backend: Backend::Raw,
+        dev: 0,
+        inode: 0,
+    };
+    let map = MemoryMap::borrowed(control_block.cast());
+    let cbp = control_block.cast::<ControlBlock>();
+    let initmap = if (*cbp).magic.0.load(Ordering::Acquire) == abi::MAGIC {
+        (*cbp).reopen(0);
+        MemoryMapInitialized::existing(map, buffer, capacity, report, backing)
+    } else {
+        ControlBlock::init(cbp, builder.clock, 0);
+        let initmap = MemoryMapInitialized::new(map, buffer, capacity, report, backing, false);
+        (*cbp).magic.0.store(abi::MAGIC, Ordering::Release);
+        initmap
+    };
+    let shared_map = std::sync::Arc::new(initmap);
+
+    let writer = Writer::new(
+        shared_map.clone(),
+        buffer,
+        capacity,
+        builder.full_policy,
+        None,
+    );
+    let reader = Reader::new(shared_map, buffer, capacity, builder.full_policy, None);
+    Ok((writer, reader))
+}
+
+/// Move the queue of `writer` and `reader` to a new queue of at least `requested_capacity`.
+///
+/// Creates a new queue, moves the unread elements into it, then replaces both handles.
+/// The sequence number, the dropped counter, the notification watermarks,
+/// the callbacks and the wait strategies are preserved.
+/// The new queue uses the page size, notification setting and full policy of the old one,
+/// but the readiness file descriptors change.
+///
+/// Requires both handles, therefore the Writer and the Reader must be paused meanwhile.
+/// Fails if the unread elements do not fit in the new queue, or the new queue can not be created,
+/// leaving the handles untouched.
+///
+///```
+/// let (mut w, mut r) = cueue::cueue::<u32>(16).unwrap();
+/// let cap = w.capacity();
+/// while w.push(1).is_ok() {}
+///
+/// cueue::resize(&mut w, &mut r, cap * 2).unwrap();
+/// assert_eq!(w.capacity(), cap * 2);
+/// assert_eq!(w.write_chunk().len(), cap);
+/// assert_eq!(r.read_chunk().len(), cap);
+///```
+pub fn resize<T>(
+    writer: &mut Writer<T>,
+    reader: &mut Reader<T>,
+    requested_capacity: usize,
+) -> Result<(), CError>
+where
+    T: Default,
+{
+    assert!(
+        std::sync::Arc::ptr_eq(&writer.mem, &reader.mem),
+        "the Writer and the Reader must belong to the same queue"
+    );
+    if reader.read_chunk().len() > requested_capacity {
+        return Err(CError {
+            hint: "unread elements do not fit in the requested capacity",
+            err: std::io::ErrorKind::InvalidInput.into(),
+        });
+    }
+    let (mut w, mut r) = Builder::new(requested_capacity)
+        .page_size(writer.page_size())
+        .notify(writer.notify.is_some())
+        .full_policy(writer.full_policy)
+        .futex(writer.futex)
+        .retention(writer.retention as usize)
+        .build::<T>()?;
+
+    if w.write_chunk().len() < reader.read_chunk().len() {
+        return Err(CError {
+            hint: "unread elements do not fit in the requested capacity",
+            err: std::io::ErrorKind::InvalidInput.into(),
+        });
+    }
+
+    let chunk = w.write_chunk();
+    let mut n = 0;
+    for (slot, elem) in chunk.iter_mut().zip(reader.read_chunk_mut()) {
+        *slot = std::mem::take(elem);
+        n += 1;
+    }
+    w.commit(n);
+    reader.commit();
+
+    unsafe {
+        let (old, new) = (&*writer.cb, &*w.cb);
+        for (from, to) in [
+            (&old.sequence, &new.sequence),
+            (&old.dropped, &new.dropped),
+            (&old.read_watermark, &new.read_watermark),
+            (&old.write_watermark, &new.write_watermark),
+            (&old.clock, &new.clock),
+            (&old.epoch_offset, &new.epoch_offset),
+        ] {
+            to.0.store(from.0.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+    }
+
+    w.on_full = writer.on_full.take();
+    w.on_wrap = writer.on_wrap.take();
+    w.backpressure = writer.backpressure.take();
+    w.commit_on_drop = writer.commit_on_drop;
+    w.drain_on_drop = writer.drain_on_drop;
+    w.sequence = writer.sequence;
+    std::mem::swap(&mut w.wait, &mut writer.wait);
+    r.seen_dropped = reader.seen_dropped;
+    r.release_consumed = reader.release_consumed;
+    r.drop_consumed = reader.drop_consumed;
+    r.on_empty = reader.on_empty.take();
+    std::mem::swap(&mut r.wait, &mut reader.wait);
+
+    *writer = w;
+    *reader = r;
+    Ok(())
+}
+
+/// Configures and creates a `cueue`.
+///
+///```
+/// let (w, r) = cueue::Builder::new(1 << 20)
+///     .noreserve(true)
+///     .build::<u8>()
+///     .unwrap();
+/// assert!(w.capacity() >= 1 << 20);
+/// # drop(r);
+///```
+#[derive(Clone, Debug)]
+pub struct Builder {
+    requested_capacity: usize,
+    noreserve: bool,
+    page_size: Option<usize>,
+    notify: bool,
+    full_policy: FullPolicy,
+    file: Option<std::path::PathBuf>,
+    durability: Durability,
+    dax: bool,
+    clock: clock::Clock,
+    commit_on_drop: bool,
+    drain_on_drop: Option<Duration>,
+    sequence: bool,
+    drop_on_consume: bool,
+    futex: bool,
+    retention: usize,
+    name: String,
+    cloexec: bool,
+}
+
+impl Builder {
+    /// Create a builder of a queue with at least `requested_capacity` capacity.
+    ///
+    /// See `cueue` for the capacity rounding rules.
+    pub fn new(requested_capacity: usize) -> Self {
+        Self {
+            requested_capacity,
+            noreserve: false,
+            page_size: None,
+            notify: false,
+            full_policy: FullPolicy::ReturnEmpty,
+            file: None,
+            durability: Durability::None,
+            dax: false,
+            clock: clock::Clock::Monotonic,
+            commit_on_drop: false,
+            drain_on_drop: None,
+            sequence: false,
+            drop_on_consume: false,
+            futex: false,
+            retention: 0,
+            name: String::from("cueue"),
+            cloexec: true,
+        }
+    }
+
+    /// Create a builder of a queue of `T` elements with at least `N` capacity,
+    /// validated at compile time, see `validated_capacity_of`.
+    ///
+    /// The queue should be built with the same element type (e.g: `build::<T>`).
+    ///
+    ///```
+    /// let (w, r) = cueue::Builder::new_checked::<u64, 1000>().build::<u64>().unwrap();
+    /// assert!(w.capacity() >= 1024);
+    /// # drop(r);
+    ///```
+    ///
+    ///```compile_fail
+    /// let (w, r) = cueue::Builder::new_checked::<u64, 0>().build::<u64>().unwrap();
+    ///```
+    pub fn new_checked<T, const N: usize>() -> Self {
+        Self::new(ConstCapacity::<T, N>::VALIDATED)
+    }
+
+    /// If enabled, the memory of the queue is mapped with MAP_NORESERVE,
+    /// and is not populated in advance.
+    ///
+    /// This allows creating very large queues (that are rarely filled)
+    /// on machines without matching swap space reserved.
+    /// The price is explicitly accepting the risk of overcommit:
+    /// if the system runs out of memory while the queue is being filled,
+    /// the process gets a SIGSEGV (or SIGBUS) instead of a construction error.
+    ///
+    /// Note: elements are default initialized at construction,
+    /// which touches every page of the buffer, unless `build_zeroed` is used.
+    pub fn noreserve(mut self, enable: bool) -> Self {
+        self.noreserve = enable;
+        self
+    }
+
+    /// Override the granularity of the layout (by default, the system page size).
+    ///
+    /// The control block occupies the first `page_size` bytes of the memory area,
+    /// the buffer follows it, and the whole area is aligned to `page_size`.
+    /// The capacity is rounded up to be at least `page_size`.
+    /// E.g: use `2 << 20` to force 2MB alignment.
+    ///
+    /// `page_size` must be a power of two and a multiple of the system page size,
+    /// otherwise `build` fails.
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// If enabled, the Writer and the Reader notify each other on commit and drop,
+    /// via file descriptors (see `Writer::readiness_fd` and `Reader::readiness_fd`).
+    ///
+    /// This allows waiting for the queue using a reactor, e.g: in async code,
+    /// see the `asynch` module. The price is a syscall on every non-empty commit.
+    pub fn notify(mut self, enable: bool) -> Self {
+        self.notify = enable;
+        self
+    }
+
+    /// Set what `Writer::push` and `Writer::push_many` do, if the queue is full.
+    ///
+    /// By default, `FullPolicy::ReturnEmpty`: the element is given back to the caller.
+    pub fn full_policy(mut self, policy: FullPolicy) -> Self {
+        self.full_policy = policy;
+        self
+    }
+
+    /// Back the queue by the file at `path`, instead of anonymous memory.
+    ///
+    /// If the file does not exist, or is empty, it is created and initialized.
+    /// Otherwise, it must have been created by a queue of the same capacity and element size,
+    /// and its unread elements are available again for the new Reader:
+    /// this allows using the queue as a spool, that survives restarts of the process,
+    /// see `durability`. The completion and the watermarks are reset.
+    ///
+    /// Elements are not initialized when the file is reopened, therefore `T`
+    /// must be plain data, valid for any bit pattern written by a previous process
+    /// (e.g: integers, byte arrays, see `SharedMemSafe`). The file must not be used by more than one queue at a time,
+    /// but Readers can be attached to the running queue, see `attach_reader`.
+    pub fn file(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self
+    }
+
+    /// Set when the Writer flushes a file backed queue (see `file`) to storage.
+    ///
+    /// Flushing makes the committed elements survive a power loss.
+    /// The read position is not flushed on Reader commit, therefore after a crash,
+    /// some consumed elements might be read again (at-least-once delivery).
+    ///
+    /// By default, `Durability::None`. Without `file`, has no effect.
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// If enabled, the queue file (see `file`) is mapped with MAP_SYNC, and flushed
+    /// by writing back CPU cache lines instead of calling msync, according to `durability`.
+    ///
+    /// This requires the file to be on a DAX filesystem (i.e: persistent memory, mounted
+    /// with `-o dax`), otherwise `build` fails. Loads and stores reach the media directly,
+    /// therefore `Durability::OnCommit` is crash-consistent without syscalls.
+    ///
+    /// Supported on Linux, x86_64 and aarch64 only.
+    pub fn dax(mut self, enable: bool) -> Self {
+        self.dax = enable;
+        self
+    }
+
+    /// If enabled, dropping the Writer (or `Writer::finish`, before the end of the stream)
+    /// commits the elements marked written by `Writer::commit_relaxed`, so the tail of a stream
+    /// is not lost, if the Writer is dropped before committing it (e.g: on shutdown, or unwinding).
+    ///
+    /// The rest of the chunk acquired last is not committed.
+    pub fn commit_on_drop(mut self, enable: bool) -> Self {
+        self.commit_on_drop = enable;
+        self
+    }
+
+    /// If enabled, each non-empty commit advances the sequence number, see `Writer::sequence`.
+    ///
+    /// Disabled by default: the sequence is stored in a separate cache line of the control block,
+    /// that every commit would write. Dropped records (`Writer::record_dropped`) are counted either way.
+    pub fn sequence(mut self, enable: bool) -> Self {
+        self.sequence = enable;
+        self
+    }
+
+    /// If set, dropping the Writer (or `Writer::finish`) waits up to `timeout` for the Reader
+    /// to consume every committed element, see `Writer::wait_empty`, e.g: so a short-lived
+    /// process does not exit while its logging consumer still has queued records.
+    ///
+    /// The Reader is not notified that the Writer is gone until then.
+    pub fn drain_on_drop(mut self, timeout: Duration) -> Self {
+        self.drain_on_drop = Some(timeout);
+        self
+    }
+
+    /// If enabled, `Reader::commit` drops the consumed elements, and replaces them
+    /// with default values, instead of leaving them in the queue until they are overwritten
+    /// (or the queue is dropped).
+    ///
+    /// For elements that hold resources, that must be released promptly
+    /// (e.g: file handles, buffers borrowed from a pool). The price is dropping
+    /// and default initializing every consumed element on the Reader side.
+    /// Can't be combined with `retention`, otherwise `build` fails.
+    pub fn drop_on_consume(mut self, enable: bool) -> Self {
+        self.drop_on_consume = enable;
+        self
+    }
+
+    /// If enabled, blocking operations (`FullPolicy::Block`, `Reader::read_exact_timeout`,
+    /// `paced::PacedReader`) sleep on a futex in the control block, when the wait strategy
+    /// parks (see `wait::WaitStrategy::park`), and are woken by the other side on commit and on drop.
+    ///
+    /// The futex is process-shared, therefore blocking works even if the Writer
+    /// and the Reader live in different processes (e.g: sharing a queue file, see `file`).
+    /// If the process of the other side dies, a blocked handle wakes up within 50ms,
+    /// and finds the queue abandoned (see `Writer::register_process`).
+    /// The price is a memory fence on every non-empty commit, and a syscall,
+    /// if the other side is waiting.
+    ///
+    /// Supported on Linux only, otherwise `build` fails.
+    pub fn futex(mut self, enable: bool) -> Self {
+        self.futex = enable;
+        self
+    }
+
+    /// Set the name of the memory file of the queue, "cueue" by default.
+    ///
+    /// The name is for debugging only: on Linux, it shows up in `/proc/<pid>/fd`,
+    /// `/proc/<pid>/maps` and `/proc/<pid>/map_files` (e.g: as `/memfd:name (deleted)`),
+    /// to tell which queue a mapping belongs to. On macOS, it is part of the name
+    /// of the shared memory object, that is limited to 31 bytes: longer names make `build` fail.
+    /// Ignored, if the queue is backed by a file (see `file`), and on QNX.
+    ///
+    /// The name must not contain NUL bytes.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// If disabled, the file descriptor of the queue memory is not close-on-exec, and it is kept open
+    /// while the queue exists (see `Writer::inheritable_fd`), to share the queue with a child process.
+    ///
+    /// By default, the descriptor is created close-on-exec, and closed once the memory is mapped.
+    /// A child created by `fork` shares the mapping of the queue anyway, but it is unmapped by `exec`:
+    /// to use the queue after `exec`, pass the number of the inherited descriptor to the child
+    /// (e.g: as an argument), drop the Reader in the parent, and attach a new Reader in the child,
+    /// see `attach_reader_on_fd`. The descriptor is inherited by every process executed meanwhile.
+    ///
+    ///```no_run
+    /// use std::os::fd::AsRawFd;
+    ///
+    /// let (w, r) = cueue::Builder::new(1 << 16).cloexec(false).build::<u8>().unwrap();
+    /// let fd = w.inheritable_fd().unwrap().as_raw_fd();
+    /// drop(r);
+    /// let child = std::process::Command::new("consumer").arg(fd.to_string()).spawn().unwrap();
+    ///
+    /// // in the consumer, with `fd` parsed from the arguments:
+    /// // let fd = unsafe { std::os::fd::BorrowedFd::borrow_raw(fd) };
+    /// // let r = cueue::Builder::new(1 << 16)
+    /// //     .attach_reader_on_fd::<u8>(fd, 0, cueue::StartPosition::Oldest)
+    /// //     .unwrap();
+    /// # drop((w, child));
+    ///```
+    pub fn cloexec(mut self, enable: bool) -> Self {
+        self.cloexec = enable;
+        self
+    }
+
+    /// Keep the last `n` consumed elements in the queue, instead of making them available
+    /// for writing: a flight recorder window of recent history, for diagnostic tools,
+    /// see `Reader::retained` and `spectator::SpectatorReader`.
+    ///
+    /// The price is capacity: at most `capacity - n` elements can be unread at a time.
+    /// `n` must be less than the capacity, otherwise `build` fails. By default, 0.
+    pub fn retention(mut self, n: usize) -> Self {
+        self.retention = n;
+        self
+    }
+
+    /// Set the clock the timestamps of the queue are taken from (see `Writer::timestamp`).
+    ///
+    /// By default, `Clock::Monotonic`. The clock is recorded in the header of the queue,
+    /// with its offset to the realtime clock. If a queue file is reopened (see `file`),
+    /// the recorded clock is kept, and the offset is measured again, as monotonic clocks
+    /// restart on boot.
+    pub fn clock(mut self, clock: clock::Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Validate the options, and compute the layout of the queue.
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    fn layout(&self) -> Result<Layout, CError> {
+        let syspagesize = unsafe { sysconf(_SC_PAGESIZE) as usize };
+        let pagesize = self.page_size.unwrap_or(syspagesize);
+        if !pagesize.is_power_of_two() || pagesize < syspagesize {
+            return Err(CError {
+                hint: "page size must be a power of two multiple of the system page size",
+                err: std::io::ErrorKind::InvalidInput.into(),
+            });
+        }
+        let capacity = next_power_two(usize::max(self.requested_capacity, pagesize))?;
+        if self.retention >= capacity {
+            return Err(CError {
+                hint: "retention must be less than the capacity",
+                err: std::io::ErrorKind::InvalidInput.into(),
+            });
+        }
+        if self.retention != 0 && self.drop_on_consume {
+            return Err(CError {
+                hint: "consumed elements can't be both retained and dropped",
+                err: std::io::ErrorKind::InvalidInput.into(),
+            });
+        }
+
+        if std::mem::size_of::<ControlBlock>() > pagesize {
+            return Err(CError {
+                hint: "ControlBlock does not fit in a single page",
+                err: std::io::ErrorKind::Other.into(),
+            });
+        }
+
+        let map_flags = if self.noreserve {
+            MAP_NORESERVE
+        } else {
+            platform_flags()
+        };
+        let share = if self.dax {
+            if self.file.is_none() {
+                return Err(CError {
+                    hint: "DAX requires a queue file",
+                    err: std::io::ErrorKind::InvalidInput.into(),
+                });
+            }
+            dax_share_flags()?
+        } else {
+            MAP_SHARED
+        };
+        if self.futex && cfg!(not(target_os = "linux")) {
+            return Err(CError {
+                hint: "futex is not supported on this platform",
+                err: std::io::ErrorKind::Unsupported.into(),
+            });
+        }
+
+        Ok(Layout {
+            pagesize,
+            capacity,
+            map_flags,
+            share,
+        })
+    }
+
+    /// Record the decisions made while constructing a queue, see `BuildReport`.
+    ///
+    /// Must be called right after mapping the buffer of `bufsize` bytes at `buffer`,
+    /// before touching it.
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    fn report(
+        &self,
+        capacity: usize,
+        pagesize: usize,
+        map_flags: i32,
+        buffer: *const u8,
+        bufsize: usize,
+    ) -> BuildReport {
+        let system_page_size = unsafe { sysconf(_SC_PAGESIZE) as usize };
+        let buffer_pages = (bufsize + system_page_size - 1) / system_page_size;
+        BuildReport {
+            requested_capacity: self.requested_capacity,
+            capacity,
+            page_size: pagesize,
+            system_page_size,
+            populate: map_flags & platform_flags() != 0,
+            buffer_pages,
+            resident_pages: resident_pages(buffer, buffer_pages),
+        }
+    }
+
+    /// Create a `cueue` using the configured options.
+    ///
+    /// On success, returns a `(Writer, Reader)` pair, that share the ownership
+    /// of the underlying circular array.
+    pub fn build<T>(self) -> Result<(Writer<T>, Reader<T>), CError>
+    where
+        T: Default,
+    {
+        self.build_elements(false, None)
+    }
+
+    /// Like `build`, but the elements are not default initialized one by one:
+    /// the memory of a new queue is zero filled by the OS already.
+    ///
+    /// Creating a large queue is much faster, and the pages of the buffer are only
+    /// touched when the Writer gets there (also see `noreserve`).
+    ///
+    ///```
+    /// let (mut w, _r) = cueue::Builder::new(1 << 24).build_zeroed::<u8>().unwrap();
+    /// assert!(w.write_chunk().iter().all(|&b| b == 0));
+    ///```
+    pub fn build_zeroed<T>(self) -> Result<(Writer<T>, Reader<T>), CError>
+    where
+        T: Zeroable,
+    {
+        self.build_elements(true, None)
+    }
+
+    /// Create a `cueue` in the memory of `fd`, starting at `offset`, instead of allocating it:
+    /// e.g: a file on hugetlbfs, device memory exported as a dmabuf, or a shared memory
+    /// segment managed by another library. Only the double mapping and the control block
+    /// are set up on top of it, `file` and `name` are ignored.
+    ///
+    /// The queue occupies `page_size + capacity * size_of::<T>()` bytes of the file,
+    /// that must be available (if the size of the file is known, e.g: of a regular file,
+    /// this is checked). `offset` must be a multiple of the system page size.
+    /// If the region holds an initialized control block (e.g: of a queue built on it
+    /// by a previous process), its unread elements are available again for the new Reader,
+    /// as with `file`, otherwise the queue is initialized.
+    ///
+    /// `fd` is duplicated, and the duplicate is closed once the queue is mapped.
+    ///
+    ///```
+    /// let path = std::env::temp_dir().join(format!("cueue-doc-fd-{}", std::process::id()));
+    /// let file = std::fs::OpenOptions::new()
+    ///     .read(true)
+    ///     .write(true)
+    ///     .create_new(true)
+    ///     .open(&path)
+    ///     .unwrap();
+    /// std::fs::remove_file(&path).unwrap();
+    /// file.set_len(1 << 20).unwrap();
+    ///
+    /// // the queue starts at 64K into the file
+    /// let (w, _r) = cueue::Builder::new(16).build_on_fd::<u8>(&file, 1 << 16).unwrap();
+    /// assert_eq!(w.backend(), cueue::Backend::External);
+    ///```
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    pub fn build_on_fd<T>(
+        self,
+        fd: impl AsFd,
+        offset: u64,
+    ) -> Result<(Writer<T>, Reader<T>), CError>
+    where
+        T: SharedMemSafe + Default,
+    {
+        let fd = external_fd(fd, offset, self.layout()?.size::<T>())?;
+        self.build_elements(false, Some((fd, offset)))
+    }
+
+    /// See `build`. If `zeroed`, the elements of a new queue are not default initialized.
+    /// If `external` is set, the queue is built in the given file at the given offset,
+    /// see `build_on_fd`.
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    fn build_elements<T>(
+        self,
+        zeroed: bool,
+        external: Option<(OwnedFd, u64)>,
+    ) -> Result<(Writer<T>, Reader<T>), CError>
+    where
+        T: Default,
+    {
+        let Layout {
+            pagesize,
+            capacity,
+            map_flags,
+            share,
+        } = self.layout()?;
+        let cbsize = pagesize;
+
+        let (mut initmap, buffer, f) = unsafe {
+            let bufsize = capacity * std::mem::size_of::<T>();
+            let (f, base, existing, backend) = match (external, &self.file) {
+                // whether the region is initialized is known once it is mapped
+                (Some((f, base)), _) => (f, base, false, Backend::External),
+                (None, Some(path)) => {
+                    let (f, len) = queuefile(path, true)?;
+                    if len != 0 && len != (cbsize + bufsize) as u64 {
+                        return Err(CError {
+                            hint: "queue file size does not match the capacity",
+                            err: std::io::ErrorKind::InvalidData.into(),
+                        });
+                    }
+                    (f, 0, len != 0, Backend::File)
+                }
+                (None, None) => {
+                    let name = CString::new(self.name.as_str()).map_err(|_| CError {
+                        hint: "queue name must not contain NUL bytes",
+                        err: std::io::ErrorKind::InvalidInput.into(),
+                    })?;
+                    let (f, backend) = memoryfile(&name)?;
+                    (f, 0, false, backend)
+                }
+            };
+            let backing = Backing::new(&f, backend)?;
+            set_cloexec(&f, self.cloexec)?;
+            let external = backend == Backend::External;
+            if !existing && !external && ftruncate(f.as_raw_fd(), (cbsize + bufsize) as i64) != 0 {
+                return Err(CError::new("ftruncate"));
+            }
+            let map = doublemap(
+                f.as_raw_fd(),
+                base,
+                cbsize,
+                bufsize,
+                map_flags,
+                share,
+                pagesize,
+            )?;
+            let cbp = map.ptr() as *mut ControlBlock;
+            let existing =
+                existing || (external && (*cbp).magic.0.load(Ordering::Acquire) == abi::MAGIC);
+            let report = self.report(
+                capacity,
+                pagesize,
+                map_flags,
+                map.ptr().add(cbsize),
+                bufsize,
+            );
+
+            if existing {
+                let cb = &*cbp;
+                if cb.magic.0.load(Ordering::Relaxed) != abi::MAGIC {
+                    return Err(CError {
+                        hint: "queue file is not initialized",
+                        err: std::io::ErrorKind::InvalidData.into(),
+                    });
+                }
+                cb.reopen(self.retention as u64);
+
+                let buffer = map.ptr().add(cbsize).cast::<T>();
+                let initmap =
+                    MemoryMapInitialized::existing(map, buffer, capacity, report, backing);
+                (initmap, buffer, f)
+            } else {
+                ControlBlock::init(cbp, self.clock, self.retention as u64);
+
+                // default initialize elems.
+                // this is required to make sure writer always sees initialized elements
+                let buffer = map.ptr().add(cbsize).cast::<T>();
+                let initmap =
+                    MemoryMapInitialized::new(map, buffer, capacity, report, backing, zeroed);
+
+                // publish the control block last: a crash before leaves the file uninitialized
+                (*cbp).magic.0.store(abi::MAGIC, Ordering::Release);
+
+                (initmap, buffer, f)
+            }
+        };
+        if !self.cloexec {
+            initmap.fd = Some(f);
+        }
+        let shared_map = std::sync::Arc::new(initmap);
+
+        let (wnotify, rnotify) = if self.notify {
+            let (w, r) = notify::Notify::pair()?;
+            (Some(w), Some(r))
+        } else {
+            (None, None)
+        };
+
+        let mut writer = Writer::new(
+            shared_map.clone(),
+            buffer,
+            capacity,
+            self.full_policy,
+            wnotify,
+        );
+        if self.file.is_some() {
+            writer.durability = self.durability;
+            writer.dax = self.dax;
+        }
+        writer.commit_on_drop = self.commit_on_drop;
+        writer.drain_on_drop = self.drain_on_drop;
+        writer.sequence = self.sequence;
+        writer.futex = self.futex;
+        writer.retention = self.retention as u64;
+
+        let mut reader = Reader::new(shared_map, buffer, capacity, self.full_policy, rnotify);
+        reader.futex = self.futex;
+        reader.drop_consumed = self.drop_on_consume;
+
+        Ok((writer, reader))
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "nto")))]
+    fn build_elements<T>(
+        self,
+        _zeroed: bool,
+        _external: Option<()>,
+    ) -> Result<(Writer<T>, Reader<T>), CError>
+    where
+        T: Default,
+    {
+        todo!("Only Linux, macOS and QNX are supported so far");
+    }
+
+    /// Attach a new Reader to the running queue of the file set by `file`,
+    /// e.g: in another process, or after the previous Reader was dropped.
+    ///
+    /// The queue must have been created by `build`, with the same capacity, page size
+    /// and element type, and its previous Reader must be dropped (or its process dead),
+    /// otherwise this fails. `start` selects the first element the new Reader gets.
+    /// The options apply to the new Reader (e.g: `futex` and `full_policy`, that must match
+    /// the ones of the Writer), except `notify`, that is not supported for attached Readers.
+    ///
+    /// The Writer finds the queue abandoned while no Reader is attached (see `Writer::is_abandoned`).
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    pub fn attach_reader<T>(self, start: StartPosition) -> Result<Reader<T>, CError>
+    where
+        T: SharedMemSafe + Default,
+    {
+        let path = self.file.as_ref().ok_or(CError {
+            hint: "attaching a Reader requires a queue file",
+            err: std::io::ErrorKind::InvalidInput.into(),
+        })?;
+        let (f, len) = queuefile(path, false)?;
+        if len != self.layout()?.size::<T>() as u64 {
+            return Err(CError {
+                hint: "queue file size does not match the capacity",
+                err: std::io::ErrorKind::InvalidData.into(),
+            });
+        }
+        self.attach_reader_to(f, Backend::File, 0, start)
+    }
+
+    /// Like `attach_reader`, but attach to the queue in the memory of `fd` at `offset`,
+    /// e.g: created by `build_on_fd`, or inherited from the parent process (see `cloexec`).
+    ///
+    /// `fd` is duplicated, and the duplicate is closed once the queue is mapped
+    /// (unless `cloexec` is disabled).
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    pub fn attach_reader_on_fd<T>(
+        self,
+        fd: impl AsFd,
+        offset: u64,
+        start: StartPosition,
+    ) -> Result<Reader<T>, CError>
+    where
+        T: SharedMemSafe + Default,
+    {
+        let f = external_fd(fd, offset, self.layout()?.size::<T>())?;
+        self.attach_reader_to(f, Backend::External, offset, start)
+    }
+
+    /// See `attach_reader`, the queue is at `base` in `f`.
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+    fn attach_reader_to<T>(
+        self,
+        f: OwnedFd,
+        backend: Backend,
+        base: u64,
+        start: StartPosition,
+    ) -> Result<Reader<T>, CError>
+    where
+        T: SharedMemSafe + Default,
+    {
+        let Layout {
+            pagesize,
+            capacity,
+            map_flags,
+            share,
+        } = self.layout()?;
+        let bufsize = capacity * std::mem::size_of::<T>();
+
+        let backing = Backing::new(&f, backend)?;
+        set_cloexec(&f, self.cloexec)?;
+        let map = unsafe {
+            doublemap(
+                f.as_raw_fd(),
+                base,
+                pagesize,
+                bufsize,
+                map_flags,
+                share,
+                pagesize,
+            )?
+        };
+        let buffer = unsafe { map.ptr().add(pagesize) };
+        let report = self.report(capacity, pagesize, map_flags, buffer, bufsize);
+        let cb = unsafe { &*(map.ptr() as *const ControlBlock) };
+        if cb.magic.0.load(Ordering::Acquire) != abi::MAGIC {
+            return Err(CError {
+                hint: "queue file is not initialized",
+                err: std::io::ErrorKind::InvalidData.into(),
+            });
+        }
+        let reader_pid = cb.reader_pid.0.load(Ordering::Relaxed);
+        if cb.closed.0.load(Ordering::Acquire) & abi::READER_CLOSED == 0
+            && process_alive(reader_pid)
+        {
+            return Err(CError {
+                hint: "the queue already has a Reader",
+                err: std::io::ErrorKind::AlreadyExists.into(),
+            });
+        }
+
+        match start {
+            StartPosition::Oldest => {
+                cb.read_position
+                    .0
+                    .fetch_and(!READER_BUSY, Ordering::Relaxed);
+            }
+            StartPosition::Latest => {
+                let w = cb.write_position.0.load(Ordering::Acquire);
+                cb.read_position.0.store(w, Ordering::Release);
+            }
+        }
+        cb.reader_pid
+            .0
+            .store(std::process::id() as u64, Ordering::Relaxed);
+        cb.closed
+            .0
+            .fetch_and(!abi::READER_CLOSED, Ordering::Release);
+        let dropped = cb.dropped.0.load(Ordering::Acquire);
+
+        let buffer = buffer.cast::<T>();
+        let mut initmap = MemoryMapInitialized::existing(map, buffer, capacity, report, backing);
+        if !self.cloexec {
+            initmap.fd = Some(f);
+        }
+        let mut reader = Reader::new(
+            std::sync::Arc::new(initmap),
+            buffer,
+            capacity,
+            self.full_policy,
+            None,
+        );
+        reader.futex = self.futex;
+        reader.drop_consumed = self.drop_on_consume;
+        reader.seen_dropped = dropped;
+        Ok(reader)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "nto")))]
+    pub fn attach_reader<T>(self, _start: StartPosition) -> Result<Reader<T>, CError>
+    where
+        T: SharedMemSafe + Default,
+    {
+        todo!("Only Linux, macOS and QNX are supported so far");
+    }
+}
+
+/// Decisions made while constructing a queue, see `Writer::build_report`.
+///
+/// The capacity is rounded up to at least the page size, then to a power of two
+/// (see `cueue`), therefore e.g: `cueue::<u8>(16)` yields a capacity of 4096 on most systems.
+/// The `Display` implementation explains the rounding.
+///
+///```
+/// let (w, _r) = cueue::cueue::<u8>(16).unwrap();
+/// let report = w.build_report();
+/// assert_eq!(report.requested_capacity, 16);
+/// assert_eq!(report.capacity, w.capacity());
+/// println!("{report}");
+///```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BuildReport {
+    /// The capacity passed to `Builder::new` (or `cueue`).
+    pub requested_capacity: usize,
+    /// The capacity of the queue.
+    pub capacity: usize,
+    /// See `Writer::page_size`.
+    pub page_size: usize,
+    /// The page size of the system, the granularity of `buffer_pages`.
+    pub system_page_size: usize,
+    /// True, if the buffer was requested to be populated when mapped (MAP_POPULATE, Linux only),
+    /// i.e: `Builder::noreserve` is disabled.
+    pub populate: bool,
+    /// Number of system pages of the buffer (not counting its mirror).
+    pub buffer_pages: usize,
+    /// Number of system pages of the buffer that were resident right after mapping,
+    /// before the elements were initialized: equals `buffer_pages`, if the population
+    /// was honored (or the pages of a queue file were cached).
+    pub resident_pages: usize,
+}
+
+impl BuildReport {
+    /// Returns true, if the capacity differs from the requested one.
+    pub fn is_rounded(&self) -> bool {
+        self.capacity != self.requested_capacity
+    }
+}
+
+impl std::fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "capacity {}", self.capacity)?;
+        if self.requested_capacity < self.page_size {
+            write!(
+                f,
+                " (requested {}, rounded up to the page size)",
+                self.requested_capacity
+            )?;
+        } else if self.is_rounded() {
+            write!(
+                f,
+                " (requested {}, rounded up to a power of two)",
+                self.requested_capacity
+            )?;
+        }
+        write!(
+            f,
+            ", page size {} (system: {}), {}/{} buffer pages resident after mapping",
+            self.page_size, self.system_page_size, self.resident_pages, self.buffer_pages
+        )?;
+        if self.populate {
+            write!(f, " (populate requested)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Number of the first `pages` system pages at `addr` that are resident in memory.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn resident_pages(addr: *const u8, pages: usize) -> usize {
+    let syspagesize = unsafe { sysconf(_SC_PAGESIZE) as usize };
+    let mut vec = vec![0u8; pages];
+    let ret = unsafe {
+        libc::mincore(
+            addr as *mut c_void,
+            pages * syspagesize,
+            vec.as_mut_ptr().cast(),
+        )
+    };
+    if ret != 0 {
+        return 0;
+    }
+    vec.iter().filter(|&&v| v & 1 != 0).count()
+}
+
+#[cfg(target_os = "nto")]
+fn resident_pages(_addr: *const u8, _pages: usize) -> usize {
+    0
+}
+
+/// The geometry and the mapping flags of a queue, see `Builder::layout`.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+struct Layout {
+    pagesize: usize,
+    capacity: usize,
+    map_flags: i32,
+    share: i32,
+}
+
+/// A capacity validated at compile time, see `Builder::new_checked`.
+struct ConstCapacity<T, const N: usize>(std::marker::PhantomData<T>);
+
+impl<T, const N: usize> ConstCapacity<T, N> {
+    const VALIDATED: usize = validated_capacity_of::<T>(N);
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+impl Layout {
+    /// The size of a queue of `T` elements: the control block and the buffer.
+    fn size<T>(&self) -> usize {
+        self.pagesize + self.capacity * std::mem::size_of::<T>()
+    }
+}
+
+/// Returns false, if process `pid` is known not to exist.
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+fn process_alive(pid: u64) -> bool {
+    if pid == 0 {
+        return true;
+    }
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+pub mod abi;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+pub mod asynch;
+pub mod audio;
+pub mod auto;
+pub mod chunks;
+pub mod clock;
+#[cfg(feature = "tokio")]
+pub mod codec;
+pub mod cursor;
+mod endian;
+pub mod fault;
+mod fork;
+pub mod framed;
+mod futex;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod merge;
+pub mod mock;
+pub mod mux;
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+pub mod net;
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+mod notify;
+pub mod paced;
+pub mod pipeline;
+#[cfg(feature = "bytemuck")]
+pub mod pod;
+#[cfg(feature = "postcard")]
+pub mod postcard;
+#[cfg(feature = "prost")]
+pub mod proto;
+pub mod rate;
+pub mod record;
+pub mod region;
+#[cfg(feature = "ringbuf-compat")]
+pub mod ringbuf_compat;
+#[cfg(feature = "rt-audit")]
+pub mod rt_audit;
+pub mod rtrb_compat;
+pub mod segments;
+pub mod shutdown;
+#[cfg(feature = "slog")]
+pub mod slog;
+pub mod snapshot;
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+pub mod socket;
+pub mod spectator;
+pub mod stats;
+pub mod tee;
+pub mod timestamped;
+#[cfg(feature = "tracing")]
+pub mod tracing;
+pub mod wait;
+
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,152 @@
+//! Fan-in: draining multiple queues into a single one.
+//!
+//! Each producer thread writes its own SPSC queue, and `Merge` moves the committed
+//! elements of their Readers into a single Writer, therefore one consumer reads
+//! every stream, without a multi-producer queue. Merge copies the readable chunk
+//! of a Reader as a whole (never a part of it), therefore the commits of the producers
+//! are not split: e.g: the records of byte queues are not interleaved.
+//!
+//!```
+//! use cueue::merge::{Merge, MergeOrder};
+//!
+//! let (mut w1, r1) = cueue::cueue::<u32>(1 << 12).unwrap();
+//! let (mut w2, r2) = cueue::cueue::<u32>(1 << 12).unwrap();
+//! let (w, mut r) = cueue::cueue::<u32>(1 << 12).unwrap();
+//! let mut merge = Merge::new(vec![r1, r2], w).order(MergeOrder::RoundRobin);
+//!
+//! w1.push(1).unwrap();
+//! w2.push(2).unwrap();
+//! assert_eq!(merge.pump(), 2);
+//! assert_eq!(r.read_chunk(), [1, 2]);
+//!```
+
+use std::sync::atomic::Ordering;
+
+use crate::wait::{Backoff, WaitStrategy};
+use crate::{Abandoned, Reader, Writer};
+
+/// Which Reader `Merge` serves first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeOrder {
+    /// Take turns: a Reader is served after the ones served before it. If the chunk of the
+    /// next Reader does not fit in the Writer, `Merge` waits, therefore none of them starves.
+    #[default]
+    RoundRobin,
+    /// Serve the Readers in the order they were given, skipping the ones whose chunk
+    /// does not fit in the Writer: the earlier Readers have priority,
+    /// and the Writer is filled as much as possible.
+    ReadyFirst,
+}
+
+/// Moves the elements of multiple Readers into a single Writer, see the module docs.
+pub struct Merge<T> {
+    readers: Vec<Reader<T>>,
+    writer: Writer<T>,
+    order: MergeOrder,
+    /// The Reader to serve first, with `MergeOrder::RoundRobin`
+    next: usize,
+    wait: Box<dyn WaitStrategy>,
+}
+
+impl<T> Merge<T>
+where
+    T: Clone + Default,
+{
+    /// Panics if the capacity of a Reader is larger than the capacity of the Writer,
+    /// as its full chunk would never fit.
+    pub fn new(readers: Vec<Reader<T>>, writer: Writer<T>) -> Self {
+        assert!(
+            readers.iter().all(|r| r.capacity() <= writer.capacity()),
+            "the capacity of the Writer must not be less than the capacity of the Readers"
+        );
+        Self {
+            readers,
+            writer,
+            order: MergeOrder::RoundRobin,
+            next: 0,
+            wait: Box::new(Backoff::default()),
+        }
+    }
+
+    /// Set which Reader is served first, `MergeOrder::RoundRobin` by default.
+    pub fn order(mut self, order: MergeOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Set the strategy `run` uses to wait for the Readers, `wait::Backoff` by default.
+    pub fn set_wait_strategy(&mut self, strategy: impl WaitStrategy + 'static) {
+        self.wait = Box::new(strategy);
+    }
+
+    /// Copy the readable chunks of the Readers that fit in the Writer, in the configured order,
+    /// commit them, and consume them, without waiting.
+    ///
+    /// Returns the number of copied elements.
+    pub fn pump(&mut self) -> usize {
+        let count = self.readers.len();
+        let mut copied = 0;
+        for turn in 0..count {
+            let i = match self.order {
+                MergeOrder::RoundRobin => (self.next + turn) % count,
+                MergeOrder::ReadyFirst => turn,
+            };
+            let reader = &mut self.readers[i];
+            let input = reader.read_chunk();
+            if input.is_empty() {
+                continue;
+            }
+            match self.writer.write_chunk_exact(input.len()) {
+                Some(output) => output.clone_from_slice(input),
+                None if self.order == MergeOrder::RoundRobin => {
+                    // wait for the Writer, keep the turn
+                    self.next = i;
+                    return copied;
+                }
+                None => continue,
+            }
+            copied += self.writer.commit(input.len());
+            reader.commit();
+        }
+        self.next = (self.next + 1) % count.max(1);
+        copied
+    }
+
+    /// Pump the elements (see `pump`), until every Reader is abandoned, and every element is copied.
+    ///
+    /// Polls the Readers using the wait strategy of the Merge (see `set_wait_strategy`),
+    /// and waits for the Reader of the Writer to make space using the wait strategy of the Writer,
+    /// or sleeps on the futex of its queue, if `Builder::futex` is enabled.
+    /// Returns Err, if the Reader of the Writer is dropped.
+    pub fn run(&mut self) -> Result<(), Abandoned> {
+        let mut iteration = 0;
+        loop {
+            // loaded before the chunks, to wait for the next commit of the peers
+            let done = self.readers.iter().all(|reader| reader.is_abandoned());
+            let r = self.writer.read_pos().load(Ordering::Acquire);
+            if self.writer.is_abandoned() {
+                return Err(Abandoned);
+            }
+
+            if self.pump() != 0 {
+                iteration = 0;
+                continue;
+            }
+            let pending = self.readers.iter_mut().any(|r| !r.read_chunk().is_empty());
+            if pending {
+                // a chunk does not fit in the Writer
+                self.writer.wait_for_reader(r, iteration, None);
+            } else if done {
+                return Ok(());
+            } else {
+                self.wait.wait(iteration, None);
+            }
+            iteration = iteration.saturating_add(1);
+        }
+    }
+
+    /// Returns the wrapped Readers and Writer.
+    pub fn into_inner(self) -> (Vec<Reader<T>>, Writer<T>) {
+        (self.readers, self.writer)
+    }
+}
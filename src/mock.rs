@@ -0,0 +1,179 @@
+//! A deterministic, single threaded test double of `cueue`.
+//!
+//! `MockWriter` and `MockReader` implement the `Producer` and `Consumer` traits,
+//! without mapping memory or requiring threads. Their behavior can be scripted:
+//! the queue can be forced to appear full or empty, or the peer abandoned,
+//! to test the handling of these conditions deterministically.
+//!
+//!```
+//! use cueue::{Consumer, Producer};
+//!
+//! fn send<P: Producer<u8>>(p: &mut P, msg: &[u8]) -> bool {
+//!     let buf = p.write_chunk();
+//!     if buf.len() < msg.len() {
+//!         return false;
+//!     }
+//!     buf[..msg.len()].copy_from_slice(msg);
+//!     p.commit(msg.len());
+//!     true
+//! }
+//!
+//! let (mut w, mut r) = cueue::mock::mock::<u8>(16);
+//! w.script_full(1);
+//! assert!(!send(&mut w, b"foo"));
+//! assert!(send(&mut w, b"foo"));
+//! assert_eq!(r.read_chunk(), b"foo");
+//!```
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::{Consumer, Producer};
+
+struct State<T> {
+    capacity: usize,
+    /// committed by the writer, not yet seen by the reader
+    queue: VecDeque<T>,
+    /// number of elements moved to the chunk of the reader, not yet committed
+    held: usize,
+    full_script: usize,
+    empty_script: usize,
+    writer_abandoned: bool,
+    reader_abandoned: bool,
+}
+
+/// Create a connected pair of test doubles, with exactly `capacity` capacity.
+pub fn mock<T>(capacity: usize) -> (MockWriter<T>, MockReader<T>)
+where
+    T: Default,
+{
+    let state = Rc::new(RefCell::new(State {
+        capacity,
+        queue: VecDeque::new(),
+        held: 0,
+        full_script: 0,
+        empty_script: 0,
+        writer_abandoned: false,
+        reader_abandoned: false,
+    }));
+    (
+        MockWriter {
+            state: state.clone(),
+            chunk: Vec::new(),
+            write_capacity: 0,
+        },
+        MockReader {
+            state,
+            chunk: Vec::new(),
+            read_size: 0,
+        },
+    )
+}
+
+/// Test double of `Writer`.
+pub struct MockWriter<T> {
+    state: Rc<RefCell<State<T>>>,
+    chunk: Vec<T>,
+    write_capacity: usize,
+}
+
+impl<T> MockWriter<T> {
+    /// The next `n` calls of `write_chunk` return an empty slice, as if the queue was full.
+    pub fn script_full(&mut self, n: usize) {
+        self.state.borrow_mut().full_script = n;
+    }
+
+    /// Make the Writer report its Reader abandoned (or not).
+    ///
+    /// Dropping the MockReader has the same effect.
+    pub fn set_abandoned(&mut self, abandoned: bool) {
+        self.state.borrow_mut().reader_abandoned = abandoned;
+    }
+}
+
+impl<T> Producer<T> for MockWriter<T>
+where
+    T: Default,
+{
+    fn capacity(&self) -> usize {
+        self.state.borrow().capacity
+    }
+
+    fn write_chunk(&mut self) -> &mut [T] {
+        let mut state = self.state.borrow_mut();
+        self.write_capacity = if state.full_script > 0 {
+            state.full_script -= 1;
+            0
+        } else {
+            state.capacity - state.queue.len() - state.held
+        };
+        self.chunk.resize_with(self.write_capacity, T::default);
+        &mut self.chunk[..]
+    }
+
+    fn commit(&mut self, n: usize) -> usize {
+        let m = usize::min(self.write_capacity, n);
+        self.write_capacity -= m;
+        self.state.borrow_mut().queue.extend(self.chunk.drain(..m));
+        m
+    }
+
+    fn is_abandoned(&self) -> bool {
+        Rc::strong_count(&self.state) < 2 || self.state.borrow().reader_abandoned
+    }
+}
+
+/// Test double of `Reader`.
+pub struct MockReader<T> {
+    state: Rc<RefCell<State<T>>>,
+    chunk: Vec<T>,
+    read_size: usize,
+}
+
+impl<T> MockReader<T> {
+    /// The next `n` calls of `read_chunk` return an empty slice, as if the queue was empty.
+    pub fn script_empty(&mut self, n: usize) {
+        self.state.borrow_mut().empty_script = n;
+    }
+
+    /// Make the Reader report its Writer abandoned (or not).
+    ///
+    /// Dropping the MockWriter has the same effect.
+    pub fn set_abandoned(&mut self, abandoned: bool) {
+        self.state.borrow_mut().writer_abandoned = abandoned;
+    }
+}
+
+impl<T> Consumer<T> for MockReader<T>
+where
+    T: Default,
+{
+    fn capacity(&self) -> usize {
+        self.state.borrow().capacity
+    }
+
+    fn read_chunk(&mut self) -> &[T] {
+        let mut state = self.state.borrow_mut();
+        if state.empty_script > 0 {
+            state.empty_script -= 1;
+            self.read_size = 0;
+        } else {
+            let state = &mut *state;
+            self.chunk.extend(state.queue.drain(..));
+            state.held = self.chunk.len();
+            self.read_size = self.chunk.len();
+        }
+        &self.chunk[..self.read_size]
+    }
+
+    fn commit(&mut self) {
+        self.chunk.drain(..self.read_size);
+        self.state.borrow_mut().held = self.chunk.len();
+        self.read_size = 0;
+    }
+
+    fn is_abandoned(&self) -> bool {
+        Rc::strong_count(&self.state) < 2 || self.state.borrow().writer_abandoned
+    }
+}
@@ -0,0 +1,160 @@
+//! Multiple logical channels over a single byte queue.
+//!
+//! Each message is a frame of the `framed` layer, starting with the id of its channel
+//! (little endian u16), followed by the payload. `Demux` dispatches the messages
+//! to the handlers registered for their channel, therefore many low-rate streams
+//! can share a single queue, and a single consumer thread.
+//!
+//!```
+//! use cueue::mux::{Demux, MuxWriter};
+//!
+//! let (w, r) = cueue::cueue::<u8>(1 << 16).unwrap();
+//! let mut w = MuxWriter::new(w);
+//! let mut demux = Demux::new(r);
+//!
+//! demux.on_channel(1, |payload| assert_eq!(payload, b"foo"));
+//! demux.on_unknown(|channel, _payload| assert_eq!(channel, 2));
+//!
+//! w.send(1, b"foo").unwrap();
+//! w.send(2, b"bar").unwrap();
+//! assert_eq!(demux.dispatch().unwrap(), 2);
+//!```
+
+use std::collections::HashMap;
+
+use crate::framed::{FrameError, FrameReader, FrameWriter, LengthPrefix};
+use crate::{Reader, Writer};
+
+/// Size of the channel id of a message
+const CHANNEL_SIZE: usize = 2;
+
+/// Sends messages to multiple channels.
+pub struct MuxWriter {
+    frames: FrameWriter,
+}
+
+impl MuxWriter {
+    pub fn new(writer: Writer<u8>) -> Self {
+        Self {
+            frames: FrameWriter::with_prefix(writer, LengthPrefix::Varint),
+        }
+    }
+
+    /// Write and commit `payload` to `channel`, or nothing, if there's not enough space.
+    pub fn send(&mut self, channel: u16, payload: &[u8]) -> Result<(), FrameError> {
+        self.send_with(channel, |buf| {
+            let buf = buf.get_mut(..payload.len())?;
+            buf.copy_from_slice(payload);
+            Some(payload.len())
+        })
+    }
+
+    /// Write and commit a payload to `channel`, that is encoded in place by `encode`.
+    ///
+    /// See `FrameWriter::write_frame_with`.
+    pub fn send_with(
+        &mut self,
+        channel: u16,
+        encode: impl FnOnce(&mut [u8]) -> Option<usize>,
+    ) -> Result<(), FrameError> {
+        self.frames.write_frame_with(|buf| {
+            if buf.len() < CHANNEL_SIZE {
+                return None;
+            }
+            let (id, payload) = buf.split_at_mut(CHANNEL_SIZE);
+            id.copy_from_slice(&channel.to_le_bytes());
+            encode(payload).map(|len| CHANNEL_SIZE + len)
+        })
+    }
+
+    /// Returns true, if the Reader counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.frames.is_abandoned()
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<u8> {
+        self.frames.into_inner()
+    }
+}
+
+type Handler = Box<dyn FnMut(&[u8]) + Send>;
+type UnknownHandler = Box<dyn FnMut(u16, &[u8]) + Send>;
+
+/// Dispatches messages to per-channel handlers.
+pub struct Demux {
+    frames: FrameReader,
+    handlers: HashMap<u16, Handler>,
+    unknown: Option<UnknownHandler>,
+}
+
+impl Demux {
+    pub fn new(reader: Reader<u8>) -> Self {
+        Self {
+            frames: FrameReader::with_prefix(reader, LengthPrefix::Varint),
+            handlers: HashMap::new(),
+            unknown: None,
+        }
+    }
+
+    /// Register the handler of the messages of `channel`.
+    ///
+    /// Replaces the previously registered handler of `channel`, if any.
+    pub fn on_channel(&mut self, channel: u16, handler: impl FnMut(&[u8]) + Send + 'static) {
+        self.handlers.insert(channel, Box::new(handler));
+    }
+
+    /// Remove the handler of `channel`: its messages are passed to the unknown handler.
+    pub fn remove_channel(&mut self, channel: u16) {
+        self.handlers.remove(&channel);
+    }
+
+    /// Register the handler of the messages of channels without a handler.
+    ///
+    /// By default, such messages are discarded.
+    pub fn on_unknown(&mut self, handler: impl FnMut(u16, &[u8]) + Send + 'static) {
+        self.unknown = Some(Box::new(handler));
+    }
+
+    /// Pass every available message to its handler, then consume them.
+    ///
+    /// Returns the number of messages dispatched, or Err, if the queue contains
+    /// a malformed message: messages before it are dispatched and consumed,
+    /// a message too short to contain a channel id is skipped.
+    pub fn dispatch(&mut self) -> Result<usize, FrameError> {
+        let mut n = 0;
+        let result = loop {
+            let frame = match self.frames.try_read_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break Ok(n),
+                Err(err) => break Err(err),
+            };
+            if frame.len() < CHANNEL_SIZE {
+                break Err(FrameError::Malformed);
+            }
+            let (id, payload) = frame.split_at(CHANNEL_SIZE);
+            let channel = u16::from_le_bytes([id[0], id[1]]);
+            match self.handlers.get_mut(&channel) {
+                Some(handler) => handler(payload),
+                None => {
+                    if let Some(unknown) = &mut self.unknown {
+                        unknown(channel, payload);
+                    }
+                }
+            }
+            n += 1;
+        };
+        self.frames.commit();
+        result
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.frames.is_abandoned()
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<u8> {
+        self.frames.into_inner()
+    }
+}
@@ -0,0 +1,120 @@
+//! A one-way relay between two sockets, through a byte queue.
+//!
+//! `relay` spawns a producer thread, that receives from a socket into the queue
+//! (see `Writer::recv_from_socket`), and a consumer thread, that sends from the queue
+//! to the other socket (see `Reader::send_to_socket`): the bytes are copied once
+//! into, and once out of the kernel. If the destination is slower than the source,
+//! the queue fills up, and the producer stops receiving: the flow control of the
+//! source connection pushes back to its peer. A proxy runs one relay per direction.
+//!
+//! If the source reaches EOF, the relayed bytes are sent, then the destination is shut down
+//! for writing. If sending fails, the producer stops at the next full queue,
+//! but a producer blocked receiving only notices it when the source is readable again.
+//!
+//!```
+//! use std::io::{Read, Write};
+//! use std::os::unix::net::UnixStream;
+//!
+//! let (mut client, from) = UnixStream::pair().unwrap();
+//! let (to, mut server) = UnixStream::pair().unwrap();
+//! let relay = cueue::net::relay(from.into(), to.into(), 1 << 16).unwrap();
+//!
+//! client.write_all(b"hello").unwrap();
+//! drop(client);
+//!
+//! let mut buf = Vec::new();
+//! server.read_to_end(&mut buf).unwrap();
+//! assert_eq!(buf, b"hello");
+//! assert_eq!(relay.join().unwrap(), 5);
+//!```
+
+use std::io;
+use std::os::unix::io::{AsFd, AsRawFd, OwnedFd};
+use std::panic::resume_unwind;
+use std::sync::atomic::Ordering;
+use std::thread::JoinHandle;
+
+use crate::{Builder, CError, Reader, Writer};
+
+/// The threads of a running relay, see `relay`.
+pub struct Relay {
+    producer: JoinHandle<io::Result<u64>>,
+    consumer: JoinHandle<io::Result<u64>>,
+}
+
+impl Relay {
+    /// Wait until the source reaches EOF (and every byte is sent), or the relay fails.
+    ///
+    /// Returns the number of bytes sent to the destination.
+    /// Panics, if a relay thread panicked.
+    pub fn join(self) -> io::Result<u64> {
+        let join = |thread: JoinHandle<_>| thread.join().unwrap_or_else(|e| resume_unwind(e));
+        let sent = join(self.consumer);
+        join(self.producer)?;
+        sent
+    }
+}
+
+/// Relay the bytes received from `from` to `to`, through a queue of at least `capacity` bytes,
+/// see the module docs.
+///
+/// The sockets must be in blocking mode.
+pub fn relay(from: OwnedFd, to: OwnedFd, capacity: usize) -> Result<Relay, CError> {
+    let (writer, reader) = Builder::new(capacity)
+        .futex(cfg!(target_os = "linux"))
+        .build::<u8>()?;
+    let spawn = |name: &str| std::thread::Builder::new().name(name.into());
+    let spawn_failed = |err| CError {
+        hint: "failed to spawn the relay thread",
+        err,
+    };
+    let producer = spawn("cueue-relay-recv")
+        .spawn(move || produce(writer, from))
+        .map_err(spawn_failed)?;
+    let consumer = spawn("cueue-relay-send")
+        .spawn(move || consume(reader, to))
+        .map_err(spawn_failed)?;
+    Ok(Relay { producer, consumer })
+}
+
+/// Receive from `from` until EOF, then drop the Writer, to let the consumer finish.
+fn produce(mut writer: Writer<u8>, from: OwnedFd) -> io::Result<u64> {
+    let mut received = 0;
+    let mut iteration = 0;
+    loop {
+        // loaded before the queue is found full, to wait for the next commit of the Reader
+        let r = writer.read_pos().load(Ordering::Acquire);
+        match writer.recv_from_socket(from.as_fd(), 0) {
+            Ok(0) => return Ok(received),
+            Ok(n) => {
+                received += n as u64;
+                iteration = 0;
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if writer.is_abandoned() {
+                    return Err(io::ErrorKind::BrokenPipe.into());
+                }
+                writer.wait_for_reader(r, iteration, None);
+                iteration = iteration.saturating_add(1);
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Send to `to` until the producer is done, then shut `to` down for writing.
+fn consume(mut reader: Reader<u8>, to: OwnedFd) -> io::Result<u64> {
+    let mut sent = 0;
+    while reader.read_chunk_blocking().is_ok() {
+        match reader.send_to_socket(to.as_fd(), 0) {
+            Ok(n) => sent += n as u64,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    if unsafe { libc::shutdown(to.as_raw_fd(), libc::SHUT_WR) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(sent)
+}
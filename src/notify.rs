@@ -0,0 +1,160 @@
+//! Readiness notification between the Writer and the Reader, using file descriptors.
+//!
+//! Each handle waits on its own event, that is signalled by the other handle
+//! on commit and on drop. The events are file descriptors that become readable
+//! when signalled, therefore they can be registered with any reactor (epoll, kqueue).
+
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::sync::Arc;
+
+use crate::CError;
+
+/// A level triggered event: readable after `signal`, until `clear`.
+#[cfg(target_os = "linux")]
+pub(crate) struct Event {
+    fd: OwnedFd,
+}
+
+#[cfg(target_os = "linux")]
+impl Event {
+    fn new() -> Result<Self, CError> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(CError::new("eventfd"));
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    fn signal(&self) {
+        let one: u64 = 1;
+        unsafe {
+            libc::write(
+                self.fd.as_raw_fd(),
+                (&one as *const u64).cast(),
+                std::mem::size_of::<u64>(),
+            );
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut counter: u64 = 0;
+        unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                (&mut counter as *mut u64).cast(),
+                std::mem::size_of::<u64>(),
+            );
+        }
+    }
+
+    pub(crate) fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// A level triggered event: readable after `signal`, until `clear`.
+#[cfg(any(target_os = "macos", target_os = "nto"))]
+pub(crate) struct Event {
+    rx: OwnedFd,
+    tx: OwnedFd,
+}
+
+#[cfg(any(target_os = "macos", target_os = "nto"))]
+impl Event {
+    fn new() -> Result<Self, CError> {
+        let mut fds = [0; 2];
+        unsafe {
+            if libc::pipe(fds.as_mut_ptr()) != 0 {
+                return Err(CError::new("pipe"));
+            }
+            let (rx, tx) = (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]));
+            for fd in fds {
+                if libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK) != 0
+                    || libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) != 0
+                {
+                    return Err(CError::new("fcntl"));
+                }
+            }
+            Ok(Self { rx, tx })
+        }
+    }
+
+    fn signal(&self) {
+        // if the pipe is full, it is readable already
+        let one = 1u8;
+        unsafe {
+            libc::write(self.tx.as_raw_fd(), (&one as *const u8).cast(), 1);
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut buf = [0u8; 64];
+        while unsafe { libc::read(self.rx.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+    }
+
+    pub(crate) fn as_fd(&self) -> BorrowedFd<'_> {
+        self.rx.as_fd()
+    }
+}
+
+struct Events {
+    /// signalled by the Writer, waited by the Reader
+    readable: Event,
+    /// signalled by the Reader, waited by the Writer
+    writable: Event,
+}
+
+/// The notification endpoint of a single handle.
+///
+/// Notifies the peer when dropped.
+pub(crate) struct Notify {
+    events: Arc<Events>,
+    writer: bool,
+}
+
+impl Notify {
+    /// Returns the endpoints of the Writer and the Reader, respectively.
+    pub(crate) fn pair() -> Result<(Notify, Notify), CError> {
+        let events = Arc::new(Events {
+            readable: Event::new()?,
+            writable: Event::new()?,
+        });
+        Ok((
+            Notify {
+                events: events.clone(),
+                writer: true,
+            },
+            Notify {
+                events,
+                writer: false,
+            },
+        ))
+    }
+
+    /// The event this handle waits on.
+    pub(crate) fn event(&self) -> &Event {
+        if self.writer {
+            &self.events.writable
+        } else {
+            &self.events.readable
+        }
+    }
+
+    /// Wake the other handle.
+    #[inline]
+    pub(crate) fn notify_peer(&self) {
+        if self.writer {
+            self.events.readable.signal();
+        } else {
+            self.events.writable.signal();
+        }
+    }
+}
+
+impl Drop for Notify {
+    fn drop(&mut self) {
+        self.notify_peer();
+    }
+}
@@ -0,0 +1,119 @@
+//! Adaptive batching on the Reader side.
+//!
+//! Processing elements in large chunks is more efficient, but waiting for a chunk
+//! to fill up adds latency. `PacedReader` estimates the rate the Writer commits elements at,
+//! and if a chunk of the target size is expected to be available soon (within a bound),
+//! it waits for it. If the traffic is light, available elements are returned immediately.
+//!
+//!```
+//! use std::time::Duration;
+//!
+//! use cueue::paced::PacedReader;
+//!
+//! let (mut w, r) = cueue::cueue::<u8>(1 << 12).unwrap();
+//! let mut r = PacedReader::new(r, 64, Duration::from_millis(1));
+//!
+//! w.push(1).unwrap();
+//! assert_eq!(r.read_chunk(), [1]);
+//! r.commit();
+//!```
+
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::Reader;
+
+/// Weight of the latest sample in the estimated rate
+const RATE_ALPHA: f64 = 0.25;
+
+/// Wraps a Reader, to wait a bounded, adaptive amount of time for larger chunks.
+pub struct PacedReader<T> {
+    reader: Reader<T>,
+    target: usize,
+    max_delay: Duration,
+    /// estimated rate of the Writer, elements per second
+    rate: f64,
+    /// time and write position of the previous `read_chunk`
+    last: Option<(Instant, u64)>,
+}
+
+impl<T> PacedReader<T>
+where
+    T: Default,
+{
+    /// Wait for chunks of `target` elements, at most `max_delay` at a time.
+    ///
+    /// Waiting uses the wait strategy of the Reader, see `Reader::set_wait_strategy`.
+    pub fn new(reader: Reader<T>, target: usize, max_delay: Duration) -> Self {
+        Self {
+            reader,
+            target,
+            max_delay,
+            rate: 0.0,
+            last: None,
+        }
+    }
+
+    /// Return a slice of elements written and committed by the Writer.
+    ///
+    /// If less than `target` elements are available, but the estimated rate of the Writer
+    /// allows reaching `target` within `max_delay`, waits until then.
+    /// Returns immediately if the queue is empty, or the traffic is light.
+    pub fn read_chunk(&mut self) -> &[T] {
+        let now = Instant::now();
+        let w = self.reader.write_pos().load(Ordering::Acquire);
+        if let Some((last_time, last_w)) = self.last {
+            let elapsed = now.duration_since(last_time).as_secs_f64();
+            if elapsed > 0.0 {
+                let sample = (w - last_w) as f64 / elapsed;
+                self.rate = RATE_ALPHA * sample + (1.0 - RATE_ALPHA) * self.rate;
+            }
+        }
+        self.last = Some((now, w));
+
+        let available = self.reader.read_chunk().len();
+        if available == 0 || available >= self.target || self.rate <= 0.0 {
+            return self.reader.read_chunk();
+        }
+
+        let fill = (self.target - available) as f64 / self.rate;
+        if fill > self.max_delay.as_secs_f64() {
+            return self.reader.read_chunk();
+        }
+
+        let deadline = now + Duration::from_secs_f64(fill);
+        let mut iteration = 0;
+        loop {
+            let w = self.reader.write_pos().load(Ordering::Acquire);
+            if self.reader.read_chunk().len() >= self.target
+                || Instant::now() >= deadline
+                || self.reader.is_abandoned()
+            {
+                break;
+            }
+            self.reader.wait_for_writer(w, iteration, Some(deadline));
+            iteration = iteration.saturating_add(1);
+        }
+        self.reader.read_chunk()
+    }
+
+    /// Mark the slice previously acquired by `read_chunk` as consumed.
+    pub fn commit(&mut self) {
+        self.reader.commit();
+    }
+
+    /// The estimated rate of the Writer, in elements per second.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.reader.is_abandoned()
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<T> {
+        self.reader
+    }
+}
@@ -0,0 +1,103 @@
+//! A transform stage, between an upstream and a downstream queue.
+//!
+//! `connect` pumps the elements of an upstream Reader through a transform function
+//! into a downstream Writer: the transform gets every readable element of the upstream
+//! queue, and every writable element of the downstream queue at once (i.e: the stage
+//! is batched), and reports how many it consumed and produced. If the downstream queue
+//! is full, the stage stops consuming: the upstream queue fills up, and the backpressure
+//! propagates to the first stage of the pipeline.
+//!
+//!```
+//! let (mut w, r) = cueue::cueue::<u8>(1 << 12).unwrap();
+//! let (w2, mut r2) = cueue::cueue::<u8>(1 << 12).unwrap();
+//!
+//! let stage = std::thread::spawn(move || {
+//!     cueue::pipeline::connect(r, w2, |input: &[u8], output: &mut [u8]| {
+//!         let n = usize::min(input.len(), output.len());
+//!         output[..n].copy_from_slice(&input[..n]);
+//!         output[..n].make_ascii_uppercase();
+//!         (n, n)
+//!     })
+//! });
+//!
+//! w.write_chunk()[..5].copy_from_slice(b"hello");
+//! w.commit(5);
+//! drop(w);
+//!
+//! assert_eq!(r2.read_exact_timeout(5, std::time::Duration::from_secs(60)), Some(&b"HELLO"[..]));
+//! stage.join().unwrap().unwrap();
+//!```
+
+use std::sync::atomic::Ordering;
+
+use crate::{Abandoned, Reader, Writer};
+
+/// Run a transform stage from `reader` to `writer`, until the upstream Writer is dropped,
+/// see the module docs.
+///
+/// `transform` gets the readable elements of `reader`, and the writable elements of `writer`,
+/// both non-empty, and returns the number of input elements it consumed, and the number of
+/// output elements it produced (both truncated to the length of the slices).
+/// If it consumes and produces nothing, it is called again once more input or more output
+/// space is available, e.g: if the input ends with an incomplete record.
+///
+/// The stage waits using the wait strategies of `reader` and `writer`,
+/// or sleeps on the futex of the queues, if `Builder::futex` is enabled.
+/// Returns Ok, once the upstream Writer is dropped, and every element is processed
+/// (except an incomplete input `transform` could not consume), or Err,
+/// if the downstream Reader is dropped.
+///
+/// Panics, if `transform` makes no progress with a full input and an empty output,
+/// as it would wait forever.
+pub fn connect<A, B, F>(
+    mut reader: Reader<A>,
+    mut writer: Writer<B>,
+    mut transform: F,
+) -> Result<(), Abandoned>
+where
+    A: Default,
+    B: Default,
+    F: FnMut(&[A], &mut [B]) -> (usize, usize),
+{
+    let (in_capacity, out_capacity) = (reader.capacity(), writer.capacity());
+    let mut iteration = 0;
+    loop {
+        // loaded before the chunks, to wait for the next commit of the peers
+        let upstream_done = reader.is_abandoned();
+        let w = reader.write_pos().load(Ordering::Acquire);
+        let r = writer.read_pos().load(Ordering::Acquire);
+        if writer.is_abandoned() {
+            return Err(Abandoned);
+        }
+
+        let input = reader.read_chunk();
+        let output = writer.write_chunk();
+        let (in_len, out_len) = (input.len(), output.len());
+        let (consumed, produced) = if in_len != 0 && out_len != 0 {
+            transform(input, output)
+        } else {
+            (0, 0)
+        };
+        let produced = writer.commit(produced);
+        let consumed = reader.limited_read_chunk(consumed).len();
+        reader.commit();
+        if consumed != 0 || produced != 0 {
+            iteration = 0;
+            continue;
+        }
+
+        if out_len == 0 || (in_len != 0 && out_len < out_capacity) {
+            // the output might be too small: wait for the downstream Reader
+            writer.wait_for_reader(r, iteration, None);
+        } else if upstream_done {
+            return Ok(());
+        } else {
+            assert!(
+                in_len < in_capacity,
+                "transform made no progress with a full input and an empty output"
+            );
+            reader.wait_for_writer(w, iteration, None);
+        }
+        iteration = iteration.saturating_add(1);
+    }
+}
@@ -0,0 +1,104 @@
+//! Typed views over byte queues, for `bytemuck::Pod` types.
+//!
+//! A single `u8` queue can carry plain data structs of any type, without a typed queue
+//! per struct type. Single values can be written and read at any position,
+//! `write_chunk_as`/`read_chunk_as` view the chunk as a slice of `T`, without copying,
+//! if the chunk is suitably aligned: the buffer is page aligned, therefore the chunk
+//! is aligned, if every preceding record is a multiple of the alignment of `T`.
+//!
+//!```
+//! #[derive(Clone, Copy, Debug, PartialEq)]
+//! #[repr(C)]
+//! struct Sample {
+//!     timestamp: u64,
+//!     value: f64,
+//! }
+//!
+//! // or #[derive(Pod, Zeroable)], with the `derive` feature of bytemuck
+//! unsafe impl bytemuck::Zeroable for Sample {}
+//! unsafe impl bytemuck::Pod for Sample {}
+//!
+//! let (mut w, mut r) = cueue::cueue::<u8>(1 << 12).unwrap();
+//!
+//! let samples = w.write_chunk_as::<Sample>().unwrap();
+//! samples[0] = Sample { timestamp: 1, value: 0.5 };
+//! samples[1] = Sample { timestamp: 2, value: 1.5 };
+//! w.commit_as::<Sample>(2);
+//! assert!(w.push_pod(&Sample { timestamp: 3, value: 2.5 }));
+//!
+//! assert_eq!(r.read_chunk_as::<Sample>().unwrap().len(), 3);
+//! r.commit();
+//! assert_eq!(r.pop_pod::<Sample>(), None);
+//!```
+
+use bytemuck::{Pod, PodCastError};
+
+use crate::{Reader, Writer};
+
+/// Size of `T`, that must not be zero.
+fn size<T>() -> usize {
+    let size = std::mem::size_of::<T>();
+    assert!(size != 0, "zero sized types are not supported");
+    size
+}
+
+impl Writer<u8> {
+    /// Get the writable chunk as a slice of `T`, see `write_chunk`.
+    ///
+    /// The slice contains the whole values that fit in the chunk.
+    /// Returns Err, if the chunk is not aligned for `T`. Use `commit_as` to commit.
+    pub fn write_chunk_as<T: Pod>(&mut self) -> Result<&mut [T], PodCastError> {
+        let size = size::<T>();
+        let chunk = self.write_chunk();
+        let len = chunk.len() / size * size;
+        bytemuck::try_cast_slice_mut(&mut chunk[..len])
+    }
+
+    /// Make `n` values, written to the slice returned by `write_chunk_as` available for reading.
+    ///
+    /// Returns the number of committed values, see `commit`.
+    pub fn commit_as<T: Pod>(&mut self, n: usize) -> usize {
+        let size = size::<T>();
+        self.commit(n.saturating_mul(size)) / size
+    }
+
+    /// Write and commit the bytes of `value`, at any alignment.
+    ///
+    /// Returns false, if there's not enough space.
+    pub fn push_pod<T: Pod>(&mut self, value: &T) -> bool {
+        let bytes = bytemuck::bytes_of(value);
+        match self.write_chunk_exact(bytes.len()) {
+            Some(buf) => {
+                buf.copy_from_slice(bytes);
+                self.commit(bytes.len());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Reader<u8> {
+    /// Return the whole values of `T` available, see `read_chunk`.
+    ///
+    /// `commit` consumes the returned values only.
+    /// Returns Err, and consumes nothing, if the chunk is not aligned for `T`.
+    pub fn read_chunk_as<T: Pod>(&mut self) -> Result<&[T], PodCastError> {
+        let size = size::<T>();
+        let chunk = self.read_chunk();
+        if chunk.as_ptr() as usize & (std::mem::align_of::<T>() - 1) != 0 {
+            self.limited_read_chunk(0);
+            return Err(PodCastError::TargetAlignmentGreaterAndInputNotAligned);
+        }
+        let len = chunk.len() / size * size;
+        bytemuck::try_cast_slice(self.limited_read_chunk(len))
+    }
+
+    /// Read and consume a single value of `T`, at any alignment,
+    /// or None, if not enough bytes are available.
+    pub fn pop_pod<T: Pod>(&mut self) -> Option<T> {
+        let value = bytemuck::pod_read_unaligned(self.read_chunk_exact(size::<T>())?);
+        self.commit();
+        Some(value)
+    }
+}
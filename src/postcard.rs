@@ -0,0 +1,143 @@
+//! Typed messages over a byte queue, serialized with postcard.
+//!
+//! Each message is a frame of the `framed` layer, serialized in place
+//! (without a temporary buffer) using the compact, `no_std` friendly
+//! postcard format, therefore embedded and host code can share protocol definitions.
+//!
+//!```
+//! use cueue::postcard::{PostcardReader, PostcardWriter};
+//!
+//! let (w, r) = cueue::cueue::<u8>(1 << 16).unwrap();
+//! let mut w = PostcardWriter::<(u32, String)>::new(w);
+//! let mut r = PostcardReader::<(u32, String)>::new(r);
+//!
+//! w.send(&(1, "foo".to_string())).unwrap();
+//! assert_eq!(r.recv().unwrap(), Some((1, "foo".to_string())));
+//! r.commit();
+//!```
+
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::framed::{FrameError, FrameReader, FrameWriter, LengthPrefix};
+use crate::{Reader, Writer};
+
+/// Error of a typed send or receive.
+#[derive(Debug)]
+pub enum Error {
+    /// The message could not be written or read as a frame
+    Frame(FrameError),
+    /// The message could not be serialized or deserialized
+    Postcard(::postcard::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Frame(err) => write!(f, "{}", err),
+            Error::Postcard(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<FrameError> for Error {
+    fn from(err: FrameError) -> Self {
+        Error::Frame(err)
+    }
+}
+
+/// Sends messages of type `T`.
+pub struct PostcardWriter<T> {
+    frames: FrameWriter,
+    _t: PhantomData<fn(&T)>,
+}
+
+impl<T> PostcardWriter<T>
+where
+    T: Serialize,
+{
+    pub fn new(writer: Writer<u8>) -> Self {
+        Self {
+            frames: FrameWriter::with_prefix(writer, LengthPrefix::Varint),
+            _t: PhantomData,
+        }
+    }
+
+    /// Serialize and commit `msg`, or nothing, if there's not enough space.
+    pub fn send(&mut self, msg: &T) -> Result<(), Error> {
+        let mut error = None;
+        let result = self
+            .frames
+            .write_frame_with(|buf| match ::postcard::to_slice(msg, buf) {
+                Ok(used) => Some(used.len()),
+                Err(::postcard::Error::SerializeBufferFull) => None,
+                Err(err) => {
+                    error = Some(err);
+                    None
+                }
+            });
+        match error {
+            Some(err) => Err(Error::Postcard(err)),
+            None => result.map_err(Error::Frame),
+        }
+    }
+
+    /// Returns true, if the Reader counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.frames.is_abandoned()
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<u8> {
+        self.frames.into_inner()
+    }
+}
+
+/// Receives messages of type `T`.
+pub struct PostcardReader<T> {
+    frames: FrameReader,
+    _t: PhantomData<fn() -> T>,
+}
+
+impl<T> PostcardReader<T>
+where
+    T: DeserializeOwned,
+{
+    pub fn new(reader: Reader<u8>) -> Self {
+        Self {
+            frames: FrameReader::with_prefix(reader, LengthPrefix::Varint),
+            _t: PhantomData,
+        }
+    }
+
+    /// Deserialize the next message, if available.
+    ///
+    /// Received messages are consumed from the queue by `commit`.
+    pub fn recv(&mut self) -> Result<Option<T>, Error> {
+        match self.frames.try_read_frame()? {
+            Some(frame) => ::postcard::from_bytes(frame)
+                .map(Some)
+                .map_err(Error::Postcard),
+            None => Ok(None),
+        }
+    }
+
+    /// Consume every message received so far, making space for the Writer.
+    pub fn commit(&mut self) {
+        self.frames.commit();
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.frames.is_abandoned()
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<u8> {
+        self.frames.into_inner()
+    }
+}
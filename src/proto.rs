@@ -0,0 +1,125 @@
+//! Protobuf messages over a byte queue, encoded with prost.
+//!
+//! Each message is encoded in place, prefixed by its varint length: the contents
+//! of the queue is a stream of length-delimited protobuf messages
+//! (`prost::Message::decode_length_delimited` compatible).
+//!
+//!```
+//! use cueue::proto::{ProtoReader, ProtoWriter};
+//!
+//! let (w, r) = cueue::cueue::<u8>(1 << 16).unwrap();
+//! let mut w = ProtoWriter::new(w);
+//! let mut r = ProtoReader::new(r);
+//!
+//! w.send(&"foo".to_string()).unwrap();
+//! assert_eq!(r.recv::<String>().unwrap(), Some("foo".to_string()));
+//! r.commit();
+//!```
+
+use prost::Message;
+
+use crate::framed::{FrameError, FrameReader, FrameWriter, LengthPrefix};
+use crate::{Reader, Writer};
+
+/// Error of receiving a message.
+#[derive(Debug)]
+pub enum Error {
+    /// The message could not be read as a frame
+    Frame(FrameError),
+    /// The message could not be decoded
+    Decode(prost::DecodeError),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Frame(err) => write!(f, "{}", err),
+            Error::Decode(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<FrameError> for Error {
+    fn from(err: FrameError) -> Self {
+        Error::Frame(err)
+    }
+}
+
+/// Sends protobuf messages.
+pub struct ProtoWriter {
+    frames: FrameWriter,
+}
+
+impl ProtoWriter {
+    pub fn new(writer: Writer<u8>) -> Self {
+        Self {
+            frames: FrameWriter::with_prefix(writer, LengthPrefix::Varint),
+        }
+    }
+
+    /// Encode and commit `msg`, or nothing, if there's not enough space.
+    pub fn send(&mut self, msg: &impl Message) -> Result<(), FrameError> {
+        let len = msg.encoded_len();
+        self.frames.write_frame_with(|mut buf| {
+            if buf.len() < len {
+                return None;
+            }
+            // cannot fail: the buffer is large enough
+            msg.encode(&mut buf).ok()?;
+            Some(len)
+        })
+    }
+
+    /// Returns true, if the Reader counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.frames.is_abandoned()
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<u8> {
+        self.frames.into_inner()
+    }
+}
+
+/// Receives protobuf messages.
+pub struct ProtoReader {
+    frames: FrameReader,
+}
+
+impl ProtoReader {
+    pub fn new(reader: Reader<u8>) -> Self {
+        Self {
+            frames: FrameReader::with_prefix(reader, LengthPrefix::Varint),
+        }
+    }
+
+    /// Decode the next message as `M`, if available.
+    ///
+    /// Received messages are consumed from the queue by `commit`.
+    pub fn recv<M>(&mut self) -> Result<Option<M>, Error>
+    where
+        M: Message + Default,
+    {
+        match self.frames.try_read_frame()? {
+            Some(frame) => M::decode(frame).map(Some).map_err(Error::Decode),
+            None => Ok(None),
+        }
+    }
+
+    /// Consume every message received so far, making space for the Writer.
+    pub fn commit(&mut self) {
+        self.frames.commit();
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.frames.is_abandoned()
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<u8> {
+        self.frames.into_inner()
+    }
+}
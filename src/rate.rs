@@ -0,0 +1,157 @@
+//! Rate limiting on the Writer side.
+//!
+//! `RateLimitedWriter` limits the number of elements (bytes, for byte queues) committed
+//! per second, using a token bucket, so a chatty producer can't starve the downstream
+//! sinks of the consumer. When the limit is exceeded, it either blocks, or sheds elements
+//! (dropping them, and reporting them to the Reader as a gap, see `Reader::gap`).
+//!
+//!```
+//! use cueue::rate::{Policy, RateLimit, RateLimitedWriter};
+//!
+//! let (w, mut r) = cueue::cueue::<u32>(1 << 12).unwrap();
+//! let limit = RateLimit { per_second: 1.0, burst: 2 };
+//! let mut w = RateLimitedWriter::new(w, limit, Policy::Shed);
+//!
+//! for i in 0..5 {
+//!     w.push(i).unwrap();
+//! }
+//! assert_eq!(w.shed(), 3);
+//! assert_eq!(r.read_chunk(), [0, 1]);
+//! assert_eq!(r.gap(), 3);
+//!```
+
+use std::time::{Duration, Instant};
+
+use crate::Writer;
+
+/// The rate a Writer is allowed to commit at.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    /// Sustained rate, in elements per second
+    pub per_second: f64,
+    /// Maximum number of elements committed at once, after a period of inactivity
+    pub burst: usize,
+}
+
+/// What to do, if the rate limit is exceeded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Wait until the rate allows writing
+    Block,
+    /// Drop the elements, and count them
+    Shed,
+}
+
+/// Wraps a Writer, to limit the rate of commits.
+pub struct RateLimitedWriter<T> {
+    writer: Writer<T>,
+    limit: RateLimit,
+    policy: Policy,
+    /// available tokens: the number of elements allowed to commit now
+    tokens: f64,
+    refilled: Instant,
+    shed: u64,
+}
+
+impl<T> RateLimitedWriter<T>
+where
+    T: Default,
+{
+    /// Create a RateLimitedWriter, with a full bucket: `limit.burst` elements can be written at once.
+    ///
+    /// Panics if `limit.burst` is zero, or `limit.per_second` is not positive.
+    pub fn new(writer: Writer<T>, limit: RateLimit, policy: Policy) -> Self {
+        assert!(limit.burst != 0, "burst must be positive");
+        assert!(limit.per_second > 0.0, "rate must be positive");
+        Self {
+            writer,
+            limit,
+            policy,
+            tokens: limit.burst as f64,
+            refilled: Instant::now(),
+            shed: 0,
+        }
+    }
+
+    /// Get a writable slice of the free space of the queue, limited by the rate.
+    ///
+    /// If `Policy::Block` is set, and the queue is not full, waits until at least one element
+    /// can be written. If `Policy::Shed` is set, the returned slice might be empty,
+    /// see `record_shed`.
+    pub fn write_chunk(&mut self) -> &mut [T] {
+        self.refill();
+        if self.policy == Policy::Block
+            && self.tokens < 1.0
+            && !self.writer.write_chunk().is_empty()
+        {
+            self.wait_for_token();
+        }
+        self.writer.limited_write_chunk(self.tokens as usize)
+    }
+
+    /// Make `n` elements, written to the slice returned by `write_chunk` available for reading.
+    ///
+    /// Returns the number of committed elements, see `Writer::commit`.
+    pub fn commit(&mut self, n: usize) -> usize {
+        let n = self.writer.commit(n);
+        self.tokens -= n as f64;
+        n
+    }
+
+    /// Write and commit a single element, or return it if the queue was full.
+    ///
+    /// If the rate limit is exceeded, waits (`Policy::Block`),
+    /// or drops the element, and counts it as shed (`Policy::Shed`).
+    pub fn push(&mut self, t: T) -> Result<(), T> {
+        if self.writer.write_chunk().is_empty() {
+            return Err(t);
+        }
+        if self.write_chunk().is_empty() {
+            self.record_shed(1);
+            return Ok(());
+        }
+        self.writer.push(t)?;
+        self.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Record that `n` elements were shed because of the rate limit.
+    ///
+    /// Each element is reported to the Reader as a dropped record, see `Writer::record_dropped`.
+    pub fn record_shed(&mut self, n: u64) {
+        self.shed += n;
+        self.writer.record_dropped(n);
+    }
+
+    /// The number of elements shed so far.
+    pub fn shed(&self) -> u64 {
+        self.shed
+    }
+
+    /// Returns true, if the Reader counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.writer.is_abandoned()
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<T> {
+        self.writer
+    }
+
+    /// Add the tokens earned since the last refill.
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let earned = now.duration_since(self.refilled).as_secs_f64() * self.limit.per_second;
+        self.tokens = f64::min(self.tokens + earned, self.limit.burst as f64);
+        self.refilled = now;
+    }
+
+    /// Sleep until a token is available.
+    fn wait_for_token(&mut self) {
+        while self.tokens < 1.0 {
+            let missing = (1.0 - self.tokens) / self.limit.per_second;
+            std::thread::sleep(Duration::from_secs_f64(missing));
+            self.refill();
+        }
+    }
+}
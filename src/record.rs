@@ -0,0 +1,279 @@
+//! Record the operations performed on a queue, and replay them offline.
+//!
+//! `RecordingWriter` and `RecordingReader` wrap any `Producer` and `Consumer`,
+//! and append every operation (with the size of the returned chunks and the committed counts)
+//! to a shared `Recorder`, timestamped. The log can be saved (`Recorder::write_log`),
+//! loaded (`read_log`), then re-executed by `replay` on a single thread,
+//! in the recorded order, to reproduce a hard to reproduce interleaving deterministically.
+//!
+//!```
+//! use cueue::record::{replay, Recorder, RecordingReader, RecordingWriter};
+//! use cueue::{Consumer, Producer};
+//!
+//! let recorder = Recorder::new();
+//! let (w, r) = cueue::cueue::<u8>(16).unwrap();
+//! let mut w = RecordingWriter::new(w, recorder.clone());
+//! let mut r = RecordingReader::new(r, recorder.clone());
+//!
+//! w.write_chunk();
+//! w.commit(3);
+//! r.read_chunk();
+//! r.commit();
+//!
+//! let mut log = Vec::new();
+//! recorder.write_log(&mut log).unwrap();
+//! let events = cueue::record::read_log(&log[..]).unwrap();
+//!
+//! let (mut w, mut r) = cueue::mock::mock::<u8>(w.capacity());
+//! assert!(replay(&events, &mut w, &mut r).is_ok());
+//!```
+
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{Consumer, Producer};
+
+/// An operation performed on a queue handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// `write_chunk` returned a slice of the given size
+    WriteChunk(usize),
+    /// The Writer committed the given number of elements
+    WriterCommit(usize),
+    /// `read_chunk` returned a slice of the given size
+    ReadChunk(usize),
+    /// The Reader committed
+    ReaderCommit,
+}
+
+/// A recorded operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// Time elapsed since the creation of the Recorder
+    pub at: Duration,
+    pub op: Op,
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let nanos = self.at.as_nanos();
+        match self.op {
+            Op::WriteChunk(n) => write!(f, "{} write_chunk {}", nanos, n),
+            Op::WriterCommit(n) => write!(f, "{} writer_commit {}", nanos, n),
+            Op::ReadChunk(n) => write!(f, "{} read_chunk {}", nanos, n),
+            Op::ReaderCommit => write!(f, "{} reader_commit", nanos),
+        }
+    }
+}
+
+impl std::str::FromStr for Event {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, s.to_string());
+        let mut fields = s.split_whitespace();
+        let nanos: u64 = fields
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?;
+        let name = fields.next().ok_or_else(invalid)?;
+        let mut arg = || -> io::Result<usize> {
+            fields
+                .next()
+                .ok_or_else(invalid)?
+                .parse()
+                .map_err(|_| invalid())
+        };
+        let op = match name {
+            "write_chunk" => Op::WriteChunk(arg()?),
+            "writer_commit" => Op::WriterCommit(arg()?),
+            "read_chunk" => Op::ReadChunk(arg()?),
+            "reader_commit" => Op::ReaderCommit,
+            _ => return Err(invalid()),
+        };
+        Ok(Event {
+            at: Duration::from_nanos(nanos),
+            op,
+        })
+    }
+}
+
+/// A shared, in-memory log of operations. Cloning it yields a handle to the same log.
+#[derive(Clone)]
+pub struct Recorder {
+    start: Instant,
+    events: Arc<Mutex<Vec<Event>>>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn record(&self, op: Op) {
+        let at = self.start.elapsed();
+        self.events.lock().unwrap().push(Event { at, op });
+    }
+
+    /// Returns the events recorded so far.
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Write the events recorded so far to `out`, one per line.
+    pub fn write_log(&self, mut out: impl Write) -> io::Result<()> {
+        for event in self.events.lock().unwrap().iter() {
+            writeln!(out, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read events written by `Recorder::write_log`.
+pub fn read_log(input: impl BufRead) -> io::Result<Vec<Event>> {
+    let mut events = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            events.push(line.parse()?);
+        }
+    }
+    Ok(events)
+}
+
+/// A Producer, that records its operations.
+pub struct RecordingWriter<P> {
+    inner: P,
+    recorder: Recorder,
+}
+
+impl<P> RecordingWriter<P> {
+    pub fn new(inner: P, recorder: Recorder) -> Self {
+        Self { inner, recorder }
+    }
+
+    /// Returns the wrapped Producer.
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+impl<T, P> Producer<T> for RecordingWriter<P>
+where
+    P: Producer<T>,
+{
+    fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    fn write_chunk(&mut self) -> &mut [T] {
+        let chunk = self.inner.write_chunk();
+        self.recorder.record(Op::WriteChunk(chunk.len()));
+        chunk
+    }
+
+    fn commit(&mut self, n: usize) -> usize {
+        let m = self.inner.commit(n);
+        self.recorder.record(Op::WriterCommit(m));
+        m
+    }
+
+    fn is_abandoned(&self) -> bool {
+        self.inner.is_abandoned()
+    }
+}
+
+/// A Consumer, that records its operations.
+pub struct RecordingReader<C> {
+    inner: C,
+    recorder: Recorder,
+}
+
+impl<C> RecordingReader<C> {
+    pub fn new(inner: C, recorder: Recorder) -> Self {
+        Self { inner, recorder }
+    }
+
+    /// Returns the wrapped Consumer.
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<T, C> Consumer<T> for RecordingReader<C>
+where
+    C: Consumer<T>,
+{
+    fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    fn read_chunk(&mut self) -> &[T] {
+        let chunk = self.inner.read_chunk();
+        self.recorder.record(Op::ReadChunk(chunk.len()));
+        chunk
+    }
+
+    fn commit(&mut self) {
+        self.inner.commit();
+        self.recorder.record(Op::ReaderCommit);
+    }
+
+    fn is_abandoned(&self) -> bool {
+        self.inner.is_abandoned()
+    }
+}
+
+/// The first replayed operation that did not yield the recorded result.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// Index of the event in the replayed log
+    pub index: usize,
+    pub expected: Op,
+    pub actual: Op,
+}
+
+/// Re-execute the recorded `events` on `w` and `r`, in order, on the calling thread.
+///
+/// The handles should refer to an empty queue, of the same capacity the log was recorded with.
+/// Returns the first operation that yielded a different result than recorded, if any.
+///
+/// If the Writer and the Reader were used on different threads, the recorded order
+/// only approximates the real order of operations (an operation and its recording
+/// are not atomic), therefore the replay might diverge even if the code under test is correct.
+pub fn replay<T, P, C>(events: &[Event], w: &mut P, r: &mut C) -> Result<(), Divergence>
+where
+    P: Producer<T>,
+    C: Consumer<T>,
+{
+    for (index, event) in events.iter().enumerate() {
+        let actual = match event.op {
+            Op::WriteChunk(_) => Op::WriteChunk(w.write_chunk().len()),
+            Op::WriterCommit(n) => Op::WriterCommit(w.commit(n)),
+            Op::ReadChunk(_) => Op::ReadChunk(r.read_chunk().len()),
+            Op::ReaderCommit => {
+                r.commit();
+                Op::ReaderCommit
+            }
+        };
+        if actual != event.op {
+            return Err(Divergence {
+                index,
+                expected: event.op,
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! The memory regions of a queue, for registering with DMA capable devices,
+//! and for tools annotating the mappings of a process (see `MappingInfo`).
+//!
+//! E.g: pinning the buffer with `cudaHostRegister` lets a GPU copy directly from
+//! (or to) the chunks of the queue, without a staging copy.
+//!
+//! The buffer is mapped twice, next to each other (see the crate docs), and a chunk
+//! can extend into the second mapping: `buffer_region` covers both, therefore every
+//! chunk returned by `write_chunk` or `read_chunk` is inside it. Both mappings refer
+//! to the same pages. The region is aligned to the page size (see `Writer::page_size`),
+//! and its length is a multiple of it.
+//!
+//! The queue is mapped once, at construction, and never remapped (`resize` creates
+//! a new queue, with a new region). The region stays mapped at the same address,
+//! until the Writer, the Reader, and every other handle sharing the mapping
+//! (e.g: `spectator::SpectatorReader`) are dropped: unregister it before dropping the last one.
+//! A `RegionLease` keeps the region mapped independently of the handles: a registration
+//! that owns a lease (e.g: an RDMA memory region, registered by `ibv_reg_mr`) stays valid,
+//! even if the handles are dropped first.
+//!
+//! To let a remote peer RDMA-write into the queue, acquire a chunk on the Writer,
+//! send the offset of the chunk (see `Region::offset_of`) to the peer, and commit
+//! the chunk once the write completed.
+//!
+//!```
+//! let (w, r) = cueue::cueue::<u32>(1 << 12).unwrap();
+//! let region = w.buffer_region();
+//! assert_eq!(region, r.buffer_region());
+//! assert_eq!(region.len, 2 * w.capacity() * 4);
+//! // e.g: cudaHostRegister(region.addr, region.len, cudaHostRegisterDefault)
+//!```
+
+use std::sync::Arc;
+
+use crate::{Backend, MemoryMapInitialized, Reader, Writer};
+
+/// A contiguous range of mapped memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    /// The first byte of the region.
+    pub addr: *mut u8,
+    /// The length of the region, in bytes.
+    pub len: usize,
+}
+
+impl Region {
+    /// Returns true, if the `len` bytes at `ptr` are inside the region.
+    pub fn contains(&self, ptr: *const u8, len: usize) -> bool {
+        let (begin, end) = (self.addr as usize, self.addr as usize + self.len);
+        let p = ptr as usize;
+        p >= begin && matches!(p.checked_add(len), Some(e) if e <= end)
+    }
+
+    /// The offset of `ptr` from the start of the region, or None, if `ptr` is outside of it.
+    ///
+    /// E.g: the offset of a chunk in a memory region registered for RDMA.
+    pub fn offset_of(&self, ptr: *const u8) -> Option<usize> {
+        self.contains(ptr, 0)
+            .then(|| ptr as usize - self.addr as usize)
+            .filter(|&offset| offset < self.len)
+    }
+}
+
+/// The mappings of a queue, see `Writer::mapping_info`.
+///
+/// E.g: for profilers, custom allocators and crash reporters, to annotate the regions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MappingInfo {
+    /// The control block, see the `abi` module.
+    pub control_block: Region,
+    /// The buffer of the queue.
+    pub buffer: Region,
+    /// The second mapping of the buffer, right after it.
+    pub mirror: Region,
+    /// See `Writer::page_size`.
+    pub page_size: usize,
+    /// See `Writer::backend`.
+    pub backend: Backend,
+    /// The device number of the backing file, that identifies the mapping in `/proc/<pid>/maps`,
+    /// with `inode`.
+    pub device: u64,
+    /// The inode number of the backing file.
+    pub inode: u64,
+}
+
+/// Keeps the buffer of a queue mapped, while registered with a device, see the `region` module.
+pub struct RegionLease<T> {
+    mem: Arc<MemoryMapInitialized<T>>,
+}
+
+impl<T> RegionLease<T> {
+    /// The buffer of the queue, and its mirror: the same as `Writer::buffer_region`.
+    pub fn region(&self) -> Region {
+        buffer_region(self.mem.buf, self.mem.cap)
+    }
+}
+
+unsafe impl<T> Send for RegionLease<T> {}
+unsafe impl<T> Sync for RegionLease<T> {}
+
+impl<T> Writer<T> {
+    /// The addresses of the mappings of the queue, and the identity of the backing file.
+    pub fn mapping_info(&self) -> MappingInfo {
+        mapping_info(&self.mem)
+    }
+
+    /// The buffer of the queue, and its mirror, see the `region` module.
+    pub fn buffer_region(&self) -> Region {
+        buffer_region(self.mem.buf, self.mem.cap)
+    }
+
+    /// Keep the buffer of the queue mapped, until the returned lease is dropped,
+    /// even if the Writer and the Reader are dropped before.
+    pub fn lease_region(&self) -> RegionLease<T> {
+        RegionLease {
+            mem: self.mem.clone(),
+        }
+    }
+}
+
+impl<T> Reader<T> {
+    /// The addresses of the mappings of the queue, see `Writer::mapping_info`.
+    pub fn mapping_info(&self) -> MappingInfo {
+        mapping_info(&self.mem)
+    }
+
+    /// The buffer of the queue, and its mirror, see the `region` module.
+    pub fn buffer_region(&self) -> Region {
+        buffer_region(self.mem.buf, self.mem.cap)
+    }
+
+    /// Keep the buffer of the queue mapped, see `Writer::lease_region`.
+    pub fn lease_region(&self) -> RegionLease<T> {
+        RegionLease {
+            mem: self.mem.clone(),
+        }
+    }
+}
+
+fn mapping_info<T>(mem: &MemoryMapInitialized<T>) -> MappingInfo {
+    let size = mem.cap * std::mem::size_of::<T>();
+    let buffer = mem.buf.cast::<u8>();
+    MappingInfo {
+        control_block: Region {
+            addr: mem.map.ptr(),
+            len: mem.pagesize,
+        },
+        buffer: Region {
+            addr: buffer,
+            len: size,
+        },
+        mirror: Region {
+            addr: buffer.wrapping_add(size),
+            len: size,
+        },
+        page_size: mem.pagesize,
+        backend: mem.backing.backend,
+        device: mem.backing.dev,
+        inode: mem.backing.inode,
+    }
+}
+
+fn buffer_region<T>(buf: *mut T, cap: usize) -> Region {
+    Region {
+        addr: buf.cast(),
+        len: 2 * cap * std::mem::size_of::<T>(),
+    }
+}
+
+unsafe impl Send for Region {}
+unsafe impl Sync for Region {}
@@ -0,0 +1,224 @@
+//! Implementation of the traits of the `ringbuf` crate.
+//!
+//! `Writer` implements `ringbuf::traits::Producer`, `Reader` implements
+//! `ringbuf::traits::Consumer`, therefore code written against those traits
+//! (e.g: `try_push`, `push_slice`, `pop_slice`, `pop_iter`) works with a cueue.
+//! Thanks to the mirrored mapping, the second slice returned by the slice accessors
+//! (e.g: `as_slices`) is always empty.
+//!
+//! `ringbuf` moves elements out of the buffer, and writes them over uninitialized memory,
+//! while cueue keeps every element initialized (see the crate docs): the traits are
+//! implemented for `Copy` elements only.
+//!
+//! Commits made through these traits notify the other side (see `Builder::notify`, `Builder::futex`),
+//! but don't flush a file backed queue (see `Builder::durability`).
+//! Don't mix them with `write_chunk`/`commit` (or `read_chunk`/`commit`) of the same chunk.
+//!
+//! Vacant slots must never be de-initialized: the safe `Producer::vacant_slices_mut` of `ringbuf`
+//! returns them as `MaybeUninit<T>`, but `Writer::write_chunk` returns the same slots later
+//! as initialized values. Only write values to them (e.g: `MaybeUninit::write`),
+//! never `MaybeUninit::uninit()`. `ringbuf` provides that method for every `Producer`,
+//! therefore the implementation can't restrict it.
+//!
+//!```
+//! use ringbuf::traits::{Consumer, Producer};
+//!
+//! let (mut w, mut r) = cueue::cueue::<u8>(1 << 12).unwrap();
+//!
+//! assert_eq!(w.push_slice(b"foo"), 3);
+//! w.try_push(b'!').unwrap();
+//!
+//! let mut buf = [0; 8];
+//! assert_eq!(r.pop_slice(&mut buf), 4);
+//! assert_eq!(&buf[..4], b"foo!");
+//!```
+
+use std::mem::MaybeUninit;
+use std::num::NonZeroUsize;
+use std::sync::atomic::Ordering;
+
+use ringbuf::traits::{Consumer, Observer, Producer};
+
+use crate::abi::READER_BUSY;
+use crate::{Reader, Writer};
+
+/// Convert a position of the queue to an index of `ringbuf`, that counts modulo twice the capacity.
+fn index(pos: u64, capacity: usize) -> usize {
+    (pos % (2 * capacity as u64)) as usize
+}
+
+/// Advance `pos` to the position that corresponds to `index`.
+fn advance(pos: u64, capacity: usize, index: usize) -> u64 {
+    let modulus = 2 * capacity;
+    let delta = (index + modulus - self::index(pos, capacity)) % modulus;
+    pos + delta as u64
+}
+
+/// The slice of `buffer` between the `ringbuf` indices `start` and `end`.
+///
+/// # Safety
+///
+/// `buffer` must be the buffer of a queue of `capacity` elements.
+unsafe fn slice<'a, T>(
+    buffer: *const T,
+    capacity: usize,
+    start: usize,
+    end: usize,
+) -> &'a mut [MaybeUninit<T>] {
+    let modulus = 2 * capacity;
+    let len = (end + modulus - start) % modulus;
+    let begin = buffer.add(start % capacity) as *mut MaybeUninit<T>;
+    std::slice::from_raw_parts_mut(begin, len)
+}
+
+impl<T> Observer for Writer<T>
+where
+    T: Copy + Default,
+{
+    type Item = T;
+
+    fn capacity(&self) -> NonZeroUsize {
+        NonZeroUsize::new(Writer::capacity(self)).unwrap()
+    }
+
+    fn read_index(&self) -> usize {
+        let r = self.read_pos().load(Ordering::Acquire) & !READER_BUSY;
+        // the retained elements are occupied for the Writer, see `Builder::retention`
+        index(r.saturating_sub(self.retention), Writer::capacity(self))
+    }
+
+    fn write_index(&self) -> usize {
+        index(
+            self.write_pos().load(Ordering::Relaxed),
+            Writer::capacity(self),
+        )
+    }
+
+    unsafe fn unsafe_slices(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> (&[MaybeUninit<T>], &[MaybeUninit<T>]) {
+        (slice(self.buffer, Writer::capacity(self), start, end), &[])
+    }
+
+    // the vacant slots are initialized, and must remain so, see the module docs
+    unsafe fn unsafe_slices_mut(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        (
+            slice(self.buffer, Writer::capacity(self), start, end),
+            &mut [],
+        )
+    }
+
+    fn read_is_held(&self) -> bool {
+        !self.is_abandoned()
+    }
+
+    fn write_is_held(&self) -> bool {
+        true
+    }
+}
+
+impl<T> Producer for Writer<T>
+where
+    T: Copy + Default,
+{
+    unsafe fn set_write_index(&self, value: usize) {
+        let w = self.write_pos().load(Ordering::Relaxed);
+        let new_w = advance(w, Writer::capacity(self), value);
+        if new_w == w {
+            return;
+        }
+        if self.sequence {
+            let seq = self.seq().load(Ordering::Relaxed);
+            self.seq().store(seq + 1, Ordering::Relaxed);
+        }
+        self.write_pos().store(new_w, Ordering::Release);
+        #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+        if let Some(notify) = &self.notify {
+            rt_check!("readiness notification");
+            notify.notify_peer();
+        }
+        if self.futex {
+            let cb = &*self.cb;
+            crate::futex::wake(&cb.reader_waiting.0, &cb.write_epoch.0);
+        }
+    }
+}
+
+impl<T> Observer for Reader<T>
+where
+    T: Copy + Default,
+{
+    type Item = T;
+
+    fn capacity(&self) -> NonZeroUsize {
+        NonZeroUsize::new(Reader::capacity(self)).unwrap()
+    }
+
+    fn read_index(&self) -> usize {
+        let r = self.read_pos().load(Ordering::Relaxed) & !READER_BUSY;
+        index(r, Reader::capacity(self))
+    }
+
+    fn write_index(&self) -> usize {
+        index(
+            self.write_pos().load(Ordering::Acquire),
+            Reader::capacity(self),
+        )
+    }
+
+    unsafe fn unsafe_slices(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> (&[MaybeUninit<T>], &[MaybeUninit<T>]) {
+        (slice(self.buffer, Reader::capacity(self), start, end), &[])
+    }
+
+    unsafe fn unsafe_slices_mut(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        (
+            slice(self.buffer, Reader::capacity(self), start, end),
+            &mut [],
+        )
+    }
+
+    fn read_is_held(&self) -> bool {
+        true
+    }
+
+    fn write_is_held(&self) -> bool {
+        !self.is_abandoned()
+    }
+}
+
+impl<T> Consumer for Reader<T>
+where
+    T: Copy + Default,
+{
+    unsafe fn set_read_index(&self, value: usize) {
+        let r = self.read_pos().load(Ordering::Relaxed) & !READER_BUSY;
+        let new_r = advance(r, Reader::capacity(self), value);
+        if new_r == r {
+            return;
+        }
+        self.read_pos().store(new_r, Ordering::Release);
+        #[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+        if let Some(notify) = &self.notify {
+            rt_check!("readiness notification");
+            notify.notify_peer();
+        }
+        if self.futex {
+            let cb = &*self.cb;
+            crate::futex::wake(&cb.writer_waiting.0, &cb.read_epoch.0);
+        }
+    }
+}
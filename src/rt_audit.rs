@@ -0,0 +1,120 @@
+//! Real-time safety audit: detect allocations and syscalls on the hot path.
+//!
+//! While a `Guard` is alive on a thread, the thread is in a real-time section.
+//! Wrap the real-time code of the application (e.g: an audio callback, that uses
+//! `write_chunk`/`commit` or `read_chunk`/`commit`) in a section, to prove it RT-safe in tests.
+//!
+//! In a real-time section:
+//!
+//!  - syscalls made by the queue (readiness notification, releasing consumed memory) panic,
+//!  - allocations abort the process, if `AuditAllocator` is the global allocator
+//!    (allocators must not unwind).
+//!
+//! The queue takes no locks.
+//!
+//!```
+//! use cueue::rt_audit::AuditAllocator;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: AuditAllocator = AuditAllocator(std::alloc::System);
+//!
+//! fn main() {
+//!     let (mut w, mut r) = cueue::cueue::<u8>(1 << 12).unwrap();
+//!
+//!     let _guard = cueue::rt_audit::Guard::new();
+//!     w.push(1).unwrap();
+//!     assert_eq!(r.read_chunk(), [1]);
+//!     r.commit();
+//! }
+//!```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+thread_local! {
+    /// Number of nested real-time sections of the current thread
+    static DEPTH: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Marks the current thread being in a real-time section, until dropped.
+pub struct Guard {
+    /// the section belongs to the current thread
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Guard {
+    pub fn new() -> Self {
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        Self {
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Default for Guard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+    }
+}
+
+/// Returns true, if the current thread is in a real-time section.
+pub fn is_active() -> bool {
+    DEPTH.try_with(|depth| depth.get() != 0).unwrap_or(false)
+}
+
+/// Panic, if the current thread is in a real-time section.
+///
+/// `what` describes the RT-unsafe operation, e.g: a syscall.
+pub fn check(what: &str) {
+    if is_active() {
+        // leave the section: panicking allocates
+        DEPTH.with(|depth| depth.set(0));
+        panic!("{} in a real-time section", what);
+    }
+}
+
+/// A global allocator, that aborts the process on allocation in a real-time section.
+///
+/// Wraps the allocator doing the actual work, usually `std::alloc::System`.
+pub struct AuditAllocator<A = System>(pub A);
+
+impl<A> AuditAllocator<A> {
+    fn check(&self) {
+        if is_active() {
+            let msg = b"allocation in a real-time section\n";
+            unsafe {
+                libc::write(2, msg.as_ptr().cast(), msg.len());
+            }
+            std::process::abort();
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for AuditAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.check();
+        self.0.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.check();
+        self.0.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.check();
+        self.0.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.check();
+        self.0.realloc(ptr, layout, new_size)
+    }
+}
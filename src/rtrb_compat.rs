@@ -0,0 +1,405 @@
+//! An API mirroring the `rtrb` crate, to ease migrating and A/B testing between the two.
+//!
+//! Replacing `use rtrb::...` with `use cueue::rtrb_compat::...` is usually enough.
+//! Differences to `rtrb`:
+//!
+//!  - the capacity is rounded up, see `cueue`, and `RingBuffer::new` panics,
+//!    if the memory map can't be created,
+//!  - the slots of a `WriteChunk` hold previously written (or default) values,
+//!    not freshly defaulted ones,
+//!  - `WriteChunkUninit::as_mut_slices` is unsafe: the slots must be left initialized,
+//!  - `pop` leaves a default value in the slot it takes the element from,
+//!  - the second slice of a chunk is always empty, thanks to the mirrored mapping.
+//!
+//!```
+//! use cueue::rtrb_compat::RingBuffer;
+//!
+//! let (mut producer, mut consumer) = RingBuffer::<u8>::new(1 << 12);
+//!
+//! let chunk = producer.write_chunk_uninit(3).unwrap();
+//! assert_eq!(chunk.fill_from_iter(*b"foo"), 3);
+//! producer.push(b'!').unwrap();
+//!
+//! let chunk = consumer.read_chunk(4).unwrap();
+//! let (first, second) = chunk.as_slices();
+//! assert_eq!(first, b"foo!");
+//! assert!(second.is_empty());
+//! chunk.commit_all();
+//! assert!(consumer.pop().is_err());
+//!```
+
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+
+use crate::{Reader, Writer};
+
+/// Creates `Producer`/`Consumer` pairs.
+pub struct RingBuffer<T> {
+    _elem: PhantomData<T>,
+}
+
+impl<T> RingBuffer<T>
+where
+    T: Default,
+{
+    /// Create a queue of at least `capacity` elements.
+    ///
+    /// Panics if the queue can't be created, see `cueue`.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(capacity: usize) -> (Producer<T>, Consumer<T>) {
+        let (writer, reader) = crate::cueue(capacity).expect("failed to create cueue");
+        (Producer { writer }, Consumer { reader })
+    }
+}
+
+/// Error of `Producer::push`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PushError<T> {
+    /// The queue is full, the element is given back.
+    Full(T),
+}
+
+/// Error of `Consumer::pop`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PopError {
+    /// The queue is empty.
+    Empty,
+}
+
+/// Error of `Consumer::peek`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PeekError {
+    /// The queue is empty.
+    Empty,
+}
+
+/// Error of the chunk acquiring methods.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChunkError {
+    /// Less slots are available than requested: the number of available slots.
+    TooFewSlots(usize),
+}
+
+impl<T> std::fmt::Display for PushError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "full ring buffer")
+    }
+}
+
+impl<T: std::fmt::Debug> std::error::Error for PushError<T> {}
+
+impl std::fmt::Display for PopError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "empty ring buffer")
+    }
+}
+
+impl std::error::Error for PopError {}
+
+impl std::fmt::Display for PeekError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "empty ring buffer")
+    }
+}
+
+impl std::error::Error for PeekError {}
+
+impl std::fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChunkError::TooFewSlots(n) => write!(f, "only {} slots available in ring buffer", n),
+        }
+    }
+}
+
+impl std::error::Error for ChunkError {}
+
+/// The writing side, see `rtrb::Producer`.
+pub struct Producer<T> {
+    writer: Writer<T>,
+}
+
+impl<T> Producer<T>
+where
+    T: Default,
+{
+    /// Write and commit a single element, or return it if the queue was full.
+    pub fn push(&mut self, value: T) -> Result<(), PushError<T>> {
+        match self.writer.write_chunk().first_mut() {
+            Some(slot) => {
+                *slot = value;
+                self.writer.commit(1);
+                Ok(())
+            }
+            None => Err(PushError::Full(value)),
+        }
+    }
+
+    /// Number of free slots.
+    pub fn slots(&mut self) -> usize {
+        self.writer.write_chunk().len()
+    }
+
+    /// Returns true, if there are no free slots.
+    pub fn is_full(&mut self) -> bool {
+        self.slots() == 0
+    }
+
+    /// Returns true, if the Consumer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.writer.is_abandoned()
+    }
+
+    /// Maximum number of elements the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.writer.capacity()
+    }
+
+    /// Get exactly `n` slots to write, see `WriteChunk`.
+    pub fn write_chunk(&mut self, n: usize) -> Result<WriteChunk<'_, T>, ChunkError> {
+        let slots = self.slots();
+        match self.writer.write_chunk_exact(n) {
+            Some(chunk) => Ok(WriteChunk {
+                begin: chunk.as_mut_ptr(),
+                len: n,
+                writer: &mut self.writer,
+            }),
+            None => Err(ChunkError::TooFewSlots(slots)),
+        }
+    }
+
+    /// Get exactly `n` slots to write, as uninitialized memory, see `WriteChunkUninit`.
+    pub fn write_chunk_uninit(&mut self, n: usize) -> Result<WriteChunkUninit<'_, T>, ChunkError> {
+        self.write_chunk(n).map(|chunk| WriteChunkUninit { chunk })
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<T> {
+        self.writer
+    }
+}
+
+/// Writable slots, acquired by `Producer::write_chunk`.
+///
+/// Dropping the chunk without commit makes nothing available for reading.
+pub struct WriteChunk<'a, T> {
+    begin: *mut T,
+    len: usize,
+    writer: &'a mut Writer<T>,
+}
+
+impl<T> WriteChunk<'_, T>
+where
+    T: Default,
+{
+    /// The slots of the chunk. The second slice is always empty.
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        let first = unsafe { std::slice::from_raw_parts_mut(self.begin, self.len) };
+        (first, &mut [])
+    }
+
+    /// Make the first `n` slots available for reading.
+    ///
+    /// Panics if `n` is greater than the length of the chunk.
+    pub fn commit(self, n: usize) {
+        assert!(n <= self.len, "cannot commit more than chunk size");
+        self.writer.commit(n);
+    }
+
+    /// Make every slot available for reading.
+    pub fn commit_all(self) {
+        self.writer.commit(self.len);
+    }
+
+    /// Number of slots of the chunk.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true, if the chunk has no slots.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Writable slots, acquired by `Producer::write_chunk_uninit`.
+///
+/// The slots are initialized, and overwriting them does not drop their previous values:
+/// for elements owning resources, see `WriteChunk` instead.
+pub struct WriteChunkUninit<'a, T> {
+    chunk: WriteChunk<'a, T>,
+}
+
+impl<T> WriteChunkUninit<'_, T>
+where
+    T: Default,
+{
+    /// The slots of the chunk. The second slice is always empty.
+    ///
+    /// # Safety
+    ///
+    /// The slots must be left initialized, even if the chunk is not committed:
+    /// unlike in `rtrb`, the slots of a cueue always hold valid values,
+    /// and later chunks (e.g: `Writer::write_chunk`) return them as such.
+    pub unsafe fn as_mut_slices(&mut self) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let first = std::slice::from_raw_parts_mut(
+            self.chunk.begin.cast::<MaybeUninit<T>>(),
+            self.chunk.len,
+        );
+        (first, &mut [])
+    }
+
+    /// Make the first `n` slots available for reading.
+    ///
+    /// # Safety
+    ///
+    /// The first `n` slots must be initialized.
+    pub unsafe fn commit(self, n: usize) {
+        self.chunk.commit(n);
+    }
+
+    /// Make every slot available for reading.
+    ///
+    /// # Safety
+    ///
+    /// Every slot must be initialized.
+    pub unsafe fn commit_all(self) {
+        self.chunk.commit_all();
+    }
+
+    /// Fill the slots from `iter`, and commit them.
+    ///
+    /// Returns the number of committed elements: less than the length of the chunk,
+    /// if `iter` runs out.
+    pub fn fill_from_iter<I>(self, iter: I) -> usize
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let slots = unsafe { std::slice::from_raw_parts_mut(self.chunk.begin, self.chunk.len) };
+        let mut n = 0;
+        for (slot, value) in slots.iter_mut().zip(iter) {
+            *slot = value;
+            n += 1;
+        }
+        self.chunk.commit(n);
+        n
+    }
+
+    /// Number of slots of the chunk.
+    pub fn len(&self) -> usize {
+        self.chunk.len
+    }
+
+    /// Returns true, if the chunk has no slots.
+    pub fn is_empty(&self) -> bool {
+        self.chunk.len == 0
+    }
+}
+
+/// The reading side, see `rtrb::Consumer`.
+pub struct Consumer<T> {
+    reader: Reader<T>,
+}
+
+impl<T> Consumer<T>
+where
+    T: Default,
+{
+    /// Take the next element, leaving a default value in its slot.
+    pub fn pop(&mut self) -> Result<T, PopError> {
+        let value = match self.reader.read_chunk_mut().first_mut() {
+            Some(slot) => std::mem::take(slot),
+            None => return Err(PopError::Empty),
+        };
+        self.reader.limited_read_chunk(1);
+        self.reader.commit();
+        Ok(value)
+    }
+
+    /// Return a reference to the next element, without consuming it.
+    pub fn peek(&mut self) -> Result<&T, PeekError> {
+        self.reader.read_chunk().first().ok_or(PeekError::Empty)
+    }
+
+    /// Number of elements available to read.
+    pub fn slots(&mut self) -> usize {
+        self.reader.read_chunk().len()
+    }
+
+    /// Returns true, if there are no elements to read.
+    pub fn is_empty(&mut self) -> bool {
+        self.slots() == 0
+    }
+
+    /// Returns true, if the Producer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.reader.is_abandoned()
+    }
+
+    /// Maximum number of elements the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.reader.capacity()
+    }
+
+    /// Get exactly `n` elements to read, see `ReadChunk`.
+    pub fn read_chunk(&mut self, n: usize) -> Result<ReadChunk<'_, T>, ChunkError> {
+        let slots = self.slots();
+        match self.reader.read_chunk_exact(n) {
+            Some(chunk) => Ok(ReadChunk {
+                begin: chunk.as_ptr(),
+                len: n,
+                reader: &mut self.reader,
+            }),
+            None => Err(ChunkError::TooFewSlots(slots)),
+        }
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<T> {
+        self.reader
+    }
+}
+
+/// Readable elements, acquired by `Consumer::read_chunk`.
+///
+/// Dropping the chunk without commit leaves the elements in the queue.
+pub struct ReadChunk<'a, T> {
+    begin: *const T,
+    len: usize,
+    reader: &'a mut Reader<T>,
+}
+
+impl<T> ReadChunk<'_, T>
+where
+    T: Default,
+{
+    /// The elements of the chunk. The second slice is always empty.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let first = unsafe { std::slice::from_raw_parts(self.begin, self.len) };
+        (first, &[])
+    }
+
+    /// Consume the first `n` elements.
+    ///
+    /// Panics if `n` is greater than the length of the chunk.
+    pub fn commit(self, n: usize) {
+        assert!(n <= self.len, "cannot commit more than chunk size");
+        self.reader.limited_read_chunk(n);
+        self.reader.commit();
+    }
+
+    /// Consume every element of the chunk.
+    pub fn commit_all(self) {
+        self.reader.commit();
+    }
+
+    /// Number of elements of the chunk.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true, if the chunk has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
@@ -0,0 +1,215 @@
+//! Cap'n Proto messages over a byte queue, without copying.
+//!
+//! Messages are written using the standard Cap'n Proto stream framing:
+//! a segment table (segment count - 1, then the size of each segment in words,
+//! as little endian u32s, padded to a whole word), followed by the segments.
+//! Each message is a whole number of words, therefore, as the queue is page aligned,
+//! every segment returned by the Reader is word aligned, as capnp requires.
+//!
+//! `Segments` borrows the segments from the queue. With the `capnp` feature, it implements
+//! `capnp::message::ReaderSegments`, therefore the message can be read in place,
+//! using `capnp::message::Reader::new`.
+//!
+//!```
+//! use cueue::segments::{SegmentReader, SegmentWriter};
+//!
+//! let (w, r) = cueue::cueue::<u8>(1 << 16).unwrap();
+//! let mut w = SegmentWriter::new(w);
+//! let mut r = SegmentReader::new(r);
+//!
+//! w.write_message(&[&[1; 8], &[2; 16]]).unwrap();
+//!
+//! let segments = r.read_message().unwrap().unwrap();
+//! assert_eq!(segments.len(), 2);
+//! assert_eq!(segments.get_segment(1), Some(&[2; 16][..]));
+//! r.commit();
+//!```
+
+use crate::framed::FrameError;
+use crate::{Reader, Writer};
+
+/// Size of a Cap'n Proto word, in bytes
+const WORD: usize = 8;
+
+/// Size of the segment table of a message of `count` segments, in bytes
+fn table_len(count: usize) -> usize {
+    (4 * (count + 1) + WORD - 1) & !(WORD - 1)
+}
+
+/// Writes Cap'n Proto messages to a byte queue.
+pub struct SegmentWriter {
+    writer: Writer<u8>,
+}
+
+impl SegmentWriter {
+    /// Write messages to the queue of `writer`.
+    pub fn new(writer: Writer<u8>) -> Self {
+        Self { writer }
+    }
+
+    /// Write and commit the message made of `segments`, or nothing, if there's not enough space.
+    ///
+    /// `segments` are usually the output of `capnp::message::Builder::get_segments_for_output`.
+    ///
+    /// Panics if `segments` is empty, or the length of a segment is not a whole number of words.
+    pub fn write_message(&mut self, segments: &[&[u8]]) -> Result<(), FrameError> {
+        assert!(!segments.is_empty(), "a message has at least one segment");
+        assert!(
+            segments.iter().all(|s| s.len() % WORD == 0),
+            "segments must be a whole number of words"
+        );
+
+        let table_len = table_len(segments.len());
+        let len = table_len + segments.iter().map(|s| s.len()).sum::<usize>();
+        if len > self.writer.capacity() || segments.len() > u32::MAX as usize {
+            return Err(FrameError::TooLarge);
+        }
+        let buf = self.writer.write_chunk_exact(len).ok_or(FrameError::Full)?;
+
+        let (table, mut data) = buf.split_at_mut(table_len);
+        table[..4].copy_from_slice(&(segments.len() as u32 - 1).to_le_bytes());
+        for (i, segment) in segments.iter().enumerate() {
+            let words = (segment.len() / WORD) as u32;
+            table[4 * (i + 1)..4 * (i + 2)].copy_from_slice(&words.to_le_bytes());
+            data[..segment.len()].copy_from_slice(segment);
+            data = &mut data[segment.len()..];
+        }
+        table[4 * (segments.len() + 1)..].fill(0);
+
+        self.writer.commit(len);
+        Ok(())
+    }
+
+    /// Returns true, if the Reader counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.writer.is_abandoned()
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<u8> {
+        self.writer
+    }
+}
+
+/// Reads Cap'n Proto messages from a byte queue.
+pub struct SegmentReader {
+    reader: Reader<u8>,
+    /// bytes of the messages returned since the last commit
+    consumed: usize,
+}
+
+impl SegmentReader {
+    /// Read messages from the queue of `reader`.
+    pub fn new(reader: Reader<u8>) -> Self {
+        Self {
+            reader,
+            consumed: 0,
+        }
+    }
+
+    /// Return the segments of the next complete message, if available.
+    ///
+    /// The returned messages remain in the queue until `commit` is called,
+    /// consuming every message returned so far.
+    /// Returns Err, if the segment table describes a message that can never fit in the queue.
+    pub fn read_message(&mut self) -> Result<Option<Segments<'_>>, FrameError> {
+        let capacity = self.reader.capacity();
+        let chunk = &self.reader.read_chunk()[self.consumed..];
+        if chunk.len() < 4 {
+            return Ok(None);
+        }
+        let count = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize + 1;
+        let table_len = table_len(count);
+        if table_len > capacity {
+            return Err(FrameError::Malformed);
+        }
+        if chunk.len() < table_len {
+            return Ok(None);
+        }
+
+        let sizes = &chunk[4..4 * (count + 1)];
+        let mut len = table_len;
+        for size in sizes.chunks_exact(4) {
+            let words = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+            len += words * WORD;
+        }
+        if len > capacity {
+            return Err(FrameError::Malformed);
+        }
+        if chunk.len() < len {
+            return Ok(None);
+        }
+
+        let begin = self.consumed;
+        self.consumed += len;
+        let message = &self.reader.read_chunk()[begin..begin + len];
+        Ok(Some(Segments {
+            sizes: &message[4..4 * (count + 1)],
+            data: &message[table_len..],
+        }))
+    }
+
+    /// Consume every message returned by `read_message` so far, making space for the Writer.
+    pub fn commit(&mut self) {
+        self.reader.limited_read_chunk(self.consumed);
+        self.reader.commit();
+        self.consumed = 0;
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.reader.is_abandoned()
+    }
+
+    /// Returns the wrapped Reader.
+    ///
+    /// Messages returned by `read_message`, but not committed are not consumed.
+    pub fn into_inner(self) -> Reader<u8> {
+        self.reader
+    }
+}
+
+/// The segments of a message, borrowed from the queue.
+#[derive(Clone, Copy, Debug)]
+pub struct Segments<'a> {
+    /// size of each segment in words, little endian u32s
+    sizes: &'a [u8],
+    /// the segments, one after the other
+    data: &'a [u8],
+}
+
+impl<'a> Segments<'a> {
+    /// Returns the segment `id`, if the message has that many segments.
+    pub fn get_segment(&self, id: u32) -> Option<&'a [u8]> {
+        let mut begin = 0;
+        for (i, size) in self.sizes.chunks_exact(4).enumerate() {
+            let len = WORD * u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+            if i == id as usize {
+                return Some(&self.data[begin..begin + len]);
+            }
+            begin += len;
+        }
+        None
+    }
+
+    /// Returns the number of segments.
+    pub fn len(&self) -> usize {
+        self.sizes.len() / 4
+    }
+
+    /// Always false: a message has at least one segment.
+    pub fn is_empty(&self) -> bool {
+        self.sizes.is_empty()
+    }
+}
+
+#[cfg(feature = "capnp")]
+impl capnp::message::ReaderSegments for Segments<'_> {
+    fn get_segment(&self, idx: u32) -> Option<&[u8]> {
+        Segments::get_segment(self, idx)
+    }
+
+    fn len(&self) -> usize {
+        Segments::len(self)
+    }
+}
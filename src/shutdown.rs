@@ -0,0 +1,121 @@
+//! Graceful shutdown of a producer/consumer pair.
+//!
+//! `Shutdown` takes the Writer, and the thread of the consumer, and in a single call:
+//! waits for the Reader to consume every committed element (up to a timeout),
+//! finishes the stream (see `Writer::finish`), therefore the consumer finds the queue abandoned,
+//! then joins its thread, in the remaining time. `Report` tells which steps completed.
+//!
+//!```
+//! use cueue::shutdown::Shutdown;
+//! use std::time::Duration;
+//!
+//! let (mut w, mut r) = cueue::cueue::<u8>(1 << 12).unwrap();
+//! let consumer = std::thread::spawn(move || {
+//!     let mut n = 0;
+//!     while let Ok(chunk) = r.read_chunk_blocking() {
+//!         n += chunk.len();
+//!         r.commit();
+//!     }
+//!     n
+//! });
+//!
+//! w.write_chunk()[..3].copy_from_slice(b"foo");
+//! w.commit(3);
+//!
+//! let report = Shutdown::new(w).join(consumer).run(Duration::from_secs(60));
+//! assert!(report.drained);
+//! assert_eq!(report.consumer.unwrap().unwrap(), 3);
+//!```
+
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::{Completion, Writer};
+
+/// Shuts down a producer/consumer pair, see the module docs.
+pub struct Shutdown<T, R = ()> {
+    writer: Writer<T>,
+    consumer: Option<JoinHandle<R>>,
+    completion: Completion,
+}
+
+/// The outcome of `Shutdown::run`.
+#[derive(Debug)]
+pub struct Report<R> {
+    /// True, if the Reader consumed every committed element before the timeout
+    pub drained: bool,
+    /// True, if the Reader was dropped (or its process died), leaving elements unread
+    pub abandoned: bool,
+    /// The result of the consumer thread, or None, if it did not finish before the timeout
+    /// (it is detached then), or no thread was given
+    pub consumer: Option<std::thread::Result<R>>,
+}
+
+impl<T> Shutdown<T>
+where
+    T: Default,
+{
+    /// Shut down the stream of `writer`, finishing it with `Completion::Ok`.
+    pub fn new(writer: Writer<T>) -> Self {
+        Self {
+            writer,
+            consumer: None,
+            completion: Completion::Ok,
+        }
+    }
+}
+
+impl<T, R> Shutdown<T, R>
+where
+    T: Default,
+{
+    /// Join `consumer`, the thread of the Reader, after the stream is finished.
+    ///
+    /// The thread must return once the queue is abandoned (e.g: `Reader::read_chunk_blocking` fails).
+    pub fn join<S>(self, consumer: JoinHandle<S>) -> Shutdown<T, S> {
+        Shutdown {
+            writer: self.writer,
+            consumer: Some(consumer),
+            completion: self.completion,
+        }
+    }
+
+    /// Set the status the stream is finished with, see `Writer::finish`.
+    pub fn completion(mut self, completion: Completion) -> Self {
+        self.completion = completion;
+        self
+    }
+
+    /// Wait for the Reader to consume every committed element, finish the stream,
+    /// and join the consumer thread, all within `timeout`.
+    ///
+    /// The Writer waits using its wait strategy, or sleeps on the futex of the queue,
+    /// if `Builder::futex` is enabled. The stream is finished even if the Reader
+    /// did not consume every element in time: the rest is left for it to read.
+    pub fn run(mut self, timeout: Duration) -> Report<R> {
+        let deadline = Instant::now() + timeout;
+        let drained = self.writer.wait_empty(timeout);
+        let abandoned = !drained && self.writer.is_abandoned();
+
+        // waited for the Reader already, see `Builder::drain_on_drop`
+        self.writer.drain_on_drop = None;
+        self.writer.finish(self.completion);
+
+        let consumer = self.consumer.and_then(|consumer| {
+            while !consumer.is_finished() {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return None;
+                }
+                std::thread::sleep(Duration::min(left, Duration::from_millis(1)));
+            }
+            Some(consumer.join())
+        });
+
+        Report {
+            drained,
+            abandoned,
+            consumer,
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! An asynchronous `slog::Drain`, that passes the records through a byte queue.
+//!
+//! `CueueDrain::log` serializes each record into a frame of the queue, without allocating,
+//! and a consumer thread decodes the records, and forwards them to the inner Drain:
+//! the logging thread does not wait for the formatting and the I/O of the inner Drain.
+//! The values are formatted by the logging thread (the message and the values are passed
+//! as strings), the values of the logger are forwarded as values of the record.
+//!
+//! If the queue is full, the record is dropped, and counted, see `CueueDrain::dropped`.
+//! Dropping the Drain (i.e: every Logger using it) forwards the records already logged,
+//! then stops the consumer thread.
+//!
+//!```
+//! use slog::{info, o, Drain, Logger};
+//!
+//! let inner = slog::Discard;
+//! let drain = cueue::slog::CueueDrain::new(inner, 1 << 16).unwrap();
+//! let log = Logger::root(drain.fuse(), o!("version" => "1.0"));
+//! info!(log, "started"; "port" => 8080);
+//!```
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+
+use ::slog::{
+    BorrowedKV, Drain, Key, Level, OwnedKVList, Record, RecordLocation, RecordStatic, KV,
+};
+
+use crate::framed::LengthPrefix;
+use crate::{Builder, CError, Reader, Writer};
+
+/// The framing of the records in the queue.
+const PREFIX: LengthPrefix = LengthPrefix::U32;
+
+/// A Drain, that forwards the records to an inner Drain on a consumer thread, see the module docs.
+pub struct CueueDrain {
+    writer: Mutex<Option<Writer<u8>>>,
+    dropped: AtomicU64,
+    // in a Mutex, to be `RefUnwindSafe`, as required by `slog::Logger`
+    consumer: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl CueueDrain {
+    /// Create a queue of at least `capacity` bytes, and a consumer thread,
+    /// that forwards the records to `inner`. Errors of `inner` are ignored.
+    pub fn new<D>(inner: D, capacity: usize) -> Result<Self, CError>
+    where
+        D: Drain + Send + 'static,
+    {
+        let (writer, reader) = Builder::new(capacity)
+            .futex(cfg!(target_os = "linux"))
+            .build::<u8>()?;
+        let consumer = std::thread::Builder::new()
+            .name("cueue-slog".into())
+            .spawn(move || consume(reader, inner))
+            .map_err(|err| CError {
+                hint: "failed to spawn the consumer thread",
+                err,
+            })?;
+        Ok(Self {
+            writer: Mutex::new(Some(writer)),
+            dropped: AtomicU64::new(0),
+            consumer: Mutex::new(Some(consumer)),
+        })
+    }
+
+    /// Number of records dropped so far, because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drain for CueueDrain {
+    type Ok = ();
+    type Err = ::slog::Never;
+
+    fn log(&self, record: &Record<'_>, values: &OwnedKVList) -> Result<(), ::slog::Never> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let writer = match writer.as_mut() {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        let chunk = writer.write_chunk();
+        let prefix_len = PREFIX.encoded_len(0);
+        let mut enc = Encoder {
+            buf: chunk,
+            len: prefix_len,
+        };
+        if encode(&mut enc, record, values).is_ok() {
+            let len = enc.len;
+            PREFIX.encode(len - prefix_len, chunk);
+            writer.commit(len);
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for CueueDrain {
+    fn drop(&mut self) {
+        // the consumer forwards the remaining records, then finds the queue abandoned
+        drop(self.writer.lock().unwrap_or_else(|e| e.into_inner()).take());
+        let consumer = self.consumer.get_mut().unwrap_or_else(|e| e.into_inner());
+        if let Some(consumer) = consumer.take() {
+            let _ = consumer.join();
+        }
+    }
+}
+
+/// The record did not fit in the queue.
+struct Full;
+
+/// Appends to the writable chunk of the queue.
+struct Encoder<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Encoder<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), Full> {
+        let end = self.len.checked_add(bytes.len()).ok_or(Full)?;
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(Full)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn u32(&mut self, n: u32) -> Result<(), Full> {
+        self.bytes(&n.to_le_bytes())
+    }
+
+    /// A string of the program: only its address is stored.
+    fn static_str(&mut self, s: &'static str) -> Result<(), Full> {
+        self.bytes(&(s.as_ptr() as usize).to_le_bytes())?;
+        self.bytes(&s.len().to_le_bytes())
+    }
+
+    /// A length prefixed string, formatted in place.
+    fn fmt(&mut self, args: std::fmt::Arguments<'_>) -> Result<(), Full> {
+        let begin = self.len;
+        self.u32(0)?;
+        let mut out = FmtOut {
+            enc: self,
+            full: false,
+        };
+        if out.write_fmt(args).is_err() || out.full {
+            return Err(Full);
+        }
+        let len = (self.len - begin - 4) as u32;
+        self.buf[begin..begin + 4].copy_from_slice(&len.to_le_bytes());
+        Ok(())
+    }
+}
+
+struct FmtOut<'a, 'b> {
+    enc: &'a mut Encoder<'b>,
+    full: bool,
+}
+
+impl std::fmt::Write for FmtOut<'_, '_> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.enc.bytes(s.as_bytes()).map_err(|_| {
+            self.full = true;
+            std::fmt::Error
+        })
+    }
+}
+
+/// Serializes the values of a record into an Encoder.
+struct ValueEncoder<'a, 'b> {
+    enc: &'a mut Encoder<'b>,
+    count: u32,
+}
+
+impl ::slog::Serializer for ValueEncoder<'_, '_> {
+    fn emit_arguments(&mut self, key: Key, val: &std::fmt::Arguments<'_>) -> ::slog::Result {
+        let full = || ::slog::Error::Other;
+        self.enc.static_str(key).map_err(|_| full())?;
+        self.enc.fmt(*val).map_err(|_| full())?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+/// Layout: level, location, tag, message, number of values, then the values.
+fn encode(enc: &mut Encoder<'_>, record: &Record<'_>, values: &OwnedKVList) -> Result<(), Full> {
+    enc.bytes(&[record.level().as_usize() as u8])?;
+    let location = record.location();
+    enc.static_str(location.file)?;
+    enc.u32(location.line)?;
+    enc.u32(location.column)?;
+    enc.static_str(location.function)?;
+    enc.static_str(location.module)?;
+    enc.fmt(format_args!("{}", record.tag()))?;
+    enc.fmt(*record.msg())?;
+
+    let count_at = enc.len;
+    enc.u32(0)?;
+    let mut values_enc = ValueEncoder { enc, count: 0 };
+    record
+        .kv()
+        .serialize(record, &mut values_enc)
+        .map_err(|_| Full)?;
+    values
+        .serialize(record, &mut values_enc)
+        .map_err(|_| Full)?;
+    let count = values_enc.count;
+    enc.buf[count_at..count_at + 4].copy_from_slice(&count.to_le_bytes());
+    Ok(())
+}
+
+/// Reads a frame written by `encode`.
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn bytes(&mut self, n: usize) -> &'a [u8] {
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        head
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.bytes(4).try_into().unwrap())
+    }
+
+    fn usize(&mut self) -> usize {
+        usize::from_le_bytes(self.bytes(std::mem::size_of::<usize>()).try_into().unwrap())
+    }
+
+    fn static_str(&mut self) -> &'static str {
+        let (ptr, len) = (self.usize(), self.usize());
+        // written by `Encoder::static_str` of the same process: the string is still there
+        unsafe { std::str::from_utf8_unchecked(std::slice::from_raw_parts(ptr as *const u8, len)) }
+    }
+
+    fn str(&mut self) -> &'a str {
+        let len = self.u32() as usize;
+        // written by `Encoder::fmt`, from a `str`
+        unsafe { std::str::from_utf8_unchecked(self.bytes(len)) }
+    }
+}
+
+/// The values of a decoded record.
+struct Values<'a> {
+    buf: &'a [u8],
+    count: u32,
+}
+
+impl KV for Values<'_> {
+    fn serialize(
+        &self,
+        _record: &Record<'_>,
+        serializer: &mut dyn ::slog::Serializer,
+    ) -> ::slog::Result {
+        let mut dec = Decoder { buf: self.buf };
+        for _ in 0..self.count {
+            let key = dec.static_str();
+            serializer.emit_str(key, dec.str())?;
+        }
+        Ok(())
+    }
+}
+
+/// Forward the records of `reader` to `inner`, until the Writer is dropped.
+fn consume<D: Drain>(mut reader: Reader<u8>, inner: D) {
+    let empty = OwnedKVList::from(::slog::o!());
+    while let Ok(chunk) = reader.read_chunk_blocking() {
+        // the Writer commits whole frames
+        let mut consumed = 0;
+        while let Ok(Some((len, prefix_len))) = PREFIX.decode(&chunk[consumed..]) {
+            let begin = consumed + prefix_len;
+            forward(&chunk[begin..begin + len], &inner, &empty);
+            consumed = begin + len;
+        }
+        reader.limited_read_chunk(consumed);
+        reader.commit();
+    }
+}
+
+fn forward<D: Drain>(frame: &[u8], inner: &D, empty: &OwnedKVList) {
+    let mut dec = Decoder { buf: frame };
+    let level = Level::from_usize(dec.bytes(1)[0] as usize).unwrap_or(Level::Info);
+    let location = RecordLocation {
+        file: dec.static_str(),
+        line: dec.u32(),
+        column: dec.u32(),
+        function: dec.static_str(),
+        module: dec.static_str(),
+    };
+    let tag = dec.str();
+    let msg = dec.str();
+    let count = dec.u32();
+    let values = Values {
+        buf: dec.buf,
+        count,
+    };
+    let rs = RecordStatic {
+        location: &location,
+        level,
+        tag,
+    };
+    let _ = inner.log(
+        &Record::new(&rs, &format_args!("{}", msg), BorrowedKV(&values)),
+        empty,
+    );
+}
@@ -0,0 +1,157 @@
+//! Passing complete, fixed size frames of state, where only the most recent one matters.
+//!
+//! The pattern of game engines: a simulation thread publishes the state of the world
+//! after every tick, and the render thread draws the latest complete state,
+//! skipping the frames it was too slow to draw. `SnapshotWriter` publishes whole frames,
+//! and `SnapshotReader::latest` consumes every older frame, then holds the newest one
+//! until the next call, therefore the frame is read in place, without copying.
+//!
+//! The elements of a frame are reused: `SnapshotWriter::frame` returns a frame published
+//! earlier (or default initialized), that must be overwritten completely.
+//! If the queue is full (the Reader did not call `latest` since a while),
+//! `frame` returns None: the Writer skips publishing, as the next frame supersedes it anyway.
+//! To keep the Writer running, make the queue hold a few frames (at least three:
+//! one held by the Reader, one published, and one being written).
+//!
+//!```
+//! use cueue::snapshot::{SnapshotReader, SnapshotWriter};
+//!
+//! let (w, r) = cueue::cueue::<u32>(1 << 12).unwrap();
+//! let mut w = SnapshotWriter::new(w, 4);
+//! let mut r = SnapshotReader::new(r, 4);
+//!
+//! for tick in 0..3 {
+//!     if let Some(frame) = w.frame() {
+//!         frame.fill(tick);
+//!         w.publish();
+//!     }
+//! }
+//!
+//! assert_eq!(r.latest(), Some(&[2, 2, 2, 2][..]));
+//! assert_eq!(r.skipped(), 2);
+//!```
+
+use crate::{Reader, Writer};
+
+/// Publishes frames of `frame_len` elements, see the module docs.
+pub struct SnapshotWriter<T> {
+    writer: Writer<T>,
+    frame_len: usize,
+}
+
+impl<T> SnapshotWriter<T>
+where
+    T: Default,
+{
+    /// Panics if `frame_len` is zero, or larger than the capacity of the queue.
+    pub fn new(writer: Writer<T>, frame_len: usize) -> Self {
+        assert!(frame_len != 0, "frame_len must be positive");
+        assert!(
+            frame_len <= writer.capacity(),
+            "frame_len must not exceed the capacity"
+        );
+        Self { writer, frame_len }
+    }
+
+    /// Number of elements in a frame.
+    pub fn frame_len(&self) -> usize {
+        self.frame_len
+    }
+
+    /// Get the next frame to write, or None, if the queue is full.
+    ///
+    /// The frame holds the elements of an earlier frame: overwrite it, then call `publish`.
+    pub fn frame(&mut self) -> Option<&mut [T]> {
+        self.writer.write_chunk_exact(self.frame_len)
+    }
+
+    /// Make the frame returned by `frame` available for the Reader.
+    ///
+    /// Returns false, if there was no frame to publish.
+    pub fn publish(&mut self) -> bool {
+        self.writer.commit_all() == self.frame_len
+    }
+
+    /// Returns true, if the Reader counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.writer.is_abandoned()
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<T> {
+        self.writer
+    }
+}
+
+/// Reads the most recent frame of `frame_len` elements, see the module docs.
+pub struct SnapshotReader<T> {
+    reader: Reader<T>,
+    frame_len: usize,
+    /// true, if the first readable frame was returned by `latest`
+    held: bool,
+    skipped: u64,
+}
+
+impl<T> SnapshotReader<T>
+where
+    T: Default,
+{
+    /// Panics if `frame_len` is zero, or larger than the capacity of the queue.
+    ///
+    /// `frame_len` must match the one of the `SnapshotWriter`.
+    pub fn new(reader: Reader<T>, frame_len: usize) -> Self {
+        assert!(frame_len != 0, "frame_len must be positive");
+        assert!(
+            frame_len <= reader.capacity(),
+            "frame_len must not exceed the capacity"
+        );
+        Self {
+            reader,
+            frame_len,
+            held: false,
+            skipped: 0,
+        }
+    }
+
+    /// Number of elements in a frame.
+    pub fn frame_len(&self) -> usize {
+        self.frame_len
+    }
+
+    /// Return the most recent frame published by the Writer,
+    /// or None, if no frame was published yet.
+    ///
+    /// Older frames (including the one returned by the previous call) are consumed,
+    /// making space for the Writer. If no frame was published since the previous call,
+    /// the same frame is returned again.
+    pub fn latest(&mut self) -> Option<&[T]> {
+        let frames = self.reader.read_chunk().len() / self.frame_len;
+        if frames == 0 {
+            return None;
+        }
+        if frames > 1 {
+            let older = frames - 1;
+            self.reader.limited_read_chunk(older * self.frame_len);
+            self.reader.commit();
+            // the frame held by the previous call was drawn, not skipped
+            self.skipped += older as u64 - u64::from(self.held);
+        }
+        self.held = true;
+        self.reader.read_chunk_exact(self.frame_len)
+    }
+
+    /// Number of frames consumed without being returned by `latest`.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.reader.is_abandoned()
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<T> {
+        self.reader
+    }
+}
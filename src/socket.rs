@@ -0,0 +1,95 @@
+//! Moving bytes between a queue and a socket, without an intermediate buffer.
+//!
+//! `Writer::recv_from_socket` receives into the writable chunk, `Reader::send_to_socket`
+//! sends from the readable chunk, and both commit the transferred length:
+//! e.g: a proxy pumps a connection through a queue with a single copy per direction.
+//! Thanks to the mirrored mapping, a chunk is always contiguous, therefore a single
+//! syscall transfers as much as the queue (and the socket) allows.
+//!
+//! The bytes are committed as soon as the syscall returns: don't pass `MSG_ZEROCOPY`,
+//! as the Writer might overwrite the sent bytes before the kernel transmitted them.
+//!
+//!```
+//! use std::io::{Read, Write};
+//! use std::os::unix::io::AsFd;
+//! use std::os::unix::net::UnixStream;
+//!
+//! let (mut w, mut r) = cueue::cueue::<u8>(1 << 12).unwrap();
+//! let (mut a, b) = UnixStream::pair().unwrap();
+//!
+//! a.write_all(b"ping").unwrap();
+//! assert_eq!(w.recv_from_socket(b.as_fd(), 0).unwrap(), 4);
+//! assert_eq!(r.send_to_socket(b.as_fd(), 0).unwrap(), 4);
+//!
+//! let mut buf = [0; 4];
+//! a.read_exact(&mut buf).unwrap();
+//! assert_eq!(&buf, b"ping");
+//!```
+
+use std::io;
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+
+use crate::{Reader, Writer};
+
+/// Suppress SIGPIPE, if the peer closed the connection: report EPIPE instead.
+#[cfg(target_os = "linux")]
+const NOSIGNAL: i32 = libc::MSG_NOSIGNAL;
+
+#[cfg(not(target_os = "linux"))]
+const NOSIGNAL: i32 = 0;
+
+impl Writer<u8> {
+    /// Receive bytes from the socket `fd` into the queue, calling `recv` with `flags`
+    /// (e.g: `MSG_DONTWAIT`), then commit the received bytes.
+    ///
+    /// Returns the number of received bytes: 0, if the peer closed the connection.
+    /// If the queue is full, returns an error of kind `WouldBlock`, without calling `recv`.
+    pub fn recv_from_socket(&mut self, fd: BorrowedFd<'_>, flags: i32) -> io::Result<usize> {
+        let chunk = self.write_chunk();
+        if chunk.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let n = unsafe {
+            libc::recv(
+                fd.as_raw_fd(),
+                chunk.as_mut_ptr().cast(),
+                chunk.len(),
+                flags,
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.commit(n as usize);
+        Ok(n as usize)
+    }
+}
+
+impl Reader<u8> {
+    /// Send the readable bytes of the queue to the socket `fd`, calling `send` with `flags`
+    /// (e.g: `MSG_DONTWAIT`), then consume the sent bytes.
+    ///
+    /// Returns the number of sent bytes: 0, if the queue is empty.
+    /// If the peer closed the connection, fails with EPIPE (without raising SIGPIPE on Linux).
+    pub fn send_to_socket(&mut self, fd: BorrowedFd<'_>, flags: i32) -> io::Result<usize> {
+        let chunk = self.read_chunk();
+        if chunk.is_empty() {
+            return Ok(0);
+        }
+        let n = unsafe {
+            libc::send(
+                fd.as_raw_fd(),
+                chunk.as_ptr().cast(),
+                chunk.len(),
+                flags | NOSIGNAL,
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // consume the sent prefix only
+        self.limited_read_chunk(n as usize);
+        self.commit();
+        Ok(n as usize)
+    }
+}
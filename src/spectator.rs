@@ -0,0 +1,131 @@
+//! Observing the elements of a queue, without consuming them.
+//!
+//! A `SpectatorReader` follows the Writer with its own cursor, and never advances
+//! the read position of the queue: the Reader consumes the elements as before,
+//! e.g: a spectator can tap a live queue for debugging or sampling metrics.
+//!
+//! The Writer does not wait for spectators: once the Reader consumed an element,
+//! the Writer is free to overwrite it (unless it is retained, see `Builder::retention`).
+//! Therefore spectators copy the elements, then skip the ones that might have been
+//! overwritten meanwhile (see `missed`).
+//!
+//!```
+//! let (mut w, mut r) = cueue::cueue::<u32>(1 << 12).unwrap();
+//! let mut spectator = r.spectator();
+//!
+//! w.write_chunk()[..3].copy_from_slice(&[1, 2, 3]);
+//! w.commit(3);
+//!
+//! let mut buf = [0; 8];
+//! assert_eq!(spectator.read(&mut buf), 3);
+//! assert_eq!(&buf[..3], &[1, 2, 3]);
+//!
+//! // the elements are still there for the Reader
+//! assert_eq!(r.read_chunk(), &[1, 2, 3]);
+//! r.commit();
+//!```
+
+use std::sync::atomic::{fence, Ordering};
+
+use crate::abi::{READER_BUSY, WRITER_CLOSED};
+use crate::{ControlBlock, MemoryMapInitialized, Reader};
+
+/// Observes the elements committed to a queue, see the module docs.
+pub struct SpectatorReader<T> {
+    // keeps the memory mapped
+    _mem: std::sync::Arc<MemoryMapInitialized<T>>,
+    cb: *const ControlBlock,
+    mask: u64,
+    buffer: *const T,
+    retention: u64,
+    position: u64,
+    missed: u64,
+}
+
+impl<T> Reader<T>
+where
+    T: Copy + Default,
+{
+    /// Create a `SpectatorReader`, that starts at the oldest element retained by the queue
+    /// (see `Builder::retention`), or at the current read position, without retention:
+    /// it observes every element not consumed by the Reader yet.
+    pub fn spectator(&self) -> SpectatorReader<T> {
+        let retention = unsafe { (*self.cb).retention.0.load(Ordering::Relaxed) };
+        let r = self.read_pos().load(Ordering::Acquire) & !READER_BUSY;
+        SpectatorReader {
+            _mem: self.mem.clone(),
+            cb: self.cb,
+            mask: self.mask,
+            buffer: self.buffer,
+            retention,
+            position: r.saturating_sub(retention),
+            missed: 0,
+        }
+    }
+}
+
+impl<T> SpectatorReader<T>
+where
+    T: Copy + Default,
+{
+    /// Copy the elements committed after the cursor to `buf`, at most `buf.len()` elements,
+    /// and advance the cursor past them.
+    ///
+    /// Returns the number of copied elements.
+    /// Elements consumed by the Reader before they could be copied are skipped.
+    pub fn read(&mut self, buf: &mut [T]) -> usize {
+        let cb = unsafe { &*self.cb };
+        let r = cb.read_position.0.load(Ordering::Acquire) & !READER_BUSY;
+        self.skip_to(r.saturating_sub(self.retention));
+        let w = cb.write_position.0.load(Ordering::Acquire);
+        let n = usize::min((w - self.position) as usize, buf.len());
+        unsafe {
+            // thanks to the mirrored mapping, the elements are contiguous
+            let begin = self.buffer.add((self.position & self.mask) as usize);
+            std::ptr::copy_nonoverlapping(begin, buf.as_mut_ptr(), n);
+        }
+
+        // the elements consumed meanwhile might have been overwritten while copied
+        fence(Ordering::Acquire);
+        let r = cb.read_position.0.load(Ordering::Relaxed) & !READER_BUSY;
+        let oldest = r.saturating_sub(self.retention);
+        let torn = usize::min(oldest.saturating_sub(self.position) as usize, n);
+        buf.copy_within(torn..n, 0);
+        self.missed += torn as u64;
+        self.position += n as u64;
+        n - torn
+    }
+
+    /// Move the cursor to the write position, skipping every element committed so far.
+    ///
+    /// The skipped elements are not counted as missed.
+    pub fn seek_to_latest(&mut self) {
+        self.position = unsafe { (*self.cb).write_position.0.load(Ordering::Acquire) };
+    }
+
+    /// The position of the cursor: the number of elements committed before the next one to read.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Number of elements skipped so far, because the Reader consumed them first
+    /// (and they were not retained).
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    /// Returns true, if the Writer was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        unsafe { (*self.cb).closed.0.load(Ordering::Acquire) & WRITER_CLOSED != 0 }
+    }
+
+    /// Advance the cursor to `oldest`, if it is behind, counting the skipped elements.
+    fn skip_to(&mut self, oldest: u64) {
+        if oldest > self.position {
+            self.missed += oldest - self.position;
+            self.position = oldest;
+        }
+    }
+}
+
+unsafe impl<T> Send for SpectatorReader<T> {}
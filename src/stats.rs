@@ -0,0 +1,147 @@
+//! A snapshot of the state of a queue, for monitoring.
+//!
+//! `Writer::stats` and `Reader::stats` read the control block, that is shared by both sides,
+//! therefore either side can report the health of the queue, e.g: over an admin endpoint.
+//! The fields are loaded one by one, while the other side keeps running:
+//! the snapshot is not atomic, but every field is consistent on its own.
+//!
+//!```
+//! let (mut w, _r) = cueue::cueue::<u8>(16).unwrap();
+//! w.write_chunk()[..3].copy_from_slice(b"foo");
+//! w.commit(3);
+//!
+//! let stats = w.stats();
+//! assert_eq!(stats.occupancy, 3);
+//! println!("{}", stats.to_json());
+//!```
+
+use std::sync::atomic::Ordering;
+
+use crate::abi::{READER_BUSY, READER_CLOSED, WRITER_CLOSED};
+use crate::{ControlBlock, Reader, Writer};
+
+/// The state of a queue, see the module docs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// See `Writer::capacity`.
+    pub capacity: usize,
+    /// Number of elements committed by the Writer, not consumed by the Reader yet.
+    pub occupancy: usize,
+    /// Number of consumed elements kept for diagnostics, see `Builder::retention`.
+    pub retention: usize,
+    /// The highest occupancy seen by the Writer after a commit, so far.
+    ///
+    /// The Writer computes the occupancy using the read position loaded by `write_chunk`,
+    /// that might be stale: the peak is never underestimated.
+    pub peak_occupancy: usize,
+    /// Number of elements committed by the Writer so far.
+    pub write_position: u64,
+    /// Number of elements consumed by the Reader so far.
+    pub read_position: u64,
+    /// See `Writer::sequence`.
+    pub sequence: u64,
+    /// Number of records dropped by the Writer so far, see `Writer::record_dropped`.
+    pub dropped: u64,
+    /// See `Reader::set_notify_watermark`.
+    pub read_watermark: u64,
+    /// See `Writer::set_notify_watermark`.
+    pub write_watermark: u64,
+    /// True, if the Writer was dropped.
+    pub writer_closed: bool,
+    /// True, if the Reader was dropped.
+    pub reader_closed: bool,
+}
+
+impl Stats {
+    fn load(cb: &ControlBlock, capacity: usize) -> Self {
+        let closed = cb.closed.0.load(Ordering::Acquire);
+        let r = cb.read_position.0.load(Ordering::Acquire) & !READER_BUSY;
+        let w = cb.write_position.0.load(Ordering::Acquire);
+        Self {
+            capacity,
+            occupancy: w.saturating_sub(r) as usize,
+            retention: cb.retention.0.load(Ordering::Relaxed) as usize,
+            peak_occupancy: cb.peak_occupancy.0.load(Ordering::Relaxed) as usize,
+            write_position: w,
+            read_position: r,
+            sequence: cb.sequence.0.load(Ordering::Relaxed),
+            dropped: cb.dropped.0.load(Ordering::Relaxed),
+            read_watermark: cb.read_watermark.0.load(Ordering::Relaxed),
+            write_watermark: cb.write_watermark.0.load(Ordering::Relaxed),
+            writer_closed: closed & WRITER_CLOSED != 0,
+            reader_closed: closed & READER_CLOSED != 0,
+        }
+    }
+
+    /// Fraction of the capacity occupied, between 0 and 1.
+    pub fn utilization(&self) -> f64 {
+        self.occupancy as f64 / self.capacity as f64
+    }
+
+    /// Suggest a capacity, based on `peak_occupancy`, e.g: after a load test, that keeps
+    /// the `target_headroom` fraction of the capacity free at the peak (e.g: 0.5 for twice the peak).
+    ///
+    /// If the queue got full, or records were dropped, the peak demand is unknown:
+    /// then at least twice the current capacity is suggested. The retained elements
+    /// (see `Builder::retention`) are added, and the result is rounded up to a power of two,
+    /// as `cueue` does.
+    ///
+    /// Panics, if `target_headroom` is not in [0, 1).
+    ///
+    ///```
+    /// let (mut w, _r) = cueue::cueue::<u8>(1 << 16).unwrap();
+    /// w.write_chunk();
+    /// w.commit(1000);
+    /// assert_eq!(w.stats().recommended_capacity(0.5), 2048);
+    ///```
+    pub fn recommended_capacity(&self, target_headroom: f64) -> usize {
+        assert!(
+            (0.0..1.0).contains(&target_headroom),
+            "target headroom must be in [0, 1)"
+        );
+        let mut needed = (self.peak_occupancy as f64 / (1.0 - target_headroom)).ceil() as usize;
+        let full = self.peak_occupancy + self.retention >= self.capacity;
+        if full || self.dropped != 0 {
+            needed = needed.max(self.capacity * 2);
+        }
+        (needed + self.retention).max(1).next_power_of_two()
+    }
+
+    /// Format the stats as a single line JSON object, with the field names as keys.
+    pub fn to_json(&self) -> String {
+        format!(
+            concat!(
+                r#"{{"capacity":{},"occupancy":{},"retention":{},"peak_occupancy":{},"#,
+                r#""write_position":{},"read_position":{},"sequence":{},"dropped":{},"#,
+                r#""read_watermark":{},"write_watermark":{},"#,
+                r#""writer_closed":{},"reader_closed":{}}}"#,
+            ),
+            self.capacity,
+            self.occupancy,
+            self.retention,
+            self.peak_occupancy,
+            self.write_position,
+            self.read_position,
+            self.sequence,
+            self.dropped,
+            self.read_watermark,
+            self.write_watermark,
+            self.writer_closed,
+            self.reader_closed,
+        )
+    }
+}
+
+impl<T> Writer<T> {
+    /// A snapshot of the state of the queue, see the `stats` module.
+    pub fn stats(&self) -> Stats {
+        Stats::load(unsafe { &*self.cb }, self.mem.cap)
+    }
+}
+
+impl<T> Reader<T> {
+    /// A snapshot of the state of the queue, see the `stats` module.
+    pub fn stats(&self) -> Stats {
+        Stats::load(unsafe { &*self.cb }, self.mem.cap)
+    }
+}
@@ -0,0 +1,140 @@
+//! Duplicating the elements of a queue into two downstream queues.
+//!
+//! `Tee` consumes a Reader, and writes every element to two Writers, e.g: to process
+//! a stream live, and to archive it to disk at the same time. If a downstream queue is full,
+//! its `LagPolicy` decides: the Tee waits for it (holding back the other one as well),
+//! or drops the elements that do not fit, reporting them to its Reader as a gap
+//! (see `Reader::gap`), therefore a slow archive does not stall the live processing.
+//!
+//!```
+//! use cueue::tee::{LagPolicy, Tee};
+//!
+//! let (mut w, r) = cueue::cueue::<u8>(1 << 12).unwrap();
+//! let (live_w, mut live) = cueue::cueue::<u8>(1 << 12).unwrap();
+//! let (archive_w, mut archive) = cueue::cueue::<u8>(1 << 12).unwrap();
+//! let mut tee = Tee::new(r, live_w, archive_w).lag_policy(LagPolicy::Wait, LagPolicy::Drop);
+//!
+//! w.write_chunk()[..3].copy_from_slice(b"foo");
+//! w.commit(3);
+//! assert_eq!(tee.pump(), 3);
+//! assert_eq!(live.read_chunk(), b"foo");
+//! assert_eq!(archive.read_chunk(), b"foo");
+//!```
+
+use std::sync::atomic::Ordering;
+
+use crate::{Abandoned, Reader, Writer};
+
+/// What the Tee does, if a downstream queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Wait for the downstream Reader to make space: the other downstream queue waits as well.
+    #[default]
+    Wait,
+    /// Drop the elements that do not fit, and report them to the downstream Reader as a gap.
+    Drop,
+}
+
+/// Copies the elements of a Reader to two Writers, see the module docs.
+pub struct Tee<T> {
+    reader: Reader<T>,
+    outputs: [Writer<T>; 2],
+    policies: [LagPolicy; 2],
+}
+
+impl<T> Tee<T>
+where
+    T: Clone + Default,
+{
+    /// Copy the elements of `reader` to `first` and `second`, waiting for both, if they are full.
+    pub fn new(reader: Reader<T>, first: Writer<T>, second: Writer<T>) -> Self {
+        Self {
+            reader,
+            outputs: [first, second],
+            policies: [LagPolicy::Wait; 2],
+        }
+    }
+
+    /// Set the policies of the first and the second Writer, used if their queue is full.
+    pub fn lag_policy(mut self, first: LagPolicy, second: LagPolicy) -> Self {
+        self.policies = [first, second];
+        self
+    }
+
+    /// Copy the readable elements to both Writers, as many as the `LagPolicy::Wait` Writers
+    /// have space for, and consume them, without waiting.
+    ///
+    /// Elements are not copied to a Writer, whose Reader was dropped.
+    /// Returns the number of consumed elements.
+    pub fn pump(&mut self) -> usize {
+        let mut n = self.reader.read_chunk().len();
+        for (output, policy) in self.outputs.iter_mut().zip(self.policies) {
+            if policy == LagPolicy::Wait && !output.is_abandoned() {
+                n = usize::min(n, output.write_chunk().len());
+            }
+        }
+        let input = self.reader.limited_read_chunk(n);
+        for output in &mut self.outputs {
+            if output.is_abandoned() {
+                continue;
+            }
+            let chunk = output.limited_write_chunk(n);
+            let m = chunk.len();
+            chunk.clone_from_slice(&input[..m]);
+            output.commit(m);
+            if m < n {
+                output.record_dropped((n - m) as u64);
+            }
+        }
+        self.reader.commit();
+        n
+    }
+
+    /// Pump the elements (see `pump`), waiting for the upstream Writer to commit,
+    /// and for the `LagPolicy::Wait` downstream Readers to make space, until the upstream
+    /// Writer is dropped, and every element is copied.
+    ///
+    /// Waits using the wait strategies of the Reader and the Writers, or sleeps on the futex
+    /// of the queues, if `Builder::futex` is enabled.
+    /// Returns Err, if both downstream Readers are dropped.
+    pub fn run(&mut self) -> Result<(), Abandoned> {
+        let mut iteration = 0;
+        loop {
+            // loaded before the chunks, to wait for the next commit of the peers
+            let upstream_done = self.reader.is_abandoned();
+            let w = self.reader.write_pos().load(Ordering::Acquire);
+            let r = [0, 1].map(|i| self.outputs[i].read_pos().load(Ordering::Acquire));
+            if self.outputs.iter().all(|output| output.is_abandoned()) {
+                return Err(Abandoned);
+            }
+
+            if self.pump() != 0 {
+                iteration = 0;
+                continue;
+            }
+            if self.reader.read_chunk().is_empty() {
+                if upstream_done {
+                    return Ok(());
+                }
+                self.reader.wait_for_writer(w, iteration, None);
+            } else {
+                // a `LagPolicy::Wait` downstream queue is full (unless its Reader committed meanwhile)
+                let full = (0..2).find(|&i| {
+                    self.policies[i] == LagPolicy::Wait
+                        && !self.outputs[i].is_abandoned()
+                        && self.outputs[i].write_chunk().is_empty()
+                });
+                if let Some(i) = full {
+                    self.outputs[i].wait_for_reader(r[i], iteration, None);
+                }
+            }
+            iteration = iteration.saturating_add(1);
+        }
+    }
+
+    /// Returns the wrapped Reader and Writers.
+    pub fn into_inner(self) -> (Reader<T>, Writer<T>, Writer<T>) {
+        let [first, second] = self.outputs;
+        (self.reader, first, second)
+    }
+}
@@ -15,6 +15,23 @@ fn test_next_power_two() {
     assert!(next_power_two((1 << 63) + 1).is_err());
 }
 
+#[test]
+fn test_validated_capacity() {
+    const CAPACITY: usize = validated_capacity(1000);
+    assert_eq!(CAPACITY, 1024);
+    assert_eq!(validated_capacity(1), 1);
+    assert_eq!(validated_capacity(1 << 63), 1 << 63);
+    assert_eq!(validated_capacity_of::<u64>(1 << 59), 1 << 59);
+    assert_eq!(validated_capacity_of::<()>(1 << 63), 1 << 63);
+
+    assert!(std::panic::catch_unwind(|| validated_capacity(0)).is_err());
+    assert!(std::panic::catch_unwind(|| validated_capacity((1 << 63) + 1)).is_err());
+    assert!(std::panic::catch_unwind(|| validated_capacity_of::<u64>(1 << 60)).is_err());
+
+    let (w, _r) = Builder::new_checked::<u32, 5000>().build::<u32>().unwrap();
+    assert!(w.capacity() >= 8192);
+}
+
 #[test]
 fn test_capacity() {
     let (w, r) = cueue::<u8>(16).unwrap();
@@ -22,6 +39,396 @@ fn test_capacity() {
     assert!(w.capacity() >= 4096);
 }
 
+#[test]
+fn test_builder_noreserve() {
+    let (mut w, mut r) = Builder::new(1 << 24).noreserve(true).build::<u8>().unwrap();
+    assert!(w.capacity() >= 1 << 24);
+
+    w.write_chunk()[..3].copy_from_slice(b"foo");
+    w.commit(3);
+    assert_eq!(r.read_chunk(), b"foo");
+    r.commit();
+}
+
+#[test]
+fn test_builder_page_size() {
+    let (w, r) = cueue::<u8>(16).unwrap();
+    assert!(w.page_size().is_power_of_two());
+    assert_eq!(w.page_size(), r.page_size());
+
+    let huge = 2 << 20;
+    let (mut w, mut r) = Builder::new(16).page_size(huge).build::<u8>().unwrap();
+    assert_eq!(w.page_size(), huge);
+    assert_eq!(w.capacity(), huge);
+
+    let buf = w.write_chunk();
+    assert_eq!(buf.as_ptr() as usize % huge, 0);
+    buf[..3].copy_from_slice(b"foo");
+    w.commit(3);
+    assert_eq!(r.read_chunk(), b"foo");
+
+    assert!(Builder::new(16).page_size(3).build::<u8>().is_err());
+    assert!(Builder::new(16).page_size(1).build::<u8>().is_err());
+}
+
+#[test]
+fn test_backend() {
+    let (w, r) = cueue::<u8>(16).unwrap();
+    assert_eq!(w.backend(), r.backend());
+    #[cfg(target_os = "linux")]
+    {
+        assert_eq!(w.backend(), Backend::Memfd);
+        // the fallbacks, if memfd_create is not available
+        let name = std::ffi::CString::new("cueue").unwrap();
+        for fd in unsafe {
+            [
+                shm_memoryfile(&name).unwrap(),
+                tmpfile_memoryfile().unwrap(),
+            ]
+        } {
+            assert_eq!(unsafe { ftruncate(fd.as_raw_fd(), 4096) }, 0);
+        }
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_doublemap_layout() {
+    let name = format!("cueue-test-doublemap-{}", std::process::id());
+    let (mut w, mut r) = Builder::new(1 << 16).name(&name).build::<u8>().unwrap();
+    let cap = w.capacity();
+
+    // the control block with the buffer, then the mirror, without gaps
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+    let ranges: Vec<(usize, usize)> = maps
+        .lines()
+        .filter(|line| line.ends_with(&format!("/memfd:{} (deleted)", name)))
+        .map(|line| {
+            let range = line.split(' ').next().unwrap();
+            let (begin, end) = range.split_once('-').unwrap();
+            let parse = |s| usize::from_str_radix(s, 16).unwrap();
+            (parse(begin), parse(end))
+        })
+        .collect();
+    assert!(ranges.windows(2).all(|pair| pair[0].1 == pair[1].0));
+    let (begin, end) = (ranges[0].0, ranges.last().unwrap().1);
+    assert_eq!(end - begin, w.page_size() + 2 * cap);
+
+    w.write_chunk()[..cap / 2].fill(1);
+    w.commit(cap / 2);
+    r.read_chunk();
+    r.commit();
+    let chunk = w.write_chunk();
+    assert_eq!(chunk.len(), cap);
+    assert_eq!(chunk.as_ptr() as usize, begin + w.page_size() + cap / 2);
+}
+
+#[test]
+fn test_builder_name() {
+    assert!(Builder::new(16).name("a\0b").build::<u8>().is_err());
+
+    let name = format!("cueue-test-{}", std::process::id());
+    let (_w, _r) = Builder::new(16).name(&name).build::<u8>().unwrap();
+    #[cfg(target_os = "linux")]
+    {
+        let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+        assert!(maps.contains(&format!("/memfd:{} ", name)));
+    }
+}
+
+#[test]
+fn test_builder_file() {
+    let path = std::env::temp_dir().join(format!("cueue-test-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let builder = || {
+        Builder::new(16)
+            .file(&path)
+            .durability(Durability::OnCommit)
+    };
+
+    {
+        let (mut w, mut r) = builder().build::<u8>().unwrap();
+        w.write_chunk()[..6].copy_from_slice(b"foobar");
+        w.commit(6);
+        r.limited_read_chunk(3);
+        r.commit();
+        w.push(b'!').unwrap();
+    }
+
+    {
+        let (mut w, mut r) = builder().build::<u8>().unwrap();
+        assert_eq!(r.read_chunk(), b"bar!");
+        r.commit();
+        w.push(b'x').unwrap();
+        w.sync();
+    }
+
+    let (_, mut r) = builder().build::<u8>().unwrap();
+    assert_eq!(r.read_chunk(), b"x");
+
+    assert!(Builder::new(1 << 20).file(&path).build::<u8>().is_err());
+    std::fs::write(&path, vec![0; 2 * r.page_size()]).unwrap();
+    assert!(builder().build::<u8>().is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_build_on_fd() {
+    let path = std::env::temp_dir().join(format!("cueue-test-fd-{}", std::process::id()));
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let pagesize = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 };
+    file.set_len(4 * pagesize).unwrap();
+    let offset = pagesize;
+
+    {
+        let (mut w, mut r) = Builder::new(16).build_on_fd::<u8>(&file, offset).unwrap();
+        assert_eq!(w.backend(), Backend::External);
+        assert_eq!(w.capacity() as u64, pagesize);
+        w.write_chunk()[..6].copy_from_slice(b"foobar");
+        w.commit(6);
+        r.limited_read_chunk(3);
+        r.commit();
+    }
+
+    // the queue is found initialized in the region
+    let (_w, mut r) = Builder::new(16).build_on_fd::<u8>(&file, offset).unwrap();
+    assert_eq!(r.read_chunk(), b"bar");
+
+    // the file is untouched before the offset
+    let mut head = vec![1; pagesize as usize];
+    std::os::unix::fs::FileExt::read_exact_at(&file, &mut head, 0).unwrap();
+    assert!(head.iter().all(|&b| b == 0));
+
+    assert!(Builder::new(16).build_on_fd::<u8>(&file, 1).is_err());
+    assert!(Builder::new(16)
+        .build_on_fd::<u8>(&file, 3 * pagesize)
+        .is_err());
+}
+
+#[test]
+fn test_from_raw_parts() {
+    // borrow the mirrored mapping of another queue
+    let (w, _) = cueue::<u8>(16).unwrap();
+    let info = w.mapping_info();
+    let cap = w.capacity();
+    let _lease = w.lease_region();
+    drop(w);
+    let cb = info.control_block.addr;
+    let buf = info.buffer.addr;
+    unsafe { cb.write_bytes(0, abi::CONTROL_BLOCK_SIZE) };
+
+    {
+        let (mut w, mut r) = unsafe { from_raw_parts(cb, buf, cap).unwrap() };
+        assert_eq!(w.backend(), Backend::Raw);
+        assert_eq!(w.write_chunk().len(), cap);
+        w.write_chunk()[..6].copy_from_slice(b"foobar");
+        w.commit(6);
+        r.limited_read_chunk(3);
+        r.commit();
+    }
+
+    // the control block is initialized
+    let (_w, mut r) = unsafe { from_raw_parts(cb, buf, cap).unwrap() };
+    assert_eq!(r.read_chunk(), b"bar");
+
+    assert!(unsafe { from_raw_parts(cb, buf, cap - 1) }.is_err());
+    assert!(unsafe { from_raw_parts(cb.add(1), buf, cap) }.is_err());
+}
+
+#[test]
+fn test_builder_dax() {
+    assert!(Builder::new(16).dax(true).build::<u8>().is_err());
+
+    // the temp dir is not expected to be on a DAX filesystem
+    let path = std::env::temp_dir().join(format!("cueue-test-dax-{}", std::process::id()));
+    let result = Builder::new(16).file(&path).dax(true).build::<u8>();
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_attach_reader() {
+    let path = std::env::temp_dir().join(format!("cueue-test-attach-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let builder = || Builder::new(16).file(&path);
+    let attach = |start| builder().attach_reader::<u8>(start);
+    assert!(attach(StartPosition::Oldest).is_err());
+
+    let (mut w, r) = builder().build::<u8>().unwrap();
+    assert!(attach(StartPosition::Oldest).is_err());
+    drop(r);
+    assert!(w.is_abandoned());
+    w.write_chunk()[..2].copy_from_slice(b"ab");
+    w.commit(2);
+
+    let mut r = attach(StartPosition::Oldest).unwrap();
+    assert!(!w.is_abandoned());
+    assert_eq!(r.read_chunk(), b"ab");
+    r.limited_read_chunk(1);
+    r.commit();
+    drop(r);
+
+    w.push(b'c').unwrap();
+    let mut r = attach(StartPosition::Oldest).unwrap();
+    assert_eq!(r.read_chunk(), b"bc");
+    drop(r);
+
+    let mut r = attach(StartPosition::Latest).unwrap();
+    assert!(r.read_chunk().is_empty());
+    w.push(b'd').unwrap();
+    assert_eq!(r.read_chunk(), b"d");
+    drop(w);
+    assert!(r.is_abandoned());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_cloexec() {
+    let (w, _r) = cueue::<u8>(16).unwrap();
+    assert!(w.inheritable_fd().is_none());
+
+    let (mut w, r) = Builder::new(16).cloexec(false).build::<u8>().unwrap();
+    w.write_chunk()[..3].copy_from_slice(b"foo");
+    w.commit(3);
+    let fd = w.inheritable_fd().unwrap();
+    let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFD) };
+    assert_eq!(flags & libc::FD_CLOEXEC, 0);
+
+    // as in a child process, that inherited the fd
+    assert!(Builder::new(16)
+        .attach_reader_on_fd::<u8>(fd, 0, StartPosition::Oldest)
+        .is_err());
+    drop(r);
+    let mut r = Builder::new(16)
+        .attach_reader_on_fd::<u8>(fd, 0, StartPosition::Oldest)
+        .unwrap();
+    assert_eq!(r.backend(), Backend::External);
+    assert_eq!(r.read_chunk(), b"foo");
+    assert!(r.inheritable_fd().is_none());
+}
+
+#[test]
+fn test_shared_mem_safe() {
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    #[repr(C)]
+    struct Sample {
+        timestamp: u64,
+        value: [f32; 2],
+    }
+    unsafe impl SharedMemSafe for Sample {}
+
+    let path = std::env::temp_dir().join(format!("cueue-test-shared-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let builder = || Builder::new(16).file(&path);
+    let (mut w, r) = builder().build::<Sample>().unwrap();
+    drop(r);
+    let sample = Sample {
+        timestamp: 1,
+        value: [0.5, 1.5],
+    };
+    w.push(sample).unwrap();
+
+    let mut r = builder()
+        .attach_reader::<Sample>(StartPosition::Oldest)
+        .unwrap();
+    assert_eq!(r.read_chunk(), &[sample]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_control_block_offsets() {
+    let cb = std::mem::MaybeUninit::<ControlBlock>::uninit();
+    let base = cb.as_ptr() as usize;
+    // std::mem::offset_of is not available in rust 1.63
+    macro_rules! offset_of {
+        ($field:ident) => {
+            unsafe { std::ptr::addr_of!((*cb.as_ptr()).$field) as usize - base }
+        };
+    }
+    assert_eq!(offset_of!(magic), abi::MAGIC_OFFSET);
+    assert_eq!(offset_of!(write_position), abi::WRITE_POSITION_OFFSET);
+    assert_eq!(offset_of!(read_position), abi::READ_POSITION_OFFSET);
+    assert_eq!(offset_of!(sequence), abi::SEQUENCE_OFFSET);
+    assert_eq!(offset_of!(dropped), abi::DROPPED_OFFSET);
+    assert_eq!(offset_of!(completion), abi::COMPLETION_OFFSET);
+    assert_eq!(offset_of!(completion_code), abi::COMPLETION_CODE_OFFSET);
+    assert_eq!(offset_of!(read_watermark), abi::READ_WATERMARK_OFFSET);
+    assert_eq!(offset_of!(write_watermark), abi::WRITE_WATERMARK_OFFSET);
+    assert_eq!(offset_of!(clock), abi::CLOCK_OFFSET);
+    assert_eq!(offset_of!(epoch_offset), abi::EPOCH_OFFSET_OFFSET);
+    assert_eq!(offset_of!(reader_waiting), abi::READER_WAITING_OFFSET);
+    assert_eq!(offset_of!(writer_waiting), abi::WRITER_WAITING_OFFSET);
+    assert_eq!(offset_of!(write_epoch), abi::WRITE_EPOCH_OFFSET);
+    assert_eq!(offset_of!(read_epoch), abi::READ_EPOCH_OFFSET);
+    assert_eq!(offset_of!(closed), abi::CLOSED_OFFSET);
+    assert_eq!(offset_of!(writer_pid), abi::WRITER_PID_OFFSET);
+    assert_eq!(offset_of!(reader_pid), abi::READER_PID_OFFSET);
+    assert_eq!(offset_of!(retention), abi::RETENTION_OFFSET);
+    assert_eq!(offset_of!(peak_occupancy), abi::PEAK_OCCUPANCY_OFFSET);
+}
+
+#[test]
+fn test_abi_layout() {
+    let path = std::env::temp_dir().join(format!("cueue-test-abi-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (mut w, mut r) = Builder::new(16)
+        .file(&path)
+        .sequence(true)
+        .build::<u32>()
+        .unwrap();
+    w.write_chunk()[..3].copy_from_slice(&[7, 8, 9]);
+    w.commit(3);
+    r.limited_read_chunk(1);
+    r.commit();
+
+    let file = std::fs::read(&path).unwrap();
+    let field = |offset: usize| u64::from_ne_bytes(file[offset..offset + 8].try_into().unwrap());
+    assert_eq!(field(abi::MAGIC_OFFSET), abi::MAGIC);
+    assert_eq!(field(abi::WRITE_POSITION_OFFSET), 3);
+    assert_eq!(field(abi::READ_POSITION_OFFSET), 1);
+    assert_eq!(field(abi::SEQUENCE_OFFSET), 1);
+
+    let buffer = &file[w.page_size()..];
+    assert_eq!(buffer.len(), w.capacity() * 4);
+    assert_eq!(&buffer[4..8], &8u32.to_ne_bytes());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_clock_info() {
+    let (w, r) = cueue::<u8>(16).unwrap();
+    assert_eq!(w.clock_info().clock, clock::Clock::Monotonic);
+    assert_eq!(w.clock_info(), r.clock_info());
+
+    let path = std::env::temp_dir().join(format!("cueue-test-clock-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (w, _r) = Builder::new(16)
+        .file(&path)
+        .clock(clock::Clock::Realtime)
+        .build::<u8>()
+        .unwrap();
+    let info = w.clock_info();
+    assert_eq!(info.clock, clock::Clock::Realtime);
+    assert_eq!(info.epoch_offset, 0);
+    let stamp = w.timestamp();
+    let now = std::time::SystemTime::now();
+    let elapsed = now.duration_since(info.to_system_time(stamp)).unwrap();
+    assert!(elapsed < std::time::Duration::from_secs(1));
+    drop((w, _r));
+
+    // the recorded clock is kept on reopen
+    let (_w, r) = Builder::new(16).file(&path).build::<u8>().unwrap();
+    assert_eq!(r.clock_info().clock, clock::Clock::Realtime);
+    std::fs::remove_file(&path).unwrap();
+}
+
 #[test]
 fn test_writer() {
     let (mut w, r) = cueue::<u8>(16).unwrap();
@@ -45,124 +452,3061 @@ fn test_writer() {
 }
 
 #[test]
-fn test_reader() {
-    let (mut w, mut r) = cueue(16).unwrap();
+fn test_write_chunk_exact() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
 
-    let empty = r.read_chunk();
-    assert_eq!(empty.len(), 0);
+    let buf = w.write_chunk_exact(3).unwrap();
+    assert_eq!(buf.len(), 3);
+    buf.copy_from_slice(b"foo");
+    assert_eq!(w.commit(10), 3);
+
+    assert!(w.write_chunk_exact(cap).is_none());
+    assert_eq!(w.write_chunk_exact(cap - 3).unwrap().len(), cap - 3);
+
+    assert_eq!(r.read_chunk(), b"foo");
+}
+
+#[test]
+fn test_limited_chunks() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+
+    let buf = w.limited_write_chunk(4);
+    assert_eq!(buf.len(), 4);
+    buf.copy_from_slice(b"abcd");
+    assert_eq!(w.commit(10), 4);
+    assert_eq!(w.limited_write_chunk(usize::MAX).len(), w.capacity() - 4);
+
+    assert_eq!(r.limited_read_chunk(3), b"abc");
     r.commit();
+    assert_eq!(r.limited_read_chunk(3), b"d");
+    r.commit();
+    assert!(r.read_chunk().is_empty());
+}
 
-    let buf = w.write_chunk();
-    buf[..3].copy_from_slice(b"foo");
-    w.commit(3);
+#[test]
+fn test_commit_all() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
 
-    let foo = r.read_chunk();
-    assert_eq!(foo, b"foo");
+    w.limited_write_chunk(4).copy_from_slice(b"abcd");
+    assert_eq!(w.commit(1), 1);
+    assert_eq!(w.commit_all(), 3);
+    assert_eq!(w.commit_all(), 0);
+    assert_eq!(r.read_chunk(), b"abcd");
     r.commit();
 
-    assert!(!r.is_abandoned());
-    std::mem::drop(w);
-    assert!(r.is_abandoned());
+    let len = w.write_chunk_exact(2).unwrap().len();
+    w.limited_write_chunk(1)[0] = b'e';
+    assert_eq!(len, 2);
+    assert_eq!(w.commit_all(), 1);
+    assert_eq!(r.read_chunk(), b"e");
 }
 
 #[test]
-fn test_full() {
+fn test_commit_twice() {
     let (mut w, mut r) = cueue::<u8>(16).unwrap();
 
-    let buf = w.write_chunk();
-    let buflen = buf.len();
-    assert_eq!(buf.len(), w.capacity());
-    w.commit(buflen);
+    // without read_chunk
+    w.write_chunk()[..2].copy_from_slice(b"ab");
+    w.commit(2);
+    r.commit();
+    assert_eq!(r.read_chunk(), b"ab");
+    r.commit();
 
-    let empty = w.write_chunk();
-    assert_eq!(empty.len(), 0);
+    // the second commit does not consume the new elements
+    w.write_chunk()[..2].copy_from_slice(b"cd");
+    w.commit(2);
+    r.commit();
+    assert_eq!(r.read_chunk(), b"cd");
+}
 
-    let full = r.read_chunk();
-    assert_eq!(full.len(), buflen);
-    assert_eq!(full.len(), r.capacity());
+#[test]
+fn test_commit_unchecked() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+
+    w.write_chunk()[..4].copy_from_slice(b"abcd");
+    unsafe { w.commit_unchecked(3) };
+    unsafe { w.commit_unchecked(1) };
+    assert_eq!(r.read_chunk(), b"abcd");
+    unsafe { r.commit_read_unchecked(2) };
+    assert_eq!(r.read_chunk(), b"cd");
+    unsafe { r.commit_read_unchecked(2) };
+    assert!(r.read_chunk().is_empty());
 }
 
 #[test]
-fn test_reuse() {
-    let (mut w, mut r) = cueue(16).unwrap();
+fn test_commit_on_drop() {
+    let (mut w, mut r) = Builder::new(16).commit_on_drop(true).build::<u8>().unwrap();
+    w.push(b'a').unwrap();
+    w.write_chunk()[..2].copy_from_slice(b"bc");
+    assert_eq!(w.commit_relaxed(2), 2);
+    assert_eq!(r.read_chunk(), b"a");
+    r.commit();
+    drop(w);
+    assert_eq!(r.read_chunk(), b"bc");
 
-    // fill the queue with strings
-    let buf = w.write_chunk();
-    for s in buf.into_iter() {
-        *s = "foobar";
-    }
-    let buflen = buf.len();
-    w.commit(buflen);
+    // nothing written, nothing committed
+    let (mut w, mut r) = Builder::new(16).commit_on_drop(true).build::<u8>().unwrap();
+    w.write_chunk();
+    drop(w);
+    assert!(r.read_chunk().is_empty());
 
-    // consume everything
-    let full = r.read_chunk();
-    assert_eq!(full.len(), buflen);
+    // only the marked elements are committed
+    let (mut w, mut r) = Builder::new(16).commit_on_drop(true).build::<u8>().unwrap();
+    w.write_chunk()[0] = b'a';
+    w.commit_relaxed(1);
+    w.write_chunk()[1] = b'b';
+    w.commit(1);
+    w.write_chunk();
+    drop(w);
+    assert_eq!(r.read_chunk(), b"a");
     r.commit();
+    assert!(r.read_chunk().is_empty());
 
-    // try writing again
-    let buf = w.write_chunk();
-    assert_eq!(buf[0], "foobar");
+    // the elements are committed before the end of the stream, not after it
+    let (mut w, mut r) = Builder::new(16)
+        .commit_on_drop(true)
+        .sequence(true)
+        .build::<u8>()
+        .unwrap();
+    w.write_chunk()[0] = b'a';
+    w.commit_relaxed(1);
+    let seq = w.sequence();
+    w.finish(Completion::Ok);
+    assert_eq!(r.sequence(), seq + 1);
+    assert_eq!(r.read_chunk(), b"a");
+    assert_eq!(r.completion(), Some(Completion::Ok));
+
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    w.write_chunk_exact(2).unwrap().copy_from_slice(b"bc");
+    drop(w);
+    assert!(r.read_chunk().is_empty());
 }
 
 #[test]
-fn test_push() {
-    let (mut w, _) = cueue(16).unwrap();
-    let cap = w.capacity();
+fn test_auto_reader() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    w.write_chunk()[..3].copy_from_slice(b"abc");
+    w.commit(3);
 
-    for i in 0..cap {
-        assert_eq!(w.push(i), Ok(()));
-    }
+    // a chunk acquired before wrapping is not consumed
+    assert_eq!(r.limited_read_chunk(1), b"a");
+    let r = auto::AutoReader::new(r).into_inner();
+    let mut r = auto::AutoReader::new(r);
+    assert_eq!(r.limited_read_chunk(2), b"ab");
+    assert_eq!(r.read_chunk(), b"c");
+    assert!(r.read_chunk().is_empty());
 
-    assert_eq!(w.push(0), Err(0));
+    w.push(b'd').unwrap();
+    assert_eq!(r.read_chunk(), b"d");
+    let mut r = r.into_inner();
+    assert!(r.read_chunk().is_empty());
+    assert_eq!(w.write_chunk().len(), w.capacity());
 }
 
 #[test]
-fn test_push_string() {
-    let (mut w, _) = cueue(16).unwrap();
+fn test_chunks() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
     let cap = w.capacity();
 
-    for i in 0..cap {
-        assert_eq!(w.push(i.to_string()), Ok(()));
-    }
+    w.write_chunk()[0] = b'x'; // not committed by the chunks
+    let mut chunks = w.chunks_mut(cap / 2);
+    chunks.next().unwrap().fill(b'a');
+    let chunk = chunks.next().unwrap();
+    chunk.fill(b'b');
+    chunk[..2].copy_from_slice(b"cd");
+    chunks.truncate(2);
+    assert_eq!(chunks.next().unwrap().len(), cap / 2 - 2);
+    chunks.truncate(0);
+    drop(chunks);
 
-    assert_eq!(w.push("foo".to_string()), Err("foo".to_string()));
+    let mut chunks = r.chunks(cap);
+    let chunk = chunks.next().unwrap();
+    assert_eq!(chunk.len(), cap / 2 + 2);
+    assert!(chunk.starts_with(b"aa"));
+    assert!(chunk.ends_with(b"acd"));
+    assert!(chunks.next().is_none());
+    drop(chunks);
+    assert!(r.read_chunk().is_empty());
+
+    let mut chunks = w.chunks_mut(cap);
+    assert_eq!(chunks.next().unwrap().len(), cap);
+    assert!(chunks.next().is_none());
 }
 
 #[test]
-fn test_cueue_threaded_w_r() {
-    let (mut w, mut r) = cueue(16).unwrap();
-    let maxi = 1_000_000;
+fn test_read_chunk_exact() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+
+    w.write_chunk()[..5].copy_from_slice(b"abcde");
+    w.commit(5);
+
+    assert_eq!(r.read_chunk_exact(2), Some(&b"ab"[..]));
+    r.commit();
+    assert_eq!(r.read_chunk_exact(2), Some(&b"cd"[..]));
+    r.commit();
+    assert_eq!(r.read_chunk_exact(2), None);
+    r.commit();
+    assert_eq!(r.read_chunk(), b"e");
+}
+
+#[test]
+fn test_read_exact_timeout() {
+    use std::time::Duration;
+
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    r.set_wait_strategy(wait::Sleep(Duration::from_millis(1)));
+
+    w.write_chunk()[..2].copy_from_slice(b"ab");
+    w.commit(2);
+    assert_eq!(r.read_exact_timeout(3, Duration::from_millis(10)), None);
 
     let wt = std::thread::spawn(move || {
-        let mut msg: u8 = 0;
-        for _ in 0..maxi {
-            let buf = loop {
-                let buf = w.write_chunk();
-                if buf.len() > 0 {
-                    break buf;
-                }
-            };
-            buf[0] = msg;
-            w.commit(1);
+        std::thread::sleep(Duration::from_millis(10));
+        w.write_chunk()[..2].copy_from_slice(b"cd");
+        w.commit(2);
+    });
 
-            msg = msg.wrapping_add(1);
+    assert_eq!(
+        r.read_exact_timeout(3, Duration::from_secs(60)),
+        Some(&b"abc"[..])
+    );
+    r.commit();
+    wt.join().unwrap();
+
+    // writer is gone, never completes
+    assert_eq!(r.read_exact_timeout(3, Duration::from_secs(60)), None);
+    assert_eq!(
+        r.read_exact_timeout(1, Duration::from_secs(60)),
+        Some(&b"d"[..])
+    );
+}
+
+#[test]
+fn test_drain_on_drop() {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let (mut w, mut r) = Builder::new(16)
+        .drain_on_drop(Duration::from_secs(60))
+        .build::<u8>()
+        .unwrap();
+    assert!(w.wait_empty(Duration::ZERO));
+    w.push(b'a').unwrap();
+    assert!(!w.wait_empty(Duration::from_millis(1)));
+
+    let consumed = Arc::new(AtomicBool::new(false));
+    let rt = std::thread::spawn({
+        let consumed = consumed.clone();
+        move || {
+            std::thread::sleep(Duration::from_millis(10));
+            assert_eq!(r.read_chunk(), b"a");
+            consumed.store(true, Ordering::Relaxed);
+            r.commit();
+            assert_eq!(r.read_chunk_blocking(), Err(Abandoned));
+        }
+    });
+    w.finish(Completion::Ok);
+    assert!(consumed.load(Ordering::Relaxed));
+    rt.join().unwrap();
+
+    // the Reader is gone, nothing to wait for
+    let (mut w, r) = Builder::new(16)
+        .drain_on_drop(Duration::from_secs(60))
+        .build::<u8>()
+        .unwrap();
+    w.push(b'a').unwrap();
+    drop(r);
+    assert!(!w.wait_empty(Duration::from_secs(60)));
+    drop(w);
+}
+
+#[test]
+fn test_shutdown() {
+    use shutdown::Shutdown;
+    use std::time::Duration;
+
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    let consumer = std::thread::spawn(move || {
+        let mut out = Vec::new();
+        while let Ok(chunk) = r.read_chunk_blocking() {
+            out.extend_from_slice(chunk);
+            r.commit();
         }
+        (out, r.completion())
+    });
+    w.write_chunk()[..3].copy_from_slice(b"abc");
+    w.commit(3);
+    let report = Shutdown::new(w)
+        .completion(Completion::Failed(7))
+        .join(consumer)
+        .run(Duration::from_secs(60));
+    assert!(report.drained);
+    assert!(!report.abandoned);
+    let (out, completion) = report.consumer.unwrap().unwrap();
+    assert_eq!(out, b"abc");
+    assert_eq!(completion, Some(Completion::Failed(7)));
+
+    // the Reader is gone, leaving elements unread
+    let (mut w, r) = cueue::<u8>(16).unwrap();
+    w.push(b'a').unwrap();
+    drop(r);
+    let report = Shutdown::new(w).run(Duration::from_secs(60));
+    assert!(!report.drained);
+    assert!(report.abandoned);
+    assert!(report.consumer.is_none());
+
+    // the consumer does not finish in time
+    let (mut w, r) = cueue::<u8>(16).unwrap();
+    w.push(b'a').unwrap();
+    let consumer = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        drop(r);
     });
+    let report = Shutdown::new(w)
+        .join(consumer)
+        .run(Duration::from_millis(1));
+    assert!(!report.drained);
+    assert!(!report.abandoned);
+    assert!(report.consumer.is_none());
+}
+
+#[test]
+fn test_push_blocking() {
+    use std::time::Duration;
+
+    let (mut w, mut r) = cueue::<u64>(16).unwrap();
+    let cap = w.capacity() as u64;
+    for i in 0..cap {
+        w.push_blocking(i, Duration::ZERO).unwrap();
+    }
+    assert_eq!(w.push_blocking(cap, Duration::from_millis(1)), Err(cap));
 
     let rt = std::thread::spawn(move || {
-        let mut emsg: u8 = 0;
-        let mut i = 0;
-        while i < maxi {
-            let rr = r.read_chunk();
-            for msg in rr {
-                assert_eq!(*msg, emsg);
-                emsg = emsg.wrapping_add(1);
-                i += 1;
-            }
+        let mut sum = 0;
+        while let Ok(chunk) = r.read_chunk_blocking() {
+            sum += chunk.iter().sum::<u64>();
             r.commit();
         }
+        sum
     });
+    for i in cap..cap * 4 {
+        w.push_blocking(i, Duration::from_secs(60)).unwrap();
+    }
+    drop(w);
+    let n = cap * 4;
+    assert_eq!(rt.join().unwrap(), n * (n - 1) / 2);
 
-    wt.join().unwrap();
-    rt.join().unwrap();
+    // the Reader is gone
+    let (mut w, r) = cueue::<u64>(16).unwrap();
+    drop(r);
+    while w.push(0).is_ok() {}
+    assert_eq!(w.push_blocking(1, Duration::from_secs(60)), Err(1));
+}
+
+#[test]
+fn test_read_at_least() {
+    use std::time::Duration;
+
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    w.write_chunk()[..2].copy_from_slice(b"ab");
+    w.commit(2);
+    assert_eq!(r.read_at_least(2), Ok(&b"ab"[..]));
+
+    let wt = std::thread::spawn(move || {
+        for &b in b"cdef" {
+            std::thread::sleep(Duration::from_millis(1));
+            w.push(b).unwrap();
+        }
+        w
+    });
+    let chunk = r.read_at_least(4).unwrap();
+    assert_eq!(&chunk[..4], b"abcd");
+    r.commit();
+    let mut w = wt.join().unwrap();
+
+    // a full queue is the largest batch
+    while w.push(b'x').is_ok() {}
+    let cap = r.capacity();
+    assert_eq!(r.read_at_least(cap + 1).unwrap().len(), cap);
+    r.commit();
+
+    // the rest, after the Writer is dropped
+    w.push(b'z').unwrap();
+    drop(w);
+    assert_eq!(r.read_at_least(2), Ok(&b"z"[..]));
+    r.commit();
+    assert_eq!(r.read_at_least(2), Err(Abandoned));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_futex() {
+    use std::time::Duration;
+
+    let (mut w, mut r) = Builder::new(16)
+        .futex(true)
+        .full_policy(FullPolicy::Block)
+        .build::<u8>()
+        .unwrap();
+    let cap = w.capacity();
+
+    // the Writer blocks on the full queue, until the Reader commits, then until it is dropped
+    let wt = std::thread::spawn(move || {
+        let mut data = std::iter::repeat(1u8).take(cap + 1);
+        assert_eq!(w.push_many(&mut data), cap + 1);
+        w.push(2).unwrap_err()
+    });
+
+    assert!(r.read_exact_timeout(cap, Duration::from_secs(60)).is_some());
+    r.limited_read_chunk(1);
+    r.commit();
+    assert_eq!(
+        r.read_exact_timeout(cap, Duration::from_secs(60))
+            .map(|c| c.len()),
+        Some(cap)
+    );
+    assert_eq!(r.read_exact_timeout(cap + 1, Duration::ZERO), None);
+    drop(r);
+    assert_eq!(wt.join().unwrap(), 2);
+
+    // the Reader wakes up when the Writer is dropped
+    let (w, mut r) = Builder::new(16).futex(true).build::<u8>().unwrap();
+    let wt = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(10));
+        drop(w);
+    });
+    assert_eq!(r.read_exact_timeout(1, Duration::from_secs(60)), None);
+    assert!(r.is_abandoned());
+    wt.join().unwrap();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_hybrid_wait() {
+    use std::sync::atomic::AtomicU32;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use wait::WaitStrategy;
+
+    let hybrid = wait::HybridWait::default();
+    assert!(!hybrid.park(0));
+    assert!(hybrid.park(hybrid.spins + hybrid.yields));
+
+    /// Counts the waits, parks after 3
+    struct Counting(Arc<AtomicU32>);
+
+    impl WaitStrategy for Counting {
+        fn wait(&mut self, _iteration: u32, _deadline: Option<Instant>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn park(&self, iteration: u32) -> bool {
+            iteration >= 3
+        }
+    }
+
+    let waits = Arc::new(AtomicU32::new(0));
+    let (mut w, mut r) = Builder::new(16).futex(true).build::<u8>().unwrap();
+    r.set_wait_strategy(Counting(waits.clone()));
+    let wt = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(10));
+        w.push(b'x').unwrap();
+    });
+    assert_eq!(r.read_chunk_blocking(), Ok(&b"x"[..]));
+    assert_eq!(waits.load(Ordering::Relaxed), 3);
+    wt.join().unwrap();
+
+    // without a futex, the strategy is used in every iteration
+    let (_w, mut r) = cueue::<u8>(16).unwrap();
+    r.set_wait_strategy(Counting(waits.clone()));
+    waits.store(0, Ordering::Relaxed);
+    assert_eq!(r.read_exact_timeout(1, Duration::from_millis(5)), None);
+    assert!(waits.load(Ordering::Relaxed) > 3);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_dead_writer_process() {
+    let (mut w, mut r) = Builder::new(16).futex(true).build::<u8>().unwrap();
+
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0);
+    if pid == 0 {
+        // the child dies without dropping the Writer
+        w.register_process();
+        w.push(b'x').unwrap();
+        unsafe { libc::_exit(0) };
+    }
+    // owned by the child
+    std::mem::forget(w);
+
+    assert_eq!(r.read_chunk_blocking(), Ok(&b"x"[..]));
+    r.commit();
+    assert_eq!(r.read_chunk_blocking(), Err(Abandoned));
+    assert!(r.is_abandoned());
+    unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) };
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_fork_guard() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    w.push(b'a').unwrap();
+
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0);
+    if pid == 0 {
+        // the handles of the parent are inert in the child, and do not close the queue on drop
+        let inert = w.check_fork() == Err(Forked)
+            && r.check_fork() == Err(Forked)
+            && w.write_chunk().is_empty()
+            && r.read_chunk().is_empty()
+            && w.is_abandoned()
+            && r.is_abandoned();
+        drop(w);
+        drop(r);
+        unsafe { libc::_exit(if inert { 0 } else { 1 }) };
+    }
+    let mut status = 0;
+    unsafe { libc::waitpid(pid, &mut status, 0) };
+    assert!(libc::WIFEXITED(status));
+    assert_eq!(libc::WEXITSTATUS(status), 0);
+
+    assert_eq!(w.check_fork(), Ok(()));
+    assert!(!w.is_abandoned());
+    assert!(!r.is_abandoned());
+    w.push(b'b').unwrap();
+    assert_eq!(r.read_chunk(), b"ab");
+}
+
+#[test]
+fn test_seek_to_latest() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    assert_eq!(r.seek_to_latest(), 0);
+
+    w.write_chunk()[..3].copy_from_slice(b"abc");
+    w.commit(3);
+    r.limited_read_chunk(1);
+    assert_eq!(r.seek_to_latest(), 3);
+    assert!(r.read_chunk().is_empty());
+    assert_eq!(r.gap(), 0);
+
+    w.push(b'd').unwrap();
+    assert_eq!(r.read_chunk(), b"d");
+}
+
+#[test]
+fn test_retention() {
+    assert!(Builder::new(16).retention(1 << 20).build::<u8>().is_err());
+
+    let (mut w, mut r) = Builder::new(16).retention(4).build::<u8>().unwrap();
+    let cap = w.capacity();
+    assert!(r.retained().is_empty());
+
+    w.write_chunk()[..6].copy_from_slice(b"abcdef");
+    w.commit(6);
+    r.limited_read_chunk(2);
+    r.commit();
+    assert_eq!(r.retained(), b"ab");
+    r.limited_read_chunk(3);
+    r.commit();
+    assert_eq!(r.retained(), b"bcde");
+
+    // the retained elements are not writable
+    assert_eq!(w.write_chunk().len(), cap - 4 - 1);
+    w.write_chunk().fill(b'x');
+    w.commit(cap);
+    assert_eq!(r.retained(), b"bcde");
+
+    let mut spectator = r.spectator();
+    let mut buf = vec![0; cap];
+    assert_eq!(spectator.read(&mut buf), cap);
+    assert_eq!(&buf[..5], b"bcdef");
+    assert_eq!(spectator.missed(), 0);
+}
+
+#[test]
+fn test_spectator() {
+    let (mut w, mut r) = cueue::<u32>(16).unwrap();
+    let cap = w.capacity();
+    let mut spectator = r.spectator();
+    let mut buf = vec![0; cap];
+
+    w.write_chunk()[..3].copy_from_slice(&[1, 2, 3]);
+    w.commit(3);
+    assert_eq!(spectator.read(&mut buf[..2]), 2);
+    assert_eq!(&buf[..2], &[1, 2]);
+    assert_eq!(r.read_chunk(), &[1, 2, 3]);
+    r.commit();
+
+    // the Reader consumed 3, and the Writer overwrote it
+    w.write_chunk().fill(4);
+    w.commit(cap);
+    assert_eq!(spectator.read(&mut buf), cap);
+    assert_eq!(spectator.missed(), 1);
+    assert!(buf.iter().all(|&x| x == 4));
+    assert_eq!(spectator.position(), cap as u64 + 3);
+    assert_eq!(spectator.read(&mut buf), 0);
+
+    r.read_chunk();
+    r.commit();
+    w.push(5).unwrap();
+    spectator.seek_to_latest();
+    assert_eq!(spectator.read(&mut buf), 0);
+    assert!(!spectator.is_abandoned());
+    drop(w);
+    drop(r);
+    assert!(spectator.is_abandoned());
+}
+
+#[test]
+fn test_buffer_region() {
+    let (mut w, mut r) = cueue::<u64>(16).unwrap();
+    let region = w.buffer_region();
+    assert_eq!(region, r.buffer_region());
+    assert_eq!(region.addr as usize % w.page_size(), 0);
+    assert_eq!(region.len, 2 * w.capacity() * 8);
+
+    // chunks crossing the end of the buffer are inside the region
+    let cap = w.capacity();
+    w.write_chunk();
+    w.commit(cap - 1);
+    r.read_chunk();
+    r.commit();
+    let chunk = w.write_chunk();
+    assert_eq!(chunk.len(), cap);
+    assert!(region.contains(chunk.as_ptr().cast(), chunk.len() * 8));
+    assert!(!region.contains(chunk.as_ptr().cast(), region.len));
+}
+
+#[test]
+fn test_socket_pump() {
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsFd;
+    use std::os::unix::net::UnixStream;
+
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+    let (mut a, b) = UnixStream::pair().unwrap();
+
+    // fill the queue from the socket
+    let data: Vec<u8> = (0..cap + 10).map(|i| i as u8).collect();
+    a.write_all(&data).unwrap();
+    let mut received = 0;
+    while received < cap {
+        received += w.recv_from_socket(b.as_fd(), 0).unwrap();
+    }
+    let err = w.recv_from_socket(b.as_fd(), 0).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+
+    // send it back
+    let mut sent = 0;
+    while sent < cap {
+        sent += r.send_to_socket(b.as_fd(), 0).unwrap();
+    }
+    assert_eq!(r.send_to_socket(b.as_fd(), 0).unwrap(), 0);
+    let mut buf = vec![0; cap];
+    a.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, data[..cap]);
+
+    // the rest, then the end of the stream
+    drop(a);
+    assert_eq!(w.recv_from_socket(b.as_fd(), 0).unwrap(), 10);
+    assert_eq!(w.recv_from_socket(b.as_fd(), 0).unwrap(), 0);
+    assert_eq!(r.read_chunk(), &data[cap..]);
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "nto"))]
+fn test_net_relay() {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    let (mut client, from) = UnixStream::pair().unwrap();
+    let (to, mut server) = UnixStream::pair().unwrap();
+    // much more than the queue holds: the producer waits for the consumer
+    let relay = crate::net::relay(from.into(), to.into(), 16).unwrap();
+    let data: Vec<u8> = (0..1 << 20).map(|i| (i % 251) as u8).collect();
+
+    let writer = {
+        let data = data.clone();
+        std::thread::spawn(move || client.write_all(&data).unwrap())
+    };
+    let mut buf = Vec::new();
+    server.read_to_end(&mut buf).unwrap();
+    writer.join().unwrap();
+    assert!(buf == data);
+    assert_eq!(relay.join().unwrap(), data.len() as u64);
+}
+
+#[test]
+fn test_write_all_blocking() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+    let data: Vec<u8> = (0..cap * 4).map(|i| i as u8).collect();
+
+    // the Reader does not make space: times out
+    assert_eq!(w.write_all_blocking(&data, Duration::from_millis(10)), cap);
+    r.read_chunk();
+    r.commit();
+
+    let reader = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        while let Ok(chunk) = r.read_chunk_blocking() {
+            received.extend_from_slice(chunk);
+            r.commit();
+        }
+        received
+    });
+    assert_eq!(
+        w.write_all_blocking(&data, Duration::from_secs(60)),
+        data.len()
+    );
+    drop(w);
+    assert!(reader.join().unwrap() == data);
+
+    // the Reader is dropped
+    let (mut w, r) = cueue::<u8>(16).unwrap();
+    drop(r);
+    assert_eq!(w.write_all_blocking(&data, Duration::from_secs(60)), cap);
+}
+
+#[test]
+fn test_read_until() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+
+    w.write_chunk()[..5].copy_from_slice(b"ab\ncd");
+    w.commit(5);
+    assert_eq!(r.read_until(b'\n'), b"ab\n");
+    r.commit();
+    assert_eq!(r.read_until(b'\n'), b"");
+    r.commit();
+
+    w.write_chunk()[..4].copy_from_slice(b"e\nf\n");
+    w.commit(4);
+    let records: Vec<_> = r
+        .read_until(b'\n')
+        .split_inclusive(|&b| b == b'\n')
+        .collect();
+    assert_eq!(records, [&b"cde\n"[..], b"f\n"]);
+    r.commit();
+
+    // a record longer than the capacity is returned in parts
+    w.write_chunk().fill(b'x');
+    w.commit(cap);
+    assert_eq!(r.read_until(b'\n').len(), cap);
+}
+
+#[test]
+fn test_drop_on_consume() {
+    let resource = std::sync::Arc::new(());
+    let (mut w, mut r) = Builder::new(16)
+        .drop_on_consume(true)
+        .build::<Option<std::sync::Arc<()>>>()
+        .unwrap();
+    for _ in 0..3 {
+        w.push(Some(resource.clone())).unwrap();
+    }
+    assert_eq!(std::sync::Arc::strong_count(&resource), 4);
+
+    assert_eq!(r.limited_read_chunk(2).len(), 2);
+    r.commit();
+    assert_eq!(std::sync::Arc::strong_count(&resource), 2);
+    assert_eq!(r.read_chunk().len(), 1);
+    r.commit();
+    assert_eq!(std::sync::Arc::strong_count(&resource), 1);
+
+    let conflict = Builder::new(16).drop_on_consume(true).retention(1);
+    assert!(conflict.build::<u8>().is_err());
+}
+
+#[test]
+fn test_cursor() {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    w.write_chunk()[..6].copy_from_slice(b"abcdef");
+    w.commit(6);
+
+    let mut parse = r.cursor();
+    let commit = r.cursor();
+    assert_eq!(parse.position(), 0);
+    parse.advance(2);
+    assert_eq!(r.read_at(&parse), b"cdef");
+    assert_eq!(r.read_at(&commit), b"abcdef");
+
+    // read_at does not change the chunk of read_chunk
+    assert_eq!(r.limited_read_chunk(1), b"a");
+    assert_eq!(r.read_at(&parse), b"cdef");
+    r.commit();
+    assert_eq!(r.read_at(&commit), b"bcdef");
+
+    r.commit_to(&parse);
+    assert_eq!(r.read_chunk(), b"cdef");
+    r.commit_to(&commit);
+    assert_eq!(r.read_chunk(), b"cdef");
+
+    parse.advance(100);
+    assert_eq!(r.read_at(&parse), b"");
+    r.commit_to(&parse);
+    assert!(r.read_chunk().is_empty());
+    r.commit();
+
+    // read_at does not call the hooks of read_chunk
+    let empty = Arc::new(AtomicUsize::new(0));
+    let counter = empty.clone();
+    r.on_empty(move || {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+    assert_eq!(r.read_at(&commit), b"");
+    assert_eq!(empty.load(Ordering::Relaxed), 0);
+
+    // with DropOldest, only a held chunk is protected from the Writer
+    let (mut w, mut r) = Builder::new(16)
+        .full_policy(FullPolicy::DropOldest)
+        .build::<u8>()
+        .unwrap();
+    let cursor = r.cursor();
+    w.push(b'a').unwrap();
+    assert_eq!(r.read_at(&cursor), b"");
+    assert_eq!(r.read_pos().load(Ordering::Relaxed) & abi::READER_BUSY, 0);
+    assert_eq!(r.limited_read_chunk(0), b"");
+    assert_eq!(r.read_at(&cursor), b"a");
+    r.commit();
+    assert_eq!(r.read_at(&cursor), b"");
+}
+
+#[test]
+fn test_backpressure() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    assert!(!w.is_backpressured());
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let events_clone = events.clone();
+    w.set_backpressure(8, 2, move |engaged| {
+        events_clone.lock().unwrap().push(engaged)
+    });
+
+    for i in 0..8 {
+        assert!(!w.is_backpressured());
+        w.push(i).unwrap();
+    }
+    assert!(w.is_backpressured());
+    assert_eq!(*events.lock().unwrap(), [true]);
+
+    // no resume above the low watermark
+    assert_eq!(r.limited_read_chunk(5).len(), 5);
+    r.commit();
+    assert!(w.is_backpressured());
+    w.write_chunk();
+    assert_eq!(*events.lock().unwrap(), [true]);
+
+    assert_eq!(r.limited_read_chunk(1).len(), 1);
+    r.commit();
+    assert!(!w.is_backpressured());
+    assert_eq!(*events.lock().unwrap(), [true, false]);
+}
+
+#[test]
+fn test_pop_n() {
+    use std::mem::MaybeUninit;
+
+    let (mut w, mut r) = cueue::<String>(16).unwrap();
+    for s in ["foo", "bar", "baz"] {
+        w.push(s.to_string()).unwrap();
+    }
+
+    let mut buf: [MaybeUninit<String>; 2] = [(); 2].map(|_| MaybeUninit::uninit());
+    assert_eq!(r.pop_n(&mut buf), 2);
+    let popped = buf.map(|s| unsafe { s.assume_init() });
+    assert_eq!(popped, ["foo", "bar"]);
+
+    let mut buf: [MaybeUninit<String>; 4] = [(); 4].map(|_| MaybeUninit::uninit());
+    assert_eq!(r.pop_n(&mut buf), 1);
+    assert_eq!(unsafe { buf[0].assume_init_read() }, "baz");
+    assert_eq!(r.pop_n(&mut buf), 0);
+}
+
+#[test]
+fn test_write_iter() {
+    let (mut w, mut r) = Builder::new(16)
+        .full_policy(FullPolicy::DropNewest)
+        .build::<u32>()
+        .unwrap();
+    let cap = w.capacity() as u32;
+    let mut iter = 0..cap + 10;
+    assert_eq!(w.write_iter(&mut iter), cap as usize);
+    // the policy is ignored: the rest is left in the iterator
+    assert_eq!(iter.next(), Some(cap));
+    assert_eq!(w.write_iter(&mut iter), 0);
+
+    assert_eq!(r.read_chunk().len(), cap as usize);
+    r.commit();
+    assert_eq!(w.write_iter(&mut iter), 9);
+    assert_eq!(r.read_chunk(), (cap + 1..cap + 10).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_pop_array() {
+    let (mut w, mut r) = cueue::<u32>(16).unwrap();
+    w.write_chunk()[..5].copy_from_slice(&[1, 2, 3, 4, 5]);
+    w.commit(5);
+
+    assert_eq!(r.pop_array::<2>(), Some([1, 2]));
+    assert_eq!(r.pop_array::<4>(), None);
+    assert_eq!(r.pop_array::<3>(), Some([3, 4, 5]));
+    assert_eq!(r.pop_array::<1>(), None);
+    assert_eq!(r.pop_array::<0>(), Some([]));
+}
+
+#[test]
+fn test_write_with_read_with() {
+    let (mut w, mut r) = Builder::new(16).commit_on_drop(true).build::<u8>().unwrap();
+    let n = w.write_with(|buf| {
+        buf[..3].copy_from_slice(b"foo");
+        3
+    });
+    assert_eq!(n, 3);
+    assert_eq!(w.write_with(|buf| buf.len() + 1), w.capacity() - 3);
+
+    assert_eq!(
+        r.read_with(|buf| buf.iter().position(|&b| b == b'o').unwrap()),
+        1
+    );
+    assert_eq!(r.read_with(|_| usize::MAX), w.capacity() - 1);
+    assert!(r.read_chunk().is_empty());
+
+    // a panic commits nothing, not even on drop
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        w.write_with(|buf| {
+            buf[0] = b'x';
+            panic!("write failed")
+        })
+    }));
+    assert!(result.is_err());
+    drop(w);
+    assert!(r.read_chunk().is_empty());
+}
+
+#[test]
+fn test_timestamped() {
+    use crate::timestamped::Timestamped;
+
+    let (mut w, mut r) = cueue::<Timestamped<u32>>(16).unwrap();
+    assert_eq!(r.oldest_age(), None);
+
+    let before = w.timestamp();
+    let chunk = w.write_chunk();
+    chunk[0].value = 1;
+    chunk[1].value = 2;
+    assert_eq!(w.commit_stamped(2), 2);
+    w.push_stamped(3).unwrap();
+    assert!(r.oldest_age().is_some());
+
+    let now = r.timestamp();
+    let chunk = r.read_chunk();
+    assert_eq!(chunk.len(), 3);
+    assert_eq!(chunk[0].timestamp, chunk[1].timestamp);
+    assert!(chunk[0].timestamp >= before);
+    assert!(chunk[2].timestamp >= chunk[1].timestamp);
+    assert_eq!(chunk.iter().map(|e| e.value).collect::<Vec<_>>(), [1, 2, 3]);
+    assert!(chunk
+        .iter()
+        .all(|e| e.age(now) == Duration::from_nanos(now - e.timestamp)));
+    assert_eq!(chunk[0].age(0), Duration::ZERO);
+    r.commit();
+
+    let cap = w.capacity();
+    while w.push_stamped(0).is_ok() {}
+    assert_eq!(w.push_stamped(7), Err(7));
+    assert_eq!(r.read_chunk().len(), cap);
+}
+
+#[test]
+fn test_fresh_reader() {
+    use crate::timestamped::{FreshReader, Timestamped};
+
+    let (mut w, r) = cueue::<Timestamped<u32>>(16).unwrap();
+    let mut r = FreshReader::new(r, Duration::from_secs(3600));
+    assert!(r.read_chunk().is_empty());
+
+    let now = w.timestamp();
+    let stale = now - Duration::from_secs(7200).as_nanos() as u64;
+    for (value, timestamp) in [(1, stale), (2, stale), (3, now)] {
+        w.push(Timestamped { timestamp, value }).unwrap();
+    }
+    w.push_stamped(4).unwrap();
+
+    let values: Vec<_> = r.read_chunk().iter().map(|e| e.value).collect();
+    assert_eq!(values, [3, 4]);
+    assert_eq!(r.skipped(), 2);
+    r.commit();
+    assert!(r.read_chunk().is_empty());
+
+    // every element is stale
+    r.set_ttl(Duration::ZERO);
+    w.push(Timestamped {
+        timestamp: stale,
+        value: 5,
+    })
+    .unwrap();
+    assert!(r.read_chunk().is_empty());
+    assert_eq!(r.skipped(), 3);
+    assert!(r.into_inner().read_chunk().is_empty());
+}
+
+#[test]
+fn test_stats() {
+    let (mut w, mut r) = Builder::new(16).sequence(true).build::<u8>().unwrap();
+    w.write_chunk()[..5].copy_from_slice(b"hello");
+    w.commit(5);
+    w.record_dropped(2);
+    r.set_notify_watermark(4);
+    assert_eq!(r.limited_read_chunk(1), b"h");
+    r.commit();
+
+    let stats = w.stats();
+    assert_eq!(stats, r.stats());
+    assert_eq!(stats.occupancy, 4);
+    assert_eq!(stats.peak_occupancy, 5);
+    assert_eq!(stats.write_position, 5);
+    assert_eq!(stats.read_position, 1);
+    assert_eq!(stats.sequence, 3);
+    assert_eq!(stats.dropped, 2);
+    assert_eq!(stats.read_watermark, 4);
+    assert!(!stats.reader_closed);
+    assert_eq!(
+        stats.to_json(),
+        format!(
+            concat!(
+                r#"{{"capacity":{},"occupancy":4,"retention":0,"peak_occupancy":5,"write_position":5,"#,
+                r#""read_position":1,"sequence":3,"dropped":2,"read_watermark":4,"#,
+                r#""write_watermark":0,"writer_closed":false,"reader_closed":false}}"#
+            ),
+            w.capacity()
+        )
+    );
+
+    drop(r);
+    assert!(w.stats().reader_closed);
+}
+
+#[test]
+fn test_recommended_capacity() {
+    let (mut w, mut r) = cueue::<u8>(1 << 16).unwrap();
+    let cap = w.capacity();
+    assert_eq!(w.stats().recommended_capacity(0.5), 1);
+
+    // the peak is kept, after the elements are consumed
+    w.limited_write_chunk(100);
+    w.commit(100);
+    w.write_chunk();
+    w.commit(900);
+    r.read_chunk();
+    r.commit();
+    w.write_chunk();
+    w.commit(10);
+    let stats = r.stats();
+    assert_eq!(stats.peak_occupancy, 1000);
+    assert_eq!(stats.recommended_capacity(0.0), 1024);
+    assert_eq!(stats.recommended_capacity(0.5), 2048);
+
+    // the queue got full
+    while w.push(0).is_ok() {}
+    assert_eq!(w.stats().peak_occupancy, cap);
+    assert_eq!(w.stats().recommended_capacity(0.5), cap * 2);
+}
+
+#[test]
+fn test_capacity_bytes() {
+    let (w, r) = cueue::<u64>(16).unwrap();
+    assert_eq!(w.element_size(), 8);
+    assert_eq!(w.capacity_bytes(), w.capacity() * 8);
+    assert_eq!(w.memory_footprint(), w.page_size() + w.capacity() * 8);
+    assert_eq!(r.capacity_bytes(), w.capacity_bytes());
+    assert_eq!(r.memory_footprint(), w.memory_footprint());
+    assert_eq!(w.buffer_region().len, 2 * w.capacity_bytes());
+}
+
+#[test]
+#[cfg(not(target_os = "nto"))]
+fn test_build_zeroed() {
+    let buffer_resident = |w: &Writer<u64>| {
+        let pages = w.capacity_bytes() / w.build_report().system_page_size;
+        resident_pages(w.buffer_region().addr, pages)
+    };
+
+    // default initialization touches every page
+    let (w, _r) = Builder::new(1 << 16)
+        .noreserve(true)
+        .build::<u64>()
+        .unwrap();
+    assert_ne!(buffer_resident(&w), 0);
+
+    let (mut w, mut r) = Builder::new(1 << 16)
+        .noreserve(true)
+        .build_zeroed::<u64>()
+        .unwrap();
+    assert_eq!(buffer_resident(&w), 0);
+    assert!(w.write_chunk().iter().all(|&e| e == 0));
+    w.push(7).unwrap();
+    assert_eq!(r.read_chunk(), [7]);
+}
+
+#[test]
+fn test_build_report() {
+    let (w, r) = cueue::<u8>(16).unwrap();
+    let report = w.build_report();
+    assert_eq!(report, r.build_report());
+    assert_eq!(report.requested_capacity, 16);
+    assert_eq!(report.capacity, w.capacity());
+    assert!(report.is_rounded());
+    assert_eq!(report.page_size, w.page_size());
+    assert_eq!(report.buffer_pages * report.system_page_size, w.capacity());
+    assert!(report.to_string().contains("rounded up to the page size"));
+    #[cfg(target_os = "linux")]
+    {
+        assert!(report.populate);
+        assert_eq!(report.resident_pages, report.buffer_pages);
+    }
+
+    let (w, _r) = Builder::new(1 << 20).noreserve(true).build::<u8>().unwrap();
+    let report = w.build_report();
+    assert!(!report.is_rounded());
+    assert!(!report.populate);
+    assert_eq!(report.resident_pages, 0);
+}
+
+#[test]
+fn test_mapping_info() {
+    let (w, r) = cueue::<u32>(16).unwrap();
+    let info = w.mapping_info();
+    assert_eq!(info, r.mapping_info());
+    assert_eq!(
+        info.control_block.addr as usize + w.page_size(),
+        info.buffer.addr as usize
+    );
+    assert_eq!(info.buffer.len, w.capacity() * 4);
+    assert_eq!(
+        info.buffer.addr as usize + info.buffer.len,
+        info.mirror.addr as usize
+    );
+    assert_eq!(info.page_size, w.page_size());
+    assert_eq!(info.backend, w.backend());
+
+    #[cfg(target_os = "linux")]
+    {
+        let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+        let start = format!("{:x}-", info.control_block.addr as usize);
+        let line = maps.lines().find(|l| l.starts_with(&start)).unwrap();
+        assert!(line.contains(&format!(" {} ", info.inode)));
+    }
+}
+
+#[test]
+fn test_region_lease() {
+    let (mut w, r) = cueue::<u8>(16).unwrap();
+    let lease = w.lease_region();
+    let region = lease.region();
+    assert_eq!(region, w.buffer_region());
+
+    let chunk = w.write_chunk_exact(3).unwrap();
+    let offset = region.offset_of(chunk.as_ptr()).unwrap();
+    assert_eq!(offset, 0);
+    assert_eq!(region.offset_of(region.addr.wrapping_add(region.len)), None);
+
+    // e.g: a remote peer writes into the chunk
+    unsafe {
+        std::ptr::copy_nonoverlapping(b"abc".as_ptr(), region.addr.add(offset), 3);
+    }
+    w.commit(3);
+    drop((w, r));
+
+    // the region is still mapped
+    let bytes = unsafe { std::slice::from_raw_parts(region.addr, 3) };
+    assert_eq!(bytes, b"abc");
+    drop(lease);
+}
+
+#[test]
+fn test_endian_helpers() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+
+    w.put_u16_be(0x0102).unwrap();
+    w.put_u32_le(0x0304_0506).unwrap();
+    w.put_i64_be(-2).unwrap();
+    w.put_f64_le(1.5).unwrap();
+    assert_eq!(&r.read_chunk()[..6], [1, 2, 6, 5, 4, 3]);
+
+    assert_eq!(r.get_u16_be(), Some(0x0102));
+    assert_eq!(r.get_u32_le(), Some(0x0304_0506));
+    assert_eq!(r.get_i64_be(), Some(-2));
+    assert_eq!(r.get_f64_le(), Some(1.5));
+    assert_eq!(r.get_u16_le(), None);
+
+    // partial values are neither written nor read
+    w.write_chunk();
+    w.commit(cap - 1);
+    assert_eq!(w.put_u16_le(7), Err(7));
+    r.read_chunk();
+    r.commit();
+    w.put_u32_le(8).unwrap();
+    assert_eq!(r.get_u64_le(), None);
+    assert_eq!(r.get_u32_le(), Some(8));
+}
+
+#[test]
+fn test_reader() {
+    let (mut w, mut r) = cueue(16).unwrap();
+
+    let empty = r.read_chunk();
+    assert_eq!(empty.len(), 0);
+    r.commit();
+
+    let buf = w.write_chunk();
+    buf[..3].copy_from_slice(b"foo");
+    w.commit(3);
+
+    let foo = r.read_chunk();
+    assert_eq!(foo, b"foo");
+    r.commit();
+
+    assert!(!r.is_abandoned());
+    std::mem::drop(w);
+    assert!(r.is_abandoned());
+}
+
+#[test]
+fn test_sequence_gap() {
+    // commits are not counted by default
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    w.write_chunk();
+    w.commit(3);
+    assert_eq!(w.sequence(), 0);
+    w.record_dropped(2);
+    assert_eq!(r.sequence(), 2);
+    assert_eq!(r.gap(), 2);
+
+    let (mut w, mut r) = Builder::new(16).sequence(true).build::<u8>().unwrap();
+    assert_eq!(w.sequence(), 0);
+    assert_eq!(r.gap(), 0);
+
+    w.write_chunk();
+    w.commit(0);
+    assert_eq!(w.sequence(), 0);
+
+    w.write_chunk();
+    w.commit(3);
+    w.write_chunk();
+    w.commit(2);
+    assert_eq!(w.sequence(), 2);
+
+    w.record_dropped(5);
+    assert_eq!(w.sequence(), 7);
+    assert_eq!(r.sequence(), 7);
+    assert_eq!(r.gap(), 5);
+    assert_eq!(r.gap(), 0);
+
+    w.record_dropped(1);
+    assert_eq!(r.gap(), 1);
+}
+
+#[test]
+fn test_completion() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    assert_eq!(r.completion(), None);
+
+    w.write_chunk()[..3].copy_from_slice(b"foo");
+    w.commit(3);
+    w.finish(Completion::Ok);
+
+    assert!(r.is_abandoned());
+    assert_eq!(r.completion(), Some(Completion::Ok));
+    assert_eq!(r.read_chunk(), b"foo");
+    r.commit();
+
+    let (w, r) = cueue::<u8>(16).unwrap();
+    w.finish(Completion::Failed(42));
+    assert_eq!(r.completion(), Some(Completion::Failed(42)));
+
+    let (w, r) = cueue::<u8>(16).unwrap();
+    std::mem::drop(w);
+    assert!(r.is_abandoned());
+    assert_eq!(r.completion(), None);
+}
+
+#[test]
+fn test_full() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+
+    let buf = w.write_chunk();
+    let buflen = buf.len();
+    assert_eq!(buf.len(), w.capacity());
+    w.commit(buflen);
+
+    let empty = w.write_chunk();
+    assert_eq!(empty.len(), 0);
+
+    let full = r.read_chunk();
+    assert_eq!(full.len(), buflen);
+    assert_eq!(full.len(), r.capacity());
+}
+
+#[test]
+fn test_on_full_on_empty() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    let fulls = Arc::new(AtomicUsize::new(0));
+    let empties = Arc::new(AtomicUsize::new(0));
+    {
+        let fulls = fulls.clone();
+        w.on_full(move || {
+            fulls.fetch_add(1, Ordering::Relaxed);
+        });
+        let empties = empties.clone();
+        r.on_empty(move || {
+            empties.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    r.read_chunk();
+    assert_eq!(empties.load(Ordering::Relaxed), 1);
+
+    let buflen = w.write_chunk().len();
+    w.commit(buflen);
+    assert_eq!(fulls.load(Ordering::Relaxed), 0);
+    assert!(w.write_chunk().is_empty());
+    assert_eq!(fulls.load(Ordering::Relaxed), 1);
+
+    assert_eq!(r.read_chunk().len(), buflen);
+    r.commit();
+    assert_eq!(empties.load(Ordering::Relaxed), 1);
+    r.read_chunk();
+    assert_eq!(empties.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn test_on_wrap() {
+    use std::sync::{Arc, Mutex};
+
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+    let cycles = Arc::new(Mutex::new(Vec::new()));
+    {
+        let cycles = cycles.clone();
+        w.on_wrap(move |cycle| cycles.lock().unwrap().push(cycle));
+    }
+
+    // reaching the end of the buffer completes a cycle
+    let n = w.write_chunk().len();
+    w.commit(n - 1);
+    assert!(cycles.lock().unwrap().is_empty());
+    w.push(1).unwrap();
+    assert_eq!(*cycles.lock().unwrap(), [1]);
+    r.read_chunk();
+    r.commit();
+
+    // a commit crossing the end
+    w.write_chunk();
+    w.commit(cap / 2);
+    r.read_chunk();
+    r.commit();
+    assert_eq!(w.write_chunk().len(), cap);
+    w.commit(cap);
+    assert_eq!(*cycles.lock().unwrap(), [1, 2]);
+}
+
+#[test]
+fn test_notify() {
+    let (w, r) = cueue::<u8>(16).unwrap();
+    assert!(w.readiness_fd().is_none());
+    assert!(r.readiness_fd().is_none());
+
+    let (mut w, mut r) = Builder::new(16).notify(true).build::<u8>().unwrap();
+    let readable = |fd: std::os::unix::io::BorrowedFd| {
+        use std::os::unix::io::AsRawFd;
+        let mut pfd = libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut pfd, 1, 0) == 1 }
+    };
+
+    assert!(!readable(r.readiness_fd().unwrap()));
+    w.write_chunk();
+    w.commit(1);
+    assert!(readable(r.readiness_fd().unwrap()));
+    r.clear_readiness();
+    assert!(!readable(r.readiness_fd().unwrap()));
+
+    assert!(!readable(w.readiness_fd().unwrap()));
+    r.read_chunk();
+    r.commit();
+    assert!(readable(w.readiness_fd().unwrap()));
+
+    std::mem::drop(w);
+    assert!(readable(r.readiness_fd().unwrap()));
+}
+
+#[test]
+fn test_notify_watermark() {
+    let (mut w, mut r) = Builder::new(16).notify(true).build::<u8>().unwrap();
+    let cap = w.capacity();
+    let readable = |fd: std::os::unix::io::BorrowedFd| {
+        use std::os::unix::io::AsRawFd;
+        let mut pfd = libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut pfd, 1, 0) == 1 }
+    };
+
+    r.set_notify_watermark(3);
+    w.push(1).unwrap();
+    w.push(2).unwrap();
+    assert!(!readable(r.readiness_fd().unwrap()));
+    w.push(3).unwrap();
+    assert!(readable(r.readiness_fd().unwrap()));
+
+    // fill the queue, then free up space in small steps
+    w.set_notify_watermark(2);
+    w.write_chunk();
+    w.commit(cap);
+    w.clear_readiness();
+    r.limited_read_chunk(1);
+    r.commit();
+    assert!(!readable(w.readiness_fd().unwrap()));
+    r.limited_read_chunk(1);
+    r.commit();
+    assert!(readable(w.readiness_fd().unwrap()));
+
+    // dropping always notifies
+    w.clear_readiness();
+    std::mem::drop(r);
+    assert!(readable(w.readiness_fd().unwrap()));
+}
+
+#[test]
+#[cfg(feature = "async-io")]
+fn test_async_io() {
+    use crate::asynch::{AsyncReader, AsyncWriter};
+    type Async = async_io::Async<std::os::unix::io::OwnedFd>;
+
+    let (w, r) = Builder::new(16).notify(true).build::<u8>().unwrap();
+    let mut w = AsyncWriter::<_, Async>::new(w).unwrap();
+    let mut r = AsyncReader::<_, Async>::new(r).unwrap();
+    let maxi = 100_000;
+
+    let wt = std::thread::spawn(move || {
+        async_io::block_on(async {
+            for i in 0..maxi {
+                let buf = w.write_chunk().await.unwrap();
+                buf[0] = i as u8;
+                w.commit(1);
+            }
+        })
+    });
+
+    async_io::block_on(async {
+        let mut i = 0;
+        loop {
+            let chunk = r.read_chunk().await.unwrap();
+            if chunk.is_empty() {
+                break;
+            }
+            for b in chunk {
+                assert_eq!(*b, i as u8);
+                i += 1;
+            }
+            r.commit();
+        }
+        assert_eq!(i, maxi);
+    });
+
+    wt.join().unwrap();
+}
+
+#[test]
+#[cfg(feature = "async-io")]
+fn test_async_chunk_stream() {
+    use crate::asynch::AsyncReader;
+    type Async = async_io::Async<std::os::unix::io::OwnedFd>;
+
+    let (mut w, r) = Builder::new(16).notify(true).build::<u32>().unwrap();
+    let mut r = AsyncReader::<_, Async>::new(r).unwrap();
+    let maxi = 10_000u32;
+
+    let wt = std::thread::spawn(move || {
+        for i in 0..maxi {
+            while w.push(i).is_err() {}
+        }
+    });
+
+    async_io::block_on(async {
+        let mut stream = r.chunk_stream(10, 100);
+        let mut next = 0;
+        while let Some(batch) = stream.next().await {
+            let batch = batch.unwrap();
+            assert!(batch.len() <= 100);
+            // smaller batches only at the end of the stream
+            assert!(batch.len() >= 10 || next + batch.len() as u32 == maxi);
+            for i in batch {
+                assert_eq!(i, next);
+                next += 1;
+            }
+        }
+        assert_eq!(next, maxi);
+    });
+
+    wt.join().unwrap();
+}
+
+#[test]
+#[cfg(feature = "tracing")]
+fn test_tracing() {
+    use crate::tracing::{InstrumentedReader, InstrumentedWriter};
+    use ::tracing::field::{Field, Visit};
+    use ::tracing::span::{Attributes, Id, Record};
+    use ::tracing::{Event, Metadata};
+    use std::sync::{Arc, Mutex};
+
+    struct Collect(Arc<Mutex<Vec<String>>>);
+
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                self.0 = format!("{:?}{}", value, self.0);
+            } else if field.name() != "wait_ns" {
+                self.0 += &format!(" {}={:?}", field.name(), value);
+            }
+        }
+    }
+
+    impl ::tracing::Subscriber for Collect {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target() == "cueue"
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Fields(format!("span {}", span.metadata().name()));
+            span.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields(String::new());
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let mut cap = 0;
+    ::tracing::subscriber::with_default(Collect(lines.clone()), || {
+        let (w, r) = cueue::<u8>(16).unwrap();
+        let mut w = InstrumentedWriter::new(w, "q");
+        let mut r = InstrumentedReader::new(r, "q");
+
+        assert!(r.read_chunk().is_empty());
+        w.write_chunk()[..2].copy_from_slice(b"ab");
+        w.commit(2);
+        assert_eq!(r.read_chunk(), b"ab");
+        r.commit();
+
+        cap = w.write_chunk().len();
+        w.commit(cap);
+        assert!(w.push(b'y').is_err());
+        r.read_chunk_blocking().unwrap();
+        r.commit();
+        w.push_blocking(b'z', std::time::Duration::ZERO).unwrap();
+        r.read_chunk();
+        r.commit();
+
+        drop(w);
+        assert_eq!(r.read_chunk_blocking(), Err(Abandoned));
+    });
+
+    let commit =
+        |batch, occupancy| format!("commit queue=\"q\" batch={} occupancy={}", batch, occupancy);
+    assert_eq!(
+        *lines.lock().unwrap(),
+        [
+            commit(2, 2),
+            "empty queue=\"q\"".to_string(),
+            commit(2, 0),
+            commit(cap, cap),
+            commit(cap, 0),
+            "full queue=\"q\"".to_string(),
+            commit(1, 1),
+            commit(1, 0),
+            "span wait queue=\"q\"".to_string(),
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "slog")]
+fn test_slog_drain() {
+    use ::slog::{info, o, warn, Drain, Logger};
+    use std::sync::{Arc, Mutex};
+
+    struct Collect(Arc<Mutex<Vec<String>>>);
+
+    struct Pairs(String);
+
+    impl ::slog::Serializer for Pairs {
+        fn emit_arguments(
+            &mut self,
+            key: ::slog::Key,
+            val: &std::fmt::Arguments,
+        ) -> ::slog::Result {
+            self.0 += &format!(" {}={}", key, val);
+            Ok(())
+        }
+    }
+
+    impl Drain for Collect {
+        type Ok = ();
+        type Err = ::slog::Never;
+
+        fn log(
+            &self,
+            record: &::slog::Record,
+            values: &::slog::OwnedKVList,
+        ) -> Result<(), ::slog::Never> {
+            let mut pairs = Pairs(String::new());
+            ::slog::KV::serialize(&record.kv(), record, &mut pairs).unwrap();
+            ::slog::KV::serialize(values, record, &mut pairs).unwrap();
+            let line = format!(
+                "{} {}:{}{}",
+                record.level(),
+                record.module(),
+                record.msg(),
+                pairs.0
+            );
+            self.0.lock().unwrap().push(line);
+            Ok(())
+        }
+    }
+
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let drain = crate::slog::CueueDrain::new(Collect(lines.clone()), 1 << 12).unwrap();
+    let log = Logger::root(drain.fuse(), o!("version" => "1.0"));
+    info!(log, "started"; "port" => 8080);
+    warn!(log, "{} is {}", "answer", 42);
+    drop(log);
+
+    let module = module_path!();
+    assert_eq!(
+        *lines.lock().unwrap(),
+        [
+            format!("INFO {}:started port=8080 version=1.0", module),
+            format!("WARN {}:answer is 42 version=1.0", module),
+        ]
+    );
+}
+
+#[test]
+fn test_reuse() {
+    let (mut w, mut r) = cueue(16).unwrap();
+
+    // fill the queue with strings
+    let buf = w.write_chunk();
+    for s in buf.iter_mut() {
+        *s = "foobar";
+    }
+    let buflen = buf.len();
+    w.commit(buflen);
+
+    // consume everything
+    let full = r.read_chunk();
+    assert_eq!(full.len(), buflen);
+    r.commit();
+
+    // try writing again
+    let buf = w.write_chunk();
+    assert_eq!(buf[0], "foobar");
+}
+
+#[test]
+fn test_push() {
+    let (mut w, _) = cueue(16).unwrap();
+    let cap = w.capacity();
+
+    for i in 0..cap {
+        assert_eq!(w.push(i), Ok(()));
+    }
+
+    assert_eq!(w.push(0), Err(0));
+}
+
+#[test]
+fn test_push_many() {
+    let (mut w, mut r) = cueue::<usize>(16).unwrap();
+    let cap = w.capacity();
+
+    let mut items = 0..cap + 10;
+    assert_eq!(w.push_many(&mut items), cap);
+    assert_eq!(items.next(), Some(cap));
+    assert_eq!(w.push_many(&mut items), 0);
+
+    let chunk = r.read_chunk();
+    assert_eq!(chunk.len(), cap);
+    assert!(chunk.iter().copied().eq(0..cap));
+    r.commit();
+
+    assert_eq!(w.push_many(&mut items), 9);
+    assert!(r.read_chunk().iter().copied().eq(cap + 1..cap + 10));
+}
+
+#[test]
+fn test_push_string() {
+    let (mut w, _) = cueue(16).unwrap();
+    let cap = w.capacity();
+
+    for i in 0..cap {
+        assert_eq!(w.push(i.to_string()), Ok(()));
+    }
+
+    assert_eq!(w.push("foo".to_string()), Err("foo".to_string()));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_release_consumed_memory() {
+    let (mut w, mut r) = cueue::<u8>(1 << 16).unwrap();
+    r.release_consumed_memory(true);
+
+    let buf = w.write_chunk();
+    buf.fill(b'x');
+    let buflen = buf.len();
+    w.commit(buflen);
+
+    let full = r.read_chunk();
+    assert_eq!(full.len(), buflen);
+    assert!(full.iter().all(|b| *b == b'x'));
+    r.commit();
+
+    // released pages are zero filled when touched again
+    let buf = w.write_chunk();
+    assert_eq!(buf.len(), buflen);
+    assert!(buf.iter().all(|b| *b == 0));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_release_consumed_memory_keeps_contents() {
+    let path = std::env::temp_dir().join(format!("cueue-test-release-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    {
+        let (mut w, mut r) = Builder::new(1 << 16).file(&path).build::<u8>().unwrap();
+        r.release_consumed_memory(true);
+        let buf = w.write_chunk();
+        buf.fill(b'x');
+        let buflen = buf.len();
+        w.commit(buflen);
+        assert_eq!(r.read_chunk().len(), buflen);
+        r.commit();
+
+        // no hole is punched into the file
+        let contents = std::fs::read(&path).unwrap();
+        let data = contents.split(|b| *b != b'x').map(|run| run.len()).max();
+        assert_eq!(data, Some(buflen));
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    let (mut w, mut r) = Builder::new(1 << 16)
+        .retention(1 << 13)
+        .build::<u8>()
+        .unwrap();
+    r.release_consumed_memory(true);
+    let buf = w.write_chunk();
+    buf.fill(b'y');
+    let buflen = buf.len();
+    w.commit(buflen);
+    assert_eq!(r.read_chunk().len(), buflen);
+    r.commit();
+
+    // the retained history is kept
+    assert_eq!(r.retained().len(), 1 << 13);
+    assert!(r.retained().iter().all(|b| *b == b'y'));
+}
+
+#[test]
+fn test_cueue_threaded_w_r() {
+    let (mut w, mut r) = cueue(16).unwrap();
+    let maxi = 1_000_000;
+
+    let wt = std::thread::spawn(move || {
+        let mut msg: u8 = 0;
+        for _ in 0..maxi {
+            let buf = loop {
+                let buf = w.write_chunk();
+                if !buf.is_empty() {
+                    break buf;
+                }
+            };
+            buf[0] = msg;
+            w.commit(1);
+
+            msg = msg.wrapping_add(1);
+        }
+    });
+
+    let rt = std::thread::spawn(move || {
+        let mut emsg: u8 = 0;
+        let mut i = 0;
+        while i < maxi {
+            let rr = r.read_chunk();
+            for msg in rr {
+                assert_eq!(*msg, emsg);
+                emsg = emsg.wrapping_add(1);
+                i += 1;
+            }
+            r.commit();
+        }
+    });
+
+    wt.join().unwrap();
+    rt.join().unwrap();
+}
+
+fn produce_consume<P: Producer<u8>, C: Consumer<u8>>(w: &mut P, r: &mut C) -> Vec<u8> {
+    let mut result = Vec::new();
+    for msg in [&b"foo"[..], b"bar", b"baz"] {
+        let buf = w.write_chunk();
+        if buf.len() >= msg.len() {
+            buf[..msg.len()].copy_from_slice(msg);
+            w.commit(msg.len());
+        }
+        let chunk = r.read_chunk();
+        result.extend_from_slice(chunk);
+        r.commit();
+    }
+    result
+}
+
+#[test]
+fn test_producer_consumer_traits() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    assert_eq!(produce_consume(&mut w, &mut r), b"foobarbaz");
+
+    let (mut w, mut r) = mock::mock::<u8>(16);
+    assert_eq!(produce_consume(&mut w, &mut r), b"foobarbaz");
+}
+
+#[test]
+fn test_mock() {
+    let (mut w, mut r) = mock::mock::<u8>(4);
+    assert_eq!(w.capacity(), 4);
+
+    w.script_full(1);
+    assert_eq!(w.write_chunk().len(), 0);
+    assert_eq!(w.write_chunk().len(), 4);
+    assert_eq!(w.commit(10), 4);
+    assert!(w.write_chunk().is_empty());
+    assert_eq!(w.push(1), Err(1));
+
+    r.script_empty(1);
+    assert!(r.read_chunk().is_empty());
+    assert_eq!(r.read_chunk().len(), 4);
+    // not committed: still no space for writing
+    assert!(w.write_chunk().is_empty());
+    r.commit();
+    assert_eq!(w.write_chunk().len(), 4);
+    assert_eq!(w.push(7), Ok(()));
+    assert_eq!(r.read_chunk(), [7]);
+
+    assert!(!w.is_abandoned());
+    w.set_abandoned(true);
+    assert!(w.is_abandoned());
+    std::mem::drop(w);
+    assert!(r.is_abandoned());
+}
+
+#[test]
+fn test_fault_injection() {
+    use fault::FaultPlan;
+    use std::time::{Duration, Instant};
+
+    let plan = FaultPlan::new()
+        .full_at(1)
+        .empty_at(0)
+        .abandon_reader_at(3)
+        .delay_commits(Duration::from_millis(5));
+    let (mut w, mut r) = plan.build::<u8>(Builder::new(16)).unwrap();
+    assert_eq!(Producer::capacity(&w), Consumer::capacity(&r));
+
+    assert_eq!(w.push(1), Ok(()));
+    assert!(w.write_chunk().is_empty());
+    assert!(!w.is_abandoned());
+    let start = Instant::now();
+    assert_eq!(w.push(2), Ok(()));
+    assert!(start.elapsed() >= Duration::from_millis(5));
+    assert!(w.is_abandoned());
+
+    assert!(r.read_chunk().is_empty());
+    r.commit();
+    assert_eq!(r.read_chunk(), [1, 2]);
+    r.commit();
+    assert!(!r.is_abandoned());
+
+    // the injected faults are not left behind
+    let r = r.into_inner();
+    drop(w.into_inner());
+    assert!(r.is_abandoned());
+
+    let err = FaultPlan::new()
+        .fail_build(libc::ENOSPC)
+        .build::<u8>(Builder::new(16))
+        .err()
+        .unwrap();
+    assert!(format!("{:?}", err).starts_with("injected fault"));
+}
+
+#[test]
+fn test_record_replay() {
+    use crate::record::*;
+
+    let recorder = Recorder::new();
+    let (w, r) = cueue::<u8>(16).unwrap();
+    let mut w = RecordingWriter::new(w, recorder.clone());
+    let mut r = RecordingReader::new(r, recorder.clone());
+    let cap = w.capacity();
+
+    for i in 0..1000 {
+        w.write_chunk();
+        w.commit(i % 7);
+        if i % 3 == 0 {
+            r.read_chunk();
+            r.commit();
+        }
+    }
+
+    let mut log = Vec::new();
+    recorder.write_log(&mut log).unwrap();
+    let events = read_log(&log[..]).unwrap();
+    assert_eq!(events, recorder.events());
+    assert_eq!(events.len(), 2000 + 334 * 2);
+    assert!(events.windows(2).all(|e| e[0].at <= e[1].at));
+
+    let (mut w, mut r) = cueue::<u8>(cap).unwrap();
+    assert_eq!(replay(&events, &mut w, &mut r), Ok(()));
+
+    let (mut w, mut r) = mock::mock::<u8>(cap);
+    assert_eq!(replay(&events, &mut w, &mut r), Ok(()));
+
+    let diverging = [Event {
+        at: std::time::Duration::ZERO,
+        op: Op::ReadChunk(1),
+    }];
+    let (mut w, mut r) = mock::mock::<u8>(cap);
+    assert_eq!(
+        replay(&diverging, &mut w, &mut r),
+        Err(Divergence {
+            index: 0,
+            expected: Op::ReadChunk(1),
+            actual: Op::ReadChunk(0),
+        })
+    );
+}
+
+#[test]
+fn test_framed() {
+    use crate::framed::*;
+
+    for prefix in [LengthPrefix::U32, LengthPrefix::Varint] {
+        let (w, r) = cueue::<u8>(16).unwrap();
+        let cap = w.capacity();
+        let mut w = FrameWriter::with_prefix(w, prefix);
+        let mut r = FrameReader::with_prefix(r, prefix);
+
+        let big = vec![7u8; 300];
+        w.write_frame(b"").unwrap();
+        w.write_frame(b"foo").unwrap();
+        w.write_frame(&big).unwrap();
+        assert_eq!(w.write_frame(&vec![0; cap]), Err(FrameError::TooLarge));
+
+        assert_eq!(r.read_frame(), Some(&b""[..]));
+        assert_eq!(r.read_frame(), Some(&b"foo"[..]));
+        assert_eq!(r.read_frame(), Some(&big[..]));
+        assert_eq!(r.read_frame(), None);
+        r.commit();
+
+        // fill the queue, frames wrap around
+        let mut sent = 0;
+        while w.write_frame(&big).is_ok() {
+            sent += 1;
+        }
+        assert_eq!(w.write_frame(&big), Err(FrameError::Full));
+        for _ in 0..sent {
+            assert_eq!(r.read_frame(), Some(&big[..]));
+        }
+        r.commit();
+        assert!(w.write_frame(&big).is_ok());
+        assert_eq!(r.read_frame(), Some(&big[..]));
+    }
+}
+
+#[test]
+fn test_varint_prefix() {
+    use crate::framed::LengthPrefix;
+
+    let mut buf = [0u8; 10];
+    for len in [
+        0,
+        1,
+        127,
+        128,
+        300,
+        16383,
+        16384,
+        u32::MAX as usize,
+        usize::MAX,
+    ] {
+        let n = LengthPrefix::Varint.encode(len, &mut buf);
+        assert_eq!(n, LengthPrefix::Varint.encoded_len(len));
+        assert_eq!(LengthPrefix::Varint.decode(&buf[..n]), Ok(Some((len, n))));
+        assert_eq!(LengthPrefix::Varint.decode(&buf[..n - 1]), Ok(None));
+    }
+    assert_eq!(LengthPrefix::Varint.encoded_len(127), 1);
+    assert_eq!(LengthPrefix::Varint.encoded_len(128), 2);
+    assert!(LengthPrefix::Varint.decode(&[0xff; 10]).is_err());
+}
+
+#[test]
+fn test_frame_write_with() {
+    use crate::framed::*;
+
+    for prefix in [LengthPrefix::U32, LengthPrefix::Varint] {
+        let (w, r) = cueue::<u8>(16).unwrap();
+        let cap = w.capacity();
+        let mut w = FrameWriter::with_prefix(w, prefix);
+        let mut r = FrameReader::with_prefix(r, prefix);
+
+        assert_eq!(w.write_frame_with(|_| None), Err(FrameError::TooLarge));
+        assert_eq!(
+            w.write_frame_with(|buf| Some(buf.len() + 1)),
+            Err(FrameError::TooLarge)
+        );
+        assert!(r.read_frame().is_none());
+        w.write_frame_with(|buf| {
+            buf[..3].copy_from_slice(b"foo");
+            Some(3)
+        })
+        .unwrap();
+        w.write_frame_with(|buf| {
+            let len = buf.len() - 16;
+            buf[..len].fill(1);
+            Some(len)
+        })
+        .unwrap();
+        assert_eq!(w.write_frame_with(|_| None), Err(FrameError::Full));
+
+        assert_eq!(r.read_frame(), Some(&b"foo"[..]));
+        let big = r.read_frame().unwrap();
+        assert!(big.len() > cap - 32);
+        assert!(big.iter().all(|b| *b == 1));
+    }
+}
+
+#[test]
+fn test_frame_read_malformed() {
+    use crate::framed::*;
+
+    for prefix in [LengthPrefix::U32, LengthPrefix::Varint] {
+        let (mut w, r) = cueue::<u8>(16).unwrap();
+        let cap = w.capacity();
+        let mut r = FrameReader::with_prefix(r, prefix);
+
+        // a frame, that can never fit in the queue
+        let buf = w.write_chunk();
+        let n = prefix.encode(cap, buf);
+        w.commit(n);
+        assert_eq!(r.try_read_frame(), Err(FrameError::Malformed));
+        assert_eq!(r.read_frame(), None);
+    }
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_json() {
+    use crate::json::*;
+
+    let (w, r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+    let mut w = JsonWriter::<(u32, String)>::new(w);
+    let mut r = JsonReader::<(u32, String)>::new(r);
+
+    w.send(&(1, "foo\nbar".to_string())).unwrap();
+    w.send(&(2, "baz".to_string())).unwrap();
+    assert!(matches!(
+        w.send(&(3, "x".repeat(cap))),
+        Err(Error::Frame(crate::framed::FrameError::Full))
+    ));
+
+    assert_eq!(r.recv().unwrap(), Some((1, "foo\nbar".to_string())));
+    assert_eq!(r.recv().unwrap(), Some((2, "baz".to_string())));
+    assert_eq!(r.recv().unwrap(), None);
+    r.commit();
+    let mut w = w.into_inner();
+    assert_eq!(w.write_chunk().len(), cap);
+    w.write_chunk()[..8].copy_from_slice(b"[3,\"x\"]\n");
+    w.commit(8);
+    let mut w = JsonWriter::<(u32, String)>::new(w);
+    assert_eq!(r.recv().unwrap(), Some((3, "x".to_string())));
+    r.commit();
+
+    // the line never fits, invalid JSON
+    assert!(matches!(
+        w.send(&(3, "x".repeat(cap))),
+        Err(Error::Frame(crate::framed::FrameError::TooLarge))
+    ));
+    let mut w = w.into_inner();
+    w.write_chunk()[..4].copy_from_slice(b"foo\n");
+    w.commit(4);
+    assert!(matches!(r.recv(), Err(Error::Json(_))));
+}
+
+#[test]
+#[cfg(feature = "postcard")]
+fn test_postcard() {
+    use crate::postcard::*;
+
+    let (w, r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+    let mut w = PostcardWriter::<(u32, String)>::new(w);
+    let mut r = PostcardReader::<(u32, String)>::new(r);
+
+    w.send(&(1, "foo".to_string())).unwrap();
+    w.send(&(2, "bar".to_string())).unwrap();
+    assert!(matches!(
+        w.send(&(3, "x".repeat(cap))),
+        Err(Error::Frame(crate::framed::FrameError::Full))
+    ));
+
+    assert_eq!(r.recv().unwrap(), Some((1, "foo".to_string())));
+    assert_eq!(r.recv().unwrap(), Some((2, "bar".to_string())));
+    assert_eq!(r.recv().unwrap(), None);
+    r.commit();
+}
+
+#[test]
+#[cfg(feature = "tokio")]
+fn test_codec() {
+    use crate::codec::*;
+    use tokio_util::codec::{LengthDelimitedCodec, LinesCodec};
+
+    let (w, r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+    let mut w = CodecWriter::new(w, LengthDelimitedCodec::new());
+    let mut r = CodecReader::new(r, LengthDelimitedCodec::new());
+
+    w.send(bytes::Bytes::from_static(b"foo")).unwrap();
+    w.send(bytes::Bytes::from_static(b"bar")).unwrap();
+    assert!(matches!(
+        w.send(bytes::Bytes::from(vec![0; cap - 16])),
+        Err(Error::Frame(crate::framed::FrameError::Full))
+    ));
+
+    assert_eq!(r.recv().unwrap().unwrap(), &b"foo"[..]);
+    assert_eq!(r.recv().unwrap().unwrap(), &b"bar"[..]);
+    assert!(r.recv().unwrap().is_none());
+    r.commit();
+
+    // the codec fails
+    let (w, r) = cueue::<u8>(16).unwrap();
+    let mut w = CodecWriter::new(w, LinesCodec::new());
+    let mut r = CodecReader::new(r, LinesCodec::new_with_max_length(4));
+    w.send("foobar").unwrap();
+    assert!(matches!(r.recv(), Err(Error::Codec(_))));
+
+    // an unterminated line is decoded at the end of the frame, an empty frame is an error
+    let (w, r) = cueue::<u8>(16).unwrap();
+    let mut w = crate::framed::FrameWriter::with_prefix(w, crate::framed::LengthPrefix::Varint);
+    let mut r = CodecReader::new(r, LinesCodec::new());
+    w.write_frame(b"baz").unwrap();
+    w.write_frame(b"").unwrap();
+    assert_eq!(r.recv().unwrap(), Some("baz".to_string()));
+    assert!(matches!(r.recv(), Err(Error::Codec(_))));
+}
+
+#[test]
+#[cfg(feature = "prost")]
+fn test_proto() {
+    use crate::proto::*;
+    use prost::Message;
+
+    let (w, r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+    let mut w = ProtoWriter::new(w);
+    let mut r = ProtoReader::new(r);
+
+    w.send(&"foo".to_string()).unwrap();
+    w.send(&42u64).unwrap();
+    assert_eq!(
+        w.send(&"x".repeat(cap)),
+        Err(crate::framed::FrameError::Full)
+    );
+
+    assert_eq!(r.recv::<String>().unwrap(), Some("foo".to_string()));
+    assert_eq!(r.recv::<u64>().unwrap(), Some(42));
+    assert_eq!(r.recv::<u64>().unwrap(), None);
+    r.commit();
+
+    // the queue contents is a stream of length-delimited messages
+    let (w, r) = cueue::<u8>(16).unwrap();
+    let mut w = ProtoWriter::new(w);
+    w.send(&"bar".to_string()).unwrap();
+    let mut r = r;
+    let bytes = r.read_chunk();
+    assert_eq!(
+        String::decode_length_delimited(bytes).unwrap(),
+        "bar".to_string()
+    );
+}
+
+#[test]
+fn test_reserve_frame() {
+    use crate::framed::*;
+    use std::io::Write;
+
+    let (w, r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+    let mut w = FrameWriter::with_prefix(w, LengthPrefix::Varint);
+    let mut r = FrameReader::with_prefix(r, LengthPrefix::Varint);
+
+    let mut grant = w.reserve_frame(9).unwrap();
+    assert_eq!(grant.len(), 9);
+    grant.extend_from_slice(b"foo");
+    grant.write_all(b"bar").unwrap();
+    assert_eq!(grant.remaining(), 3);
+    assert_eq!(grant.write(b"bazqux").unwrap(), 3);
+    assert!(grant.write_all(b"!").is_err());
+    assert_eq!(r.read_frame(), None);
+    grant.publish();
+    assert_eq!(r.read_frame(), Some(&b"foobarbaz"[..]));
+
+    // discarded
+    w.reserve_frame(3)
+        .unwrap()
+        .payload_mut()
+        .copy_from_slice(b"abc");
+    w.reserve_frame(0).unwrap().publish();
+    assert_eq!(r.read_frame(), Some(&b""[..]));
+    assert_eq!(r.read_frame(), None);
+    r.commit();
+
+    assert_eq!(w.reserve_frame(cap).err(), Some(FrameError::TooLarge));
+    w.reserve_frame(cap - 10).unwrap().publish();
+    assert_eq!(w.reserve_frame(10).err(), Some(FrameError::Full));
+}
+
+#[test]
+fn test_frames() {
+    use crate::framed::*;
+
+    let (mut w, r) = cueue::<u8>(16).unwrap();
+    let mut r = FrameReader::new(r);
+
+    w.write_chunk()[..12].copy_from_slice(b"\x01\0\0\0a\x02\0\0\0bc\x03");
+    w.commit(12);
+
+    let mut frames = r.frames();
+    assert_eq!(frames.next(), Some(&b"a"[..]));
+    assert_eq!(frames.next(), Some(&b"bc"[..]));
+    assert_eq!(frames.try_next(), Ok(None));
+    drop(frames);
+
+    w.write_chunk()[..6].copy_from_slice(b"\0\0\0def");
+    w.commit(6);
+    let mut frames = r.frames();
+    assert_eq!(frames.next(), Some(&b"def"[..]));
+    assert_eq!(frames.next(), None);
+    drop(frames);
+    assert!(r.into_inner().read_chunk().is_empty());
+}
+
+#[test]
+fn test_segments() {
+    use crate::segments::*;
+
+    let (w, r) = cueue::<u8>(64).unwrap();
+    let cap = w.capacity();
+    let mut w = SegmentWriter::new(w);
+    let mut r = SegmentReader::new(r);
+
+    w.write_message(&[&[1; 8]]).unwrap();
+    w.write_message(&[&[2; 16], &[3; 8], &[4; 24]]).unwrap();
+    assert_eq!(
+        w.write_message(&[&vec![0; cap]]),
+        Err(crate::framed::FrameError::TooLarge)
+    );
+
+    let segments = r.read_message().unwrap().unwrap();
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments.get_segment(0), Some(&[1; 8][..]));
+    assert_eq!(segments.get_segment(1), None);
+
+    let segments = r.read_message().unwrap().unwrap();
+    assert_eq!(segments.len(), 3);
+    assert_eq!(segments.get_segment(0), Some(&[2; 16][..]));
+    assert_eq!(segments.get_segment(1), Some(&[3; 8][..]));
+    assert_eq!(segments.get_segment(2), Some(&[4; 24][..]));
+    for id in 0..3 {
+        let addr = segments.get_segment(id).unwrap().as_ptr() as usize;
+        assert_eq!(addr % 8, 0);
+    }
+    assert!(r.read_message().unwrap().is_none());
+    r.commit();
+}
+
+#[test]
+#[cfg(feature = "capnp")]
+fn test_segments_capnp() {
+    use crate::segments::*;
+
+    let (w, r) = cueue::<u8>(1 << 16).unwrap();
+    let mut w = SegmentWriter::new(w);
+    let mut r = SegmentReader::new(r);
+
+    let mut message = capnp::message::Builder::new_default();
+    message
+        .set_root(capnp::text::Reader::from("foobar"))
+        .unwrap();
+    w.write_message(&message.get_segments_for_output()).unwrap();
+
+    let segments = r.read_message().unwrap().unwrap();
+    let segment = segments.get_segment(0).unwrap().as_ptr_range();
+    let message = capnp::message::Reader::new(segments, Default::default());
+    let root: capnp::text::Reader = message.get_root().unwrap();
+    assert_eq!(root.to_str().unwrap(), "foobar");
+    // read in place
+    assert!(segment.contains(&root.as_bytes().as_ptr()));
+    r.commit();
+}
+
+#[test]
+#[cfg(feature = "arrow")]
+fn test_arrow() {
+    use crate::arrow::*;
+    use arrow_array::types::Int8Type;
+    use arrow_array::{Array, DictionaryArray, Int32Array, RecordBatch};
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("a", DataType::Int32, false),
+        Field::new(
+            "b",
+            DataType::Dictionary(Box::new(DataType::Int8), Box::new(DataType::Utf8)),
+            false,
+        ),
+    ]));
+    let batch = |n: i32| {
+        let a = Int32Array::from(vec![n, n + 1, n + 2]);
+        let b: DictionaryArray<Int8Type> = vec!["x", "y", "x"].into_iter().collect();
+        RecordBatch::try_new(schema.clone(), vec![Arc::new(a), Arc::new(b)]).unwrap()
+    };
+
+    let (w, r) = cueue::<u8>(1 << 16).unwrap();
+    let mut w = ArrowWriter::new(w, schema.clone());
+    let mut r = ArrowReader::new(r);
+
+    w.write_batch(&batch(1)).unwrap();
+    w.write_batch(&batch(2)).unwrap();
+
+    let first = r.read_batch().unwrap().unwrap();
+    assert_eq!(first, batch(1));
+    let values = first.column(0).to_data().buffers()[0].as_ptr() as usize;
+    assert_eq!(values % 64, 0);
+    assert_eq!(r.read_batch().unwrap(), Some(batch(2)));
+    assert_eq!(r.read_batch().unwrap(), None);
+
+    // the first batch is alive: nothing is consumed
+    r.commit();
+    let mut r = r.into_inner().err().unwrap();
+    assert_eq!(first, batch(1));
+
+    drop(first);
+    r.commit();
+    let mut r = r.into_inner().ok().unwrap();
+    assert!(r.read_chunk().is_empty());
+}
+
+#[test]
+fn test_scope() {
+    let data: Vec<u32> = (0..100).collect();
+    let sum = scope(16, |s, mut w, mut r| {
+        // the elements borrow `data`
+        let data = &data;
+        s.spawn(move || {
+            for window in data.windows(3) {
+                while w.push(window).is_err() {}
+            }
+        });
+        let mut sum = 0;
+        while let Ok(chunk) = r.read_chunk_blocking() {
+            sum += chunk.iter().map(|w: &&[u32]| w.len()).sum::<usize>();
+            r.commit();
+        }
+        sum
+    })
+    .unwrap();
+    assert_eq!(sum, 98 * 3);
+}
+
+#[test]
+fn test_merge() {
+    use crate::merge::*;
+
+    let (mut w1, r1) = cueue::<u8>(16).unwrap();
+    let (mut w2, r2) = cueue::<u8>(16).unwrap();
+    let (w, mut r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+    let mut merge = Merge::new(vec![r1, r2], w);
+
+    // the chunks are not split
+    w1.write_chunk()[..cap - 1].fill(1);
+    w1.commit(cap - 1);
+    w2.write_chunk()[..2].fill(2);
+    w2.commit(2);
+    assert_eq!(merge.pump(), cap - 1);
+    assert_eq!(merge.pump(), 0);
+    r.read_chunk();
+    r.commit();
+    w1.push(1).unwrap();
+    // round robin: the second Reader is next
+    assert_eq!(merge.pump(), 3);
+    assert_eq!(r.read_chunk(), [2, 2, 1]);
+    r.commit();
+
+    // ready first: the first Reader is preferred, a chunk that does not fit is skipped
+    let (_, w) = merge.into_inner();
+    let (mut w1, r1) = cueue::<u8>(16).unwrap();
+    let (mut w2, r2) = cueue::<u8>(16).unwrap();
+    let mut merge = Merge::new(vec![r1, r2], w).order(MergeOrder::ReadyFirst);
+    w1.write_chunk()[..cap - 1].fill(1);
+    w1.commit(cap - 1);
+    w2.push(2).unwrap();
+    assert_eq!(merge.pump(), cap);
+    assert_eq!(r.read_chunk().last(), Some(&2));
+    r.commit();
+
+    // producer threads
+    let (_, w) = merge.into_inner();
+    let mut readers = Vec::new();
+    let mut producers = Vec::new();
+    for p in 0..4u8 {
+        let (mut w, r) = cueue::<u8>(16).unwrap();
+        readers.push(r);
+        producers.push(std::thread::spawn(move || {
+            for _ in 0..1000 {
+                while w.push(p).is_err() {}
+            }
+        }));
+    }
+    let mut merge = Merge::new(readers, w);
+    let consumer = std::thread::spawn(move || {
+        let mut counts = [0; 4];
+        while let Ok(chunk) = r.read_chunk_blocking() {
+            chunk.iter().for_each(|&p| counts[p as usize] += 1);
+            r.commit();
+        }
+        counts
+    });
+    merge.run().unwrap();
+    producers.into_iter().for_each(|p| p.join().unwrap());
+    drop(merge);
+    assert_eq!(consumer.join().unwrap(), [1000; 4]);
+}
+
+#[test]
+fn test_tee() {
+    use crate::tee::*;
+
+    let (mut w, r) = cueue::<u32>(16).unwrap();
+    let (live_w, mut live) = cueue::<u32>(16).unwrap();
+    let (archive_w, mut archive) = cueue::<u32>(16).unwrap();
+    let cap = w.capacity();
+    let mut tee = Tee::new(r, live_w, archive_w).lag_policy(LagPolicy::Wait, LagPolicy::Drop);
+
+    w.push_many(&mut (0..cap as u32));
+    assert_eq!(tee.pump(), cap);
+    w.push_many(&mut (0..2));
+    assert_eq!(archive.read_chunk().len(), cap);
+    assert_eq!(archive.gap(), 0);
+    archive.commit();
+
+    // the live queue is full: the elements stay in the upstream queue
+    assert_eq!(tee.pump(), 0);
+    assert_eq!(live.read_chunk().len(), cap);
+    live.commit();
+    assert_eq!(tee.pump(), 2);
+    assert_eq!(archive.gap(), 0);
+    assert_eq!(archive.read_chunk(), [0, 1]);
+    archive.commit();
+    assert_eq!(live.read_chunk(), [0, 1]);
+    live.commit();
+
+    let live_consumer = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        while let Ok(chunk) = live.read_chunk_blocking() {
+            received.extend_from_slice(chunk);
+            live.commit();
+        }
+        received
+    });
+    let count = 10_000;
+    let tee = std::thread::spawn(move || {
+        tee.run().unwrap();
+        tee
+    });
+    for i in 0..count {
+        while w.push(i).is_err() {}
+    }
+    drop(w);
+    let (_, live_w, archive_w) = tee.join().unwrap().into_inner();
+    drop(live_w);
+    assert_eq!(
+        live_consumer.join().unwrap(),
+        (0..count).collect::<Vec<_>>()
+    );
+
+    // the archive was not consumed: the rest of the elements are dropped
+    let archived = archive.read_chunk().len() as u64;
+    assert_eq!(archived + archive.gap(), count as u64);
+    drop(archive_w);
+}
+
+#[test]
+fn test_pipeline_connect() {
+    let (mut w, r) = cueue::<u8>(16).unwrap();
+    let (w2, mut r2) = cueue::<u32>(16).unwrap();
+
+    // decode little endian u32 records: the input might end with an incomplete one
+    let stage = std::thread::spawn(move || {
+        crate::pipeline::connect(r, w2, |input: &[u8], output: &mut [u32]| {
+            let n = usize::min(input.len() / 4, output.len());
+            for (out, record) in output.iter_mut().zip(input.chunks_exact(4)).take(n) {
+                *out = u32::from_le_bytes(record.try_into().unwrap());
+            }
+            (n * 4, n)
+        })
+    });
+
+    // many more than the queues hold
+    let count = 10_000u32;
+    let consumer = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        while let Ok(chunk) = r2.read_chunk_blocking() {
+            received.extend_from_slice(chunk);
+            r2.commit();
+        }
+        received
+    });
+    let bytes: Vec<u8> = (0..count).flat_map(u32::to_le_bytes).collect();
+    for piece in bytes.chunks(3) {
+        assert_eq!(
+            w.write_all_blocking(piece, Duration::from_secs(60)),
+            piece.len()
+        );
+    }
+    drop(w);
+
+    stage.join().unwrap().unwrap();
+    assert_eq!(consumer.join().unwrap(), (0..count).collect::<Vec<_>>());
+
+    // the downstream Reader is dropped
+    let (_w, r) = cueue::<u8>(16).unwrap();
+    let (w2, r2) = cueue::<u8>(16).unwrap();
+    drop(r2);
+    assert!(crate::pipeline::connect(r, w2, |_: &[u8], _: &mut [u8]| (0, 0)).is_err());
+}
+
+#[test]
+fn test_snapshot_frames() {
+    use crate::snapshot::*;
+
+    let (w, r) = cueue::<u64>(16).unwrap();
+    let cap = w.capacity();
+    let mut w = SnapshotWriter::new(w, 4);
+    let mut r = SnapshotReader::new(r, 4);
+    assert_eq!(r.latest(), None);
+
+    let mut tick = 0;
+    let mut publish = |w: &mut SnapshotWriter<u64>| match w.frame() {
+        Some(frame) => {
+            tick += 1;
+            frame.fill(tick);
+            assert!(w.publish());
+            true
+        }
+        None => false,
+    };
+
+    // the queue fills up: the Writer skips publishing
+    while publish(&mut w) {}
+    assert!(!w.publish());
+    let frames = (cap / 4) as u64;
+    assert_eq!(r.latest(), Some(&[frames; 4][..]));
+    assert_eq!(r.skipped(), frames - 1);
+
+    // nothing new: the held frame again
+    assert_eq!(r.latest(), Some(&[frames; 4][..]));
+    assert_eq!(r.skipped(), frames - 1);
+
+    // the held frame is consumed, but not counted as skipped
+    assert!(publish(&mut w));
+    assert!(publish(&mut w));
+    assert_eq!(r.latest(), Some(&[frames + 2; 4][..]));
+    assert_eq!(r.skipped(), frames);
+}
+
+#[test]
+fn test_audio_frames() {
+    use crate::audio::*;
+
+    let (w, r) = cueue::<f32>(16).unwrap();
+    let cap = w.capacity();
+    let mut w = AudioWriter::new(w, 3);
+    let mut r = AudioReader::new(r, 3);
+    assert_eq!(w.capacity(), cap / 3);
+
+    let buf = w.write_frames();
+    assert_eq!(buf.len(), cap / 3 * 3);
+    for (i, frame) in buf.chunks_exact_mut(3).enumerate() {
+        frame.fill(i as f32);
+    }
+    // too large: truncated to whole frames
+    assert_eq!(w.commit(usize::MAX), cap / 3);
+
+    let frames = r.limited_read_frames(2);
+    assert_eq!(frames, [0.0, 0.0, 0.0, 1.0, 1.0, 1.0]);
+    r.commit();
+
+    // free space is not a multiple of the channels
+    assert_eq!(w.write_frames().len(), 6);
+    assert!(w.write_frames_exact(3).is_none());
+    w.write_frames_exact(1).unwrap().fill(9.0);
+    assert_eq!(w.commit(1), 1);
+
+    let frames = r.read_frames();
+    assert_eq!(frames.len() % 3, 0);
+    assert_eq!(&frames[frames.len() - 3..], [9.0, 9.0, 9.0]);
+    r.commit();
+    assert!(r.read_frames_exact(1).is_none());
+}
+
+#[test]
+#[cfg(feature = "rt-audit")]
+fn test_rt_audit() {
+    use crate::rt_audit::*;
+
+    let (mut w, mut r) = Builder::new(16).notify(true).build::<u8>().unwrap();
+    assert!(!is_active());
+    {
+        let _outer = Guard::new();
+        let _inner = Guard::new();
+        // nothing to notify: no syscall
+        w.write_chunk();
+        w.commit(0);
+        assert!(r.read_chunk().is_empty());
+        r.commit();
+    }
+    assert!(!is_active());
+
+    // syscalls outside of real-time sections are allowed
+    w.push(1).unwrap();
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _guard = Guard::new();
+        w.push(2).unwrap();
+    }));
+    assert!(result.is_err());
+    assert!(!is_active());
+}
+
+#[test]
+fn test_paced_reader() {
+    use crate::paced::PacedReader;
+    use std::time::{Duration, Instant};
+
+    let (mut w, r) = cueue::<u64>(1 << 12).unwrap();
+    let max_delay = Duration::from_millis(2);
+    let mut r = PacedReader::new(r, 256, max_delay);
+
+    // light traffic: no waiting
+    w.push(0).unwrap();
+    let begin = Instant::now();
+    assert_eq!(r.read_chunk(), [0]);
+    assert!(begin.elapsed() < Duration::from_millis(100));
+    r.commit();
+
+    let maxi = 100_000;
+    let wt = std::thread::spawn(move || {
+        for i in 1..maxi {
+            while w.push(i).is_err() {}
+        }
+    });
+
+    let mut i = 1;
+    while i < maxi {
+        let begin = Instant::now();
+        let chunk = r.read_chunk();
+        // generous slack for a loaded machine
+        assert!(begin.elapsed() < max_delay + Duration::from_millis(100));
+        for elem in chunk {
+            assert_eq!(*elem, i);
+            i += 1;
+        }
+        r.commit();
+    }
+    assert!(r.rate() > 0.0);
+
+    wt.join().unwrap();
+}
+
+#[test]
+fn test_rate_limit() {
+    use crate::rate::*;
+    use std::time::{Duration, Instant};
+
+    let (w, mut r) = cueue::<u32>(1 << 12).unwrap();
+    let limit = RateLimit {
+        per_second: 0.001,
+        burst: 4,
+    };
+    let mut w = RateLimitedWriter::new(w, limit, Policy::Shed);
+    assert_eq!(w.write_chunk().len(), 4);
+    w.commit(3);
+    w.push(3).unwrap();
+    w.push(4).unwrap();
+    w.push(5).unwrap();
+    assert!(w.write_chunk().is_empty());
+    assert_eq!(w.shed(), 2);
+    assert_eq!(r.read_chunk().len(), 4);
+    assert_eq!(r.gap(), 2);
+    r.commit();
+
+    let w = w.into_inner();
+    let limit = RateLimit {
+        per_second: 1000.0,
+        burst: 1,
+    };
+    let mut w = RateLimitedWriter::new(w, limit, Policy::Block);
+    let begin = Instant::now();
+    for i in 0..21 {
+        w.push(i).unwrap();
+    }
+    assert!(begin.elapsed() >= Duration::from_millis(20));
+    assert_eq!(w.shed(), 0);
+    assert_eq!(r.read_chunk().len(), 21);
+}
+
+#[test]
+fn test_full_policy() {
+    let full = |policy| {
+        let (mut w, r) = Builder::new(16).full_policy(policy).build::<u32>().unwrap();
+        let cap = w.capacity() as u32;
+        assert_eq!(w.push_many(&mut (0..cap)), cap as usize);
+        (w, r, cap)
+    };
+
+    let (mut w, _r, cap) = full(FullPolicy::ReturnEmpty);
+    assert_eq!(w.push(cap), Err(cap));
+    assert_eq!(w.push_many(&mut (0..10)), 0);
+
+    let (mut w, mut r, cap) = full(FullPolicy::DropNewest);
+    assert_eq!(w.push(cap), Ok(()));
+    assert_eq!(w.push_many(&mut (0..10)), 0);
+    assert_eq!(r.gap(), 11);
+    assert_eq!(r.read_chunk().last(), Some(&(cap - 1)));
+
+    let (mut w, mut r, cap) = full(FullPolicy::DropOldest);
+    assert_eq!(w.push(cap), Ok(()));
+    assert_eq!(w.push_many(&mut (cap + 1..cap + 3)), 2);
+    assert_eq!(r.gap(), 3);
+    let chunk = r.read_chunk();
+    assert_eq!(chunk.len(), cap as usize);
+    assert_eq!(chunk[0], 3);
+    assert_eq!(chunk.last(), Some(&(cap + 2)));
+    // the Reader holds the chunk: the newest is dropped
+    assert_eq!(w.push(0), Ok(()));
+    assert_eq!(r.gap(), 1);
+    assert_eq!(r.read_chunk()[0], 3);
+    r.commit();
+    assert!(r.read_chunk().is_empty());
+    r.commit();
+
+    // generic code honors the full policy as well
+    let (mut w, mut r, cap) = full(FullPolicy::DropOldest);
+    let producer: &mut dyn Producer<u32> = &mut w;
+    assert_eq!(producer.push(cap), Ok(()));
+    assert_eq!(r.gap(), 1);
+    assert_eq!(r.read_chunk()[0], 1);
+    assert_eq!(r.read_chunk().last(), Some(&cap));
+
+    let (mut w, mut r, cap) = full(FullPolicy::Block);
+    let rt = std::thread::spawn(move || {
+        let mut expected = 0;
+        while expected <= cap {
+            for elem in r.read_chunk() {
+                assert_eq!(*elem, expected);
+                expected += 1;
+            }
+            r.commit();
+        }
+    });
+    assert_eq!(w.push(cap), Ok(()));
+    rt.join().unwrap();
+    assert_eq!(w.push_many(&mut (0..cap + 1)), cap as usize);
+}
+
+#[test]
+fn test_drop_oldest_race() {
+    let (mut w, mut r) = Builder::new(16)
+        .full_policy(FullPolicy::DropOldest)
+        .build::<u64>()
+        .unwrap();
+    let cap = w.capacity();
+    let wt = std::thread::spawn(move || {
+        for i in 0..cap as u64 * 64 {
+            w.push(i).unwrap();
+        }
+    });
+
+    let mut iteration = 0u64;
+    while !r.is_abandoned() {
+        // a commit without a chunk must not undo the elements dropped by the Writer
+        r.commit();
+        iteration += 1;
+        if iteration & 63 == 0 {
+            let chunk = r.read_chunk();
+            assert!(chunk.len() <= cap);
+            assert!(chunk.windows(2).all(|pair| pair[0] < pair[1]));
+            r.commit();
+        }
+    }
+    wt.join().unwrap();
+    let chunk = r.read_chunk();
+    assert!(chunk.len() <= cap);
+    assert!(chunk.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn test_resize() {
+    let (mut w, mut r) = cueue::<String>(16).unwrap();
+    let cap = w.capacity();
+    let fulls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = fulls.clone();
+    w.on_full(move || {
+        counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    });
+
+    for i in 0..cap {
+        w.push(i.to_string()).unwrap();
+    }
+    assert!(w.push("x".into()).is_err());
+    w.record_dropped(1);
+    r.limited_read_chunk(2);
+    r.commit();
+    let sequence = w.sequence();
+
+    assert!(resize(&mut w, &mut r, cap / 2).is_err());
+    assert_eq!(w.capacity(), cap);
+
+    resize(&mut w, &mut r, cap * 4).unwrap();
+    assert_eq!(w.capacity(), cap * 4);
+    assert_eq!(r.capacity(), cap * 4);
+    assert_eq!(w.sequence(), sequence);
+    assert_eq!(r.gap(), 1);
+
+    w.push("last".into()).unwrap();
+    let chunk = r.read_chunk();
+    assert_eq!(chunk.len(), cap - 1);
+    assert_eq!(chunk[0], "2");
+    assert_eq!(chunk[cap - 3], (cap - 1).to_string());
+    assert_eq!(chunk[cap - 2], "last");
+    r.commit();
+
+    while w.push(String::new()).is_ok() {}
+    assert_eq!(fulls.load(std::sync::atomic::Ordering::Relaxed), 2);
+}
+
+#[test]
+fn test_rtrb_compat() {
+    use crate::rtrb_compat::*;
+
+    let (mut p, mut c) = RingBuffer::<String>::new(16);
+    let cap = p.capacity();
+    assert_eq!(c.pop(), Err(PopError::Empty));
+    assert_eq!(c.peek(), Err(PeekError::Empty));
+
+    p.push("foo".into()).unwrap();
+    let mut chunk = p.write_chunk(2).unwrap();
+    chunk
+        .as_mut_slices()
+        .0
+        .clone_from_slice(&["bar".into(), "baz".into()]);
+    chunk.commit(1);
+    assert_eq!(p.slots(), cap - 2);
+
+    assert_eq!(c.peek().unwrap(), "foo");
+    assert_eq!(c.pop().unwrap(), "foo");
+    assert_eq!(c.read_chunk(2).err(), Some(ChunkError::TooFewSlots(1)));
+    let chunk = c.read_chunk(1).unwrap();
+    assert_eq!(chunk.as_slices().0, ["bar"]);
+    chunk.commit_all();
+    assert!(c.is_empty());
+
+    assert_eq!(p.write_chunk_uninit(cap).unwrap().fill_from_iter(None), 0);
+    assert!(p.write_chunk(cap + 1).is_err());
+    while p.push(String::new()).is_ok() {}
+    assert!(p.is_full());
+    assert!(matches!(p.push("x".into()), Err(PushError::Full(_))));
+    drop(c);
+    assert!(p.is_abandoned());
+
+    // the slots of an uncommitted uninit chunk remain initialized
+    let (mut p, mut c) = RingBuffer::<u32>::new(16);
+    let mut chunk = p.write_chunk_uninit(3).unwrap();
+    let (first, second) = unsafe { chunk.as_mut_slices() };
+    assert!(second.is_empty());
+    for (i, slot) in first.iter_mut().enumerate() {
+        slot.write(i as u32 + 1);
+    }
+    unsafe { chunk.commit(2) };
+    let chunk = c.read_chunk(2).unwrap();
+    assert_eq!(chunk.as_slices().0, [1, 2]);
+    chunk.commit_all();
+    let mut w = p.into_inner();
+    assert_eq!(w.write_chunk()[0], 3);
+}
+
+#[test]
+fn test_mux() {
+    use crate::mux::*;
+    use std::sync::{Arc, Mutex};
+
+    let (w, r) = cueue::<u8>(1 << 12).unwrap();
+    let mut w = MuxWriter::new(w);
+    let mut demux = Demux::new(r);
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    for channel in [1, 2] {
+        let log = log.clone();
+        demux.on_channel(channel, move |payload| {
+            log.lock().unwrap().push((channel, payload.to_vec()));
+        });
+    }
+    let unknown = log.clone();
+    demux.on_unknown(move |channel, payload| {
+        unknown
+            .lock()
+            .unwrap()
+            .push((channel + 1000, payload.to_vec()));
+    });
+
+    w.send(1, b"foo").unwrap();
+    w.send(2, b"").unwrap();
+    w.send(3, b"bar").unwrap();
+    w.send_with(1, |buf| {
+        buf[..3].copy_from_slice(b"baz");
+        Some(3)
+    })
+    .unwrap();
+    assert_eq!(demux.dispatch(), Ok(4));
+    assert_eq!(demux.dispatch(), Ok(0));
+
+    demux.remove_channel(2);
+    w.send(2, b"qux").unwrap();
+    assert_eq!(demux.dispatch(), Ok(1));
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            (1, b"foo".to_vec()),
+            (2, vec![]),
+            (1003, b"bar".to_vec()),
+            (1, b"baz".to_vec()),
+            (1002, b"qux".to_vec()),
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "bytemuck")]
+fn test_pod() {
+    let (mut w, mut r) = cueue::<u8>(16).unwrap();
+    let cap = w.capacity();
+
+    assert_eq!(w.write_chunk_as::<u32>().unwrap().len(), cap / 4);
+    w.write_chunk_as::<u32>().unwrap()[..2].copy_from_slice(&[1, 2]);
+    assert_eq!(w.commit_as::<u32>(2), 2);
+    assert_eq!(r.read_chunk_as::<u64>().unwrap(), [1 | 2 << 32]);
+    assert_eq!(r.read_chunk_as::<u32>().unwrap(), [1, 2]);
+    r.commit();
+
+    // unaligned
+    assert!(w.push_pod(&3u8));
+    assert!(w.push_pod(&4u32));
+    assert!(w.write_chunk_as::<u32>().is_err());
+    assert_eq!(r.pop_pod::<u8>(), Some(3));
+    assert!(r.read_chunk_as::<u32>().is_err());
+    r.commit();
+    assert_eq!(r.read_chunk().len(), 4);
+    assert_eq!(r.pop_pod::<u32>(), Some(4));
+    assert_eq!(r.pop_pod::<u8>(), None);
+}
+
+#[test]
+#[cfg(feature = "ringbuf-compat")]
+fn test_ringbuf_compat() {
+    use ringbuf::traits::{Consumer, Observer, Producer};
+
+    let (mut w, mut r) = Builder::new(16).sequence(true).build::<u32>().unwrap();
+    let cap = w.capacity();
+
+    // wrap around the buffer, and the ringbuf indices
+    for round in 0..5 {
+        let data: Vec<u32> = (0..cap as u32 - 1).map(|i| i + round).collect();
+        assert_eq!(w.push_slice(&data), cap - 1);
+        assert_eq!(Observer::occupied_len(&r), cap - 1);
+        assert_eq!(w.vacant_len(), 1);
+        let (first, second) = r.as_slices();
+        assert_eq!(first, &data[..]);
+        assert!(second.is_empty());
+        assert_eq!(r.pop_iter().sum::<u32>(), data.iter().sum::<u32>());
+        assert!(Observer::is_empty(&r));
+    }
+
+    w.try_push(7).unwrap();
+    w.push(8).unwrap();
+    assert_eq!(r.read_chunk(), [7, 8]);
+    r.commit();
+    assert_eq!(r.try_pop(), None);
+    assert_eq!(w.sequence(), 7);
+
+    drop(r);
+    assert!(!w.read_is_held());
 }
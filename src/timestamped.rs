@@ -0,0 +1,175 @@
+//! Elements stamped with the time of their commit, to measure the age of each record.
+//!
+//! `Timestamped<T>` wraps the elements of a queue with a timestamp of the clock of the queue
+//! (see `Writer::timestamp`). `Writer::commit_stamped` stamps the elements of a batch
+//! with a single timestamp (i.e: the clock is read once per commit, not once per element),
+//! therefore the Reader can compute the age (i.e: the latency) of every record,
+//! not only of the batch it reads them in.
+//!
+//!```
+//! use cueue::timestamped::Timestamped;
+//! use std::time::Duration;
+//!
+//! let (mut w, mut r) = cueue::cueue::<Timestamped<u32>>(16).unwrap();
+//! let chunk = w.write_chunk();
+//! chunk[0].value = 1;
+//! chunk[1].value = 2;
+//! assert_eq!(w.commit_stamped(2), 2);
+//! w.push_stamped(3).unwrap();
+//!
+//! let now = r.timestamp();
+//! for element in r.read_chunk() {
+//!     assert!(element.age(now) < Duration::from_secs(60));
+//! }
+//! r.commit();
+//!```
+//!
+//! For data where stale values are worse than no values (e.g: sensor readings, market data),
+//! `FreshReader` skips the elements older than a TTL, and counts them.
+
+use std::time::Duration;
+
+use crate::{Reader, SharedMemSafe, Writer};
+
+/// An element, and the time it was committed at, see the module docs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Timestamped<T> {
+    /// The commit time, of the clock of the queue, in nanoseconds
+    pub timestamp: u64,
+    pub value: T,
+}
+
+impl<T> Timestamped<T> {
+    /// The time elapsed since the commit of the element, until `now`
+    /// (a timestamp of the same clock, e.g: `Reader::timestamp`), zero, if `now` is earlier.
+    pub fn age(&self, now: u64) -> Duration {
+        Duration::from_nanos(now.saturating_sub(self.timestamp))
+    }
+}
+
+unsafe impl<T: SharedMemSafe> SharedMemSafe for Timestamped<T> {}
+
+impl<T> Writer<Timestamped<T>>
+where
+    T: Default,
+{
+    /// Stamp the first `n` elements of the slice returned by `write_chunk` with the current time,
+    /// then commit them, see `commit`.
+    ///
+    /// Returns the number of committed elements.
+    pub fn commit_stamped(&mut self, n: usize) -> usize {
+        let n = usize::min(self.write_capacity, n);
+        let timestamp = self.timestamp();
+        for i in 0..n {
+            unsafe { (*self.write_begin.add(i)).timestamp = timestamp };
+        }
+        self.commit(n)
+    }
+
+    /// Stamp `value` with the current time, and push it, see `push`.
+    ///
+    /// Returns Err with the value, if it is given back by the full policy of the queue.
+    pub fn push_stamped(&mut self, value: T) -> Result<(), T> {
+        let timestamp = self.timestamp();
+        self.push(Timestamped { timestamp, value })
+            .map_err(|element| element.value)
+    }
+}
+
+impl<T> Reader<Timestamped<T>>
+where
+    T: Default,
+{
+    /// The age of the oldest unread element, i.e: how long it has been waiting in the queue,
+    /// or None, if the queue is empty.
+    pub fn oldest_age(&mut self) -> Option<Duration> {
+        let now = self.timestamp();
+        self.read_chunk().first().map(|element| element.age(now))
+    }
+}
+
+/// Reads `Timestamped` elements, skipping the ones older than a TTL.
+///
+/// The elements of a queue are committed in order, therefore the stale elements are
+/// the oldest ones, at the start of the readable chunk: they are consumed, and counted
+/// by `skipped`, before the chunk is returned.
+///
+///```
+/// use cueue::timestamped::{FreshReader, Timestamped};
+/// use std::time::Duration;
+///
+/// let (mut w, r) = cueue::cueue::<Timestamped<u32>>(16).unwrap();
+/// let mut r = FreshReader::new(r, Duration::from_millis(1));
+///
+/// w.push_stamped(1).unwrap();
+/// std::thread::sleep(Duration::from_millis(2));
+/// w.push_stamped(2).unwrap();
+///
+/// let chunk = r.read_chunk();
+/// assert!(chunk.iter().all(|element| element.value == 2));
+/// r.commit();
+/// assert!(r.skipped() >= 1);
+///```
+pub struct FreshReader<T> {
+    reader: Reader<Timestamped<T>>,
+    ttl: Duration,
+    skipped: u64,
+}
+
+impl<T> FreshReader<T>
+where
+    T: Default,
+{
+    /// Skip the elements older than `ttl`, when they are read.
+    pub fn new(reader: Reader<Timestamped<T>>, ttl: Duration) -> Self {
+        Self {
+            reader,
+            ttl,
+            skipped: 0,
+        }
+    }
+
+    /// Consume the elements older than the TTL, then return the readable elements, see `Reader::read_chunk`.
+    ///
+    /// The returned elements are not older than the TTL at the time of the call.
+    pub fn read_chunk(&mut self) -> &[Timestamped<T>] {
+        let now = self.reader.timestamp();
+        let ttl = self.ttl;
+        let stale = self
+            .reader
+            .read_chunk()
+            .partition_point(|element| element.age(now) > ttl);
+        if stale != 0 {
+            self.reader.limited_read_chunk(stale);
+            self.reader.commit();
+            self.skipped += stale as u64;
+        }
+        self.reader.read_chunk()
+    }
+
+    /// Consume the elements returned by the last `read_chunk`.
+    pub fn commit(&mut self) {
+        self.reader.commit();
+    }
+
+    /// Set the maximum age of the returned elements.
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
+    /// Number of elements consumed without being returned by `read_chunk`, as they were stale.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Returns true, if the Writer counterpart was dropped.
+    pub fn is_abandoned(&self) -> bool {
+        self.reader.is_abandoned()
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<Timestamped<T>> {
+        self.reader
+    }
+}
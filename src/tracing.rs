@@ -0,0 +1,196 @@
+//! Handles emitting `tracing` events on each operation, to see the behavior of a queue
+//! in existing trace tooling.
+//!
+//! `InstrumentedWriter` and `InstrumentedReader` wrap a Writer and a Reader, and implement
+//! the `Producer` and `Consumer` traits. Each non-empty commit emits a `commit` event
+//! with the size of the batch, and the occupancy of the queue after the commit.
+//! If a handle finds the queue full (or empty), the next successful `write_chunk`
+//! (or `read_chunk`) emits a `full` (or `empty`) event with the time it waited for,
+//! and the blocking operations run in a `wait` span.
+//!
+//! The events and spans are at the TRACE level, with the `cueue` target,
+//! and carry the name of the queue in the `queue` field.
+//!
+//!```
+//! use cueue::tracing::{InstrumentedReader, InstrumentedWriter};
+//! use cueue::{Consumer, Producer};
+//!
+//! let (w, r) = cueue::cueue::<u8>(1 << 12).unwrap();
+//! let mut w = InstrumentedWriter::new(w, "requests");
+//! let mut r = InstrumentedReader::new(r, "requests");
+//!
+//! w.push(1).unwrap();
+//! assert_eq!(r.read_chunk(), [1]);
+//! r.commit();
+//!```
+
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use ::tracing::{trace, trace_span};
+
+use crate::abi::READER_BUSY;
+use crate::{Abandoned, Consumer, Producer, Reader, Writer};
+
+/// Writer emitting tracing events, see the module docs.
+pub struct InstrumentedWriter<T> {
+    writer: Writer<T>,
+    name: &'static str,
+    /// when `write_chunk` first found the queue full, since the last successful one
+    full_since: Option<Instant>,
+}
+
+impl<T> InstrumentedWriter<T>
+where
+    T: Default,
+{
+    /// Emit the events of `writer`, with `name` in the `queue` field.
+    pub fn new(writer: Writer<T>, name: &'static str) -> Self {
+        Self {
+            writer,
+            name,
+            full_since: None,
+        }
+    }
+
+    /// Push `t`, waiting for a free slot, in a `wait` span, see `Writer::push_blocking`.
+    pub fn push_blocking(&mut self, t: T, timeout: Duration) -> Result<(), T> {
+        if let Some(slot) = self.write_chunk().first_mut() {
+            *slot = t;
+            self.commit(1);
+            return Ok(());
+        }
+        let result = {
+            let _span = trace_span!(target: "cueue", "wait", queue = self.name).entered();
+            self.writer.push_blocking(t, timeout)
+        };
+        if result.is_ok() {
+            if let Some(since) = self.full_since.take() {
+                let wait_ns = since.elapsed().as_nanos() as u64;
+                trace!(target: "cueue", queue = self.name, wait_ns, "full");
+            }
+            self.trace_commit(1);
+        }
+        result
+    }
+
+    /// Returns the wrapped Writer.
+    pub fn into_inner(self) -> Writer<T> {
+        self.writer
+    }
+
+    fn trace_commit(&self, n: usize) {
+        let w = self.writer.write_pos().load(Ordering::Relaxed);
+        let r = self.writer.read_pos().load(Ordering::Relaxed) & !READER_BUSY;
+        let occupancy = w.saturating_sub(r);
+        trace!(target: "cueue", queue = self.name, batch = n, occupancy, "commit");
+    }
+}
+
+impl<T> Producer<T> for InstrumentedWriter<T>
+where
+    T: Default,
+{
+    fn capacity(&self) -> usize {
+        self.writer.capacity()
+    }
+
+    fn write_chunk(&mut self) -> &mut [T] {
+        let chunk = self.writer.write_chunk();
+        if chunk.is_empty() {
+            self.full_since.get_or_insert_with(Instant::now);
+        } else if let Some(since) = self.full_since.take() {
+            let wait_ns = since.elapsed().as_nanos() as u64;
+            trace!(target: "cueue", queue = self.name, wait_ns, "full");
+        }
+        chunk
+    }
+
+    fn commit(&mut self, n: usize) -> usize {
+        let n = self.writer.commit(n);
+        if n != 0 {
+            self.trace_commit(n);
+        }
+        n
+    }
+
+    fn is_abandoned(&self) -> bool {
+        self.writer.is_abandoned()
+    }
+}
+
+/// Reader emitting tracing events, see the module docs.
+pub struct InstrumentedReader<T> {
+    reader: Reader<T>,
+    name: &'static str,
+    /// when `read_chunk` first found the queue empty, since the last successful one
+    empty_since: Option<Instant>,
+    /// length of the chunk returned by the last `read_chunk`
+    read_size: usize,
+}
+
+impl<T> InstrumentedReader<T>
+where
+    T: Default,
+{
+    /// Emit the events of `reader`, with `name` in the `queue` field.
+    pub fn new(reader: Reader<T>, name: &'static str) -> Self {
+        Self {
+            reader,
+            name,
+            empty_since: None,
+            read_size: 0,
+        }
+    }
+
+    /// Wait until elements are available, in a `wait` span, then return them,
+    /// see `Reader::read_chunk_blocking`.
+    pub fn read_chunk_blocking(&mut self) -> Result<&[T], Abandoned> {
+        if self.read_chunk().is_empty() {
+            let _span = trace_span!(target: "cueue", "wait", queue = self.name).entered();
+            self.reader.read_chunk_blocking()?;
+        }
+        Ok(self.read_chunk())
+    }
+
+    /// Returns the wrapped Reader.
+    pub fn into_inner(self) -> Reader<T> {
+        self.reader
+    }
+}
+
+impl<T> Consumer<T> for InstrumentedReader<T>
+where
+    T: Default,
+{
+    fn capacity(&self) -> usize {
+        self.reader.capacity()
+    }
+
+    fn read_chunk(&mut self) -> &[T] {
+        let chunk = self.reader.read_chunk();
+        if chunk.is_empty() {
+            self.empty_since.get_or_insert_with(Instant::now);
+        } else if let Some(since) = self.empty_since.take() {
+            let wait_ns = since.elapsed().as_nanos() as u64;
+            trace!(target: "cueue", queue = self.name, wait_ns, "empty");
+        }
+        self.read_size = chunk.len();
+        chunk
+    }
+
+    fn commit(&mut self) {
+        self.reader.commit();
+        let n = std::mem::take(&mut self.read_size);
+        if n != 0 {
+            let w = self.reader.write_pos().load(Ordering::Relaxed);
+            let r = self.reader.read_pos().load(Ordering::Relaxed) & !READER_BUSY;
+            let occupancy = w.saturating_sub(r);
+            trace!(target: "cueue", queue = self.name, batch = n, occupancy, "commit");
+        }
+    }
+
+    fn is_abandoned(&self) -> bool {
+        self.reader.is_abandoned()
+    }
+}
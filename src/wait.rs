@@ -0,0 +1,148 @@
+//! Strategies to wait for the other side of the queue in blocking operations.
+//!
+//! A blocking operation (e.g: `Reader::read_exact_timeout`) checks the queue,
+//! and if it is not ready, calls `WaitStrategy::wait` before checking it again.
+//! The strategy decides how to trade latency for CPU usage.
+
+use std::time::{Duration, Instant};
+
+/// Decides how to wait between two checks of the queue.
+pub trait WaitStrategy: Send {
+    /// Wait before the next check of the queue.
+    ///
+    /// `iteration` is the number of previous waits of the same blocking operation,
+    /// and `deadline` is the point in time the operation gives up at, if any.
+    /// Should return no later than `deadline`.
+    fn wait(&mut self, iteration: u32, deadline: Option<Instant>);
+
+    /// Returns true, if the operation should sleep on the futex of the queue at `iteration`
+    /// (i.e: until the other side commits), instead of calling `wait`.
+    ///
+    /// Only called if `Builder::futex` is enabled. By default, the futex is used right away.
+    fn park(&self, iteration: u32) -> bool {
+        let _ = iteration;
+        true
+    }
+}
+
+/// Busy wait: lowest latency, keeps a core busy.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Spin;
+
+impl WaitStrategy for Spin {
+    fn wait(&mut self, _iteration: u32, _deadline: Option<Instant>) {
+        std::hint::spin_loop();
+    }
+}
+
+/// Yield the CPU to other threads between checks.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Yield;
+
+impl WaitStrategy for Yield {
+    fn wait(&mut self, _iteration: u32, _deadline: Option<Instant>) {
+        std::thread::yield_now();
+    }
+}
+
+/// Sleep a fixed amount of time between checks.
+#[derive(Clone, Copy, Debug)]
+pub struct Sleep(pub Duration);
+
+impl WaitStrategy for Sleep {
+    fn wait(&mut self, _iteration: u32, deadline: Option<Instant>) {
+        sleep_until(self.0, deadline);
+    }
+}
+
+/// Spin first, then yield, then sleep for exponentially increasing durations, up to a limit.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    /// Number of iterations to spin for
+    pub spins: u32,
+    /// Number of iterations to yield for, after spinning
+    pub yields: u32,
+    /// Maximum sleep duration, after yielding
+    pub max_sleep: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            spins: 64,
+            yields: 64,
+            max_sleep: Duration::from_millis(1),
+        }
+    }
+}
+
+impl WaitStrategy for Backoff {
+    fn wait(&mut self, iteration: u32, deadline: Option<Instant>) {
+        if iteration < self.spins {
+            std::hint::spin_loop();
+        } else if iteration < self.spins + self.yields {
+            std::thread::yield_now();
+        } else {
+            let exp = u32::min(iteration - self.spins - self.yields, 20);
+            let sleep = Duration::from_micros(1 << exp);
+            sleep_until(Duration::min(sleep, self.max_sleep), deadline);
+        }
+    }
+}
+
+/// Spin first, then yield, then park: sleep until the other side commits.
+///
+/// The default strategy of blocking operations. A peer that commits within a few microseconds
+/// is caught while spinning, without a syscall, and a peer that is scheduled out is given
+/// the CPU by yielding. After that, with `Builder::futex`, the operation sleeps on the futex
+/// of the queue, and is woken by the next commit: an idle queue uses no CPU, and the wakeup
+/// does not wait for a sleep to elapse. Without a futex, it sleeps like `Backoff`.
+///
+/// The defaults are tuned on the `wait_strategy` benchmark (see `benches/bench.rs`): spinning
+/// covers a handoff between two cores. If the threads outnumber the cores, spinning only
+/// delays the peer, lower `spins` then.
+#[derive(Clone, Copy, Debug)]
+pub struct HybridWait {
+    /// Number of iterations to spin for
+    pub spins: u32,
+    /// Number of iterations to yield for, after spinning
+    pub yields: u32,
+    /// Maximum sleep duration, after yielding, if the futex is not enabled
+    pub max_sleep: Duration,
+}
+
+impl Default for HybridWait {
+    fn default() -> Self {
+        Self {
+            spins: 64,
+            yields: 8,
+            max_sleep: Duration::from_millis(1),
+        }
+    }
+}
+
+impl WaitStrategy for HybridWait {
+    fn wait(&mut self, iteration: u32, deadline: Option<Instant>) {
+        let mut backoff = Backoff {
+            spins: self.spins,
+            yields: self.yields,
+            max_sleep: self.max_sleep,
+        };
+        backoff.wait(iteration, deadline);
+    }
+
+    fn park(&self, iteration: u32) -> bool {
+        iteration >= self.spins.saturating_add(self.yields)
+    }
+}
+
+/// Sleep for `duration`, but do not sleep past `deadline`.
+fn sleep_until(duration: Duration, deadline: Option<Instant>) {
+    let duration = match deadline {
+        Some(deadline) => {
+            Duration::min(duration, deadline.saturating_duration_since(Instant::now()))
+        }
+        None => duration,
+    };
+    std::thread::sleep(duration);
+}